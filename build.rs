@@ -4,6 +4,9 @@ fn main() {
     println!("cargo:rerun-if-changed=../proto/game.proto");
     println!("cargo:rerun-if-changed=../omoba-core/src/generated/game.rs");
 
+    #[cfg(all(feature = "grpc", feature = "kcp"))]
+    compile_lockstep_service();
+
     if protoc_available() {
         compile_with_protoc();
     } else {
@@ -40,3 +43,26 @@ fn compile_with_protoc() {
             .expect("Failed to compile proto files");
     }
 }
+
+/// 階段 6.19：gRPC lockstep 串流服務。訊息是 `src/lockstep/wire.rs` 手寫的
+/// prost 信封，不經 game.proto，所以不需要 protoc。
+#[cfg(all(feature = "grpc", feature = "kcp"))]
+fn compile_lockstep_service() {
+    let play = tonic_build::manual::Method::builder()
+        .name("play")
+        .route_name("Play")
+        .input_type("crate::lockstep::wire::LockstepClientFrame")
+        .output_type("crate::lockstep::wire::LockstepServerFrame")
+        .codec_path("tonic::codec::ProstCodec")
+        .client_streaming()
+        .server_streaming()
+        .build();
+    let service = tonic_build::manual::Service::builder()
+        .name("Lockstep")
+        .package("omoba.lockstep")
+        .method(play)
+        .build();
+    tonic_build::manual::Builder::new()
+        .build_client(false)
+        .compile(&[service]);
+}
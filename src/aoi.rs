@@ -20,13 +20,24 @@
 //! 資源是 `Arc<Mutex<AoiGrid>>` 因此傳輸執行緒 (tokio) 可以
 //! 與遊戲循環 (rayon) 共享唯讀存取權限。便宜：重建是
 //! 本質上是兩張平面地圖的重新分配。
+//!
+//! 階段 6.22：每位玩家的興趣集合（`InterestSet`）也存在網格上，
+//! `rebuild` 不會清掉。`State` 在重建後以玩家視口呼叫
+//! `update_interest`，得到進入 / 離開的實體並發出對應訊息；廣播線程以
+//! `interested` 決定 `AoiEntity` 更新送給哪些會話。進入以視口矩形判斷，
+//! 離開則要超出視口再外擴 `AOI_HYSTERESIS_MARGIN`，邊緣上的實體不會
+//! 反覆進出。
 
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 
 /// 遊戲世界單位中單一 AOI 單元的邊長。
 /// 選擇 256，以便 1024x768 視口接觸 ≤ 16 個單元格（4x3 加填充）。
 pub const AOI_CELL_SIZE: f32 = 256.0;
 
+/// 離開興趣集合的外擴距離（遊戲單位）。半個單元格，大於實體在一次
+/// 興趣更新間隔內的移動量。
+pub const AOI_HYSTERESIS_MARGIN: f32 = 128.0;
+
 /// 單元內單一實體的位置快照。
 #[derive(Copy, Clone, Debug)]
 pub struct AoiEntry {
@@ -43,6 +54,8 @@ pub struct AoiEntry {
 pub struct AoiGrid {
     cells: HashMap<(i32, i32), Vec<AoiEntry>>,
     positions: HashMap<u64, (f32, f32)>,
+    /// 以玩家名稱為鍵的興趣集合。
+    interests: HashMap<String, InterestSet>,
 }
 
 impl AoiGrid {
@@ -50,6 +63,7 @@ impl AoiGrid {
         Self {
             cells: HashMap::new(),
             positions: HashMap::new(),
+            interests: HashMap::new(),
         }
     }
 
@@ -77,7 +91,7 @@ impl AoiGrid {
 
     /// 半徑查詢：對“radius”內的每個實體呼叫“cb(entity_id)”
     /// 的「中心」。用於 VFX 扇出/AOI 飛濺策略（保留用於
-    /// P6；目前未被廣播線程呼叫）。
+    /// P6；目前未被廣播線程呼叫）。興趣集合用 `query_rect`。
     pub fn query<F: FnMut(u64)>(&self, center: (f32, f32), radius: f32, mut cb: F) {
         let r2 = radius * radius;
         let (min_cx, min_cy) = Self::cell_key((center.0 - radius, center.1 - radius));
@@ -98,6 +112,54 @@ impl AoiGrid {
         }
     }
 
+    /// 矩形查詢：對 `center ± half` 內（含邊界）的每個實體呼叫 `cb`，
+    /// 判斷方式與 `Viewport::contains` 相同。
+    pub fn query_rect<F: FnMut(u64)>(&self, center: (f32, f32), half: (f32, f32), mut cb: F) {
+        let (min_cx, min_cy) = Self::cell_key((center.0 - half.0, center.1 - half.1));
+        let (max_cx, max_cy) = Self::cell_key((center.0 + half.0, center.1 + half.1));
+        for cx in min_cx..=max_cx {
+            for cy in min_cy..=max_cy {
+                let Some(bucket) = self.cells.get(&(cx, cy)) else {
+                    continue;
+                };
+                for e in bucket {
+                    if in_rect(e.pos, center, half) {
+                        cb(e.entity_id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 以玩家目前的視口（中心與半寬高）更新其興趣集合，回傳這次進入與
+    /// 離開的實體。第一次呼叫時視口內的實體全部算進入。
+    pub fn update_interest(
+        &mut self,
+        player: &str,
+        center: (f32, f32),
+        half: (f32, f32),
+        margin: f32,
+    ) -> InterestChanges {
+        let mut set = self.interests.remove(player).unwrap_or_default();
+        let changes = set.update(self, center, half, margin);
+        self.interests.insert(player.to_string(), set);
+        changes
+    }
+
+    /// 玩家的興趣集合是否包含 `entity_id`。玩家尚未有興趣集合（還沒
+    /// 送出視口或尚未更新過）時回傳 `None`，呼叫者自行退回視口判斷。
+    pub fn interested(&self, player: &str, entity_id: u64) -> Option<bool> {
+        self.interests
+            .get(player)
+            .map(|set| set.contains(entity_id))
+    }
+
+    /// 丟棄玩家的興趣集合（斷線 / 重新同步），下次更新時視口內的實體
+    /// 重新算進入。
+    pub fn drop_interest(&mut self, player: &str) {
+        self.interests.remove(player);
+    }
+
     /// 目前實體計數。對於診斷/測試很有用。
     pub fn len(&self) -> usize {
         self.positions.len()
@@ -115,6 +177,78 @@ impl AoiGrid {
     }
 }
 
+#[inline]
+fn in_rect(pos: (f32, f32), center: (f32, f32), half: (f32, f32)) -> bool {
+    (pos.0 - center.0).abs() <= half.0 && (pos.1 - center.1).abs() <= half.1
+}
+
+/// 一次興趣更新的結果，皆已排序。
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct InterestChanges {
+    pub entered: Vec<u64>,
+    pub left: Vec<u64>,
+}
+
+impl InterestChanges {
+    pub fn is_empty(&self) -> bool {
+        self.entered.is_empty() && self.left.is_empty()
+    }
+}
+
+/// 單一玩家目前視野內的實體 id。
+#[derive(Default, Debug, Clone)]
+pub struct InterestSet {
+    ids: HashSet<u64>,
+}
+
+impl InterestSet {
+    /// 視口內且不在集合中的實體進入；集合中已不在網格上、或超出視口
+    /// 外擴 `margin` 的實體離開。
+    pub fn update(
+        &mut self,
+        grid: &AoiGrid,
+        center: (f32, f32),
+        half: (f32, f32),
+        margin: f32,
+    ) -> InterestChanges {
+        let outer = (half.0 + margin, half.1 + margin);
+        let mut left: Vec<u64> = self
+            .ids
+            .iter()
+            .copied()
+            .filter(|id| {
+                !grid
+                    .lookup_pos(*id)
+                    .is_some_and(|pos| in_rect(pos, center, outer))
+            })
+            .collect();
+        for id in &left {
+            self.ids.remove(id);
+        }
+        let mut entered = Vec::new();
+        grid.query_rect(center, half, |id| {
+            if self.ids.insert(id) {
+                entered.push(id);
+            }
+        });
+        entered.sort_unstable();
+        left.sort_unstable();
+        InterestChanges { entered, left }
+    }
+
+    pub fn contains(&self, entity_id: u64) -> bool {
+        self.ids.contains(&entity_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -182,6 +316,53 @@ mod tests {
         assert_eq!(hits, vec![1]);
     }
 
+    #[test]
+    fn interest_enters_inside_viewport_and_leaves_past_the_margin() {
+        let mut g = AoiGrid::new();
+        let half = (100.0, 100.0);
+        g.rebuild([entry(1, 50.0, 0.0), entry(2, 150.0, 0.0)]);
+        let first = g.update_interest("alice", (0.0, 0.0), half, 40.0);
+        assert_eq!(first.entered, vec![1]);
+        assert!(first.left.is_empty());
+        assert_eq!(g.interested("alice", 1), Some(true));
+        assert_eq!(g.interested("alice", 2), Some(false));
+        assert_eq!(g.interested("bob", 1), None);
+
+        // 剛出視口但仍在外擴範圍內：不離開。
+        g.rebuild([entry(1, 120.0, 0.0), entry(2, 150.0, 0.0)]);
+        assert!(g
+            .update_interest("alice", (0.0, 0.0), half, 40.0)
+            .is_empty());
+
+        // 超出外擴範圍才離開；消失的實體同樣離開。
+        g.rebuild([entry(1, 141.0, 0.0), entry(3, 0.0, 0.0)]);
+        let moved = g.update_interest("alice", (0.0, 0.0), half, 40.0);
+        assert_eq!(moved.entered, vec![3]);
+        assert_eq!(moved.left, vec![1]);
+
+        // 回到視口內重新進入。
+        g.rebuild([entry(1, 100.0, 0.0), entry(3, 0.0, 0.0)]);
+        let back = g.update_interest("alice", (0.0, 0.0), half, 40.0);
+        assert_eq!(back.entered, vec![1]);
+
+        g.drop_interest("alice");
+        assert_eq!(g.interested("alice", 1), None);
+    }
+
+    #[test]
+    fn query_rect_matches_viewport_bounds() {
+        let mut g = AoiGrid::new();
+        g.rebuild([
+            entry(1, 300.0, 200.0),
+            entry(2, 301.0, 0.0),
+            entry(3, -300.0, -200.0),
+        ]);
+        let mut hits: Vec<u64> = Vec::new();
+        g.query_rect((0.0, 0.0), (300.0, 200.0), |id| hits.push(id));
+        hits.sort();
+        assert_eq!(hits, vec![1, 3]);
+    }
+
     #[test]
     fn negative_coordinates_are_supported() {
         let mut g = AoiGrid::new();
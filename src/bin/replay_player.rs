@@ -0,0 +1,400 @@
+//! omobab-replay — 無頭重播 lockstep replay 檔並驗證狀態雜湊。
+//!
+//! 讀取 `TickBroadcaster` 錄下的 replay（見 `omobab::lockstep::replay`），
+//! 以相同 story / master_seed 建立 `State`，逐刻把錄到的輸入送進
+//! `PendingPlayerInputs`（走與正式伺服器相同的 host_input 橋接），
+//! 並把 `compute_state_hash` 與錄到的 `StateHash` 比對。回報第一個
+//! 不一致的調度器刻度；完全一致時以 0 結束。
+//!
+//! 重播契約：廣播器刻度 `N` 的 `TickBatch` 在調度器刻度
+//! `N - start_tick` 套用，與 lockstep 客戶端的 sim_runner 相同。
+//!
+//! 世界初始化會讀取 `MasterSeed`，所以無法在 `State` 建好後再改種子：
+//! 錄到的 master_seed 與本機 `MasterSeed::default()` 不同時直接拒絕。
+//!
+//! 從 `[lockstep] resume_snapshot` 續局錄下的 replay（`start_tick != 0`）
+//! 必須以 `--resume-snapshot` 提供同一份快照；快照還原後調度器刻度
+//! 從 `start_tick` 繼續，批次 `N` 在調度器刻度 `N` 套用。
+//!
+//! `TickBatch.server_events`：加入 / 離開只是通知，模擬不讀取；開波 /
+//! 對局結束由調度器產生，重播時會重新產生，並依序與錄到的事件比對。
+//!
+//! 落後暫停（`LagPause` record）與正式伺服器一樣經
+//! `State::set_lag_pause_rx` 在同一個刻度設定 / 清除 `GamePause`。
+//!
+//! 用法：
+//!
+//! ```text
+//! omobab-replay <replay.omrp> [--until-tick N] [--keep-going] [--resume-snapshot FILE]
+//! ```
+//!
+//! 必須在錄製時相同的 `game.toml`（STEP_FPS / content 路徑）下執行。
+
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+
+use crossbeam_channel::unbounded;
+use omobab::comp::MasterSeed;
+use omobab::config::server_config::{apply_runtime_env_from_game_toml, CONFIG};
+use omobab::lockstep::{PlayerInput, ReplayReader, ReplayRecord, ServerEvent, ServerEventEnum};
+use omobab::state::State;
+use specs::WorldExt;
+
+struct Args {
+    replay: String,
+    until_tick: Option<u32>,
+    keep_going: bool,
+    resume_snapshot: Option<String>,
+}
+
+const USAGE: &str =
+    "usage: omobab-replay <replay.omrp> [--until-tick N] [--keep-going] [--resume-snapshot FILE]";
+
+fn parse_args() -> Result<Args, String> {
+    let mut replay = None;
+    let mut until_tick = None;
+    let mut keep_going = false;
+    let mut resume_snapshot = None;
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--until-tick" => {
+                let v = it.next().ok_or("--until-tick requires a value")?;
+                until_tick = Some(
+                    v.parse::<u32>()
+                        .map_err(|e| format!("invalid --until-tick {:?}: {}", v, e))?,
+                );
+            }
+            "--keep-going" => keep_going = true,
+            "--resume-snapshot" => {
+                let v = it.next().ok_or("--resume-snapshot requires a file")?;
+                resume_snapshot = Some(v);
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            other if replay.is_none() => replay = Some(other.to_string()),
+            other => return Err(format!("unexpected argument {:?}", other)),
+        }
+    }
+    Ok(Args {
+        replay: replay.ok_or("missing replay file argument")?,
+        until_tick,
+        keep_going,
+        resume_snapshot,
+    })
+}
+
+/// 某個調度器刻度上的雜湊比對結果。
+#[derive(Debug, PartialEq, Eq)]
+struct Divergence {
+    tick: u32,
+    expected: u64,
+    actual: u64,
+}
+
+/// 比對一筆重播產生的雜湊。`expected == 0` 代表錄製時廣播器沒有
+/// 拿到調度器樣本（見 `TickBroadcaster::latest_state_hash`），略過不比。
+fn check_hash(expected: &BTreeMap<u32, u64>, tick: u32, actual: u64) -> Option<Divergence> {
+    match expected.get(&tick) {
+        Some(&exp) if exp != 0 && exp != actual => Some(Divergence {
+            tick,
+            expected: exp,
+            actual,
+        }),
+        _ => None,
+    }
+}
+
+/// 調度器產生的事件（開波 / 對局結束）才需要比對；加入 / 離開由 kcp
+/// 連線產生，模擬不讀取。
+fn is_scheduler_event(event: &ServerEvent) -> bool {
+    match event.event {
+        Some(ServerEventEnum::WaveStart(_) | ServerEventEnum::GameEnd(_)) => true,
+        Some(ServerEventEnum::PlayerJoin(_) | ServerEventEnum::PlayerLeave(_)) | None => false,
+    }
+}
+
+/// 錄到的調度器事件必須依序是重播事件的前綴（錄影尾端的事件可能還在
+/// `SCHEDULER_EVENT_LEAD_TICKS` 提前量內未送出）。回傳第一個不一致的索引。
+fn check_server_events(recorded: &[ServerEvent], replayed: &[ServerEvent]) -> Option<usize> {
+    recorded
+        .iter()
+        .enumerate()
+        .find(|(i, event)| replayed.get(*i) != Some(*event))
+        .map(|(i, _)| i)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::from(2);
+        }
+    };
+    let _ = log4rs::init_file("log4rs.yml", Default::default());
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("replay failed: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// 回傳 `Ok(true)` 表示所有雜湊一致。
+fn run(args: &Args) -> Result<bool, String> {
+    apply_runtime_env_from_game_toml();
+    omoba_template_ids::ensure_runtime_lua_content().map_err(|e| format!("{}", e))?;
+
+    let reader = ReplayReader::open(&args.replay)
+        .map_err(|e| format!("failed to open replay {}: {}", args.replay, e))?;
+    let header = reader.header().clone();
+    if reader.truncated() {
+        eprintln!("warning: replay has a truncated tail (recording crashed); replaying complete records only");
+    }
+
+    let timing = CONFIG.lockstep_timing();
+    if timing.step_fps() != header.step_fps {
+        return Err(format!(
+            "replay recorded at STEP_FPS={} but game.toml has STEP_FPS={}",
+            header.step_fps,
+            timing.step_fps()
+        ));
+    }
+    let lua_hash = omoba_template_ids::runtime_lua_content_hash()
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    if lua_hash != header.lua_content_hash {
+        eprintln!(
+            "warning: lua content hash differs (recorded {:?}, current {:?}); divergence may be content drift",
+            header.lua_content_hash, lua_hash
+        );
+    }
+
+    // 種子必須在世界初始化前就正確；State 只會用預設種子建立。
+    let default_seed = MasterSeed::default().0;
+    if header.start_tick == 0 && default_seed != header.master_seed {
+        return Err(format!(
+            "replay recorded with master_seed {:#x} but this build initializes the world with {:#x}",
+            header.master_seed, default_seed
+        ));
+    }
+    let resume_bytes = match (header.start_tick, args.resume_snapshot.as_ref()) {
+        (0, None) => None,
+        (0, Some(_)) => {
+            return Err("--resume-snapshot given but the replay starts at tick 0".to_string())
+        }
+        (start, None) => {
+            return Err(format!(
+                "replay starts from a resumed snapshot at tick {start}; pass the same snapshot with --resume-snapshot"
+            ))
+        }
+        (_, Some(path)) => Some(
+            std::fs::read(path).map_err(|e| format!("failed to read snapshot {path}: {e}"))?,
+        ),
+    };
+    // 續局時調度器刻度從快照刻度繼續。
+    let base_tick = if resume_bytes.is_some() {
+        header.start_tick
+    } else {
+        0
+    };
+
+    // 依調度器刻度整理輸入、期望雜湊與調度器事件。
+    let mut inputs: BTreeMap<u32, Vec<(u32, PlayerInput)>> = BTreeMap::new();
+    let mut expected: BTreeMap<u32, u64> = BTreeMap::new();
+    let mut recorded_events: Vec<ServerEvent> = Vec::new();
+    let (lag_pause_tx, lag_pause_rx) = unbounded();
+    for record in reader.records() {
+        match record {
+            ReplayRecord::TickBatch(batch) => {
+                let tick = batch
+                    .tick
+                    .wrapping_sub(header.start_tick)
+                    .wrapping_add(base_tick);
+                if args.until_tick.map_or(true, |until| tick <= until) {
+                    recorded_events
+                        .extend(batch.server_events.into_iter().filter(is_scheduler_event));
+                }
+                let entry = inputs.entry(tick).or_default();
+                for ifp in batch.inputs {
+                    if let Some(input) = ifp.input {
+                        entry.push((ifp.player_id, input));
+                    }
+                }
+            }
+            ReplayRecord::StateHash(sh) => {
+                expected.insert(sh.tick, sh.hash);
+            }
+            ReplayRecord::DesyncMarker(d) => {
+                let players: Vec<u32> = d.diverged.iter().map(|(pid, _)| *pid).collect();
+                println!(
+                    "note: live desync recorded at tick {} (players {:?})",
+                    d.tick, players
+                );
+            }
+            ReplayRecord::LagPause(mut pause) => {
+                pause.tick = pause
+                    .tick
+                    .wrapping_sub(header.start_tick)
+                    .wrapping_add(base_tick);
+                let _ = lag_pause_tx.send(pause);
+            }
+            // 聊天不影響模擬。
+            ReplayRecord::Chat(_) | ReplayRecord::Unknown { .. } => {}
+        }
+    }
+    let last_tick = inputs
+        .keys()
+        .chain(expected.keys())
+        .copied()
+        .max()
+        .unwrap_or(0);
+    let last_tick = args.until_tick.map_or(last_tick, |t| t.min(last_tick));
+
+    let campaign = omobab::ue4::import_campaign::load_generated(&header.story)
+        .map_err(|e| format!("failed to load campaign '{}': {}", header.story, e))?;
+    let (out_tx, _out_rx) = unbounded();
+    let (_in_tx, in_rx) = unbounded();
+    let (_query_tx, query_rx) = unbounded();
+    let (_viewport_tx, viewport_rx) = unbounded();
+    let mut state = State::new_with_campaign(campaign, out_tx, in_rx, query_rx, viewport_rx);
+    if let Some(bytes) = resume_bytes.as_ref() {
+        let report = state.restore_snapshot(bytes)?;
+        if report.tick != header.start_tick {
+            return Err(format!(
+                "snapshot is at tick {} but the replay starts at tick {}",
+                report.tick, header.start_tick
+            ));
+        }
+        let seed = state.ecs().read_resource::<MasterSeed>().0;
+        if seed != header.master_seed {
+            return Err(format!(
+                "snapshot master_seed {:#x} differs from recorded {:#x}",
+                seed, header.master_seed
+            ));
+        }
+    }
+    let (host_input_tx, host_input_rx) = unbounded();
+    state.attach_host_input_rx(host_input_rx);
+    let (state_hash_tx, state_hash_rx) = unbounded();
+    state.set_state_hash_tx(state_hash_tx);
+    let (server_event_tx, server_event_rx) = unbounded();
+    state.set_server_event_tx(server_event_tx);
+    state.set_lag_pause_rx(lag_pause_rx);
+    let mut replayed_events: Vec<ServerEvent> = Vec::new();
+
+    println!(
+        "replaying {} (story={}, seed={:#x}, step_fps={}) — {} ticks, {} recorded hashes",
+        args.replay,
+        header.story,
+        header.master_seed,
+        header.step_fps,
+        last_tick,
+        expected.len()
+    );
+
+    let dt = timing.dt_duration();
+    let mut verified = 0usize;
+    let mut divergences = Vec::new();
+    for tick in base_tick + 1..=last_tick {
+        if let Some(batch) = inputs.remove(&tick) {
+            if !batch.is_empty() {
+                host_input_tx
+                    .send(batch)
+                    .map_err(|e| format!("host input channel closed: {e}"))?;
+            }
+        }
+        state
+            .tick(dt)
+            .map_err(|e| format!("State::tick failed at tick {}: {:?}", tick, e))?;
+        while let Ok((hash_tick, actual)) = state_hash_rx.try_recv() {
+            if !expected.contains_key(&hash_tick) {
+                continue;
+            }
+            match check_hash(&expected, hash_tick, actual) {
+                Some(d) => {
+                    println!(
+                        "DIVERGED at tick {}: recorded {:#018x}, replayed {:#018x}",
+                        d.tick, d.expected, d.actual
+                    );
+                    divergences.push(d);
+                    if !args.keep_going {
+                        return Ok(false);
+                    }
+                }
+                None => verified += 1,
+            }
+        }
+        replayed_events.extend(server_event_rx.try_iter().map(|(_, event)| event));
+    }
+
+    if let Some(i) = check_server_events(&recorded_events, &replayed_events) {
+        println!(
+            "DIVERGED server events at #{}: recorded {:?}, replayed {:?}",
+            i,
+            recorded_events[i],
+            replayed_events.get(i)
+        );
+        return Ok(false);
+    }
+
+    match divergences.first() {
+        Some(first) => {
+            println!(
+                "{} divergent hash(es); first divergence at tick {}",
+                divergences.len(),
+                first.tick
+            );
+            Ok(false)
+        }
+        None => {
+            println!("OK: {} state hashes verified, no divergence", verified);
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_hash_flags_only_recorded_mismatches() {
+        let expected: BTreeMap<u32, u64> = [(10, 0xAA), (20, 0), (30, 0xCC)].into_iter().collect();
+        assert_eq!(check_hash(&expected, 10, 0xAA), None);
+        assert_eq!(
+            check_hash(&expected, 30, 0xCD),
+            Some(Divergence {
+                tick: 30,
+                expected: 0xCC,
+                actual: 0xCD
+            })
+        );
+        // hash=0 表示錄製時沒有樣本，不能算不一致。
+        assert_eq!(check_hash(&expected, 20, 0x1234), None);
+        // 未錄到的刻度不比對。
+        assert_eq!(check_hash(&expected, 40, 0x1234), None);
+    }
+
+    #[test]
+    fn recorded_scheduler_events_must_prefix_the_replayed_ones() {
+        use omobab::lockstep::server_events::{game_end, player_join, wave_start};
+
+        let recorded = [wave_start(0), wave_start(1)];
+        // 錄影尾端之後重播多出的事件不算不一致。
+        assert_eq!(
+            check_server_events(&recorded, &[wave_start(0), wave_start(1), game_end("left")]),
+            None
+        );
+        assert_eq!(
+            check_server_events(&recorded, &[wave_start(0), game_end("left")]),
+            Some(1)
+        );
+        assert_eq!(check_server_events(&recorded, &[wave_start(0)]), Some(1));
+        assert!(!is_scheduler_event(&player_join(1, "p1")));
+        assert!(is_scheduler_event(&wave_start(0)));
+    }
+}
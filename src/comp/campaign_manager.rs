@@ -102,9 +102,6 @@ impl CampaignManager {
                 hero_data.id
             )
         });
-        // Phase 1c.4: CProperty / TAttack are Fixed64 (Phase 1c.2). Pass Fixed64 直送。
-        let hero_properties = Self::create_hero_properties(&hero, s.base_armor);
-        let hero_attack = Self::create_hero_attack(&hero, s.attack_range);
         let abilities: Vec<String> = if hero_data.abilities.is_empty() {
             active_hero_abilities(id)
                 .iter()
@@ -113,27 +110,83 @@ impl CampaignManager {
         } else {
             hero_data.abilities.clone()
         };
+        let hero_entity = Self::build_hero_entity(
+            ecs,
+            hero,
+            &abilities,
+            s.base_armor,
+            s.attack_range,
+            Pos::from_xy_f32(0.0, 0.0),
+        );
+
+        log::info!(
+            "Created hero entity '{}' with full combat components",
+            hero_data.id
+        );
+        Self::create_hero_abilities(ecs, hero_entity, &abilities, campaign_data);
+    }
+
+    /// 階段 6.6：快照還原時重建世界裡沒有的英雄。模板取自
+    /// `init_campaign_data` 載入的英雄表，元件與開局相同；等級、技能與
+    /// 冷卻之後由快照覆寫。找不到模板或 stats 時回傳 `None`。
+    pub fn respawn_hero(ecs: &mut World, hero_id: &str, pos: Pos) -> Option<specs::Entity> {
+        use omoba_template_ids::{active_hero_abilities, active_hero_stats, hero_by_name};
+        let hero = ecs
+            .try_fetch::<BTreeMap<String, Hero>>()?
+            .get(hero_id)?
+            .clone();
+        let id = hero_by_name(hero_id)?;
+        let s = active_hero_stats(id)?;
+        let abilities: Vec<String> = if hero.abilities.is_empty() {
+            active_hero_abilities(id)
+                .iter()
+                .map(|a| a.as_str().to_string())
+                .collect()
+        } else {
+            hero.abilities.clone()
+        };
+        Some(Self::build_hero_entity(
+            ecs,
+            hero,
+            &abilities,
+            s.base_armor,
+            s.attack_range,
+            pos,
+        ))
+    }
+
+    /// 建立帶完整戰鬥元件的英雄實體（開局與快照還原共用）。
+    fn build_hero_entity(
+        ecs: &mut World,
+        hero: Hero,
+        abilities: &[String],
+        base_armor: Fixed64,
+        attack_range: Fixed64,
+        hero_pos: Pos,
+    ) -> specs::Entity {
+        // Phase 1c.4: CProperty / TAttack are Fixed64 (Phase 1c.2). Pass Fixed64 直送。
+        let hero_properties = Self::create_hero_properties(&hero, base_armor);
+        let hero_attack = Self::create_hero_attack(&hero, attack_range);
 
         // 注意：Unit.{current_hp, max_hp, base_damage} 設計為 i32（整數遊戲值）；
         // 在此邊界處從固定64 模板轉換。
         let max_hp_i = hero.get_max_hp().to_f32_for_render() as i32;
         let base_damage_i = hero.get_base_damage().to_f32_for_render() as i32;
-        let attack_range_fx = s.attack_range;
         let hero_unit = Unit {
             id: hero.id.clone(),
             name: hero.name.clone(),
             unit_type: UnitType::Hero,
             max_hp: max_hp_i,
             current_hp: max_hp_i,
-            base_armor: s.base_armor,
+            base_armor,
             magic_resistance: Fixed64::ZERO,
             base_damage: base_damage_i,
-            attack_range: attack_range_fx,
+            attack_range,
             move_speed: hero.get_move_speed(),
             attack_speed: hero.get_attack_speed_multiplier(),
             ai_type: unit::AiType::None,
-            aggro_range: attack_range_fx + Fixed64::from_i32(200),
-            abilities: abilities.clone(),
+            aggro_range: attack_range + Fixed64::from_i32(200),
+            abilities: abilities.to_vec(),
             current_target: None,
             last_attack_time: Fixed64::ZERO,
             spawn_position: (0.0, 0.0),
@@ -143,17 +196,15 @@ impl CampaignManager {
         };
 
         let hero_faction = Faction::new(FactionType::Player, 0);
-        let hero_pos = Pos::from_xy_f32(0.0, 0.0);
         let hero_vel = Vel::zero();
         // 注意：CircularVision 是客戶端渲染提示（戰爭迷霧）；從權威 Pos 進行的每次報價重建可保持跨客戶端的一致性。
         let hero_vision = CircularVision::new(
-            (attack_range_fx + Fixed64::from_i32(300)).to_f32_for_render(),
+            (attack_range + Fixed64::from_i32(300)).to_f32_for_render(),
             30.0,
         )
         .with_precision(720);
 
-        let hero_entity = ecs
-            .create_entity()
+        ecs.create_entity()
             .with(hero_pos)
             .with(hero_vel)
             .with(hero)
@@ -162,13 +213,7 @@ impl CampaignManager {
             .with(hero_properties)
             .with(hero_attack)
             .with(hero_vision)
-            .build();
-
-        log::info!(
-            "Created hero entity '{}' with full combat components",
-            hero_data.id
-        );
-        Self::create_hero_abilities(ecs, hero_entity, &abilities, campaign_data);
+            .build()
     }
 
     fn create_hero_properties(hero: &Hero, base_armor: Fixed64) -> CProperty {
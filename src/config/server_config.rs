@@ -19,6 +19,10 @@ fn default_step_fps() -> u32 {
     LOCKSTEP_TPS
 }
 
+fn default_ws_port() -> String {
+    "50062".to_string()
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ServerSetting {
     pub SERVER_IP: String,
@@ -40,6 +44,17 @@ pub struct ServerSetting {
     /// Runtime 可由 stdin 指令 `:speed N` 動態切換（範圍 1..=16）。
     #[serde(default = "default_speed_mult")]
     pub SPEED_MULT: u32,
+    /// `websocket` feature 的監聽埠（瀏覽器客戶端）。與 `SERVER_IP` 搭配。
+    #[serde(default = "default_ws_port")]
+    pub WS_PORT: String,
+    /// MQTT broker 埠。未設定時沿用 `SERVER_PORT`；與 gRPC 同時啟用時需
+    /// 分開（兩者都是 TCP）。
+    #[serde(default)]
+    pub MQTT_PORT: Option<String>,
+    /// 階段 6.23：玩家名稱 → 隊伍編號，填入 `BroadcastPolicy::Team` 使用的
+    /// 分隊表。未列出的玩家不屬於任何隊伍。
+    #[serde(default)]
+    pub TEAMS: BTreeMap<String, u32>,
 }
 
 /// `[hero_knowledge]` section in `game.toml`.
@@ -80,6 +95,160 @@ impl Default for HeroKnowledgeSetting {
     }
 }
 
+/// `[lockstep]` section in `game.toml`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LockstepSetting {
+    /// 是否把 lockstep 串流（TickBatch / StateHash）錄成 replay 檔。預設 true。
+    #[serde(default = "default_true")]
+    pub replay_enabled: bool,
+    /// Replay 檔輸出目錄。相對路徑以 cwd 為準。預設 "replays"。
+    #[serde(default = "default_replay_dir")]
+    pub replay_dir: String,
+    /// 偵測到客戶端 state hash 不一致時，是否把最近的 SnapshotStore
+    /// 傾印到 `desync_dump_dir`。預設 false。
+    #[serde(default)]
+    pub desync_dump_snapshot: bool,
+    /// 不同步快照傾印目錄。預設 "desync_dumps"。
+    #[serde(default = "default_desync_dump_dir")]
+    pub desync_dump_dir: String,
+    /// `SnapshotReq` 補送用的 TickBatch 環形緩衝區長度（秒）。需大於
+    /// 快照週期（30 秒），才能從任一快照向前滾動。預設 40。
+    #[serde(default = "default_tick_history_seconds")]
+    pub tick_history_seconds: u32,
+    /// 玩家斷線後保留座位等待重連（帶 resume token 的 JoinRequest）的
+    /// 秒數。0 表示斷線立即釋放座位。預設 30。
+    #[serde(default = "default_resume_grace_seconds")]
+    pub resume_grace_seconds: u32,
+    /// 觀察者串流延遲秒數（賽事 / 直播防即時資訊洩漏）。0 表示不延遲。
+    #[serde(default)]
+    pub spectator_delay_seconds: u32,
+    /// 同時在線的觀察者上限。預設 8。
+    #[serde(default = "default_max_observers")]
+    pub max_observers: usize,
+    /// 玩家 `TickAck` 確認的刻度落後目前刻度超過此秒數即視為落後。
+    /// 0 表示不檢查。預設 10。
+    #[serde(default = "default_lag_ack_budget_seconds")]
+    pub lag_ack_budget_seconds: u32,
+    /// 玩家的平滑 RTT（伺服器以 ping 量測）超過此毫秒數即視為落後。
+    /// 0 表示不檢查。預設 1000。
+    #[serde(default = "default_lag_rtt_budget_ms")]
+    pub lag_rtt_budget_ms: u32,
+    /// 落後時的處理：`warn`（只通知）、`pause`（以 `GamePause` 暫停對局
+    /// 直到追上）、`ai`（交由伺服器代管直到恢復）。預設 "warn"。
+    #[serde(default = "default_lag_policy")]
+    pub lag_policy: String,
+    /// 每個玩家每刻最多接受的 InputSubmit 數。0 表示不限制。預設 16。
+    #[serde(default = "default_max_inputs_per_tick")]
+    pub max_inputs_per_tick: u32,
+    /// 每個玩家每秒最多接受的 InputSubmit 數。0 表示不限制。預設 480。
+    #[serde(default = "default_max_inputs_per_second")]
+    pub max_inputs_per_second: u32,
+    /// `target_tick` 最多可超前目前刻度的秒數。0 表示不限制。預設 2
+    /// （與 InputBuffer 的未來輸入保留窗口相同）。
+    #[serde(default = "default_max_input_lead_seconds")]
+    pub max_input_lead_seconds: u32,
+    /// 地圖範圍 `[min_x, min_y, max_x, max_y]`（世界座標），InputSubmit 與
+    /// 地圖標記的座標必須落在其中。未設定表示不檢查座標。
+    #[serde(default)]
+    pub map_bounds: Option<[f32; 4]>,
+    /// 對局內聊天每則的字元數上限。0 表示不限制。預設 200。
+    #[serde(default = "default_chat_max_chars")]
+    pub chat_max_chars: usize,
+    /// 每個玩家在 `chat_window_seconds` 內最多送出的聊天 / 地圖標記數。
+    /// 0 表示不限制。預設 5。
+    #[serde(default = "default_chat_max_messages")]
+    pub chat_max_messages: u32,
+    /// 聊天速率窗口秒數。預設 5。
+    #[serde(default = "default_chat_window_seconds")]
+    pub chat_window_seconds: u32,
+    /// 開局時載入的快照檔（`serialize_snapshot` 位元組，例如不同步傾印），
+    /// 對局從快照的刻度繼續。空字串表示從頭開始。
+    #[serde(default)]
+    pub resume_snapshot: String,
+}
+
+fn default_replay_dir() -> String {
+    "replays".to_string()
+}
+
+fn default_desync_dump_dir() -> String {
+    "desync_dumps".to_string()
+}
+
+fn default_tick_history_seconds() -> u32 {
+    40
+}
+
+fn default_resume_grace_seconds() -> u32 {
+    30
+}
+
+fn default_max_observers() -> usize {
+    8
+}
+
+fn default_lag_ack_budget_seconds() -> u32 {
+    10
+}
+
+fn default_lag_rtt_budget_ms() -> u32 {
+    1000
+}
+
+fn default_lag_policy() -> String {
+    "warn".to_string()
+}
+
+fn default_max_inputs_per_tick() -> u32 {
+    16
+}
+
+fn default_max_inputs_per_second() -> u32 {
+    480
+}
+
+fn default_max_input_lead_seconds() -> u32 {
+    2
+}
+
+fn default_chat_max_chars() -> usize {
+    200
+}
+
+fn default_chat_max_messages() -> u32 {
+    5
+}
+
+fn default_chat_window_seconds() -> u32 {
+    5
+}
+
+impl Default for LockstepSetting {
+    fn default() -> Self {
+        Self {
+            replay_enabled: true,
+            replay_dir: default_replay_dir(),
+            desync_dump_snapshot: false,
+            desync_dump_dir: default_desync_dump_dir(),
+            tick_history_seconds: default_tick_history_seconds(),
+            resume_grace_seconds: default_resume_grace_seconds(),
+            spectator_delay_seconds: 0,
+            max_observers: default_max_observers(),
+            lag_ack_budget_seconds: default_lag_ack_budget_seconds(),
+            lag_rtt_budget_ms: default_lag_rtt_budget_ms(),
+            lag_policy: default_lag_policy(),
+            max_inputs_per_tick: default_max_inputs_per_tick(),
+            max_inputs_per_second: default_max_inputs_per_second(),
+            max_input_lead_seconds: default_max_input_lead_seconds(),
+            map_bounds: None,
+            chat_max_chars: default_chat_max_chars(),
+            chat_max_messages: default_chat_max_messages(),
+            chat_window_seconds: default_chat_window_seconds(),
+            resume_snapshot: String::new(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct Setting {
     server: ServerSetting,
@@ -87,6 +256,8 @@ struct Setting {
     content: ContentSetting,
     #[serde(default)]
     hero_knowledge: HeroKnowledgeSetting,
+    #[serde(default)]
+    lockstep: LockstepSetting,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -214,6 +385,18 @@ pub fn read_hero_knowledge_setting() -> HeroKnowledgeSetting {
     }
 }
 
+/// 讀取 `game.toml` 的 `[lockstep]` section。
+/// 讀取失敗時回傳 default。
+pub fn read_lockstep_setting() -> LockstepSetting {
+    match read_setting() {
+        Ok(s) => s.lockstep,
+        Err(e) => {
+            log::warn!("failed to read lockstep config: {}; using defaults", e);
+            LockstepSetting::default()
+        }
+    }
+}
+
 impl ServerSetting {
     pub fn validate(&self) -> Result<(), String> {
         LockstepTiming::new(self.STEP_FPS).map(|_| ())
@@ -223,6 +406,12 @@ impl ServerSetting {
         LockstepTiming::new(self.STEP_FPS)
             .expect("ServerSetting::validate should reject unsupported STEP_FPS")
     }
+
+    pub fn mqtt_port(&self) -> String {
+        self.MQTT_PORT
+            .clone()
+            .unwrap_or_else(|| self.SERVER_PORT.clone())
+    }
 }
 /*
 impl ServerSetting {
@@ -307,4 +496,111 @@ RENDER_DELAY_MS = 100
         assert_eq!(setting.STEP_FPS, LOCKSTEP_TPS);
         assert!(setting.validate().is_ok());
     }
+
+    #[test]
+    fn missing_ws_port_defaults() {
+        let setting = toml::from_str::<Setting>(server_only_toml()).unwrap().server;
+        assert_eq!(setting.WS_PORT, "50062");
+
+        let raw = server_only_toml().replace("[server]\n", "[server]\nWS_PORT = \"8081\"\n");
+        let setting = toml::from_str::<Setting>(&raw).unwrap().server;
+        assert_eq!(setting.WS_PORT, "8081");
+    }
+
+    #[test]
+    fn mqtt_port_falls_back_to_server_port() {
+        let setting = toml::from_str::<Setting>(server_only_toml()).unwrap().server;
+        assert_eq!(setting.mqtt_port(), "50061");
+
+        let raw = server_only_toml().replace("[server]\n", "[server]\nMQTT_PORT = \"1883\"\n");
+        let setting = toml::from_str::<Setting>(&raw).unwrap().server;
+        assert_eq!(setting.mqtt_port(), "1883");
+    }
+
+    #[test]
+    fn teams_default_to_empty_and_parse_inline_table() {
+        let setting = toml::from_str::<Setting>(server_only_toml()).unwrap().server;
+        assert!(setting.TEAMS.is_empty());
+
+        let raw = server_only_toml().replace(
+            "[server]\n",
+            "[server]\nTEAMS = { alice = 1, bob = 2 }\n",
+        );
+        let setting = toml::from_str::<Setting>(&raw).unwrap().server;
+        assert_eq!(setting.TEAMS.get("alice"), Some(&1));
+        assert_eq!(setting.TEAMS.get("bob"), Some(&2));
+    }
+
+    #[test]
+    fn lockstep_section_keys_override_defaults() {
+        let missing = toml::from_str::<Setting>(server_only_toml())
+            .unwrap()
+            .lockstep;
+        assert_eq!(missing, LockstepSetting::default());
+
+        let cases: Vec<(&str, fn(&mut LockstepSetting))> = vec![
+            (
+                "replay_enabled = false\nreplay_dir = \"out/replays\"",
+                |s| {
+                    s.replay_enabled = false;
+                    s.replay_dir = "out/replays".to_string();
+                },
+            ),
+            (
+                "desync_dump_snapshot = true\ndesync_dump_dir = \"dumps\"",
+                |s| {
+                    s.desync_dump_snapshot = true;
+                    s.desync_dump_dir = "dumps".to_string();
+                },
+            ),
+            ("tick_history_seconds = 90", |s| s.tick_history_seconds = 90),
+            ("resume_grace_seconds = 0", |s| s.resume_grace_seconds = 0),
+            ("spectator_delay_seconds = 120\nmax_observers = 2", |s| {
+                s.spectator_delay_seconds = 120;
+                s.max_observers = 2;
+            }),
+            (
+                "lag_ack_budget_seconds = 3\nlag_rtt_budget_ms = 0\nlag_policy = \"ai\"",
+                |s| {
+                    s.lag_ack_budget_seconds = 3;
+                    s.lag_rtt_budget_ms = 0;
+                    s.lag_policy = "ai".to_string();
+                },
+            ),
+            ("max_inputs_per_tick = 0\nmax_input_lead_seconds = 5", |s| {
+                s.max_inputs_per_tick = 0;
+                s.max_input_lead_seconds = 5;
+            }),
+            ("map_bounds = [-8000.0, -6000.0, 8000.0, 6000.0]", |s| {
+                s.map_bounds = Some([-8000.0, -6000.0, 8000.0, 6000.0]);
+            }),
+            ("chat_max_chars = 80\nchat_max_messages = 0", |s| {
+                s.chat_max_chars = 80;
+                s.chat_max_messages = 0;
+            }),
+            ("resume_snapshot = \"dumps/t1200.bin\"", |s| {
+                s.resume_snapshot = "dumps/t1200.bin".to_string();
+            }),
+        ];
+        for (section, expect) in cases {
+            let raw = format!("{}\n[lockstep]\n{}\n", server_only_toml(), section);
+            let parsed = toml::from_str::<Setting>(&raw).unwrap().lockstep;
+            let mut expected = LockstepSetting::default();
+            expect(&mut expected);
+            assert_eq!(parsed, expected, "[lockstep] {section}");
+        }
+    }
+
+    fn server_only_toml() -> &'static str {
+        r#"
+[server]
+MAP = "map.json"
+MAX_PLAYER = 10000
+SERVER_IP = "localhost"
+SERVER_PORT = "50061"
+CLIENT_ID = "omobab"
+PLAYER_NAME = "player1"
+RENDER_DELAY_MS = 100
+"#
+    }
 }
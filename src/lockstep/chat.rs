@@ -0,0 +1,213 @@
+//! 階段 6.24：對局內玩家聊天與地圖標記。
+//!
+//! 客戶端以 `ChatSend`（標籤 0x1C）送出；會話以
+//! `LockstepState::submit_chat` 驗證（座位授權、速率、長度、標記範圍）
+//! 後排入待送佇列，`TickBroadcaster` 在下一刻取走：先寫進 replay
+//! （`ReplayRecordKind::Chat`），再以 `BroadcastPolicy::All` /
+//! `BroadcastPolicy::Team` 經既有的隊伍路由送出。被拒絕時以
+//! `ChatRejected`（標籤 0x1D）只回給送出者。
+//!
+//! 聊天不進 `TickBatch`，不影響 lockstep 決定論與 state hash。
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::lockstep::input_validation::MapBounds;
+use crate::lockstep::rate_limit::{FixedWindow, Rejection};
+use crate::lockstep::wire::ChatRejectReason;
+use crate::transport::{BroadcastPolicy, OutboundMsg};
+
+/// 聊天上限。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChatLimits {
+    /// 每則文字的字元數上限（以 Unicode 字元計），0 表示不限制。
+    pub max_chars: usize,
+    /// 每個窗口最多接受的則數（含地圖標記），0 表示不限制。
+    pub max_per_window: u32,
+    /// 速率窗口長度（刻度）。
+    pub window_ticks: u32,
+}
+
+pub type ChatRejection = Rejection<ChatRejectReason>;
+
+/// 單一座位的聊天計數。存在 `PlayerSession` 上，重連後沿用。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatRateWindow(FixedWindow);
+
+impl ChatRateWindow {
+    pub fn admit(&mut self, now: u32, limits: &ChatLimits) -> Result<(), ChatRejection> {
+        if !self
+            .0
+            .admit(now, limits.window_ticks, limits.max_per_window)
+        {
+            return Err(ChatRejection::new(
+                ChatRejectReason::RateLimited,
+                format!(
+                    "more than {} chat messages in {} ticks",
+                    limits.max_per_window, limits.window_ticks
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// 收件範圍。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatScope {
+    All,
+    /// 送出時送出者所在的隊伍。
+    Team(u32),
+}
+
+/// 通過驗證的一則聊天，也是 replay 的 `Chat` record（bincode）。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// 送出時的 `LockstepState.current_tick`。
+    pub tick: u32,
+    pub player_id: u32,
+    pub player_name: String,
+    pub scope: ChatScope,
+    pub text: String,
+    /// 地圖標記位置（世界座標）。
+    pub ping: Option<(f32, f32)>,
+}
+
+impl ChatMessage {
+    pub fn policy(&self) -> BroadcastPolicy {
+        match self.scope {
+            ChatScope::All => BroadcastPolicy::All,
+            ChatScope::Team(team) => BroadcastPolicy::Team(team),
+        }
+    }
+
+    /// 出站訊息：有標記時為 `chat`/`ping`，否則 `chat`/`msg`。
+    pub fn to_outbound(&self) -> OutboundMsg {
+        let action = if self.ping.is_some() { "ping" } else { "msg" };
+        let channel = match self.scope {
+            ChatScope::All => "all",
+            ChatScope::Team(_) => "team",
+        };
+        let ping = self.ping.map(|(x, y)| json!({ "x": x, "y": y }));
+        OutboundMsg::new_s(
+            "td/all/res",
+            "chat",
+            action,
+            json!({
+                "tick": self.tick,
+                "player_id": self.player_id,
+                "player": self.player_name,
+                "channel": channel,
+                "text": self.text,
+                "ping": ping,
+            }),
+        )
+        .with_policy(self.policy())
+    }
+}
+
+/// 去掉控制字元與前後空白，再檢查長度。
+pub fn clean_chat_text(raw: &str, limits: &ChatLimits) -> Result<String, ChatRejection> {
+    let text: String = raw.chars().filter(|c| !c.is_control()).collect();
+    let text = text.trim();
+    let chars = text.chars().count();
+    if limits.max_chars > 0 && chars > limits.max_chars {
+        return Err(ChatRejection::new(
+            ChatRejectReason::TooLong,
+            format!(
+                "{} characters exceeds the {} limit",
+                chars, limits.max_chars
+            ),
+        ));
+    }
+    Ok(text.to_string())
+}
+
+/// 標記座標必須是有限值，且在地圖範圍內（有設定範圍時）。
+pub fn check_ping(x: f32, y: f32, bounds: Option<MapBounds>) -> Result<(), ChatRejection> {
+    let on_map = match bounds {
+        Some(b) => b.contains_world(x, y),
+        None => true,
+    };
+    if !(x.is_finite() && y.is_finite() && on_map) {
+        return Err(ChatRejection::new(
+            ChatRejectReason::OutOfBounds,
+            format!("ping ({}, {}) is outside the map", x, y),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> ChatLimits {
+        ChatLimits {
+            max_chars: 8,
+            max_per_window: 2,
+            window_ticks: 60,
+        }
+    }
+
+    #[test]
+    fn rate_window_resets_after_window_ticks() {
+        let mut rate = ChatRateWindow::default();
+        assert!(rate.admit(10, &limits()).is_ok());
+        assert!(rate.admit(20, &limits()).is_ok());
+        assert_eq!(
+            rate.admit(30, &limits()).unwrap_err().reason,
+            ChatRejectReason::RateLimited
+        );
+        assert!(rate.admit(70, &limits()).is_ok());
+
+        let mut unlimited = ChatRateWindow::default();
+        for tick in 0..100 {
+            assert!(unlimited.admit(tick, &ChatLimits::default()).is_ok());
+        }
+    }
+
+    #[test]
+    fn text_is_stripped_and_capped_by_characters() {
+        assert_eq!(
+            clean_chat_text("  go\u{7}mid \n", &limits()).unwrap(),
+            "gomid"
+        );
+        // 8 個中文字元（24 位元組）仍在上限內。
+        assert!(clean_chat_text("這裡蓋塔這裡蓋塔", &limits()).is_ok());
+        assert_eq!(
+            clean_chat_text("123456789", &limits()).unwrap_err().reason,
+            ChatRejectReason::TooLong
+        );
+    }
+
+    #[test]
+    fn ping_must_be_finite_and_on_the_map() {
+        let bounds = Some(MapBounds::from_world(0.0, 0.0, 1000.0, 1000.0));
+        assert!(check_ping(500.0, 20.0, bounds).is_ok());
+        assert!(check_ping(-5.0, 20.0, bounds).is_err());
+        assert!(check_ping(f32::NAN, 0.0, None).is_err());
+        assert!(check_ping(-5.0, 20.0, None).is_ok());
+    }
+
+    #[test]
+    fn team_chat_routes_to_the_team() {
+        let msg = ChatMessage {
+            tick: 42,
+            player_id: 2,
+            player_name: "bob".into(),
+            scope: ChatScope::Team(1),
+            text: String::new(),
+            ping: Some((64.0, 128.0)),
+        };
+        let out = msg.to_outbound();
+        assert!(matches!(out.policy, Some(BroadcastPolicy::Team(1))));
+        let body: serde_json::Value = serde_json::from_str(&out.msg).unwrap();
+        assert_eq!(
+            (body["t"].as_str(), body["a"].as_str()),
+            (Some("chat"), Some("ping"))
+        );
+        assert_eq!(body["d"]["channel"], "team");
+        assert_eq!(body["d"]["ping"]["y"], 128.0);
+    }
+}
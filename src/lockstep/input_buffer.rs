@@ -27,6 +27,18 @@ pub enum InputSubmitResult {
     },
 }
 
+impl InputSubmitResult {
+    /// 輸入實際排入的刻度；被拒絕時為 `None`。
+    pub fn effective_tick(&self) -> Option<u32> {
+        match *self {
+            Self::Accepted { effective_tick } | Self::Retargeted { effective_tick, .. } => {
+                Some(effective_tick)
+            }
+            Self::RejectedLate { .. } => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct BufferedPlayerInput {
     pub input: PlayerInput,
@@ -135,6 +147,11 @@ impl InputBuffer {
         self.by_tick.retain(|&t, _| t >= before_tick);
     }
 
+    /// 丟棄所有待處理輸入（快照還原換時間軸時使用）。
+    pub fn clear(&mut self) {
+        self.by_tick.clear();
+    }
+
     /// 所有未來報價的待處理輸入總數（用於診斷）。
     pub fn pending_count(&self) -> usize {
         self.by_tick
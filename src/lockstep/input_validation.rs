@@ -0,0 +1,259 @@
+//! 階段 6.15：`TAG_INPUT_SUBMIT` 進入 `InputBuffer` 之前的驗證層。
+//!
+//! 依序檢查：
+//! 1. 每個玩家每刻 / 每秒的輸入數上限（`InputRateWindow`，存在
+//!    `PlayerSession` 上，被拒絕的輸入同樣計數，避免以無效輸入洗頻）；
+//! 2. `target_tick` 不得超過目前刻度 + `max_future_ticks`（更遠的輸入
+//!    反正會被 `InputBuffer` 的保留窗口淘汰）；
+//! 3. `MoveTo` / `TowerPlace` / `CastAbility` 的座標必須在地圖範圍內
+//!    （`[lockstep] map_bounds`）；
+//! 4. 技能與物品格索引必須在合法範圍內。
+//!
+//! 被拒絕的輸入以 `InputRejected`（標籤 0x1B）附原因回覆給送出的客戶端。
+
+use crate::lockstep::rate_limit::{FixedWindow, Rejection};
+use crate::lockstep::wire::InputRejectReason;
+use crate::lockstep::{PlayerInput, PlayerInputEnum, Vec2I};
+
+/// 英雄技能格數（W/E/R/T）。
+pub const ABILITY_SLOTS: u32 = 4;
+
+/// 地圖範圍，Fixed64 raw 單位（與 `Vec2I` 相同）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapBounds {
+    pub min_x: i64,
+    pub min_y: i64,
+    pub max_x: i64,
+    pub max_y: i64,
+}
+
+impl MapBounds {
+    /// 以世界座標（f32）建立，轉成 Fixed64 raw。
+    pub fn from_world(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Self {
+        Self {
+            min_x: world_to_raw(min_x.min(max_x)),
+            min_y: world_to_raw(min_y.min(max_y)),
+            max_x: world_to_raw(min_x.max(max_x)),
+            max_y: world_to_raw(min_y.max(max_y)),
+        }
+    }
+
+    pub fn contains(&self, x: i64, y: i64) -> bool {
+        (self.min_x..=self.max_x).contains(&x) && (self.min_y..=self.max_y).contains(&y)
+    }
+
+    /// 同 `contains`，座標為世界座標（階段 6.24 地圖標記）。
+    pub fn contains_world(&self, x: f32, y: f32) -> bool {
+        self.contains(world_to_raw(x), world_to_raw(y))
+    }
+}
+
+fn world_to_raw(v: f32) -> i64 {
+    (v as f64 * omoba_sim::fixed::SCALE as f64) as i64
+}
+
+/// 驗證上限。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputLimits {
+    /// 每刻輸入數上限，0 表示不限制。
+    pub max_per_tick: u32,
+    /// 每秒輸入數上限，0 表示不限制。
+    pub max_per_second: u32,
+    /// 「每秒」對應的刻度數（= step_fps）。
+    pub second_ticks: u32,
+    /// `target_tick` 最多超前的刻度數，0 表示不限制。
+    pub max_future_ticks: u32,
+    pub item_slots: u32,
+    /// `None` 表示不檢查座標。
+    pub map_bounds: Option<MapBounds>,
+}
+
+impl Default for InputLimits {
+    fn default() -> Self {
+        Self {
+            max_per_tick: 0,
+            max_per_second: 0,
+            second_ticks: 0,
+            max_future_ticks: 0,
+            item_slots: crate::comp::INVENTORY_SLOTS as u32,
+            map_bounds: None,
+        }
+    }
+}
+
+pub type InputRejection = Rejection<InputRejectReason>;
+
+/// 單一玩家的輸入計數：每刻計數為單刻窗口，每秒計數為 `second_ticks`
+/// 刻的窗口。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputRateWindow {
+    tick: FixedWindow,
+    second: FixedWindow,
+}
+
+impl InputRateWindow {
+    pub fn admit(&mut self, now: u32, limits: &InputLimits) -> Result<(), InputRejection> {
+        let tick_ok = self.tick.admit(now, 1, limits.max_per_tick);
+        let second_ok = self
+            .second
+            .admit(now, limits.second_ticks, limits.max_per_second);
+        if !tick_ok {
+            return Err(InputRejection::new(
+                InputRejectReason::RateLimitedTick,
+                format!("more than {} inputs in tick {}", limits.max_per_tick, now),
+            ));
+        }
+        if !second_ok {
+            return Err(InputRejection::new(
+                InputRejectReason::RateLimitedSecond,
+                format!("more than {} inputs per second", limits.max_per_second),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// 與速率無關的檢查：未來刻度、座標與格索引。
+pub fn check_input(
+    input: &PlayerInput,
+    current_tick: u32,
+    target_tick: u32,
+    limits: &InputLimits,
+) -> Result<(), InputRejection> {
+    if limits.max_future_ticks > 0
+        && target_tick > current_tick.saturating_add(limits.max_future_ticks)
+    {
+        return Err(InputRejection::new(
+            InputRejectReason::FarFuture,
+            format!(
+                "target_tick {} is more than {} ticks ahead of {}",
+                target_tick, limits.max_future_ticks, current_tick
+            ),
+        ));
+    }
+    let Some(action) = input.action.as_ref() else {
+        return Ok(());
+    };
+    let (point, slot): (Option<&Vec2I>, Option<(u32, u32)>) = match action {
+        PlayerInputEnum::MoveTo(m) => (m.target.as_ref(), None),
+        PlayerInputEnum::TowerPlace(t) => (t.pos.as_ref(), None),
+        PlayerInputEnum::CastAbility(c) => (c.target_pos.as_ref(), Some((c.slot, ABILITY_SLOTS))),
+        PlayerInputEnum::UpgradeAbility(u) => (None, Some((u.slot, ABILITY_SLOTS))),
+        PlayerInputEnum::ItemUse(i) => (None, Some((i.slot, limits.item_slots))),
+        _ => (None, None),
+    };
+    if let (Some(p), Some(bounds)) = (point, limits.map_bounds) {
+        let (x, y) = (i64::from(p.x), i64::from(p.y));
+        if !bounds.contains(x, y) {
+            return Err(InputRejection::new(
+                InputRejectReason::OutOfBounds,
+                format!("position ({}, {}) is outside the map", x, y),
+            ));
+        }
+    }
+    if let Some((slot, count)) = slot {
+        if slot >= count {
+            return Err(InputRejection::new(
+                InputRejectReason::InvalidSlot,
+                format!("slot {} out of range 0..{}", slot, count),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lockstep::{ItemUse, MoveTo, NoOp};
+
+    fn limits() -> InputLimits {
+        InputLimits {
+            max_per_tick: 2,
+            max_per_second: 3,
+            second_ticks: 60,
+            max_future_ticks: 120,
+            item_slots: 6,
+            map_bounds: Some(MapBounds {
+                min_x: 0,
+                min_y: 0,
+                max_x: 1000,
+                max_y: 1000,
+            }),
+        }
+    }
+
+    fn input(action: PlayerInputEnum) -> PlayerInput {
+        PlayerInput {
+            action: Some(action),
+        }
+    }
+
+    fn reason(r: Result<(), InputRejection>) -> Option<InputRejectReason> {
+        r.err().map(|e| e.reason)
+    }
+
+    #[test]
+    fn rate_window_caps_per_tick_and_per_second() {
+        let l = limits();
+        let mut w = InputRateWindow::default();
+        assert!(w.admit(10, &l).is_ok());
+        assert!(w.admit(10, &l).is_ok());
+        assert_eq!(reason(w.admit(10, &l)), Some(InputRejectReason::RateLimitedTick));
+        // 新的一刻，但同一秒內已有 3 筆。
+        assert_eq!(reason(w.admit(11, &l)), Some(InputRejectReason::RateLimitedSecond));
+        // 下一個窗口重新計數。
+        assert!(w.admit(70, &l).is_ok());
+    }
+
+    #[test]
+    fn far_future_and_bounds_and_slots_are_rejected() {
+        let l = limits();
+        let noop = input(PlayerInputEnum::NoOp(NoOp {}));
+        assert!(check_input(&noop, 100, 220, &l).is_ok());
+        assert_eq!(
+            reason(check_input(&noop, 100, 221, &l)),
+            Some(InputRejectReason::FarFuture)
+        );
+
+        let inside = input(PlayerInputEnum::MoveTo(MoveTo {
+            target: Some(Vec2I { x: 500, y: 500 }),
+            ..Default::default()
+        }));
+        assert!(check_input(&inside, 0, 1, &l).is_ok());
+        let outside = input(PlayerInputEnum::MoveTo(MoveTo {
+            target: Some(Vec2I { x: -1, y: 500 }),
+            ..Default::default()
+        }));
+        assert_eq!(
+            reason(check_input(&outside, 0, 1, &l)),
+            Some(InputRejectReason::OutOfBounds)
+        );
+
+        let bad_item = input(PlayerInputEnum::ItemUse(ItemUse {
+            slot: 6,
+            ..Default::default()
+        }));
+        assert_eq!(
+            reason(check_input(&bad_item, 0, 1, &l)),
+            Some(InputRejectReason::InvalidSlot)
+        );
+    }
+
+    #[test]
+    fn disabled_limits_accept_everything() {
+        let l = InputLimits::default();
+        let mut w = InputRateWindow::default();
+        for _ in 0..1000 {
+            assert!(w.admit(5, &l).is_ok());
+        }
+        let far = input(PlayerInputEnum::MoveTo(MoveTo {
+            target: Some(Vec2I {
+                x: i32::MAX.into(),
+                y: 0,
+            }),
+            ..Default::default()
+        }));
+        assert!(check_input(&far, 0, u32::MAX, &l).is_ok());
+    }
+}
@@ -0,0 +1,169 @@
+//! 階段 6.13：落後玩家偵測。
+//!
+//! `LockstepState::check_lag` 每刻檢查每個玩家座位，兩項都是伺服器自己
+//! 觀察到的訊號：
+//!
+//! - 確認差距：目前刻度與最後確認收到的刻度（`TickAck.acked_tick`）之差，
+//!   超過 `lag_ack_budget_ticks` 即落後；
+//! - RTT：`PlayerSession::latency` 的平滑 RTT（kcp 的 ping 處理以
+//!   `PingProbe` 往返量測），超過 `lag_rtt_budget_us` 即落後。
+//!
+//! 不採用客戶端自報的 RTT，也不看玩家有沒有送輸入（閒置的玩家不算
+//! 落後）。回到預算內即恢復。狀態轉換由 `TickBroadcaster` 廣播給所有
+//! 客戶端，並依 `LagPolicy` 處理：
+//!
+//! - `Warn`：只通知；
+//! - `Pause`：任一連線中的玩家落後時，排定從
+//!   `目前刻度 + LAG_PAUSE_LEAD_TICKS` 起設定 `GamePause`；全部恢復後以
+//!   同樣的提前量解除。調度器（`State::set_lag_pause_rx`）與客戶端在同一
+//!   刻度套用，刻度照常推進，只有 gameplay 時間停住；
+//! - `DropToAi`：座位交由伺服器代管，直到恢復。代管期間廣播器每
+//!   `AI_INPUT_INTERVAL_TICKS` 刻替該座位產生一筆輸入（依序升級技能格），
+//!   與玩家輸入一起放進 `TickBatch`，所有客戶端與 replay 看到同一份。
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::lockstep::input_validation::ABILITY_SLOTS;
+use crate::lockstep::{PlayerInput, PlayerInputEnum, UpgradeAbility};
+
+/// 暫停 / 解除的生效刻度與宣告刻度的差距。通知走 `td/all/res`，要在
+/// 客戶端與調度器跑到生效刻度前送達。
+pub const LAG_PAUSE_LEAD_TICKS: u32 = 30;
+
+/// `DropToAi` 代管座位產生輸入的間隔（刻度）。
+pub const AI_INPUT_INTERVAL_TICKS: u32 = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LagPolicy {
+    #[default]
+    Warn,
+    Pause,
+    DropToAi,
+}
+
+impl LagPolicy {
+    /// 解析 `[lockstep] lag_policy`（`warn` / `pause` / `ai`）。
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "warn" => Some(Self::Warn),
+            "pause" => Some(Self::Pause),
+            "ai" | "drop_to_ai" => Some(Self::DropToAi),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Warn => "warn",
+            Self::Pause => "pause",
+            Self::DropToAi => "ai",
+        }
+    }
+}
+
+/// 單一玩家的落後 / 恢復轉換。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LagTransition {
+    pub player_id: u32,
+    /// `true` 為進入落後，`false` 為恢復。
+    pub lagging: bool,
+    pub policy: LagPolicy,
+    /// 目前刻度與該玩家最後確認刻度的差距。
+    pub ack_gap_ticks: u32,
+    /// 該玩家的平滑 RTT（微秒）；尚無樣本時為 0。
+    pub rtt_us: u32,
+}
+
+impl LagTransition {
+    /// 廣播給客戶端的 JSON 內容（`td/all/res` 的 `lockstep` / `lag`）。
+    pub fn to_json(&self, tick: u32) -> Value {
+        json!({
+            "tick": tick,
+            "player_id": self.player_id,
+            "state": if self.lagging { "lagging" } else { "recovered" },
+            "policy": self.policy.as_str(),
+            "ack_gap_ticks": self.ack_gap_ticks,
+            "rtt_us": self.rtt_us,
+        })
+    }
+}
+
+/// `Pause` 策略的暫停 / 解除，送往調度器、寫進 replay 並廣播給客戶端。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LagPause {
+    pub paused: bool,
+    /// 生效刻度：從這一刻起（含）設定 / 清除 `GamePause`。
+    pub tick: u32,
+}
+
+impl LagPause {
+    /// 廣播給客戶端的 JSON 內容（`td/all/res` 的 `lockstep` / `pause`）。
+    pub fn to_json(&self) -> Value {
+        json!({
+            "state": if self.paused { "paused" } else { "resumed" },
+            "tick": self.tick,
+        })
+    }
+}
+
+/// `check_lag` 的結果。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LagReport {
+    pub transitions: Vec<LagTransition>,
+    /// `Pause` 策略下暫停狀態的變化；`None` 表示不變。
+    pub pause: Option<LagPause>,
+}
+
+/// `DropToAi` 代管座位在刻度 `tick` 的輸入；不在產生間隔上時為 `None`。
+/// 只依刻度決定，伺服器重算或 replay 都得到同一份。
+pub fn ai_input(tick: u32) -> Option<PlayerInput> {
+    if tick % AI_INPUT_INTERVAL_TICKS != 0 {
+        return None;
+    }
+    let slot = (tick / AI_INPUT_INTERVAL_TICKS) % ABILITY_SLOTS;
+    Some(PlayerInput {
+        action: Some(PlayerInputEnum::UpgradeAbility(UpgradeAbility {
+            slot,
+            ..Default::default()
+        })),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_parses_config_names() {
+        assert_eq!(LagPolicy::parse("warn"), Some(LagPolicy::Warn));
+        assert_eq!(LagPolicy::parse(" Pause "), Some(LagPolicy::Pause));
+        assert_eq!(LagPolicy::parse("ai"), Some(LagPolicy::DropToAi));
+        assert_eq!(LagPolicy::parse("drop_to_ai"), Some(LagPolicy::DropToAi));
+        assert_eq!(LagPolicy::parse("kick"), None);
+    }
+
+    #[test]
+    fn pause_notice_carries_its_tick() {
+        let pause = LagPause {
+            paused: true,
+            tick: 42,
+        };
+        assert_eq!(pause.to_json(), json!({ "state": "paused", "tick": 42 }));
+    }
+
+    #[test]
+    fn ai_input_cycles_ability_slots_on_the_interval() {
+        assert_eq!(ai_input(AI_INPUT_INTERVAL_TICKS - 1), None);
+        let slots: Vec<u32> = (1..=ABILITY_SLOTS + 1)
+            .map(
+                |n| match ai_input(n * AI_INPUT_INTERVAL_TICKS).unwrap().action {
+                    Some(PlayerInputEnum::UpgradeAbility(u)) => u.slot,
+                    other => panic!("unexpected AI action {other:?}"),
+                },
+            )
+            .collect();
+        let expected: Vec<u32> = (1..=ABILITY_SLOTS + 1).map(|n| n % ABILITY_SLOTS).collect();
+        assert_eq!(slots, expected);
+    }
+}
@@ -0,0 +1,169 @@
+//! 階段 6.14：每個座位的延遲統計與建議輸入提前量。
+//!
+//! RTT 樣本由伺服器自己量（`LockstepState::record_ping_rtt`：kcp 的 ping
+//! 處理送出 `PingProbe` 到客戶端帶回的時間），以 RFC 6298 的方式平滑成
+//! `srtt` 與 `rttvar`（抖動）。不採用客戶端自報的 RTT。每次回覆 `PingResponse` 時
+//! 附上 `InputLeadHint`，建議客戶端把 `target_tick` 提前多少刻度：
+//!
+//! `lead = ceil((srtt + 4 × rttvar) / 刻度週期) + bias`
+//!
+//! `bias` 依上次建議之後的晚到輸入（retarget / 拒絕）自動調整：有晚到就
+//! 加一，沒有就減一，讓高延遲玩家的輸入不再被改刻度或丟棄。
+//!
+//! 同一份抖動估計也放寬該玩家的晚到寬限（`late_grace_ticks`）。
+
+use crate::lockstep::wire::InputLeadHint;
+use crate::lockstep::InputSubmitResult;
+
+/// 建議提前量下限（刻度）。
+pub const MIN_LEAD_TICKS: u32 = 2;
+/// 晚到修正的上限（刻度）。
+pub const MAX_LEAD_BIAS_TICKS: u32 = 8;
+/// 依抖動放寬的晚到寬限上限（毫秒）。
+pub const MAX_JITTER_GRACE_MS: u32 = 250;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    srtt_us: u32,
+    rttvar_us: u32,
+    samples: u32,
+    /// 被 retarget 到下一刻的輸入總數。
+    pub retargeted_inputs: u32,
+    /// 晚到超過寬限而被丟棄的輸入總數。
+    pub rejected_late_inputs: u32,
+    late_since_hint: u32,
+    lead_bias_ticks: u32,
+}
+
+impl LatencyStats {
+    pub fn record_rtt(&mut self, rtt_us: u32) {
+        if self.samples == 0 {
+            self.srtt_us = rtt_us;
+            self.rttvar_us = rtt_us / 2;
+        } else {
+            let delta = self.srtt_us.abs_diff(rtt_us);
+            self.rttvar_us = ((3 * self.rttvar_us as u64 + delta as u64) / 4) as u32;
+            self.srtt_us = ((7 * self.srtt_us as u64 + rtt_us as u64) / 8) as u32;
+        }
+        self.samples = self.samples.saturating_add(1);
+    }
+
+    pub fn record_submit(&mut self, result: &InputSubmitResult) {
+        match result {
+            InputSubmitResult::Accepted { .. } => return,
+            InputSubmitResult::Retargeted { .. } => {
+                self.retargeted_inputs = self.retargeted_inputs.saturating_add(1)
+            }
+            InputSubmitResult::RejectedLate { .. } => {
+                self.rejected_late_inputs = self.rejected_late_inputs.saturating_add(1)
+            }
+        }
+        self.late_since_hint = self.late_since_hint.saturating_add(1);
+    }
+
+    /// 平滑 RTT（微秒）；尚無樣本時為 0。
+    pub fn srtt_us(&self) -> u32 {
+        self.srtt_us
+    }
+
+    /// RTT 抖動估計（微秒）。
+    pub fn jitter_us(&self) -> u32 {
+        self.rttvar_us
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// 該玩家的晚到寬限：基本寬限加上兩倍抖動（上限 `MAX_JITTER_GRACE_MS`）。
+    pub fn late_grace_ticks(&self, step_fps: u32, base_ticks: u32) -> u32 {
+        let jitter_us = self
+            .rttvar_us
+            .saturating_mul(2)
+            .min(MAX_JITTER_GRACE_MS * 1000);
+        base_ticks.saturating_add(us_to_ticks_ceil(jitter_us, step_fps))
+    }
+
+    /// 產生下一個建議並依期間的晚到輸入調整修正量。
+    pub fn next_lead_hint(&mut self, step_fps: u32) -> InputLeadHint {
+        if self.late_since_hint > 0 {
+            self.lead_bias_ticks = (self.lead_bias_ticks + 1).min(MAX_LEAD_BIAS_TICKS);
+        } else {
+            self.lead_bias_ticks = self.lead_bias_ticks.saturating_sub(1);
+        }
+        self.late_since_hint = 0;
+        let budget_us = self
+            .srtt_us
+            .saturating_add(self.rttvar_us.saturating_mul(4));
+        let lead = us_to_ticks_ceil(budget_us, step_fps)
+            .saturating_add(self.lead_bias_ticks)
+            .max(MIN_LEAD_TICKS);
+        InputLeadHint {
+            recommended_lead_ticks: lead,
+            srtt_us: self.srtt_us,
+            jitter_us: self.rttvar_us,
+            retargeted_inputs: self.retargeted_inputs,
+            rejected_late_inputs: self.rejected_late_inputs,
+        }
+    }
+}
+
+fn us_to_ticks_ceil(us: u32, step_fps: u32) -> u32 {
+    let ticks = (us as u64 * step_fps as u64).div_ceil(1_000_000);
+    ticks.min(u32::MAX as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rtt_is_smoothed_with_jitter_estimate() {
+        let mut stats = LatencyStats::default();
+        stats.record_rtt(100_000);
+        assert_eq!((stats.srtt_us(), stats.jitter_us()), (100_000, 50_000));
+        stats.record_rtt(180_000);
+        assert_eq!(stats.srtt_us(), 110_000);
+        assert_eq!(stats.jitter_us(), 57_500);
+        assert_eq!(stats.samples(), 2);
+    }
+
+    #[test]
+    fn lead_covers_rtt_and_grows_after_late_inputs() {
+        let mut stats = LatencyStats::default();
+        assert_eq!(stats.next_lead_hint(60).recommended_lead_ticks, MIN_LEAD_TICKS);
+
+        // 200ms srtt、±0 抖動 @ 60fps → 12 刻度。
+        for _ in 0..32 {
+            stats.record_rtt(200_000);
+        }
+        let base = stats.next_lead_hint(60).recommended_lead_ticks;
+        assert!((12..=13).contains(&base), "lead {base}");
+
+        stats.record_submit(&InputSubmitResult::Retargeted {
+            original_tick: 10,
+            effective_tick: 11,
+        });
+        stats.record_submit(&InputSubmitResult::RejectedLate {
+            original_tick: 3,
+            current_tick: 10,
+        });
+        let hint = stats.next_lead_hint(60);
+        assert_eq!(hint.recommended_lead_ticks, base + 1);
+        assert_eq!((hint.retargeted_inputs, hint.rejected_late_inputs), (1, 1));
+
+        // 沒有新的晚到輸入：修正量逐步退回。
+        assert_eq!(stats.next_lead_hint(60).recommended_lead_ticks, base);
+    }
+
+    #[test]
+    fn jitter_widens_late_grace_up_to_cap() {
+        let mut stats = LatencyStats::default();
+        assert_eq!(stats.late_grace_ticks(60, 4), 4);
+        stats.record_rtt(60_000);
+        // 2 × 30ms 抖動 = 60ms → 4 刻度 @ 60fps。
+        assert_eq!(stats.late_grace_ticks(60, 4), 8);
+        stats.record_rtt(2_000_000);
+        assert_eq!(stats.late_grace_ticks(60, 4), 4 + 15);
+    }
+}
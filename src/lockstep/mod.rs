@@ -7,23 +7,52 @@
 //! 該模組位於`#[cfg(feature = "kcp")]`後面，因為它依賴於
 //! prost 產生的原型類型僅在 kcp 功能下建置。
 
+pub mod chat;
 pub mod input_buffer;
+pub mod input_validation;
+pub mod lag;
+pub mod latency;
+pub mod rate_limit;
+pub mod replay;
+pub mod server_events;
 pub mod snapshot_producer;
+pub mod snapshot_restore;
+pub mod spectator;
 pub mod state;
 pub mod state_hash_producer;
 pub mod tick_broadcaster;
+pub mod tick_history;
+pub mod wire;
 
 #[cfg(test)]
 mod metadata_guard;
 
+pub use self::chat::{ChatLimits, ChatMessage, ChatRateWindow, ChatRejection, ChatScope};
 pub use self::input_buffer::{InputBuffer, InputSubmitResult};
+pub use self::input_validation::{InputLimits, InputRateWindow, InputRejection, MapBounds};
+pub use self::lag::{LagPause, LagPolicy, LagReport, LagTransition};
+pub use self::latency::LatencyStats;
+pub use self::rate_limit::{FixedWindow, Rejection};
+pub use self::replay::{ReplayHeader, ReplayReader, ReplayRecord, ReplayWriter};
 pub use self::snapshot_producer::{
-    serialize_snapshot, EntityKindTag, EntitySnapshot, WorldSnapshot,
-    SCHEMA_VERSION as SNAPSHOT_SCHEMA_VERSION,
+    capture_snapshot, deserialize_snapshot, serialize_snapshot, BuffSnapshot, CreepSnapshot,
+    CreepWaveSnapshot, EntityKindTag, EntitySnapshot, HeroSnapshot, ItemSlotSnapshot,
+    ProjectileSnapshot, TowerSnapshot, WorldSnapshot, SCHEMA_VERSION as SNAPSHOT_SCHEMA_VERSION,
+};
+pub use self::snapshot_restore::{
+    restore_snapshot, restore_world_snapshot, rewind_lockstep, RestoreReport,
+};
+pub use self::state::{
+    DesyncReport, DesyncStats, JoinRoleEnum, LockstepState, PlayerSession, SeatGrant,
+    OBSERVER_ID_BASE,
+};
+pub use self::spectator::SpectatorDelay;
+pub use self::state_hash_producer::{
+    compute_entity_hashes, compute_state_hash, compute_state_hash_report, diff_entity_hashes,
+    ComponentHashes, EntityHash, EntityHashDiff, StateHashReport,
 };
-pub use self::state::{JoinRoleEnum, LockstepState, PlayerSession};
-pub use self::state_hash_producer::compute_state_hash;
 pub use self::tick_broadcaster::{TickBroadcaster, TickBroadcasterConfig};
+pub use self::tick_history::{plan_catch_up, CatchUpPlan, TickHistory};
 
 // 重新導出該模組使用的 protocol 類型，以便呼叫者不需要
 // 了解 prost 生成的路徑。Protocol source of truth 來自 `omoba-core`。
@@ -65,6 +94,9 @@ pub enum LockstepFrame {
     GameStart {
         client_session_id: String,
         msg: GameStart,
+        /// 階段 6.9：重連 token，以 `wire::ResumeToken` 附加在 GameStart
+        /// 編碼之後。空字串（觀察者）時不附加。
+        resume_token: String,
     },
     /// 每個客戶端快照回覆。
     SnapshotResp {
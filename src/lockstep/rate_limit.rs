@@ -0,0 +1,65 @@
+//! 輸入驗證（階段 6.15）與聊天（階段 6.24）共用的固定窗口計數器與
+//! 拒絕類型。
+
+/// 被拒絕的原因與說明（回覆給客戶端）。`R` 為線路上的原因列舉，例如
+/// `InputRejectReason`、`ChatRejectReason`。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection<R> {
+    pub reason: R,
+    pub detail: String,
+}
+
+impl<R> Rejection<R> {
+    pub fn new(reason: R, detail: impl Into<String>) -> Self {
+        Self {
+            reason,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// 固定窗口計數。窗口從第一次計數的刻度開始，滿 `window_ticks` 刻
+/// （或刻度倒退）後重新起算；被拒絕的次數同樣計入。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FixedWindow {
+    window_start: u32,
+    count: u32,
+}
+
+impl FixedWindow {
+    /// 在刻度 `now` 計一次，回傳本窗口的次數是否仍在 `limit` 以內。
+    /// `limit` 為 0 表示不限制；`window_ticks` 為 0 時每次都重新起算。
+    pub fn admit(&mut self, now: u32, window_ticks: u32, limit: u32) -> bool {
+        if window_ticks == 0 || now.wrapping_sub(self.window_start) >= window_ticks {
+            self.window_start = now;
+            self.count = 0;
+        }
+        self.count = self.count.saturating_add(1);
+        limit == 0 || self.count <= limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_counts_until_it_rolls_over() {
+        let mut window = FixedWindow::default();
+        assert!(window.admit(10, 60, 2));
+        assert!(window.admit(20, 60, 2));
+        assert!(!window.admit(30, 60, 2));
+        // 被拒絕的也計入，窗口內持續拒絕。
+        assert!(!window.admit(69, 60, 2));
+        assert!(window.admit(70, 60, 2));
+
+        // 單刻窗口：刻度改變就歸零。
+        let mut per_tick = FixedWindow::default();
+        assert!(per_tick.admit(5, 1, 1));
+        assert!(!per_tick.admit(5, 1, 1));
+        assert!(per_tick.admit(6, 1, 1));
+
+        let mut unlimited = FixedWindow::default();
+        assert!((0..100).all(|tick| unlimited.admit(tick, 60, 0)));
+    }
+}
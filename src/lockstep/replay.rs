@@ -0,0 +1,709 @@
+//! 階段 6.1：伺服器端 lockstep replay 錄製。
+//!
+//! `TickBroadcaster::fire_one_tick` 在每個刻度把送出的 `TickBatch`
+//! 以及週期性 `StateHash` 追加寫入 replay 檔，讓回報 bug 的對局可以離線
+//! 重現（見 `omobab-replay` binary）。
+//!
+//! # 檔案格式（`REPLAY_FORMAT_VERSION = 1`）
+//!
+//! ```text
+//! [8B magic "OMBRPLY\0"][4B BE format version]
+//! record*
+//! record = [1B kind][4B BE payload len][payload]
+//! ```
+//!
+//! - 第一筆 record 永遠是 `ReplayRecordKind::Header`（bincode 的
+//!   `ReplayHeader`：master_seed / step_fps / start_tick / lua_content_hash）。
+//! - `TickBatch` / `StateHash` 的 payload 是 prost 編碼，與 KCP 線上
+//!   0x11 / 0x12 的 payload 完全相同（未壓縮）。
+//! - `DesyncMarker`（階段 6.4）：bincode 的 `DesyncReport`，在伺服器
+//!   偵測到客戶端回報的雜湊與廣播不一致時寫入。
+//! - `Chat`（階段 6.24）：bincode 的 `ChatMessage`，玩家聊天與地圖標記，
+//!   在送出前寫入。不參與重播模擬，只供回放顯示。
+//! - `LagPause`（階段 6.13）：bincode 的 `LagPause`，落後暫停 / 解除
+//!   宣告時寫入。會設定 `GamePause`，重播時必須照樣套用。
+//! - 讀取端遇到未知 kind 會跳過，因此新增 record 類型不需要升版。
+//!
+//! # 當機安全
+//!
+//! 檔案只追加、不回寫：每筆 record 先在記憶體組好再一次 `write_all`，
+//! 所以程序中途當掉最多只留下一筆被截斷的尾巴，`ReplayReader` 會在該處
+//! 停止並設定 `truncated()`。每寫一筆索引會請專用的落盤執行緒
+//! `sync_data`，把 OS 層級的遺失窗口限制在一個索引間隔內；fsync 不在
+//! `TickBroadcaster` 的 tokio 任務內執行，慢速磁碟不會拉長刻度間隔。
+//!
+//! # 索引
+//!
+//! 旁邊的 `<file>.idx` 也是只追加：`[8B magic "OMBRIDX\0"][4B BE version]`
+//! 後接 `[4B BE tick][8B BE offset]` 條目，每 `index_interval` 個刻度一筆，
+//! offset 指向該刻度 `TickBatch` record 的起點。索引檔遺失或損毀時，
+//! `ReplayReader::open` 會掃描主檔重建。
+
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+
+use crossbeam_channel::{Sender, TrySendError};
+
+use crate::lockstep::{ChatMessage, DesyncReport, LagPause, StateHash, TickBatch};
+
+pub const REPLAY_MAGIC: &[u8; 8] = b"OMBRPLY\0";
+pub const REPLAY_INDEX_MAGIC: &[u8; 8] = b"OMBRIDX\0";
+/// 檔案格式版本。改動 record framing 或 `ReplayHeader` 欄位時遞增。
+pub const REPLAY_FORMAT_VERSION: u32 = 1;
+/// Replay 檔副檔名。
+pub const REPLAY_EXTENSION: &str = "omrp";
+
+const FILE_PREAMBLE_LEN: u64 = 12;
+const RECORD_HEADER_LEN: usize = 5;
+const INDEX_ENTRY_LEN: usize = 12;
+
+/// Record 種類標籤。順序已固定在檔案格式中，只能在尾端新增。
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayRecordKind {
+    Header = 1,
+    TickBatch = 2,
+    StateHash = 3,
+    DesyncMarker = 4,
+    Chat = 5,
+    LagPause = 6,
+}
+
+impl ReplayRecordKind {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            1 => Some(Self::Header),
+            2 => Some(Self::TickBatch),
+            3 => Some(Self::StateHash),
+            4 => Some(Self::DesyncMarker),
+            5 => Some(Self::Chat),
+            6 => Some(Self::LagPause),
+            _ => None,
+        }
+    }
+}
+
+/// 對局開始時的參數（等同 `GameStart` 廣播給玩家的內容）。重播端用它
+/// 重建同一個 seed / cadence 的 `State`。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReplayHeader {
+    pub master_seed: u64,
+    pub step_fps: u32,
+    pub start_tick: u32,
+    /// 錄製時的 runtime Lua content hash；重播前用來確認腳本內容一致。
+    pub lua_content_hash: String,
+    /// `scripts/lua_data/{STORY}` 場景名稱。
+    pub story: String,
+    /// 錄製開始的 unix 毫秒時間，僅供檔案辨識。
+    pub recorded_at_unix_ms: u64,
+}
+
+/// 追加寫入器。由 `TickBroadcaster` 透過 `Arc<Mutex<_>>` 持有。
+pub struct ReplayWriter {
+    path: PathBuf,
+    file: File,
+    index: File,
+    offset: u64,
+    index_interval: u32,
+    syncer: SyncWorker,
+}
+
+/// 背景落盤執行緒。持有主檔與索引檔的複製 handle，收到請求就
+/// `sync_data` 兩者；請求通道容量為 1，前一次尚未處理時新的請求直接
+/// 合併，寫入端永遠不會被磁碟拖住。
+struct SyncWorker {
+    tx: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl SyncWorker {
+    fn spawn(path: &Path, file: &File, index: &File) -> io::Result<Self> {
+        let file = file.try_clone()?;
+        let index = index.try_clone()?;
+        let (tx, rx) = crossbeam_channel::bounded::<()>(1);
+        let name = path.display().to_string();
+        let handle = std::thread::Builder::new()
+            .name("replay-fsync".to_string())
+            .spawn(move || {
+                while rx.recv().is_ok() {
+                    if let Err(e) = file.sync_data().and_then(|_| index.sync_data()) {
+                        log::warn!("ReplayWriter: fsync of {name} failed: {e}");
+                    }
+                }
+            })?;
+        Ok(Self {
+            tx: Some(tx),
+            handle: Some(handle),
+        })
+    }
+
+    /// 排入一次落盤；已有待處理的請求時視為已排入。
+    fn request(&self) {
+        if let Some(tx) = self.tx.as_ref() {
+            match tx.try_send(()) {
+                Ok(()) | Err(TrySendError::Full(())) => {}
+                Err(TrySendError::Disconnected(())) => {
+                    log::warn!("ReplayWriter: fsync thread exited");
+                }
+            }
+        }
+    }
+}
+
+impl Drop for SyncWorker {
+    fn drop(&mut self) {
+        // 關閉通道後等執行緒處理完最後一個請求。
+        self.tx.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl ReplayWriter {
+    /// 建立新的 replay 檔（已存在則失敗，絕不覆寫舊錄影）並寫入 header。
+    /// `index_interval` 為每幾個刻度寫一筆索引（0 視為 1）。
+    pub fn create(
+        path: impl AsRef<Path>,
+        header: &ReplayHeader,
+        index_interval: u32,
+    ) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(&path)?;
+        let mut index = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(index_path_for(&path))?;
+
+        file.write_all(&preamble(REPLAY_MAGIC))?;
+        index.write_all(&preamble(REPLAY_INDEX_MAGIC))?;
+
+        let syncer = SyncWorker::spawn(&path, &file, &index)?;
+        let mut writer = Self {
+            path,
+            file,
+            index,
+            offset: FILE_PREAMBLE_LEN,
+            index_interval: index_interval.max(1),
+            syncer,
+        };
+        let header_bytes = omoba_sim::snapshot::serialize(header)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{e:?}")))?;
+        writer.append_record(ReplayRecordKind::Header, &header_bytes)?;
+        writer.file.sync_data()?;
+        Ok(writer)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 追加一個 `TickBatch`。刻度落在索引間隔上時同時寫入索引，並請
+    /// 背景執行緒 fsync（不等待完成）。
+    pub fn record_tick_batch(&mut self, batch: &TickBatch) -> io::Result<()> {
+        let offset = self.append_record(ReplayRecordKind::TickBatch, &batch.encode_to_vec())?;
+        if batch.tick % self.index_interval == 0 {
+            let mut entry = [0u8; INDEX_ENTRY_LEN];
+            entry[..4].copy_from_slice(&batch.tick.to_be_bytes());
+            entry[4..].copy_from_slice(&offset.to_be_bytes());
+            self.index.write_all(&entry)?;
+            self.syncer.request();
+        }
+        Ok(())
+    }
+
+    pub fn record_state_hash(&mut self, sh: &StateHash) -> io::Result<()> {
+        self.append_record(ReplayRecordKind::StateHash, &sh.encode_to_vec())
+            .map(|_| ())
+    }
+
+    /// 階段 6.4：寫入不同步標記並立即排入落盤，確保當機時標記仍在。
+    pub fn record_desync_marker(&mut self, report: &DesyncReport) -> io::Result<()> {
+        let payload = omoba_sim::snapshot::serialize(report)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{e:?}")))?;
+        self.append_record(ReplayRecordKind::DesyncMarker, &payload)?;
+        self.syncer.request();
+        Ok(())
+    }
+
+    /// 階段 6.24：寫入一則聊天 / 地圖標記。
+    pub fn record_chat(&mut self, chat: &ChatMessage) -> io::Result<()> {
+        let payload = omoba_sim::snapshot::serialize(chat)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{e:?}")))?;
+        self.append_record(ReplayRecordKind::Chat, &payload)
+            .map(|_| ())
+    }
+
+    /// 階段 6.13：寫入一筆落後暫停 / 解除。
+    pub fn record_lag_pause(&mut self, pause: &LagPause) -> io::Result<()> {
+        let payload = omoba_sim::snapshot::serialize(pause)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{e:?}")))?;
+        self.append_record(ReplayRecordKind::LagPause, &payload)
+            .map(|_| ())
+    }
+
+    /// 同步強制落盤（正常結束時呼叫；會阻塞呼叫端）。
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()?;
+        self.index.sync_data()
+    }
+
+    /// 寫入一筆 record，回傳該 record 的起始 offset。整筆在記憶體組好後
+    /// 一次寫出，避免中途當掉時留下半個 header。
+    fn append_record(&mut self, kind: ReplayRecordKind, payload: &[u8]) -> io::Result<u64> {
+        let mut buf = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+        buf.push(kind as u8);
+        buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        buf.extend_from_slice(payload);
+        self.file.write_all(&buf)?;
+        let offset = self.offset;
+        self.offset += buf.len() as u64;
+        Ok(offset)
+    }
+}
+
+/// `<file>.idx` 索引檔路徑。
+pub fn index_path_for(path: &Path) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(".idx");
+    PathBuf::from(p)
+}
+
+/// 依 master_seed 與開始時間產生預設檔名：`match_<unix_ms>_<seed>.omrp`。
+pub fn default_replay_file_name(header: &ReplayHeader) -> String {
+    format!(
+        "match_{}_{:016x}.{}",
+        header.recorded_at_unix_ms, header.master_seed, REPLAY_EXTENSION
+    )
+}
+
+fn preamble(magic: &[u8; 8]) -> [u8; FILE_PREAMBLE_LEN as usize] {
+    let mut out = [0u8; FILE_PREAMBLE_LEN as usize];
+    out[..8].copy_from_slice(magic);
+    out[8..].copy_from_slice(&REPLAY_FORMAT_VERSION.to_be_bytes());
+    out
+}
+
+fn check_preamble(bytes: &[u8], magic: &[u8; 8]) -> io::Result<()> {
+    if bytes.len() < FILE_PREAMBLE_LEN as usize || &bytes[..8] != magic {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an omobab replay file (bad magic)",
+        ));
+    }
+    let version = u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+    if version != REPLAY_FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "unsupported replay format version {} (expected {})",
+                version, REPLAY_FORMAT_VERSION
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// 解碼後的 record。
+#[derive(Clone, Debug)]
+pub enum ReplayRecord {
+    TickBatch(TickBatch),
+    StateHash(StateHash),
+    DesyncMarker(DesyncReport),
+    Chat(ChatMessage),
+    LagPause(LagPause),
+    /// 本版本不認得的 kind — 讀取端略過內容。
+    Unknown { kind: u8 },
+}
+
+/// 整檔讀入記憶體的 replay 讀取器。
+pub struct ReplayReader {
+    bytes: Vec<u8>,
+    header: ReplayHeader,
+    /// 第一筆非 header record 的 offset。
+    body_offset: u64,
+    /// `(tick, offset)`，依 tick 遞增。
+    index: Vec<(u32, u64)>,
+    truncated: bool,
+}
+
+impl ReplayReader {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        let index_bytes = std::fs::read(index_path_for(path)).ok();
+        Self::from_bytes(bytes, index_bytes.as_deref())
+    }
+
+    /// 由原始位元組建立。`index_bytes` 為 `None` 或損毀時掃描主檔重建索引。
+    pub fn from_bytes(bytes: Vec<u8>, index_bytes: Option<&[u8]>) -> io::Result<Self> {
+        check_preamble(&bytes, REPLAY_MAGIC)?;
+        let (kind, payload, next) = read_record(&bytes, FILE_PREAMBLE_LEN).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "replay header record missing")
+        })?;
+        if kind != ReplayRecordKind::Header as u8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("first replay record must be a header, got kind {}", kind),
+            ));
+        }
+        let header: ReplayHeader = omoba_sim::snapshot::deserialize(payload)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{e:?}")))?;
+
+        let mut reader = Self {
+            bytes,
+            header,
+            body_offset: next,
+            index: Vec::new(),
+            truncated: false,
+        };
+        reader.truncated = reader.scan_end() < reader.bytes.len() as u64;
+        reader.index = match index_bytes.and_then(|b| reader.parse_index(b)) {
+            Some(index) => index,
+            None => reader.rebuild_index(),
+        };
+        Ok(reader)
+    }
+
+    pub fn header(&self) -> &ReplayHeader {
+        &self.header
+    }
+
+    /// 檔尾是否有被截斷的 record（錄製中途當機）。
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn index(&self) -> &[(u32, u64)] {
+        &self.index
+    }
+
+    /// 從頭迭代所有 record。
+    pub fn records(&self) -> ReplayRecordIter<'_> {
+        ReplayRecordIter {
+            bytes: &self.bytes,
+            offset: self.body_offset,
+        }
+    }
+
+    /// 透過索引跳到 `tick` 之前最近的索引點，並略過 tick 更早的
+    /// `TickBatch`。`StateHash` 使用調度器 tick，與 batch tick 不同，
+    /// 因此只有在第一個符合的 batch 之後才會開始回傳。
+    pub fn records_from_tick(&self, tick: u32) -> impl Iterator<Item = ReplayRecord> + '_ {
+        let start = match self.index.partition_point(|(t, _)| *t <= tick) {
+            0 => self.body_offset,
+            i => self.index[i - 1].1,
+        };
+        let mut reached = false;
+        ReplayRecordIter {
+            bytes: &self.bytes,
+            offset: start,
+        }
+        .filter(move |record| {
+            if !reached {
+                if let ReplayRecord::TickBatch(b) = record {
+                    reached = b.tick >= tick;
+                }
+            }
+            reached
+        })
+    }
+
+    fn scan_end(&self) -> u64 {
+        let mut offset = self.body_offset;
+        while let Some((_, _, next)) = read_record(&self.bytes, offset) {
+            offset = next;
+        }
+        offset
+    }
+
+    fn parse_index(&self, bytes: &[u8]) -> Option<Vec<(u32, u64)>> {
+        check_preamble(bytes, REPLAY_INDEX_MAGIC).ok()?;
+        let entries = &bytes[FILE_PREAMBLE_LEN as usize..];
+        let mut out = Vec::with_capacity(entries.len() / INDEX_ENTRY_LEN);
+        // 尾端不滿一筆的條目視為當機截斷，直接忽略。
+        for chunk in entries.chunks_exact(INDEX_ENTRY_LEN) {
+            let tick = u32::from_be_bytes(chunk[..4].try_into().ok()?);
+            let offset = u64::from_be_bytes(chunk[4..].try_into().ok()?);
+            // 索引指向主檔外（主檔比索引先截斷）→ 不可信，改為重建。
+            if offset >= self.bytes.len() as u64 {
+                return None;
+            }
+            out.push((tick, offset));
+        }
+        Some(out)
+    }
+
+    fn rebuild_index(&self) -> Vec<(u32, u64)> {
+        let mut out = Vec::new();
+        let mut offset = self.body_offset;
+        while let Some((kind, payload, next)) = read_record(&self.bytes, offset) {
+            if kind == ReplayRecordKind::TickBatch as u8 {
+                if let Ok(batch) = TickBatch::decode(payload) {
+                    out.push((batch.tick, offset));
+                }
+            }
+            offset = next;
+        }
+        out
+    }
+}
+
+/// 依序解碼 record；遇到截斷或無法解碼的 record 即停止。
+pub struct ReplayRecordIter<'a> {
+    bytes: &'a [u8],
+    offset: u64,
+}
+
+impl<'a> Iterator for ReplayRecordIter<'a> {
+    type Item = ReplayRecord;
+
+    fn next(&mut self) -> Option<ReplayRecord> {
+        loop {
+            let (kind, payload, next) = read_record(self.bytes, self.offset)?;
+            self.offset = next;
+            let record = match ReplayRecordKind::from_u8(kind) {
+                Some(ReplayRecordKind::Header) => continue,
+                Some(ReplayRecordKind::TickBatch) => {
+                    ReplayRecord::TickBatch(TickBatch::decode(payload).ok()?)
+                }
+                Some(ReplayRecordKind::StateHash) => {
+                    ReplayRecord::StateHash(StateHash::decode(payload).ok()?)
+                }
+                Some(ReplayRecordKind::DesyncMarker) => {
+                    ReplayRecord::DesyncMarker(omoba_sim::snapshot::deserialize(payload).ok()?)
+                }
+                Some(ReplayRecordKind::Chat) => {
+                    ReplayRecord::Chat(omoba_sim::snapshot::deserialize(payload).ok()?)
+                }
+                Some(ReplayRecordKind::LagPause) => {
+                    ReplayRecord::LagPause(omoba_sim::snapshot::deserialize(payload).ok()?)
+                }
+                None => ReplayRecord::Unknown { kind },
+            };
+            return Some(record);
+        }
+    }
+}
+
+/// 讀取 `offset` 處的 record，回傳 `(kind, payload, next_offset)`。
+/// 長度超出檔尾（截斷）時回傳 `None`。
+fn read_record(bytes: &[u8], offset: u64) -> Option<(u8, &[u8], u64)> {
+    let start = usize::try_from(offset).ok()?;
+    let header = bytes.get(start..start.checked_add(RECORD_HEADER_LEN)?)?;
+    let kind = header[0];
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    let payload_start = start + RECORD_HEADER_LEN;
+    let payload = bytes.get(payload_start..payload_start.checked_add(len)?)?;
+    Some((kind, payload, (payload_start + len) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn temp_replay(name: &str) -> PathBuf {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir()
+            .join(format!("omoba_replay_{name}_{stamp}"))
+            .join("match.omrp")
+    }
+
+    fn header() -> ReplayHeader {
+        ReplayHeader {
+            master_seed: 0xCAFE_BABE_DEAD_BEEF,
+            step_fps: 120,
+            start_tick: 0,
+            lua_content_hash: "abc123".to_string(),
+            story: "TD_1".to_string(),
+            recorded_at_unix_ms: 1,
+        }
+    }
+
+    fn batch(tick: u32) -> TickBatch {
+        TickBatch {
+            tick,
+            ..Default::default()
+        }
+    }
+
+    fn write_ticks(path: &Path, ticks: u32, index_interval: u32) {
+        let mut w = ReplayWriter::create(path, &header(), index_interval).unwrap();
+        for tick in 1..=ticks {
+            w.record_tick_batch(&batch(tick)).unwrap();
+            if tick % 5 == 0 {
+                w.record_state_hash(&StateHash {
+                    tick,
+                    hash: tick as u64 * 7,
+                })
+                .unwrap();
+            }
+        }
+        w.sync().unwrap();
+    }
+
+    fn batch_ticks(records: impl Iterator<Item = ReplayRecord>) -> Vec<u32> {
+        records
+            .filter_map(|r| match r {
+                ReplayRecord::TickBatch(b) => Some(b.tick),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_header_batches_and_hashes() {
+        let path = temp_replay("round_trip");
+        write_ticks(&path, 12, 4);
+
+        let reader = ReplayReader::open(&path).unwrap();
+        assert_eq!(reader.header(), &header());
+        assert!(!reader.truncated());
+        assert_eq!(batch_ticks(reader.records()), (1..=12).collect::<Vec<_>>());
+        let hashes: Vec<_> = reader
+            .records()
+            .filter_map(|r| match r {
+                ReplayRecord::StateHash(sh) => Some((sh.tick, sh.hash)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(hashes, vec![(5, 35), (10, 70)]);
+        assert_eq!(
+            reader.index().iter().map(|(t, _)| *t).collect::<Vec<_>>(),
+            vec![4, 8, 12]
+        );
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn seeks_to_tick_through_index() {
+        let path = temp_replay("seek");
+        write_ticks(&path, 20, 4);
+
+        let reader = ReplayReader::open(&path).unwrap();
+        assert_eq!(
+            batch_ticks(reader.records_from_tick(10)),
+            (10..=20).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            batch_ticks(reader.records_from_tick(0)),
+            (1..=20).collect::<Vec<_>>()
+        );
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn truncated_tail_keeps_complete_records() {
+        let path = temp_replay("truncated");
+        write_ticks(&path, 8, 4);
+
+        // 模擬錄製中途當機：砍掉最後一筆 record 的一部分。
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - 2);
+        let reader = ReplayReader::from_bytes(bytes, None).unwrap();
+        assert!(reader.truncated());
+        assert_eq!(batch_ticks(reader.records()), (1..=7).collect::<Vec<_>>());
+        // 沒有索引檔時由主檔重建。
+        assert_eq!(reader.index().len(), 7);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn round_trips_desync_marker() {
+        let path = temp_replay("desync");
+        let report = DesyncReport {
+            tick: 1200,
+            server_hash: 0xAA,
+            diverged: vec![(2, 0xBB)],
+        };
+        let mut w = ReplayWriter::create(&path, &header(), 4).unwrap();
+        w.record_tick_batch(&batch(1)).unwrap();
+        w.record_desync_marker(&report).unwrap();
+        w.record_tick_batch(&batch(2)).unwrap();
+        w.sync().unwrap();
+
+        let reader = ReplayReader::open(&path).unwrap();
+        let markers: Vec<_> = reader
+            .records()
+            .filter_map(|r| match r {
+                ReplayRecord::DesyncMarker(d) => Some(d),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(markers, vec![report]);
+        assert_eq!(batch_ticks(reader.records()), vec![1, 2]);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn round_trips_chat_between_batches() {
+        use crate::lockstep::ChatScope;
+
+        let path = temp_replay("chat");
+        let chat = ChatMessage {
+            tick: 1,
+            player_id: 2,
+            player_name: "bob".to_string(),
+            scope: ChatScope::Team(1),
+            text: "build slow towers".to_string(),
+            ping: Some((100.0, -20.0)),
+        };
+        let mut w = ReplayWriter::create(&path, &header(), 4).unwrap();
+        w.record_tick_batch(&batch(1)).unwrap();
+        w.record_chat(&chat).unwrap();
+        w.record_tick_batch(&batch(2)).unwrap();
+        w.sync().unwrap();
+
+        let reader = ReplayReader::open(&path).unwrap();
+        let chats: Vec<_> = reader
+            .records()
+            .filter_map(|r| match r {
+                ReplayRecord::Chat(c) => Some(c),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(chats, vec![chat]);
+        assert_eq!(batch_ticks(reader.records()), vec![1, 2]);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn refuses_to_overwrite_existing_replay() {
+        let path = temp_replay("no_overwrite");
+        write_ticks(&path, 1, 1);
+        assert!(ReplayWriter::create(&path, &header(), 1).is_err());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn rejects_foreign_files() {
+        assert!(ReplayReader::from_bytes(b"definitely not a replay".to_vec(), None).is_err());
+    }
+
+    #[test]
+    fn record_kind_values_pinned() {
+        assert_eq!(ReplayRecordKind::Header as u8, 1);
+        assert_eq!(ReplayRecordKind::TickBatch as u8, 2);
+        assert_eq!(ReplayRecordKind::StateHash as u8, 3);
+        assert_eq!(ReplayRecordKind::DesyncMarker as u8, 4);
+        assert_eq!(ReplayRecordKind::Chat as u8, 5);
+        assert_eq!(ReplayRecordKind::LagPause as u8, 6);
+        assert_eq!(REPLAY_FORMAT_VERSION, 1);
+    }
+}
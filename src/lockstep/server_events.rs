@@ -0,0 +1,46 @@
+//! 階段 6.12：`TickBatch.server_events` 的伺服器權威事件。
+//!
+//! 來源：
+//! - kcp 加入 / 離開 → `LockstepState` 暫存（`take_server_events`）；
+//! - 調度器的 `CurrentCreepWave` 開波轉換與 `flush_runtime_events` 的
+//!   對局結束偵測 → `State::set_server_event_tx` 通道。
+//!
+//! `LockstepState` 的事件由 `TickBroadcaster` 放進下一個 `TickBatch`；
+//! 調度器事件帶著發生時的調度器刻度，放進刻度
+//! `發生刻度 + SCHEDULER_EVENT_LEAD_TICKS` 的批次（同一批次內先
+//! `LockstepState`、後調度器，各自保持發生順序），所以所有客戶端與
+//! replay 在同一個刻度看到同一組事件。
+
+use crate::lockstep::{
+    GameEndEvent, PlayerJoinEvent, PlayerLeaveEvent, ServerEvent, ServerEventEnum, WaveStartEvent,
+};
+
+pub fn player_join(player_id: u32, player_name: &str) -> ServerEvent {
+    ServerEvent {
+        event: Some(ServerEventEnum::PlayerJoin(PlayerJoinEvent {
+            player_id,
+            player_name: player_name.to_string(),
+        })),
+    }
+}
+
+pub fn player_leave(player_id: u32) -> ServerEvent {
+    ServerEvent {
+        event: Some(ServerEventEnum::PlayerLeave(PlayerLeaveEvent { player_id })),
+    }
+}
+
+/// `wave` 為 `CurrentCreepWave.wave`（從 0 起算的波次索引）。
+pub fn wave_start(wave: u32) -> ServerEvent {
+    ServerEvent {
+        event: Some(ServerEventEnum::WaveStart(WaveStartEvent { wave })),
+    }
+}
+
+pub fn game_end(winner: &str) -> ServerEvent {
+    ServerEvent {
+        event: Some(ServerEventEnum::GameEnd(GameEndEvent {
+            winner: winner.to_string(),
+        })),
+    }
+}
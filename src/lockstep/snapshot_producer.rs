@@ -12,7 +12,7 @@
 //!
 //! # 模式版本控制
 //!
-//! `WorldSnapshot::schema_version` 固定在 `SCHEMA_VERSION = 1`。這
+//! `WorldSnapshot::schema_version` 固定在 `SCHEMA_VERSION = 4`。這
 //! omfx 端 LockstepClient 根據其編譯的預期檢查此內容
 //! 應用位元組之前的版本；不匹配的情況會從
 //! 沒有引導的當前刻度。 **將欄位新增至末尾
@@ -22,28 +22,65 @@
 //! 階段 5.3 發布 **伺服器端只寫** — omfx 觀察者
 //! 消費者目前僅記錄（反序列化 + 應用是階段 5+
 //! 一旦實際執行觀察者模式，就進行後續操作）。
+//!
+//! # Schema v2（階段 6.5）
+//!
+//! v1 只有 id / pos / vel / facing / hp / kind，中途加入者無法重建玩法
+//! 狀態。v2 另外帶：
+//!
+//! - 每實體：`CProperty` 其餘欄位、`ScriptUnitTag.unit_id`、`Gold`、
+//!   塔的 `upgrade_levels`、英雄等級 / 技能等級 / 技能冷卻、背包與
+//!   物品冷卻、`BuffStore` 上的 buff（剩餘時間 + payload）。
+//! - 世界：`PlayerEconomy` 餘額、`CurrentCreepWave` 進度、`Time` /
+//!   `TimeOfDay`（波次計時以 `Time` 為基準）。
+//!
+//! RNG 沒有額外狀態：所有 `SimRng` 流都由
+//! `(master_seed, tick, entity id, op_kind)` 派生，`master_seed` + `tick`
+//! 加上保留原 entity id 即足以重現。
+//!
+//! # Schema v3
+//!
+//! 中盤快照要能還原整波：`WorldSnapshot` 尾端另帶小兵的路徑進度
+//! （`CreepSnapshot`，還原時依 `CreepEmiter` 模板重建）與飛行中投射物的
+//! 完整 `Projectile` 欄位（`ProjectileSnapshot`）。
+//!
+//! # Schema v4
+//!
+//! `EntitySnapshot` 尾端帶 specs entity generation。specs 會重用已刪除
+//! 實體的 id，還原時必須以 `(id, generation)` 比對，才不會把快照裡的
+//! 實體套到之後重用同一 id 的另一個實體上。
+//!
+//! 讀取端請用 `deserialize_snapshot`，它同時接受 v1 / v2 / v3 位元組（缺的
+//! 欄位以預設值補上；舊版沒有 generation，還原時只能比對 id）。
 
 use serde::{Deserialize, Serialize};
-use specs::{Join, World, WorldExt};
+use specs::{Entity, Join, ReadStorage, World, WorldExt};
 
 use crate::comp::creep::CProperty;
 use crate::comp::facing::Facing;
+use crate::comp::gold::Gold;
 use crate::comp::hero::Hero;
+use crate::comp::inventory::Inventory;
 use crate::comp::phys::{Pos, Vel};
 use crate::comp::projectile::Projectile;
 use crate::comp::resources::{MasterSeed, Tick};
 use crate::comp::tower::Tower;
+use crate::comp::{CollisionRadius, Creep};
+use crate::comp::{PlayerEconomy, Time, TimeOfDay};
+use crate::scripting::ScriptUnitTag;
+use omoba_core::comp::CurrentCreepWave;
+use omoba_core::runtime::ability_runtime::BuffStore;
 
 /// 線上架構版本。新增/重新排序欄位時出現碰撞
 /// “EntitySnapshot”或“WorldSnapshot”。客戶拒絕申請不匹配
 /// 版本並回退到無開機重新加入。
-pub const SCHEMA_VERSION: u32 = 1;
+pub const SCHEMA_VERSION: u32 = 4;
 
 /// 實體類型標籤 — 與 omfx 端 `EntityKind` 判別式匹配
 /// 觀察者重新加入可以將每個實體分派到正確的 sprite/渲染
 /// 路徑而無需重新查詢腳本登錄。訂單已固定為 bincode。
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum EntityKindTag {
     Other = 0,
     Hero = 1,
@@ -52,6 +89,20 @@ pub enum EntityKindTag {
     Projectile = 4,
 }
 
+impl EntityKindTag {
+    /// 解析查詢參數中的種類名稱（不分大小寫，與 serde 輸出的名稱相同）。
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "other" => Some(Self::Other),
+            "hero" => Some(Self::Hero),
+            "tower" => Some(Self::Tower),
+            "creep" => Some(Self::Creep),
+            "projectile" => Some(Self::Projectile),
+            _ => None,
+        }
+    }
+}
+
 /// 每個實體的狀態在快照中傳送。
 ///
 /// **在末尾添加字段，切勿對現有字段重新排序** - bincode 是
@@ -67,6 +118,95 @@ pub struct EntitySnapshot {
     pub hp_raw: i64,
     pub mhp_raw: i64,
     pub kind: EntityKindTag,
+    // ---- v2 ----
+    pub msd_raw: i64,
+    pub def_physic_raw: i64,
+    pub def_magic_raw: i64,
+    /// `ScriptUnitTag.unit_id`（塔 / 小兵的腳本單位 id）。
+    pub unit_id: Option<String>,
+    pub gold: Option<i64>,
+    pub tower: Option<TowerSnapshot>,
+    pub hero: Option<HeroSnapshot>,
+    /// 背包格，長度與 `Inventory.slots` 相同，空格為 `None`。
+    pub inventory: Option<Vec<Option<ItemSlotSnapshot>>>,
+    /// 依 buff_id 排序。
+    pub buffs: Vec<BuffSnapshot>,
+    // ---- v4 ----
+    /// specs entity generation；從 v1–v3 位元組讀入時為 `None`。
+    pub generation: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TowerSnapshot {
+    pub upgrade_levels: [u8; 3],
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HeroSnapshot {
+    pub hero_id: String,
+    pub level: i32,
+    /// `(ability_id, level)`，依 ability_id 排序。
+    pub ability_levels: Vec<(String, i32)>,
+    /// `(ability_id, 剩餘冷卻 Fixed64 raw)`，依 `hero.abilities` 順序，
+    /// 只列出仍在冷卻中的技能。
+    pub ability_cooldowns_raw: Vec<(String, i64)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ItemSlotSnapshot {
+    pub item_id: String,
+    pub cooldown_remaining: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BuffSnapshot {
+    pub buff_id: String,
+    /// 剩餘時間 Fixed64 raw；`i64::MAX` 代表永久。
+    pub remaining_raw: i64,
+    /// payload JSON 字串（bincode 無法直接編 `serde_json::Value`）。
+    pub payload_json: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CreepWaveSnapshot {
+    pub wave: u32,
+    pub is_running: bool,
+    pub wave_start_time: f32,
+}
+
+/// v3：小兵的路徑進度。其餘 `Creep` 欄位來自同名的 `CreepEmiter` 模板。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CreepSnapshot {
+    pub id: u32,
+    /// `Creep.name`，也是 `CreepEmiter` 的鍵。
+    pub name: String,
+    pub path: String,
+    /// 下一個要前往的路徑檢查點索引。
+    pub pidx: u32,
+    pub collision_radius_raw: i64,
+}
+
+/// v3：飛行中的投射物。`owner` / `target` 為快照內的 entity id。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ProjectileSnapshot {
+    pub id: u32,
+    pub owner: u32,
+    pub target: Option<u32>,
+    pub tpos_x_raw: i64,
+    pub tpos_y_raw: i64,
+    pub time_left_raw: i64,
+    pub radius_raw: i64,
+    pub msd_raw: i64,
+    pub damage_phys_raw: i64,
+    pub damage_magi_raw: i64,
+    pub damage_real_raw: i64,
+    pub slow_factor_raw: i64,
+    pub slow_duration_raw: i64,
+    pub hit_radius_raw: i64,
+    pub stun_duration_raw: i64,
+    pub kind_id: u64,
+    pub generation: u64,
+    pub damage_profile: u64,
 }
 
 /// 頂級快照框架。 `master_seed` 讓觀察者重新加入
@@ -77,6 +217,266 @@ pub struct WorldSnapshot {
     pub tick: u32,
     pub master_seed: u64,
     pub entities: Vec<EntitySnapshot>,
+    // ---- v2 ----
+    /// `PlayerEconomy` 餘額 `(player_id, balance)`；沒有該資源時為 `None`。
+    pub economy: Option<Vec<(u32, i64)>>,
+    /// `CurrentCreepWave`；沒有該資源時為 `None`。
+    pub creep_wave: Option<CreepWaveSnapshot>,
+    /// `Time` / `TimeOfDay` 資源（秒）。
+    pub time: Option<f64>,
+    pub time_of_day: Option<f64>,
+    // ---- v3 ----
+    /// 依 entity id 排序。
+    pub creeps: Vec<CreepSnapshot>,
+    /// 依 entity id 排序。
+    pub projectiles: Vec<ProjectileSnapshot>,
+}
+
+/// v3 線上格式，只供 `deserialize_snapshot` 讀舊位元組。`EntitySnapshot`
+/// 沒有 v4 的 generation；v2 的實體格式與 v3 相同。
+pub mod v3 {
+    use super::{
+        BuffSnapshot, CreepSnapshot, CreepWaveSnapshot, EntityKindTag, HeroSnapshot,
+        ItemSlotSnapshot, ProjectileSnapshot, TowerSnapshot,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct EntitySnapshot {
+        pub id: u32,
+        pub pos_x_raw: i64,
+        pub pos_y_raw: i64,
+        pub vel_x_raw: i64,
+        pub vel_y_raw: i64,
+        pub facing_ticks: i32,
+        pub hp_raw: i64,
+        pub mhp_raw: i64,
+        pub kind: EntityKindTag,
+        pub msd_raw: i64,
+        pub def_physic_raw: i64,
+        pub def_magic_raw: i64,
+        pub unit_id: Option<String>,
+        pub gold: Option<i64>,
+        pub tower: Option<TowerSnapshot>,
+        pub hero: Option<HeroSnapshot>,
+        pub inventory: Option<Vec<Option<ItemSlotSnapshot>>>,
+        pub buffs: Vec<BuffSnapshot>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct WorldSnapshot {
+        pub schema_version: u32,
+        pub tick: u32,
+        pub master_seed: u64,
+        pub entities: Vec<EntitySnapshot>,
+        pub economy: Option<Vec<(u32, i64)>>,
+        pub creep_wave: Option<CreepWaveSnapshot>,
+        pub time: Option<f64>,
+        pub time_of_day: Option<f64>,
+        pub creeps: Vec<CreepSnapshot>,
+        pub projectiles: Vec<ProjectileSnapshot>,
+    }
+}
+
+/// v2 線上格式，只供 `deserialize_snapshot` 讀舊位元組。
+pub mod v2 {
+    use super::v3::EntitySnapshot;
+    use super::CreepWaveSnapshot;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct WorldSnapshot {
+        pub schema_version: u32,
+        pub tick: u32,
+        pub master_seed: u64,
+        pub entities: Vec<EntitySnapshot>,
+        pub economy: Option<Vec<(u32, i64)>>,
+        pub creep_wave: Option<CreepWaveSnapshot>,
+        pub time: Option<f64>,
+        pub time_of_day: Option<f64>,
+    }
+}
+
+/// v1 線上格式，只供 `deserialize_snapshot` 讀舊位元組。
+pub mod v1 {
+    use super::EntityKindTag;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct EntitySnapshot {
+        pub id: u32,
+        pub pos_x_raw: i64,
+        pub pos_y_raw: i64,
+        pub vel_x_raw: i64,
+        pub vel_y_raw: i64,
+        pub facing_ticks: i32,
+        pub hp_raw: i64,
+        pub mhp_raw: i64,
+        pub kind: EntityKindTag,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct WorldSnapshot {
+        pub schema_version: u32,
+        pub tick: u32,
+        pub master_seed: u64,
+        pub entities: Vec<EntitySnapshot>,
+    }
+}
+
+impl From<v1::EntitySnapshot> for EntitySnapshot {
+    fn from(e: v1::EntitySnapshot) -> Self {
+        Self {
+            id: e.id,
+            pos_x_raw: e.pos_x_raw,
+            pos_y_raw: e.pos_y_raw,
+            vel_x_raw: e.vel_x_raw,
+            vel_y_raw: e.vel_y_raw,
+            facing_ticks: e.facing_ticks,
+            hp_raw: e.hp_raw,
+            mhp_raw: e.mhp_raw,
+            kind: e.kind,
+            msd_raw: 0,
+            def_physic_raw: 0,
+            def_magic_raw: 0,
+            unit_id: None,
+            gold: None,
+            tower: None,
+            hero: None,
+            inventory: None,
+            buffs: Vec::new(),
+            generation: None,
+        }
+    }
+}
+
+impl From<v3::EntitySnapshot> for EntitySnapshot {
+    fn from(e: v3::EntitySnapshot) -> Self {
+        Self {
+            id: e.id,
+            pos_x_raw: e.pos_x_raw,
+            pos_y_raw: e.pos_y_raw,
+            vel_x_raw: e.vel_x_raw,
+            vel_y_raw: e.vel_y_raw,
+            facing_ticks: e.facing_ticks,
+            hp_raw: e.hp_raw,
+            mhp_raw: e.mhp_raw,
+            kind: e.kind,
+            msd_raw: e.msd_raw,
+            def_physic_raw: e.def_physic_raw,
+            def_magic_raw: e.def_magic_raw,
+            unit_id: e.unit_id,
+            gold: e.gold,
+            tower: e.tower,
+            hero: e.hero,
+            inventory: e.inventory,
+            buffs: e.buffs,
+            generation: None,
+        }
+    }
+}
+
+impl From<v1::WorldSnapshot> for WorldSnapshot {
+    fn from(s: v1::WorldSnapshot) -> Self {
+        Self {
+            schema_version: s.schema_version,
+            tick: s.tick,
+            master_seed: s.master_seed,
+            entities: s.entities.into_iter().map(Into::into).collect(),
+            economy: None,
+            creep_wave: None,
+            time: None,
+            time_of_day: None,
+            creeps: Vec::new(),
+            projectiles: Vec::new(),
+        }
+    }
+}
+
+impl From<v2::WorldSnapshot> for WorldSnapshot {
+    fn from(s: v2::WorldSnapshot) -> Self {
+        Self {
+            schema_version: s.schema_version,
+            tick: s.tick,
+            master_seed: s.master_seed,
+            entities: s.entities.into_iter().map(Into::into).collect(),
+            economy: s.economy,
+            creep_wave: s.creep_wave,
+            time: s.time,
+            time_of_day: s.time_of_day,
+            creeps: Vec::new(),
+            projectiles: Vec::new(),
+        }
+    }
+}
+
+impl From<v3::WorldSnapshot> for WorldSnapshot {
+    fn from(s: v3::WorldSnapshot) -> Self {
+        Self {
+            schema_version: s.schema_version,
+            tick: s.tick,
+            master_seed: s.master_seed,
+            entities: s.entities.into_iter().map(Into::into).collect(),
+            economy: s.economy,
+            creep_wave: s.creep_wave,
+            time: s.time,
+            time_of_day: s.time_of_day,
+            creeps: s.creeps,
+            projectiles: s.projectiles,
+        }
+    }
+}
+
+/// 解碼任一支援版本的快照位元組。v1 / v2 / v3 轉成目前形狀，新欄位為空；
+/// 回傳值的 `schema_version` 保留原始版本，呼叫端可據此判斷
+/// 玩法狀態是否完整。
+pub fn deserialize_snapshot(bytes: &[u8]) -> Result<WorldSnapshot, String> {
+    if let Ok(snap) = omoba_sim::snapshot::deserialize::<WorldSnapshot>(bytes) {
+        if snap.schema_version == SCHEMA_VERSION {
+            return Ok(snap);
+        }
+    }
+    if let Ok(snap) = omoba_sim::snapshot::deserialize::<v3::WorldSnapshot>(bytes) {
+        if snap.schema_version == 3 {
+            return Ok(snap.into());
+        }
+    }
+    if let Ok(snap) = omoba_sim::snapshot::deserialize::<v2::WorldSnapshot>(bytes) {
+        if snap.schema_version == 2 {
+            return Ok(snap.into());
+        }
+    }
+    let snap: v1::WorldSnapshot =
+        omoba_sim::snapshot::deserialize(bytes).map_err(|e| format!("{e:?}"))?;
+    if snap.schema_version != 1 {
+        return Err(format!(
+            "unsupported snapshot schema_version {} (expected 1, 2, 3 or {})",
+            snap.schema_version, SCHEMA_VERSION
+        ));
+    }
+    Ok(snap.into())
+}
+
+/// 依元件存在與否判斷實體種類。`Hero` > `Tower` > `Projectile` >
+/// 有 `CProperty` 的視為 `Creep`；快照與狀態雜湊報告共用同一規則。
+pub(crate) fn classify_entity(
+    e: Entity,
+    heroes: &ReadStorage<Hero>,
+    towers: &ReadStorage<Tower>,
+    projectiles: &ReadStorage<Projectile>,
+    cprops: &ReadStorage<CProperty>,
+) -> EntityKindTag {
+    if heroes.get(e).is_some() {
+        EntityKindTag::Hero
+    } else if towers.get(e).is_some() {
+        EntityKindTag::Tower
+    } else if projectiles.get(e).is_some() {
+        EntityKindTag::Projectile
+    } else if cprops.get(e).is_some() {
+        EntityKindTag::Creep
+    } else {
+        EntityKindTag::Other
+    }
 }
 
 /// 走遍世界，用「Pos」收集每個實體，透過
@@ -87,6 +487,11 @@ pub struct WorldSnapshot {
 /// 作為“沒有快照保存此刻度”和之前的（可能是空的）位元組
 /// 留在“SnapshotStore”中。
 pub fn serialize_snapshot(world: &World) -> Vec<u8> {
+    omoba_sim::snapshot::serialize(&capture_snapshot(world)).unwrap_or_default()
+}
+
+/// 擷取 `WorldSnapshot`（不序列化）。
+pub fn capture_snapshot(world: &World) -> WorldSnapshot {
     let entities = world.entities();
     let pos_storage = world.read_storage::<Pos>();
     let vel_storage = world.read_storage::<Vel>();
@@ -95,31 +500,45 @@ pub fn serialize_snapshot(world: &World) -> Vec<u8> {
     let hero_storage = world.read_storage::<Hero>();
     let tower_storage = world.read_storage::<Tower>();
     let proj_storage = world.read_storage::<Projectile>();
+    let tag_storage = world.read_storage::<ScriptUnitTag>();
+    let gold_storage = world.read_storage::<Gold>();
+    let inventory_storage = world.read_storage::<Inventory>();
+    let creep_storage = world.read_storage::<Creep>();
+    let radius_storage = world.read_storage::<CollisionRadius>();
+    let buff_store = world.try_fetch::<BuffStore>();
 
     let snapshot_entities: Vec<EntitySnapshot> = (&entities, &pos_storage)
         .join()
         .map(|(e, pos)| {
-            let kind = if hero_storage.get(e).is_some() {
-                EntityKindTag::Hero
-            } else if tower_storage.get(e).is_some() {
-                EntityKindTag::Tower
-            } else if proj_storage.get(e).is_some() {
-                EntityKindTag::Projectile
-            } else if cprop_storage.get(e).is_some() {
-                EntityKindTag::Creep
-            } else {
-                EntityKindTag::Other
-            };
+            let kind = classify_entity(
+                e,
+                &hero_storage,
+                &tower_storage,
+                &proj_storage,
+                &cprop_storage,
+            );
 
             let (vel_x_raw, vel_y_raw) = vel_storage
                 .get(e)
                 .map(|v| (v.0.x.raw(), v.0.y.raw()))
                 .unwrap_or((0, 0));
             let facing_ticks = facing_storage.get(e).map(|f| f.0.ticks()).unwrap_or(0);
-            let (hp_raw, mhp_raw) = cprop_storage
-                .get(e)
-                .map(|c| (c.hp.raw(), c.mhp.raw()))
-                .unwrap_or((0, 0));
+            let cprop = cprop_storage.get(e);
+            let (hp_raw, mhp_raw) = cprop.map(|c| (c.hp.raw(), c.mhp.raw())).unwrap_or((0, 0));
+            let mut buffs: Vec<BuffSnapshot> = buff_store
+                .as_ref()
+                .map(|store| {
+                    store
+                        .iter_for(e)
+                        .map(|(buff_id, entry)| BuffSnapshot {
+                            buff_id: buff_id.to_string(),
+                            remaining_raw: entry.remaining.raw(),
+                            payload_json: entry.payload.to_string(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            buffs.sort_by(|a, b| a.buff_id.cmp(&b.buff_id));
 
             EntitySnapshot {
                 id: e.id(),
@@ -131,18 +550,114 @@ pub fn serialize_snapshot(world: &World) -> Vec<u8> {
                 hp_raw,
                 mhp_raw,
                 kind,
+                msd_raw: cprop.map(|c| c.msd.raw()).unwrap_or(0),
+                def_physic_raw: cprop.map(|c| c.def_physic.raw()).unwrap_or(0),
+                def_magic_raw: cprop.map(|c| c.def_magic.raw()).unwrap_or(0),
+                unit_id: tag_storage.get(e).map(|t| t.unit_id.clone()),
+                gold: gold_storage.get(e).map(|g| g.0 as i64),
+                tower: tower_storage.get(e).map(|t| TowerSnapshot {
+                    upgrade_levels: t.upgrade_levels,
+                }),
+                hero: hero_storage.get(e).map(hero_snapshot),
+                inventory: inventory_storage.get(e).map(|inv| {
+                    inv.slots
+                        .iter()
+                        .map(|slot| {
+                            slot.as_ref().map(|inst| ItemSlotSnapshot {
+                                item_id: inst.item_id.clone(),
+                                cooldown_remaining: inst.cooldown_remaining,
+                            })
+                        })
+                        .collect()
+                }),
+                buffs,
+                generation: Some(e.gen().id()),
             }
         })
         .collect();
 
-    let snapshot = WorldSnapshot {
+    let economy = world.try_fetch::<PlayerEconomy>().map(|economy| {
+        economy
+            .balances()
+            .iter()
+            .map(|(player_id, balance)| (*player_id as u32, *balance as i64))
+            .collect()
+    });
+    let creep_wave = world
+        .try_fetch::<CurrentCreepWave>()
+        .map(|ccw| CreepWaveSnapshot {
+            wave: ccw.wave as u32,
+            is_running: ccw.is_running,
+            wave_start_time: ccw.wave_start_time,
+        });
+
+    let creeps = (&entities, &pos_storage, &creep_storage)
+        .join()
+        .map(|(e, _, creep)| CreepSnapshot {
+            id: e.id(),
+            name: creep.name.clone(),
+            path: creep.path.clone(),
+            pidx: creep.pidx as u32,
+            collision_radius_raw: radius_storage.get(e).map(|r| r.0.raw()).unwrap_or(0),
+        })
+        .collect();
+    let projectiles = (&entities, &pos_storage, &proj_storage)
+        .join()
+        .map(|(e, _, p)| ProjectileSnapshot {
+            id: e.id(),
+            owner: p.owner.id(),
+            target: p.target.map(|t| t.id()),
+            tpos_x_raw: p.tpos.x.raw(),
+            tpos_y_raw: p.tpos.y.raw(),
+            time_left_raw: p.time_left.raw(),
+            radius_raw: p.radius.raw(),
+            msd_raw: p.msd.raw(),
+            damage_phys_raw: p.damage_phys.raw(),
+            damage_magi_raw: p.damage_magi.raw(),
+            damage_real_raw: p.damage_real.raw(),
+            slow_factor_raw: p.slow_factor.raw(),
+            slow_duration_raw: p.slow_duration.raw(),
+            hit_radius_raw: p.hit_radius.raw(),
+            stun_duration_raw: p.stun_duration.raw(),
+            kind_id: p.kind_id as u64,
+            generation: p.generation as u64,
+            damage_profile: p.damage_profile as u64,
+        })
+        .collect();
+
+    WorldSnapshot {
         schema_version: SCHEMA_VERSION,
         tick: world.read_resource::<Tick>().0 as u32,
         master_seed: world.read_resource::<MasterSeed>().0,
         entities: snapshot_entities,
-    };
+        economy,
+        creep_wave,
+        time: world.try_fetch::<Time>().map(|t| t.0),
+        time_of_day: world.try_fetch::<TimeOfDay>().map(|t| t.0),
+        creeps,
+        projectiles,
+    }
+}
 
-    omoba_sim::snapshot::serialize(&snapshot).unwrap_or_default()
+fn hero_snapshot(hero: &Hero) -> HeroSnapshot {
+    let mut ability_levels: Vec<(String, i32)> = hero
+        .ability_levels
+        .iter()
+        .map(|(id, level)| (id.clone(), *level))
+        .collect();
+    ability_levels.sort();
+    let ability_cooldowns_raw = hero
+        .abilities
+        .iter()
+        .filter(|id| hero.is_on_cooldown(id))
+        .map(|id| (id.clone(), hero.get_cooldown(id).raw()))
+        .collect();
+    HeroSnapshot {
+        hero_id: hero.id.clone(),
+        level: hero.level,
+        ability_levels,
+        ability_cooldowns_raw,
+    }
 }
 
 #[cfg(test)]
@@ -160,6 +675,11 @@ mod tests {
         w.register::<Hero>();
         w.register::<Tower>();
         w.register::<Projectile>();
+        w.register::<Creep>();
+        w.register::<CollisionRadius>();
+        w.register::<ScriptUnitTag>();
+        w.register::<Gold>();
+        w.register::<Inventory>();
         w.insert(Tick(0));
         w.insert(MasterSeed::default());
         w
@@ -256,6 +776,200 @@ mod tests {
         // 要有意識——客戶將他們的預期版本與此相對應
         // 持續的。如果您修改了它，也要更新 omfx LockstepClient
         // lockstep_client.rs 中的觀察者重新加入處理程序。
-        assert_eq!(SCHEMA_VERSION, 1);
+        assert_eq!(SCHEMA_VERSION, 3);
+    }
+
+    #[test]
+    fn captures_v2_gameplay_state() {
+        let mut w = make_world();
+        let mut economy = PlayerEconomy::default();
+        economy.initialize(1, 650);
+        w.insert(economy);
+        w.insert(CurrentCreepWave {
+            wave: 3,
+            is_running: true,
+            ..Default::default()
+        });
+        let e = w
+            .create_entity()
+            .with(pos_xy(5, 5))
+            .with(cprop(80, 100))
+            .with(Gold(42))
+            .with(ScriptUnitTag {
+                unit_id: "creep_goblin".to_string(),
+            })
+            .build();
+
+        let bytes = serialize_snapshot(&w);
+        let snap = deserialize_snapshot(&bytes).expect("v2 snapshot must deserialize");
+        assert_eq!(snap.schema_version, SCHEMA_VERSION);
+        assert_eq!(snap.economy, Some(vec![(1, 650)]));
+        assert_eq!(
+            snap.creep_wave.as_ref().map(|c| (c.wave, c.is_running)),
+            Some((3, true))
+        );
+        let ent = snap.entities.iter().find(|s| s.id == e.id()).unwrap();
+        assert_eq!(ent.unit_id.as_deref(), Some("creep_goblin"));
+        assert_eq!(ent.gold, Some(42));
+        assert!(ent.tower.is_none() && ent.hero.is_none() && ent.inventory.is_none());
+        assert!(ent.buffs.is_empty(), "no BuffStore resource → no buffs");
+    }
+
+    #[test]
+    fn captures_v3_projectiles() {
+        let mut w = make_world();
+        let owner = w.create_entity().with(pos_xy(0, 0)).build();
+        let target = w
+            .create_entity()
+            .with(pos_xy(9, 0))
+            .with(cprop(30, 30))
+            .build();
+        let proj = w
+            .create_entity()
+            .with(pos_xy(4, 0))
+            .with(Projectile {
+                time_left: Fixed64::from_i32(2),
+                owner,
+                target: Some(target),
+                tpos: SimVec2 {
+                    x: Fixed64::from_i32(9),
+                    y: Fixed64::ZERO,
+                },
+                radius: Fixed64::from_i32(5),
+                msd: Fixed64::from_i32(500),
+                damage_phys: Fixed64::from_i32(25),
+                damage_magi: Fixed64::ZERO,
+                damage_real: Fixed64::ZERO,
+                slow_factor: Fixed64::ZERO,
+                slow_duration: Fixed64::ZERO,
+                hit_radius: Fixed64::ZERO,
+                stun_duration: Fixed64::ZERO,
+                kind_id: 0,
+                generation: 0,
+                damage_profile: 0,
+            })
+            .build();
+
+        let snap = deserialize_snapshot(&serialize_snapshot(&w)).expect("v3 snapshot");
+        assert!(snap.creeps.is_empty());
+        assert_eq!(snap.projectiles.len(), 1);
+        let saved = &snap.projectiles[0];
+        assert_eq!(saved.id, proj.id());
+        assert_eq!(saved.owner, owner.id());
+        assert_eq!(saved.target, Some(target.id()));
+        assert_eq!(saved.msd_raw, Fixed64::from_i32(500).raw());
+        assert_eq!(saved.damage_phys_raw, Fixed64::from_i32(25).raw());
+    }
+
+    #[test]
+    fn reads_v3_snapshot_bytes() {
+        let old = v3::WorldSnapshot {
+            schema_version: 3,
+            tick: 99,
+            master_seed: 0xABCD,
+            entities: vec![v3::EntitySnapshot {
+                id: 4,
+                pos_x_raw: 1024,
+                pos_y_raw: 0,
+                vel_x_raw: 0,
+                vel_y_raw: 0,
+                facing_ticks: 0,
+                hp_raw: 10,
+                mhp_raw: 20,
+                kind: EntityKindTag::Tower,
+                msd_raw: 0,
+                def_physic_raw: 0,
+                def_magic_raw: 0,
+                unit_id: Some("tower_arrow".to_string()),
+                gold: None,
+                tower: Some(TowerSnapshot {
+                    upgrade_levels: [1, 0, 0],
+                }),
+                hero: None,
+                inventory: None,
+                buffs: Vec::new(),
+            }],
+            economy: None,
+            creep_wave: None,
+            time: None,
+            time_of_day: None,
+            creeps: Vec::new(),
+            projectiles: Vec::new(),
+        };
+        let bytes = omoba_sim::snapshot::serialize(&old).unwrap();
+        let snap = deserialize_snapshot(&bytes).expect("v3 bytes must still load");
+        assert_eq!(snap.schema_version, 3);
+        assert_eq!(snap.entities[0].unit_id.as_deref(), Some("tower_arrow"));
+        assert_eq!(snap.entities[0].generation, None);
+    }
+
+    #[test]
+    fn reads_v2_snapshot_bytes() {
+        let old = v2::WorldSnapshot {
+            schema_version: 2,
+            tick: 88,
+            master_seed: 0xABCD,
+            entities: Vec::new(),
+            economy: Some(vec![(1, 300)]),
+            creep_wave: Some(CreepWaveSnapshot {
+                wave: 2,
+                is_running: true,
+                wave_start_time: 1.5,
+            }),
+            time: Some(12.0),
+            time_of_day: None,
+        };
+        let bytes = omoba_sim::snapshot::serialize(&old).unwrap();
+        let snap = deserialize_snapshot(&bytes).expect("v2 bytes must still load");
+        assert_eq!(snap.schema_version, 2);
+        assert_eq!(snap.tick, 88);
+        assert_eq!(snap.economy, Some(vec![(1, 300)]));
+        assert!(snap.creeps.is_empty() && snap.projectiles.is_empty());
+    }
+
+    #[test]
+    fn reads_v1_snapshot_bytes() {
+        let old = v1::WorldSnapshot {
+            schema_version: 1,
+            tick: 77,
+            master_seed: 0xABCD,
+            entities: vec![v1::EntitySnapshot {
+                id: 9,
+                pos_x_raw: 1024,
+                pos_y_raw: 2048,
+                vel_x_raw: 0,
+                vel_y_raw: 0,
+                facing_ticks: 0,
+                hp_raw: 10,
+                mhp_raw: 20,
+                kind: EntityKindTag::Creep,
+            }],
+        };
+        let bytes = omoba_sim::snapshot::serialize(&old).unwrap();
+        let snap = deserialize_snapshot(&bytes).expect("v1 bytes must still load");
+        assert_eq!(snap.schema_version, 1);
+        assert_eq!(snap.tick, 77);
+        assert_eq!(snap.entities.len(), 1);
+        assert_eq!(snap.entities[0].pos_y_raw, 2048);
+        assert_eq!(snap.entities[0].unit_id, None);
+        assert_eq!(snap.economy, None);
+    }
+
+    #[test]
+    fn entity_kind_tag_parses_its_serialized_names() {
+        for kind in [
+            EntityKindTag::Other,
+            EntityKindTag::Hero,
+            EntityKindTag::Tower,
+            EntityKindTag::Creep,
+            EntityKindTag::Projectile,
+        ] {
+            let name = serde_json::to_value(kind).unwrap();
+            let name = name.as_str().unwrap();
+            assert_eq!(EntityKindTag::parse(name), Some(kind));
+            assert_eq!(EntityKindTag::parse(&name.to_ascii_uppercase()), Some(kind));
+        }
+        assert_eq!(EntityKindTag::parse("creeps"), None);
+        assert_eq!(EntityKindTag::parse(""), None);
     }
 }
@@ -0,0 +1,673 @@
+//! 階段 6.6：把 `WorldSnapshot` 載回權威 `World`。
+//!
+//! `serialize_snapshot` 的對應消費者。用途：當機復原、單人 TD 存讀檔、
+//! 以及從中盤狀態開始跑平衡測試。
+//!
+//! # 前提
+//!
+//! 目標 `World` 必須已由同一個 story 建好（`State::new_with_campaign`），
+//! 也就是登錄表、地圖、英雄等「開局就有」的實體都在。還原流程：
+//!
+//! 1. 寫回 `Tick` / `MasterSeed` / `Time` / `TimeOfDay` 與 v2 的世界資源
+//!    （`PlayerEconomy`、`CurrentCreepWave`）。
+//! 2. 依 `(entity id, generation)` 比對：世界裡已有同一實體的直接覆寫
+//!    元件；快照沒有的 `Pos` 實體，以及 id 相同但 generation 不同（specs
+//!    重用了 id）的實體刪除。v1–v3 快照沒有 generation，只比對 id。
+//! 3. 快照有、世界沒有的實體重建：塔用 `spawn_td_tower` 依 `unit_id`；
+//!    英雄（v2）以 `CampaignManager::respawn_hero` 依 `hero_id` 模板；
+//!    小兵（v3）以同名 `CreepEmiter` 模板加上快照的路徑進度；投射物（v3）
+//!    直接由 `ProjectileSnapshot` 組回，`owner` / `target` 對到還原後的
+//!    實體。specs 無法指定 entity id，新 id 記在 `RestoreReport::remapped`；
+//!    缺少重建資料（v1 / v2 快照、找不到模板）的列入 `unresolved`。
+//!
+//! 因為 `SimRng` 流以 entity id 為鍵，`remapped` / `unresolved` 不為空時
+//! 還原後的對局不保證與原對局逐位元組一致。
+//!
+//! 世界還原後，lockstep 時間軸要用 `rewind_lockstep` 對齊到同一刻度。
+
+use serde::Serialize;
+use specs::{Builder, Entity, Join, World, WorldExt};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use omoba_core::comp::CurrentCreepWave;
+use omoba_core::runtime::ability_runtime::BuffStore;
+use omoba_sim::{Angle, Fixed64, Vec2 as SimVec2};
+
+use crate::comp::creep::CProperty;
+use crate::comp::facing::Facing;
+use crate::comp::gold::Gold;
+use crate::comp::hero::Hero;
+use crate::comp::phys::{Pos, Vel};
+use crate::comp::projectile::Projectile;
+use crate::comp::resources::{MasterSeed, Tick};
+use crate::comp::tower::Tower;
+use crate::comp::{
+    CollisionRadius, Creep, CreepEmiter, Inventory, ItemEffects, ItemInstance, PlayerEconomy,
+    Searcher, Time, TimeOfDay,
+};
+use crate::lockstep::snapshot_producer::{
+    deserialize_snapshot, CreepSnapshot, EntityKindTag, EntitySnapshot, ProjectileSnapshot,
+    WorldSnapshot,
+};
+use crate::lockstep::{InputBuffer, LockstepState, TickHistory};
+use crate::scripting::{ScriptEvent, ScriptEventQueue, ScriptUnitTag};
+
+/// 還原結果摘要。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RestoreReport {
+    pub schema_version: u32,
+    pub tick: u32,
+    /// 覆寫了元件的既有實體數。
+    pub updated: usize,
+    /// 快照中不存在而被刪除的實體數。
+    pub deleted: usize,
+    /// `(快照 id, 新 id)`：重建後拿到不同 id 的實體。
+    pub remapped: Vec<(u32, u32)>,
+    /// 無法重建的快照實體 id。
+    pub unresolved: Vec<u32>,
+}
+
+impl RestoreReport {
+    /// 所有實體都以原 id 還原。
+    pub fn is_exact(&self) -> bool {
+        self.remapped.is_empty() && self.unresolved.is_empty()
+    }
+}
+
+/// 解碼並還原快照位元組（v1 / v2 皆可；v1 只還原物理狀態）。
+pub fn restore_snapshot(world: &mut World, bytes: &[u8]) -> Result<RestoreReport, String> {
+    let snapshot = deserialize_snapshot(bytes)?;
+    Ok(restore_world_snapshot(world, &snapshot))
+}
+
+/// 把已解碼的 `WorldSnapshot` 寫回 `world`。
+pub fn restore_world_snapshot(world: &mut World, snapshot: &WorldSnapshot) -> RestoreReport {
+    let mut report = RestoreReport {
+        schema_version: snapshot.schema_version,
+        tick: snapshot.tick,
+        ..Default::default()
+    };
+    let full = snapshot.schema_version >= 2;
+
+    restore_resources(world, snapshot);
+
+    // 依 (id, generation) 比對既有實體；舊版快照沒有 generation 時只比 id。
+    let wanted: BTreeMap<u32, Option<i32>> = snapshot
+        .entities
+        .iter()
+        .map(|s| (s.id, s.generation))
+        .collect();
+    let (live, stale): (BTreeMap<u32, Entity>, BTreeMap<u32, Entity>) = {
+        let entities = world.entities();
+        let positions = world.read_storage::<Pos>();
+        (&entities, &positions)
+            .join()
+            .map(|(e, _)| (e.id(), e))
+            .partition(|(id, e)| {
+                wanted
+                    .get(id)
+                    .is_some_and(|generation| generation.map_or(true, |g| g == e.gen().id()))
+            })
+    };
+
+    let mut towers_changed = false;
+    for (id, e) in &stale {
+        towers_changed |= world.read_storage::<Tower>().get(*e).is_some();
+        if let Err(err) = world.delete_entity(*e) {
+            log::warn!(
+                "restore_snapshot: failed to delete entity {}: {:?}",
+                id,
+                err
+            );
+        } else {
+            report.deleted += 1;
+        }
+    }
+
+    let creeps: BTreeMap<u32, &CreepSnapshot> = snapshot.creeps.iter().map(|c| (c.id, c)).collect();
+    let projectiles: BTreeMap<u32, &ProjectileSnapshot> =
+        snapshot.projectiles.iter().map(|p| (p.id, p)).collect();
+    // 快照 id → 還原後的實體。投射物最後處理，`owner` / `target` 才能
+    // 對到已重建的塔與小兵。
+    let mut restored: BTreeMap<u32, Entity> = BTreeMap::new();
+    let (shots, others): (Vec<&EntitySnapshot>, Vec<&EntitySnapshot>) = snapshot
+        .entities
+        .iter()
+        .partition(|s| s.kind == EntityKindTag::Projectile);
+    for snap in others.into_iter().chain(shots) {
+        let entity = match live.get(&snap.id) {
+            Some(e) => *e,
+            None => {
+                let respawned = match snap.kind {
+                    EntityKindTag::Creep => creeps
+                        .get(&snap.id)
+                        .and_then(|saved| respawn_creep(world, snap, saved)),
+                    EntityKindTag::Projectile => projectiles
+                        .get(&snap.id)
+                        .map(|saved| respawn_projectile(world, snap, saved, &restored)),
+                    _ => respawn_entity(world, snap),
+                };
+                match respawned {
+                    Some(e) => {
+                        towers_changed |= snap.kind == EntityKindTag::Tower;
+                        if e.id() != snap.id {
+                            report.remapped.push((snap.id, e.id()));
+                        }
+                        e
+                    }
+                    None => {
+                        report.unresolved.push(snap.id);
+                        continue;
+                    }
+                }
+            }
+        };
+        restored.insert(snap.id, entity);
+        apply_entity(world, entity, snap, full);
+        if let Some(saved) = creeps.get(&snap.id) {
+            if let Some(creep) = world.write_storage::<Creep>().get_mut(entity) {
+                creep.path = saved.path.clone();
+                creep.pidx = saved.pidx as _;
+            }
+        }
+        if let Some(saved) = projectiles.get(&snap.id) {
+            let projectile = projectile_from_snapshot(world, saved, &restored);
+            if let Some(p) = world.write_storage::<Projectile>().get_mut(entity) {
+                *p = projectile;
+            }
+        }
+        report.updated += 1;
+    }
+
+    world.maintain();
+    if towers_changed {
+        if let Some(mut searcher) = world.try_fetch_mut::<Searcher>() {
+            searcher.tower.mark_dirty();
+        }
+    }
+
+    if !report.is_exact() {
+        log::warn!(
+            "restore_snapshot: tick {} restored with {} remapped and {} unresolved entities; replay determinism not guaranteed",
+            report.tick,
+            report.remapped.len(),
+            report.unresolved.len()
+        );
+    }
+    report
+}
+
+fn restore_resources(world: &mut World, snapshot: &WorldSnapshot) {
+    world.write_resource::<Tick>().0 = snapshot.tick.into();
+    world.write_resource::<MasterSeed>().0 = snapshot.master_seed;
+    if let Some(time) = snapshot.time {
+        world.write_resource::<Time>().0 = time;
+    }
+    if let Some(time_of_day) = snapshot.time_of_day {
+        world.write_resource::<TimeOfDay>().0 = time_of_day;
+    }
+    if let Some(balances) = &snapshot.economy {
+        let mut economy = PlayerEconomy::default();
+        for (player_id, balance) in balances {
+            economy.initialize(*player_id as _, *balance as _);
+        }
+        world.insert(economy);
+    }
+    if let Some(wave) = &snapshot.creep_wave {
+        if let Some(mut ccw) = world.try_fetch_mut::<CurrentCreepWave>() {
+            ccw.wave = wave.wave as _;
+            ccw.is_running = wave.is_running;
+            ccw.wave_start_time = wave.wave_start_time;
+        }
+    }
+}
+
+/// 重建世界裡沒有的塔（需有 `unit_id`）與英雄（需有 `hero`）。小兵 /
+/// 投射物見 `respawn_creep` / `respawn_projectile`。
+fn respawn_entity(world: &mut World, snap: &EntitySnapshot) -> Option<Entity> {
+    match (snap.kind, snap.unit_id.as_deref(), snap.hero.as_ref()) {
+        (EntityKindTag::Tower, Some(unit_id), _) => {
+            let (x, y) = snapshot_pos(snap).xy_f32();
+            crate::comp::tower_template::spawn_td_tower(world, vek::Vec2::new(x, y), unit_id)
+        }
+        (EntityKindTag::Hero, _, Some(hero)) => {
+            crate::comp::campaign_manager::CampaignManager::respawn_hero(
+                world,
+                &hero.hero_id,
+                snapshot_pos(snap),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// 以同名 `CreepEmiter` 模板重建小兵，元件與波次生成
+/// （`handle_creep_creation`）相同；找不到模板時回傳 `None`。
+fn respawn_creep(
+    world: &mut World,
+    snap: &EntitySnapshot,
+    saved: &CreepSnapshot,
+) -> Option<Entity> {
+    let mut creep = world
+        .try_fetch::<BTreeMap<String, CreepEmiter>>()?
+        .get(&saved.name)?
+        .root
+        .clone();
+    creep.path = saved.path.clone();
+    creep.pidx = saved.pidx as _;
+    creep.block_tower = None;
+    let unit_id = snap
+        .unit_id
+        .clone()
+        .unwrap_or_else(|| format!("creep_{}", saved.name));
+    let entity = world
+        .create_entity()
+        .with(snapshot_pos(snap))
+        .with(creep)
+        .with(CProperty {
+            hp: Fixed64::from_raw(snap.hp_raw),
+            mhp: Fixed64::from_raw(snap.mhp_raw),
+            msd: Fixed64::from_raw(snap.msd_raw),
+            def_physic: Fixed64::from_raw(snap.def_physic_raw),
+            def_magic: Fixed64::from_raw(snap.def_magic_raw),
+        })
+        .with(CollisionRadius(Fixed64::from_raw(
+            saved.collision_radius_raw,
+        )))
+        .with(ScriptUnitTag { unit_id })
+        .build();
+    if let Some(mut queue) = world.try_fetch_mut::<ScriptEventQueue>() {
+        queue.push(ScriptEvent::Spawn { e: entity });
+    }
+    Some(entity)
+}
+
+fn respawn_projectile(
+    world: &mut World,
+    snap: &EntitySnapshot,
+    saved: &ProjectileSnapshot,
+    restored: &BTreeMap<u32, Entity>,
+) -> Entity {
+    let projectile = projectile_from_snapshot(world, saved, restored);
+    world
+        .create_entity()
+        .with(snapshot_pos(snap))
+        .with(projectile)
+        .build()
+}
+
+/// 組回 `Projectile`。`owner` / `target` 先查已還原的實體；快照當下就
+/// 已不存在的實體沿用原 id（與原對局一樣指向失效的實體）。
+fn projectile_from_snapshot(
+    world: &World,
+    saved: &ProjectileSnapshot,
+    restored: &BTreeMap<u32, Entity>,
+) -> Projectile {
+    let resolve = |id: u32| {
+        restored
+            .get(&id)
+            .copied()
+            .unwrap_or_else(|| world.entities().entity(id))
+    };
+    Projectile {
+        time_left: Fixed64::from_raw(saved.time_left_raw),
+        owner: resolve(saved.owner),
+        target: saved.target.map(resolve),
+        tpos: SimVec2 {
+            x: Fixed64::from_raw(saved.tpos_x_raw),
+            y: Fixed64::from_raw(saved.tpos_y_raw),
+        },
+        radius: Fixed64::from_raw(saved.radius_raw),
+        msd: Fixed64::from_raw(saved.msd_raw),
+        damage_phys: Fixed64::from_raw(saved.damage_phys_raw),
+        damage_magi: Fixed64::from_raw(saved.damage_magi_raw),
+        damage_real: Fixed64::from_raw(saved.damage_real_raw),
+        slow_factor: Fixed64::from_raw(saved.slow_factor_raw),
+        slow_duration: Fixed64::from_raw(saved.slow_duration_raw),
+        hit_radius: Fixed64::from_raw(saved.hit_radius_raw),
+        stun_duration: Fixed64::from_raw(saved.stun_duration_raw),
+        kind_id: saved.kind_id as _,
+        generation: saved.generation as _,
+        damage_profile: saved.damage_profile as _,
+    }
+}
+
+fn snapshot_pos(snap: &EntitySnapshot) -> Pos {
+    Pos(SimVec2 {
+        x: Fixed64::from_raw(snap.pos_x_raw),
+        y: Fixed64::from_raw(snap.pos_y_raw),
+    })
+}
+
+/// 把 lockstep 時間軸對齊到還原後的刻度：下一個 `TickBatch` 為
+/// `tick + 1`，舊時間軸的待處理輸入與批次歷史一併清掉，避免補送或
+/// 排入不存在的刻度。須在 `TickBroadcaster` 開始前呼叫。
+pub fn rewind_lockstep(
+    tick: u32,
+    state: &Mutex<LockstepState>,
+    input_buffer: &Mutex<InputBuffer>,
+    history: &Mutex<TickHistory>,
+) {
+    state.lock().unwrap().rewind_to_tick(tick);
+    input_buffer.lock().unwrap().clear();
+    history.lock().unwrap().clear();
+}
+
+fn apply_entity(world: &mut World, e: Entity, snap: &EntitySnapshot, full: bool) {
+    let _ = world.write_storage::<Pos>().insert(e, snapshot_pos(snap));
+    if let Some(vel) = world.write_storage::<Vel>().get_mut(e) {
+        vel.0 = SimVec2 {
+            x: Fixed64::from_raw(snap.vel_x_raw),
+            y: Fixed64::from_raw(snap.vel_y_raw),
+        };
+    }
+    if let Some(facing) = world.write_storage::<Facing>().get_mut(e) {
+        facing.0 = Angle::from_ticks(snap.facing_ticks);
+    }
+    if let Some(cprop) = world.write_storage::<CProperty>().get_mut(e) {
+        cprop.hp = Fixed64::from_raw(snap.hp_raw);
+        cprop.mhp = Fixed64::from_raw(snap.mhp_raw);
+        if full {
+            cprop.msd = Fixed64::from_raw(snap.msd_raw);
+            cprop.def_physic = Fixed64::from_raw(snap.def_physic_raw);
+            cprop.def_magic = Fixed64::from_raw(snap.def_magic_raw);
+        }
+    }
+    if !full {
+        return;
+    }
+
+    if let (Some(gold), Some(g)) = (snap.gold, world.write_storage::<Gold>().get_mut(e)) {
+        g.0 = gold as _;
+    }
+    if let (Some(tower), Some(t)) = (&snap.tower, world.write_storage::<Tower>().get_mut(e)) {
+        t.upgrade_levels = tower.upgrade_levels;
+    }
+    if let (Some(hero), Some(h)) = (&snap.hero, world.write_storage::<Hero>().get_mut(e)) {
+        h.level = hero.level;
+        h.ability_levels = hero.ability_levels.iter().cloned().collect();
+        h.ability_cooldowns = hero
+            .ability_cooldowns_raw
+            .iter()
+            .map(|(id, raw)| (id.clone(), Fixed64::from_raw(*raw)))
+            .collect();
+    }
+    if let Some(slots) = &snap.inventory {
+        if let Some(inv) = world.write_storage::<Inventory>().get_mut(e) {
+            for (slot, saved) in inv.slots.iter_mut().zip(slots) {
+                *slot = saved.as_ref().map(|s| ItemInstance {
+                    item_id: s.item_id.clone(),
+                    cooldown_remaining: s.cooldown_remaining,
+                });
+            }
+            if let Some(eff) = world.write_storage::<ItemEffects>().get_mut(e) {
+                eff.dirty = true;
+            }
+        }
+    }
+    if let Some(mut store) = world.try_fetch_mut::<BuffStore>() {
+        store.remove_all_for(e);
+        for buff in &snap.buffs {
+            let payload = serde_json::from_str(&buff.payload_json)
+                .unwrap_or_else(|_| serde_json::Value::Object(Default::default()));
+            store.add(
+                e,
+                &buff.buff_id,
+                Fixed64::from_raw(buff.remaining_raw),
+                payload,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lockstep::snapshot_producer::{capture_snapshot, serialize_snapshot};
+
+    fn make_world() -> World {
+        let mut w = World::new();
+        w.register::<Pos>();
+        w.register::<Vel>();
+        w.register::<Facing>();
+        w.register::<CProperty>();
+        w.register::<Hero>();
+        w.register::<Tower>();
+        w.register::<Projectile>();
+        w.register::<Creep>();
+        w.register::<CollisionRadius>();
+        w.register::<ScriptUnitTag>();
+        w.register::<Gold>();
+        w.register::<Inventory>();
+        w.register::<ItemEffects>();
+        w.insert(Tick(0));
+        w.insert(MasterSeed::default());
+        w.insert(Time(0.0));
+        w.insert(TimeOfDay(0.0));
+        w
+    }
+
+    fn cprop(hp: i32) -> CProperty {
+        CProperty {
+            hp: Fixed64::from_i32(hp),
+            mhp: Fixed64::from_i32(100),
+            msd: Fixed64::ZERO,
+            def_physic: Fixed64::ZERO,
+            def_magic: Fixed64::ZERO,
+        }
+    }
+
+    fn pos_xy(x: i32, y: i32) -> Pos {
+        Pos(SimVec2 {
+            x: Fixed64::from_i32(x),
+            y: Fixed64::from_i32(y),
+        })
+    }
+
+    #[test]
+    fn restores_components_and_deletes_extra_entities() {
+        let mut w = make_world();
+        let kept = w
+            .create_entity()
+            .with(pos_xy(1, 1))
+            .with(cprop(90))
+            .with(Gold(100))
+            .build();
+        w.insert(Tick(500));
+        w.insert(MasterSeed(0x55));
+        let bytes = serialize_snapshot(&w);
+
+        // 快照之後世界繼續變化：扣血、花錢、多生一個實體。
+        w.write_storage::<CProperty>().get_mut(kept).unwrap().hp = Fixed64::from_i32(10);
+        w.write_storage::<Gold>().get_mut(kept).unwrap().0 = 5;
+        w.write_storage::<Pos>().insert(kept, pos_xy(9, 9)).unwrap();
+        let extra = w.create_entity().with(pos_xy(3, 3)).build();
+        w.insert(Tick(900));
+
+        let report = restore_snapshot(&mut w, &bytes).expect("restore");
+        assert!(report.is_exact());
+        assert_eq!(report.tick, 500);
+        assert_eq!(report.updated, 1);
+        assert_eq!(report.deleted, 1);
+        assert!(!w.is_alive(extra));
+        assert_eq!(w.read_resource::<Tick>().0, 500);
+        assert_eq!(
+            w.read_storage::<CProperty>().get(kept).unwrap().hp,
+            Fixed64::from_i32(90)
+        );
+        assert_eq!(w.read_storage::<Gold>().get(kept).unwrap().0, 100);
+        let restored = w.read_storage::<Pos>().get(kept).unwrap().0;
+        assert_eq!(restored.x, Fixed64::from_i32(1));
+        assert_eq!(restored.y, Fixed64::from_i32(1));
+    }
+
+    #[test]
+    fn restore_round_trips_state_hash() {
+        let mut w = make_world();
+        let e = w
+            .create_entity()
+            .with(pos_xy(4, 2))
+            .with(Vel(SimVec2 {
+                x: Fixed64::from_i32(1),
+                y: Fixed64::ZERO,
+            }))
+            .with(Facing(Angle::from_ticks(77)))
+            .with(cprop(60))
+            .build();
+        let expected = crate::lockstep::compute_state_hash(&w);
+        let snapshot = capture_snapshot(&w);
+
+        w.write_storage::<Vel>().get_mut(e).unwrap().0 = SimVec2 {
+            x: Fixed64::ZERO,
+            y: Fixed64::ZERO,
+        };
+        w.write_storage::<Facing>().get_mut(e).unwrap().0 = Angle::from_ticks(0);
+        assert_ne!(crate::lockstep::compute_state_hash(&w), expected);
+
+        restore_world_snapshot(&mut w, &snapshot);
+        assert_eq!(crate::lockstep::compute_state_hash(&w), expected);
+    }
+
+    #[test]
+    fn missing_projectiles_are_rebuilt_with_remapped_targets() {
+        let mut src = make_world();
+        let owner = src.create_entity().with(pos_xy(0, 0)).build();
+        let target = src
+            .create_entity()
+            .with(pos_xy(9, 0))
+            .with(cprop(40))
+            .build();
+        src.create_entity()
+            .with(pos_xy(4, 0))
+            .with(Projectile {
+                time_left: Fixed64::from_i32(2),
+                owner,
+                target: Some(target),
+                tpos: SimVec2 {
+                    x: Fixed64::from_i32(9),
+                    y: Fixed64::ZERO,
+                },
+                radius: Fixed64::from_i32(5),
+                msd: Fixed64::from_i32(500),
+                damage_phys: Fixed64::from_i32(25),
+                damage_magi: Fixed64::ZERO,
+                damage_real: Fixed64::ZERO,
+                slow_factor: Fixed64::ZERO,
+                slow_duration: Fixed64::ZERO,
+                hit_radius: Fixed64::ZERO,
+                stun_duration: Fixed64::ZERO,
+                kind_id: 0,
+                generation: 0,
+                damage_profile: 0,
+            })
+            .build();
+        let bytes = serialize_snapshot(&src);
+
+        // 目標世界只有 owner 與 target（同 id），投射物需重建。
+        let mut dst = make_world();
+        dst.create_entity().with(pos_xy(0, 0)).build();
+        let dst_target = dst
+            .create_entity()
+            .with(pos_xy(7, 7))
+            .with(cprop(1))
+            .build();
+        let report = restore_snapshot(&mut dst, &bytes).expect("restore");
+        assert!(report.unresolved.is_empty());
+
+        let projectiles = dst.read_storage::<Projectile>();
+        let positions = dst.read_storage::<Pos>();
+        let (p, pos) = (&projectiles, &positions)
+            .join()
+            .next()
+            .expect("projectile rebuilt");
+        assert_eq!(p.target, Some(dst_target));
+        assert_eq!(p.damage_phys, Fixed64::from_i32(25));
+        assert_eq!(pos.0.x, Fixed64::from_i32(4));
+    }
+
+    #[test]
+    fn rewind_lockstep_restarts_the_tick_timeline() {
+        let state = Mutex::new(LockstepState::new(0x1234));
+        state.lock().unwrap().current_tick = 900;
+        let input_buffer = Mutex::new(InputBuffer::new());
+        input_buffer.lock().unwrap().submit(
+            900,
+            1,
+            905,
+            crate::lockstep::PlayerInput::default(),
+            1,
+        );
+        let history = Mutex::new(TickHistory::new(8));
+        history.lock().unwrap().push(crate::lockstep::TickBatch {
+            tick: 900,
+            ..Default::default()
+        });
+
+        rewind_lockstep(500, &state, &input_buffer, &history);
+        assert_eq!(state.lock().unwrap().current_tick, 500);
+        assert_eq!(input_buffer.lock().unwrap().pending_count(), 0);
+        assert!(history.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reused_entity_id_with_another_generation_is_not_overwritten() {
+        let mut src = make_world();
+        let original = src.create_entity().with(pos_xy(1, 1)).build();
+        let bytes = serialize_snapshot(&src);
+
+        // 目標世界的同一 id 已被刪除後重用（generation 不同）。
+        let mut dst = make_world();
+        let first = dst.create_entity().with(pos_xy(5, 5)).build();
+        dst.delete_entity(first).unwrap();
+        dst.maintain();
+        let reused = dst.create_entity().with(pos_xy(5, 5)).build();
+        assert_eq!(reused.id(), original.id());
+        assert_ne!(reused.gen(), original.gen());
+
+        let report = restore_snapshot(&mut dst, &bytes).expect("restore");
+        assert!(!dst.is_alive(reused));
+        assert_eq!(report.deleted, 1);
+        assert_eq!(report.updated, 0);
+        // 一般實體沒有重建資料。
+        assert_eq!(report.unresolved, vec![original.id()]);
+    }
+
+    #[test]
+    fn missing_hero_without_template_is_reported_unresolved() {
+        use crate::lockstep::snapshot_producer::HeroSnapshot;
+
+        let mut src = make_world();
+        let hero = src.create_entity().with(pos_xy(0, 0)).build();
+        let mut snapshot = capture_snapshot(&src);
+        snapshot.entities[0].kind = EntityKindTag::Hero;
+        snapshot.entities[0].hero = Some(HeroSnapshot {
+            hero_id: "no_such_hero".to_string(),
+            level: 3,
+            ability_levels: Vec::new(),
+            ability_cooldowns_raw: Vec::new(),
+        });
+
+        // 目標世界沒有英雄模板表，`respawn_hero` 無從重建。
+        let mut dst = make_world();
+        let report = restore_world_snapshot(&mut dst, &snapshot);
+        assert_eq!(report.unresolved, vec![hero.id()]);
+        assert_eq!(report.deleted, 0);
+    }
+
+    #[test]
+    fn missing_creeps_are_reported_unresolved() {
+        let mut src = make_world();
+        let creep = src
+            .create_entity()
+            .with(pos_xy(0, 0))
+            .with(cprop(50))
+            .build();
+        let bytes = serialize_snapshot(&src);
+
+        let mut dst = make_world();
+        let report = restore_snapshot(&mut dst, &bytes).expect("restore");
+        assert_eq!(report.unresolved, vec![creep.id()]);
+        assert!(!report.is_exact());
+    }
+}
@@ -0,0 +1,107 @@
+//! 階段 6.11：觀察者延遲串流。
+//!
+//! 觀察者（`JoinRoleEnum::Observer`）收到的 lockstep 幀一律延後
+//! `delay_ticks` 刻度釋出，避免直播/賽事觀戰洩漏即時資訊。傳輸層在
+//! 每個 `TickBatch` 刻度把要給觀察者的幀推入 `SpectatorDelay`，再以目前
+//! 刻度 `release` 出已到期的幀送給觀察者會話。
+//!
+//! 單播項目（例如觀察者的 bootstrap 快照）以快照刻度 + 延遲排程，
+//! 因此恰好在延遲串流播到該刻度時送達，之後的批次自然銜接。
+
+use std::collections::BTreeMap;
+
+/// 延遲釋出的佇列。`T` 通常是已編碼的幀。
+pub struct SpectatorDelay<T> {
+    delay_ticks: u32,
+    /// `release_tick → [(目標會話, 幀)]`；目標為 `None` 表示所有觀察者。
+    pending: BTreeMap<u32, Vec<(Option<String>, T)>>,
+}
+
+impl<T> SpectatorDelay<T> {
+    pub fn new(delay_ticks: u32) -> Self {
+        Self {
+            delay_ticks,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn delay_ticks(&self) -> u32 {
+        self.delay_ticks
+    }
+
+    /// 尚未釋出的幀數。
+    pub fn len(&self) -> usize {
+        self.pending.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// 排程一個給所有觀察者、屬於 `tick` 的幀。
+    pub fn push_broadcast(&mut self, tick: u32, item: T) {
+        self.push(tick, None, item);
+    }
+
+    /// 排程一個只給 `session_id` 的幀。
+    pub fn push_unicast(&mut self, tick: u32, session_id: String, item: T) {
+        self.push(tick, Some(session_id), item);
+    }
+
+    fn push(&mut self, tick: u32, target: Option<String>, item: T) {
+        let release = tick.saturating_add(self.delay_ticks);
+        self.pending.entry(release).or_default().push((target, item));
+    }
+
+    /// 取出 `now_tick` 時已到期的幀，依釋出刻度、推入順序排列。
+    pub fn release(&mut self, now_tick: u32) -> Vec<(Option<String>, T)> {
+        let later = self.pending.split_off(&now_tick.saturating_add(1));
+        let due = std::mem::replace(&mut self.pending, later);
+        due.into_values().flatten().collect()
+    }
+
+    /// 觀察者在 `now_tick` 最多可以看到的刻度。
+    pub fn visible_tick(&self, now_tick: u32) -> u32 {
+        now_tick.saturating_sub(self.delay_ticks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_released_after_delay() {
+        let mut q = SpectatorDelay::new(3);
+        q.push_broadcast(1, "b1");
+        q.push_broadcast(2, "b2");
+        assert!(q.release(3).is_empty());
+        assert_eq!(q.release(4), vec![(None, "b1")]);
+        assert_eq!(q.release(10), vec![(None, "b2")]);
+        assert!(q.is_empty());
+        assert_eq!(q.visible_tick(10), 7);
+    }
+
+    #[test]
+    fn unicast_snapshot_lines_up_with_delayed_batches() {
+        let mut q = SpectatorDelay::new(5);
+        for tick in 1..=8 {
+            q.push_broadcast(tick, format!("b{tick}"));
+        }
+        // 觀察者在刻度 8 加入，快照屬於刻度 6。
+        q.push_unicast(6, "obs".into(), "snap6".to_string());
+        assert_eq!(q.len(), 9);
+
+        let due = q.release(11);
+        let order: Vec<&str> = due.iter().map(|(_, f)| f.as_str()).collect();
+        assert_eq!(order, vec!["b1", "b2", "b3", "b4", "b5", "b6", "snap6"]);
+        assert_eq!(due.last().unwrap().0.as_deref(), Some("obs"));
+    }
+
+    #[test]
+    fn zero_delay_releases_immediately() {
+        let mut q = SpectatorDelay::new(0);
+        q.push_broadcast(7, 1u8);
+        assert_eq!(q.release(7), vec![(None, 1u8)]);
+    }
+}
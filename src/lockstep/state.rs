@@ -4,8 +4,55 @@
 //! - `TickBroadcaster` 任務（每個刻度推進 `current_tick`），
 //! - kcp 傳輸（任務 2.3）JoinRequest 處理程序（註冊玩家），
 //! - 遊戲循環（讀取確定性 SimRng 流的 master_seed）。
+//!
+//! 階段 6.4：另外記錄伺服器廣播的 `StateHash` 與各客戶端回報的
+//! 雜湊（0x19 ClientStateHash），逐刻比對並累計不同步統計。
+//!
+//! 階段 6.9：玩家座位附帶重連 token。會話斷線時座位進入寬限狀態
+//! （`resume_grace_ticks`），期間帶 token 的 JoinRequest 可以接回同一個
+//! `player_id`；寬限結束仍未接回才真正移除。
+//!
+//! 階段 6.12：玩家座位的加入 / 釋放會暫存成 `ServerEvent`，由
+//! `TickBroadcaster` 放進下一個 `TickBatch.server_events`。
+//!
+//! 階段 6.13：`check_lag` 依 `last_ack_tick`（TickAck）與 ping 量到的
+//! RTT 偵測落後玩家，排定 / 解除 `Pause` 策略的暫停刻度並標記
+//! `DropToAi` 的代管座位，見 `lockstep::lag`。
+//!
+//! 階段 6.14：每個座位保留 `LatencyStats`（平滑 RTT / 抖動 / 晚到輸入
+//! 計數），用來建議輸入提前量並放寬該玩家的晚到寬限。RTT 由伺服器自己
+//! 量：kcp 的 ping 處理在 `PingResponse` 附上 `PingProbe`，客戶端帶回時
+//! 以往返時間呼叫 `record_ping_rtt`。
+//!
+//! 階段 6.15：`validate_input` 在輸入進入 `InputBuffer` 前做速率與語意
+//! 檢查，見 `lockstep::input_validation`。
+//!
+//! 階段 6.24：`submit_chat` 驗證玩家聊天 / 地圖標記並暫存，由
+//! `TickBroadcaster` 寫進 replay 後送出，見 `lockstep::chat`。
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::lockstep::chat::{
+    check_ping, clean_chat_text, ChatLimits, ChatMessage, ChatRateWindow, ChatRejection, ChatScope,
+};
+use crate::lockstep::input_validation::{
+    check_input, InputLimits, InputRateWindow, InputRejection,
+};
+use crate::lockstep::lag::{
+    ai_input, LagPause, LagPolicy, LagReport, LagTransition, LAG_PAUSE_LEAD_TICKS,
+};
+use crate::lockstep::latency::LatencyStats;
+use crate::lockstep::wire::{ChatChannel, ChatRejectReason, ChatSend, InputLeadHint};
+use crate::lockstep::{server_events, InputSubmitResult, PlayerInput, ServerEvent};
 
-use std::collections::BTreeMap;
+/// 階段 6.11：觀察者不佔玩家座位，改由伺服器從此值起分配 id，
+/// 避免多個觀察者共用客戶端送來的 0 而互相覆蓋。
+pub const OBSERVER_ID_BASE: u32 = 0x8000_0000;
+
+/// 保留最近幾個 state-hash 刻度的伺服器雜湊，以及每名玩家尚未比對的
+/// 回報。雜湊每 10 秒一次，32 筆約 5 分鐘，足以容納最慢的客戶端回報。
+pub const STATE_HASH_HISTORY_LEN: usize = 32;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum JoinRoleEnum {
@@ -18,10 +65,67 @@ pub struct PlayerSession {
     pub player_id: u32,
     pub player_name: String,
     pub role: JoinRoleEnum,
-    /// 該玩家最近成功提交的target_tick
-    /// 為了。用於偵測卡住的客戶端（階段 3+ 可能會暫停滴答循環
-    /// 當玩家的滯後超過預算時）。
+    /// 該玩家最近被接受輸入的生效刻度（加入 / 接回時為當下刻度）。
     pub last_input_tick: u32,
+    /// 階段 6.13：客戶端 `TickAck` 確認過的最後刻度（加入 / 接回時為當下
+    /// 刻度）。`check_lag` 以此偵測卡住的客戶端。
+    pub last_ack_tick: u32,
+    /// 階段 6.14：延遲統計。
+    pub latency: LatencyStats,
+    /// 階段 6.13：目前是否超出落後預算。
+    pub lagging: bool,
+    /// 階段 6.13：`DropToAi` 策略下由伺服器代管，見 `lag::ai_input`。
+    pub ai_controlled: bool,
+    /// 階段 6.15：輸入速率計數。
+    pub input_rate: InputRateWindow,
+    /// 階段 6.24：聊天速率計數。
+    pub chat_rate: ChatRateWindow,
+    /// 階段 6.9：重連 token，隨 GameStart 發給客戶端；每次接回都換新。
+    /// 觀察者沒有座位，token 為空。
+    pub resume_token: String,
+    /// 階段 6.9：座位目前綁定的連線代數。舊連線晚一步斷線時，代數不符
+    /// 的 `disconnect_player` 會被忽略，不會把新連線的座位掛起。
+    pub binding: u32,
+    /// 階段 6.9：斷線後保留座位到此刻度；`None` 表示連線中。
+    pub suspended_until_tick: Option<u32>,
+}
+
+impl PlayerSession {
+    pub fn is_suspended(&self) -> bool {
+        self.suspended_until_tick.is_some()
+    }
+}
+
+/// 階段 6.9：成功加入 / 接回座位的結果。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeatGrant {
+    pub player_id: u32,
+    /// 交給 `disconnect_player`，用來辨識是哪一條連線斷的。
+    pub binding: u32,
+    pub resume_token: String,
+}
+
+/// 某個 state-hash 刻度上偵測到的不同步。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DesyncReport {
+    /// 調度器刻度（與 `StateHash.tick` 相同）。
+    pub tick: u32,
+    pub server_hash: u64,
+    /// `(player_id, 客戶端回報的雜湊)`，只列出與伺服器不同者。
+    pub diverged: Vec<(u32, u64)>,
+}
+
+/// 不同步統計，供 playtest 估算不同步率。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DesyncStats {
+    /// 已與伺服器雜湊比對過的客戶端回報數。
+    pub reports_checked: u64,
+    /// 其中雜湊不一致的回報數。
+    pub reports_mismatched: u64,
+    /// 至少一名玩家不一致的刻度數。
+    pub desynced_ticks: u64,
+    /// 刻度超出範圍（晚於目前刻度或早於最舊的伺服器雜湊）而丟棄的回報數。
+    pub reports_rejected: u64,
 }
 
 pub struct LockstepState {
@@ -32,6 +136,41 @@ pub struct LockstepState {
     /// `SimRng::from_master_*` 建構子。必須匹配所有同行。
     pub master_seed: u64,
     pub players: BTreeMap<u32, PlayerSession>,
+    /// 階段 6.9：斷線玩家保留座位的刻度數。0 表示斷線立即移除
+    /// （舊行為）。
+    pub resume_grace_ticks: u32,
+    /// 階段 6.11：觀察者串流延後的刻度數。0 表示不延遲。
+    pub spectator_delay_ticks: u32,
+    /// 階段 6.11：同時在線的觀察者上限。
+    pub max_observers: usize,
+    next_observer_id: u32,
+    /// 階段 6.13：目前刻度領先玩家確認刻度超過此數即視為落後；0 表示
+    /// 不檢查。
+    pub lag_ack_budget_ticks: u32,
+    /// 階段 6.13：平滑 RTT 超過此數（微秒）即視為落後；0 表示不檢查。
+    pub lag_rtt_budget_us: u32,
+    /// 階段 6.13：玩家落後時的處理方式。
+    pub lag_policy: LagPolicy,
+    /// 階段 6.13：`Pause` 策略已宣告暫停、尚未宣告解除。
+    lag_paused: bool,
+    /// 階段 6.15：輸入驗證上限，預設不限制。
+    pub input_limits: InputLimits,
+    /// 階段 6.24：聊天上限，預設不限制。
+    pub chat_limits: ChatLimits,
+    /// 階段 6.12：尚未放進 TickBatch 的加入 / 離開事件。
+    pending_server_events: Vec<ServerEvent>,
+    /// 階段 6.4：伺服器廣播過的 `(tick → hash)`。
+    server_hashes: BTreeMap<u32, u64>,
+    /// 階段 6.4：`player_id → (tick → 回報雜湊)`，尚未比對的回報。每名
+    /// 玩家各自保留最多 `STATE_HASH_HISTORY_LEN` 筆，互不擠掉。
+    client_hashes: BTreeMap<u32, BTreeMap<u32, u64>>,
+    /// 已標記不同步的刻度，讓 `desynced_ticks` 統計每刻只算一次。
+    desynced_ticks: BTreeSet<u32>,
+    desync_stats: DesyncStats,
+    /// 尚未被 `TickBroadcaster` 取走（寫 replay 標記 / 快照傾印）的不同步。
+    pending_desyncs: Vec<DesyncReport>,
+    /// 階段 6.24：尚未被 `TickBroadcaster` 取走（寫 replay / 送出）的聊天。
+    pending_chats: Vec<ChatMessage>,
 }
 
 impl LockstepState {
@@ -40,6 +179,23 @@ impl LockstepState {
             current_tick: 0,
             master_seed,
             players: BTreeMap::new(),
+            resume_grace_ticks: 0,
+            spectator_delay_ticks: 0,
+            max_observers: usize::MAX,
+            next_observer_id: OBSERVER_ID_BASE,
+            lag_ack_budget_ticks: 0,
+            lag_rtt_budget_us: 0,
+            lag_policy: LagPolicy::default(),
+            lag_paused: false,
+            input_limits: InputLimits::default(),
+            chat_limits: ChatLimits::default(),
+            pending_server_events: Vec::new(),
+            server_hashes: BTreeMap::new(),
+            client_hashes: BTreeMap::new(),
+            desynced_ticks: BTreeSet::new(),
+            desync_stats: DesyncStats::default(),
+            pending_desyncs: Vec::new(),
+            pending_chats: Vec::new(),
         }
     }
 
@@ -49,30 +205,510 @@ impl LockstepState {
         name: String,
         role: JoinRoleEnum,
     ) -> Result<u32, String> {
+        self.join_player(player_id, name, role)
+            .map(|grant| grant.player_id)
+    }
+
+    /// 階段 6.9：同 `register_player`，另外回傳連線代數與重連 token。
+    pub fn join_player(
+        &mut self,
+        player_id: u32,
+        name: String,
+        role: JoinRoleEnum,
+    ) -> Result<SeatGrant, String> {
         if role == JoinRoleEnum::Player && player_id == 0 {
             return Err("player join missing non-zero client-declared player_id".to_string());
         }
-        if role == JoinRoleEnum::Player && self.players.contains_key(&player_id) {
-            return Err(format!(
-                "player_id {} already has an active session",
-                player_id
-            ));
+        // 階段 6.11：觀察者忽略自報 id，改配發專屬 id，並受上限保護。
+        let player_id = match role {
+            JoinRoleEnum::Player => player_id,
+            JoinRoleEnum::Observer => {
+                if self.observer_count() >= self.max_observers {
+                    return Err(format!(
+                        "observer cap reached ({} sessions)",
+                        self.max_observers
+                    ));
+                }
+                let id = self.next_observer_id;
+                self.next_observer_id = self.next_observer_id.wrapping_add(1).max(OBSERVER_ID_BASE);
+                id
+            }
+        };
+        if role == JoinRoleEnum::Player {
+            if let Some(existing) = self.players.get(&player_id) {
+                return Err(if existing.is_suspended() {
+                    format!(
+                        "player_id {} is reserved for reconnect; present its resume token",
+                        player_id
+                    )
+                } else {
+                    format!("player_id {} already has an active session", player_id)
+                });
+            }
         }
+        let resume_token = match role {
+            JoinRoleEnum::Player => new_resume_token(),
+            JoinRoleEnum::Observer => String::new(),
+        };
         let id = player_id;
+        if role == JoinRoleEnum::Player {
+            self.pending_server_events
+                .push(server_events::player_join(id, &name));
+        }
         self.players.insert(
             id,
             PlayerSession {
                 player_id: id,
                 player_name: name,
                 role,
-                last_input_tick: 0,
+                last_input_tick: self.current_tick,
+                last_ack_tick: self.current_tick,
+                latency: LatencyStats::default(),
+                lagging: false,
+                ai_controlled: false,
+                input_rate: InputRateWindow::default(),
+                chat_rate: ChatRateWindow::default(),
+                resume_token: resume_token.clone(),
+                binding: 0,
+                suspended_until_tick: None,
             },
         );
-        Ok(id)
+        Ok(SeatGrant {
+            player_id: id,
+            binding: 0,
+            resume_token,
+        })
+    }
+
+    /// 階段 6.9：以重連 token 接回座位。舊連線可能還沒被偵測斷線，
+    /// 因此連線中的座位也可以被接手；代數遞增、token 換新。
+    pub fn resume_player(&mut self, resume_token: &str) -> Result<SeatGrant, String> {
+        if resume_token.is_empty() {
+            return Err("empty resume token".to_string());
+        }
+        let session = self
+            .players
+            .values_mut()
+            .find(|p| p.role == JoinRoleEnum::Player && p.resume_token == resume_token)
+            .ok_or_else(|| "unknown or expired resume token".to_string())?;
+        session.binding = session.binding.wrapping_add(1);
+        session.suspended_until_tick = None;
+        session.last_input_tick = self.current_tick;
+        session.last_ack_tick = self.current_tick;
+        session.resume_token = new_resume_token();
+        Ok(SeatGrant {
+            player_id: session.player_id,
+            binding: session.binding,
+            resume_token: session.resume_token.clone(),
+        })
+    }
+
+    /// 階段 6.11：對局中的玩家座位數（含寬限中的斷線座位），不含觀察者。
+    pub fn player_count(&self) -> usize {
+        self.players
+            .values()
+            .filter(|p| p.role == JoinRoleEnum::Player)
+            .count()
+    }
+
+    pub fn observer_count(&self) -> usize {
+        self.players
+            .values()
+            .filter(|p| p.role == JoinRoleEnum::Observer)
+            .count()
     }
 
     pub fn unregister_player(&mut self, player_id: u32) {
-        self.players.remove(&player_id);
+        self.release_seat(player_id);
+    }
+
+    /// 移除座位；玩家座位另外記一筆離開事件。
+    fn release_seat(&mut self, player_id: u32) {
+        self.client_hashes.remove(&player_id);
+        if let Some(session) = self.players.remove(&player_id) {
+            if session.role == JoinRoleEnum::Player {
+                self.pending_server_events
+                    .push(server_events::player_leave(player_id));
+            }
+        }
+    }
+
+    /// 階段 6.12：取走尚未放進 TickBatch 的加入 / 離開事件。
+    pub fn take_server_events(&mut self) -> Vec<ServerEvent> {
+        std::mem::take(&mut self.pending_server_events)
+    }
+
+    /// 階段 6.9：連線代數 `binding` 的會話斷線。玩家座位進入寬限狀態
+    /// （`resume_grace_ticks` 為 0 時直接移除），觀察者直接移除。
+    /// 代數不符（座位已被新連線接回）時不做事。
+    pub fn disconnect_player(&mut self, player_id: u32, binding: u32) {
+        let Some(session) = self.players.get_mut(&player_id) else {
+            return;
+        };
+        if session.binding != binding {
+            return;
+        }
+        if session.role == JoinRoleEnum::Observer || self.resume_grace_ticks == 0 {
+            self.release_seat(player_id);
+            return;
+        }
+        session.suspended_until_tick =
+            Some(self.current_tick.wrapping_add(self.resume_grace_ticks));
+    }
+
+    /// 階段 6.10：確認 InputSubmit 來自加入該座位的連線。`session` 為
+    /// 連線加入時拿到的 `(player_id, binding)`；通過時回傳該 player_id。
+    /// payload 自報的 `claimed_player_id` 必須與連線相符，觀察者一律拒絕。
+    pub fn authorize_input(
+        &self,
+        session: Option<(u32, u32)>,
+        claimed_player_id: u32,
+    ) -> Result<u32, String> {
+        let (player_id, binding) =
+            session.ok_or_else(|| "session has not joined the lockstep stream".to_string())?;
+        let seat = self
+            .players
+            .get(&player_id)
+            .ok_or_else(|| format!("player_id {} has no seat", player_id))?;
+        if seat.role == JoinRoleEnum::Observer {
+            return Err("observers cannot submit inputs".to_string());
+        }
+        if seat.binding != binding {
+            return Err(format!(
+                "player_id {} was resumed by another connection",
+                player_id
+            ));
+        }
+        if claimed_player_id != player_id {
+            return Err(format!(
+                "claims player_id {} but session joined as {}",
+                claimed_player_id, player_id
+            ));
+        }
+        Ok(player_id)
+    }
+
+    /// 階段 6.15：已授權的輸入在進入緩衝區前的速率與語意檢查。
+    /// 被拒絕的輸入同樣計入速率。
+    pub fn validate_input(
+        &mut self,
+        player_id: u32,
+        target_tick: u32,
+        input: &PlayerInput,
+    ) -> Result<(), InputRejection> {
+        let now = self.current_tick;
+        let limits = self.input_limits;
+        if let Some(session) = self.players.get_mut(&player_id) {
+            session.input_rate.admit(now, &limits)?;
+        }
+        check_input(input, now, target_tick, &limits)
+    }
+
+    /// 階段 6.13：記錄被接受輸入的生效刻度（只增不減）。
+    pub fn record_input_tick(&mut self, player_id: u32, tick: u32) {
+        if let Some(session) = self.players.get_mut(&player_id) {
+            session.last_input_tick = session.last_input_tick.max(tick);
+        }
+    }
+
+    /// 階段 6.13：記錄客戶端 `TickAck` 確認的刻度（只增不減）。
+    pub fn record_tick_ack(&mut self, player_id: u32, acked_tick: u32) {
+        if let Some(session) = self.players.get_mut(&player_id) {
+            session.last_ack_tick = session.last_ack_tick.max(acked_tick);
+        }
+    }
+
+    /// 階段 6.14：記錄 ping 處理以 `PingProbe` 往返量到的 RTT 樣本。
+    pub fn record_ping_rtt(&mut self, player_id: u32, rtt_us: u32) {
+        if let Some(session) = self.players.get_mut(&player_id) {
+            session.latency.record_rtt(rtt_us);
+        }
+    }
+
+    /// 階段 6.14：記錄一次輸入提交的結果（生效刻度與晚到計數）。
+    pub fn record_input_result(&mut self, player_id: u32, result: &InputSubmitResult) {
+        if let Some(tick) = result.effective_tick() {
+            self.record_input_tick(player_id, tick);
+        }
+        if let Some(session) = self.players.get_mut(&player_id) {
+            session.latency.record_submit(result);
+        }
+    }
+
+    /// 階段 6.14：該玩家的晚到寬限（`base_ticks` 加上依抖動的放寬）。
+    pub fn late_grace_ticks(&self, player_id: u32, step_fps: u32, base_ticks: u32) -> u32 {
+        self.players
+            .get(&player_id)
+            .map(|p| p.latency.late_grace_ticks(step_fps, base_ticks))
+            .unwrap_or(base_ticks)
+    }
+
+    /// 階段 6.14：回覆 PingResponse 時附帶的建議提前量。
+    pub fn next_lead_hint(&mut self, player_id: u32, step_fps: u32) -> Option<InputLeadHint> {
+        self.players
+            .get_mut(&player_id)
+            .map(|p| p.latency.next_lead_hint(step_fps))
+    }
+
+    /// 階段 6.13：檢查每個連線中的玩家座位，回傳落後 / 恢復轉換以及
+    /// `Pause` 策略下暫停狀態的變化。斷線寬限中的座位維持原狀態，由重連 /
+    /// 釋放處理，也不會讓對局停住。
+    ///
+    /// 暫停 / 解除都排在 `目前刻度 + LAG_PAUSE_LEAD_TICKS` 生效。
+    pub fn check_lag(&mut self) -> LagReport {
+        let mut report = LagReport::default();
+        if self.lag_ack_budget_ticks == 0 && self.lag_rtt_budget_us == 0 {
+            return report;
+        }
+        let now = self.current_tick;
+        let (ack_budget, rtt_budget) = (self.lag_ack_budget_ticks, self.lag_rtt_budget_us);
+        let policy = self.lag_policy;
+        for session in self.players.values_mut() {
+            if session.role != JoinRoleEnum::Player || session.is_suspended() {
+                continue;
+            }
+            let ack_gap_ticks = now.saturating_sub(session.last_ack_tick);
+            let rtt_us = session.latency.srtt_us();
+            let lagging = (ack_budget > 0 && ack_gap_ticks > ack_budget)
+                || (rtt_budget > 0 && rtt_us > rtt_budget);
+            if lagging != session.lagging {
+                session.lagging = lagging;
+                session.ai_controlled = lagging && policy == LagPolicy::DropToAi;
+                report.transitions.push(LagTransition {
+                    player_id: session.player_id,
+                    lagging,
+                    policy,
+                    ack_gap_ticks,
+                    rtt_us,
+                });
+            }
+        }
+        let any_lagging = self
+            .players
+            .values()
+            .any(|p| p.lagging && !p.is_suspended());
+        let paused = policy == LagPolicy::Pause && any_lagging;
+        if paused != self.lag_paused {
+            self.lag_paused = paused;
+            report.pause = Some(LagPause {
+                paused,
+                tick: now.wrapping_add(LAG_PAUSE_LEAD_TICKS),
+            });
+        }
+        report
+    }
+
+    /// 階段 6.13：`DropToAi` 代管座位在刻度 `tick` 的伺服器輸入，依
+    /// `player_id` 排序。
+    pub fn ai_inputs(&self, tick: u32) -> Vec<(u32, PlayerInput)> {
+        let Some(input) = ai_input(tick) else {
+            return Vec::new();
+        };
+        self.players
+            .values()
+            .filter(|p| p.ai_controlled)
+            .map(|p| (p.player_id, input.clone()))
+            .collect()
+    }
+
+    /// 階段 6.9：移除寬限已過的座位，回傳被移除的 player_id。
+    pub fn expire_suspended(&mut self) -> Vec<u32> {
+        let now = self.current_tick;
+        let expired: Vec<u32> = self
+            .players
+            .values()
+            .filter(|p| p.suspended_until_tick.is_some_and(|until| now >= until))
+            .map(|p| p.player_id)
+            .collect();
+        for player_id in &expired {
+            self.release_seat(*player_id);
+        }
+        expired
+    }
+
+    /// 階段 6.6：快照還原後把刻度移到 `tick`。舊時間軸的雜湊紀錄一併
+    /// 清掉；座位的輸入計時從新刻度重新起算。
+    pub fn rewind_to_tick(&mut self, tick: u32) {
+        self.current_tick = tick;
+        self.server_hashes.clear();
+        self.client_hashes.clear();
+        self.desynced_ticks.clear();
+        self.pending_desyncs.clear();
+        self.lag_paused = false;
+        for seat in self.players.values_mut() {
+            seat.last_input_tick = tick;
+            seat.last_ack_tick = tick;
+        }
+    }
+
+    /// 階段 6.4：記錄伺服器於 `tick` 廣播的雜湊，並比對該刻度已收到的
+    /// 客戶端回報。
+    pub fn record_server_hash(&mut self, tick: u32, hash: u64) -> Option<DesyncReport> {
+        self.server_hashes.insert(tick, hash);
+        trim_history(&mut self.server_hashes);
+        let reports: BTreeMap<u32, u64> = self
+            .client_hashes
+            .iter_mut()
+            .filter_map(|(player_id, pending)| Some((*player_id, pending.remove(&tick)?)))
+            .collect();
+        self.check_reports(tick, hash, reports)
+    }
+
+    /// 階段 6.4：記錄客戶端回報。伺服器雜湊已知時立即比對，否則
+    /// 暫存到 `record_server_hash`。比對過的刻度之後再收到的回報也會
+    /// 立即比對並計入統計；該刻度已經產生過 `DesyncReport` 時不再重複
+    /// 產生（replay 標記與快照傾印每刻只做一次）。
+    ///
+    /// 晚於 `current_tick`（伺服器還沒廣播到）或早於最舊伺服器雜湊
+    /// （已無從比對）的回報直接丟棄，只計入 `reports_rejected`。
+    pub fn record_client_hash(
+        &mut self,
+        player_id: u32,
+        tick: u32,
+        hash: u64,
+    ) -> Option<DesyncReport> {
+        let oldest = self.server_hashes.keys().next().copied();
+        if tick > self.current_tick || oldest.is_some_and(|oldest| tick < oldest) {
+            log::warn!(
+                "dropping state hash report from player_id={} for tick {} (current {}, oldest server hash {:?})",
+                player_id,
+                tick,
+                self.current_tick,
+                oldest
+            );
+            self.desync_stats.reports_rejected += 1;
+            return None;
+        }
+        match self.server_hashes.get(&tick).copied() {
+            Some(server_hash) => {
+                let reports = BTreeMap::from([(player_id, hash)]);
+                self.check_reports(tick, server_hash, reports)
+            }
+            None => {
+                let pending = self.client_hashes.entry(player_id).or_default();
+                pending.insert(tick, hash);
+                trim_history(pending);
+                None
+            }
+        }
+    }
+
+    pub fn desync_stats(&self) -> DesyncStats {
+        self.desync_stats
+    }
+
+    /// 取走尚未處理的不同步紀錄。
+    pub fn take_pending_desyncs(&mut self) -> Vec<DesyncReport> {
+        std::mem::take(&mut self.pending_desyncs)
+    }
+
+    /// 階段 6.24：驗證一則聊天 / 地圖標記並暫存。`session` 同
+    /// `authorize_input`（觀察者一律拒絕）；`team_of` 以座位名稱查隊伍，
+    /// 隊伍頻道找不到隊伍時拒絕。被拒絕的聊天同樣計入速率。
+    pub fn submit_chat(
+        &mut self,
+        session: Option<(u32, u32)>,
+        send: &ChatSend,
+        team_of: impl Fn(&str) -> Option<u32>,
+    ) -> Result<ChatMessage, ChatRejection> {
+        let claimed = session.map(|(player_id, _)| player_id).unwrap_or(0);
+        let player_id = self
+            .authorize_input(session, claimed)
+            .map_err(|e| ChatRejection::new(ChatRejectReason::Unauthorized, e))?;
+        let now = self.current_tick;
+        let limits = self.chat_limits;
+        let bounds = self.input_limits.map_bounds;
+        let seat = self
+            .players
+            .get_mut(&player_id)
+            .ok_or_else(|| ChatRejection::new(ChatRejectReason::Unauthorized, "no seat"))?;
+        seat.chat_rate.admit(now, &limits)?;
+        let player_name = seat.player_name.clone();
+
+        let text = clean_chat_text(&send.text, &limits)?;
+        let ping = send.ping.as_ref().map(|p| (p.x, p.y));
+        if let Some((x, y)) = ping {
+            check_ping(x, y, bounds)?;
+        }
+        if text.is_empty() && ping.is_none() {
+            return Err(ChatRejection::new(
+                ChatRejectReason::Empty,
+                "chat has neither text nor a ping",
+            ));
+        }
+        let scope = match send.channel() {
+            ChatChannel::All => ChatScope::All,
+            ChatChannel::Team => team_of(&player_name).map(ChatScope::Team).ok_or_else(|| {
+                ChatRejection::new(
+                    ChatRejectReason::NoTeam,
+                    format!("{} is not on a team", player_name),
+                )
+            })?,
+        };
+        let msg = ChatMessage {
+            tick: now,
+            player_id,
+            player_name,
+            scope,
+            text,
+            ping,
+        };
+        self.pending_chats.push(msg.clone());
+        Ok(msg)
+    }
+
+    /// 階段 6.24：取走尚未送出的聊天，依送出順序。
+    pub fn take_pending_chats(&mut self) -> Vec<ChatMessage> {
+        std::mem::take(&mut self.pending_chats)
+    }
+
+    fn check_reports(
+        &mut self,
+        tick: u32,
+        server_hash: u64,
+        reports: BTreeMap<u32, u64>,
+    ) -> Option<DesyncReport> {
+        if reports.is_empty() {
+            return None;
+        }
+        self.desync_stats.reports_checked += reports.len() as u64;
+        let diverged: Vec<(u32, u64)> = reports
+            .into_iter()
+            .filter(|(_, hash)| *hash != server_hash)
+            .collect();
+        if diverged.is_empty() {
+            return None;
+        }
+        self.desync_stats.reports_mismatched += diverged.len() as u64;
+        if !self.desynced_ticks.insert(tick) {
+            log::warn!(
+                "late desync report for already-flagged tick {}: {:?}",
+                tick,
+                diverged
+            );
+            return None;
+        }
+        self.desync_stats.desynced_ticks += 1;
+        while self.desynced_ticks.len() > STATE_HASH_HISTORY_LEN {
+            self.desynced_ticks.pop_first();
+        }
+        let report = DesyncReport {
+            tick,
+            server_hash,
+            diverged,
+        };
+        self.pending_desyncs.push(report.clone());
+        Some(report)
+    }
+}
+
+fn new_resume_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+fn trim_history<V>(map: &mut BTreeMap<u32, V>) {
+    while map.len() > STATE_HASH_HISTORY_LEN {
+        map.pop_first();
     }
 }
 
@@ -112,4 +748,425 @@ mod tests {
             .is_err());
         assert_eq!(state.players.len(), 1);
     }
+
+    #[test]
+    fn disconnected_seat_can_be_resumed_within_grace() {
+        let mut state = LockstepState::new(0x1234);
+        state.resume_grace_ticks = 100;
+        let grant = state
+            .join_player(3, "p3".into(), JoinRoleEnum::Player)
+            .unwrap();
+        assert!(!grant.resume_token.is_empty());
+
+        state.current_tick = 50;
+        state.disconnect_player(3, grant.binding);
+        assert!(state.players[&3].is_suspended());
+        // 沒有 token 不能搶座位。
+        assert!(state
+            .join_player(3, "intruder".into(), JoinRoleEnum::Player)
+            .is_err());
+        assert!(state.resume_player("not-a-token").is_err());
+
+        let resumed = state.resume_player(&grant.resume_token).unwrap();
+        assert_eq!(resumed.player_id, 3);
+        assert_ne!(resumed.binding, grant.binding);
+        assert_ne!(resumed.resume_token, grant.resume_token);
+        assert!(!state.players[&3].is_suspended());
+        // 舊 token 作廢。
+        assert!(state.resume_player(&grant.resume_token).is_err());
+
+        // 舊連線晚一步斷線不影響新綁定。
+        state.disconnect_player(3, grant.binding);
+        assert!(!state.players[&3].is_suspended());
+    }
+
+    #[test]
+    fn suspended_seat_expires_after_grace() {
+        let mut state = LockstepState::new(0x1234);
+        state.resume_grace_ticks = 10;
+        let grant = state
+            .join_player(4, "p4".into(), JoinRoleEnum::Player)
+            .unwrap();
+        state.current_tick = 5;
+        state.disconnect_player(4, grant.binding);
+        state.current_tick = 14;
+        assert!(state.expire_suspended().is_empty());
+        state.current_tick = 15;
+        assert_eq!(state.expire_suspended(), vec![4]);
+        assert!(state.resume_player(&grant.resume_token).is_err());
+    }
+
+    #[test]
+    fn zero_grace_and_observers_unregister_immediately() {
+        let mut state = LockstepState::new(0x1234);
+        let player = state
+            .join_player(5, "p5".into(), JoinRoleEnum::Player)
+            .unwrap();
+        state.disconnect_player(5, player.binding);
+        assert!(!state.players.contains_key(&5));
+
+        state.resume_grace_ticks = 10;
+        let observer = state
+            .join_player(0, "obs".into(), JoinRoleEnum::Observer)
+            .unwrap();
+        assert!(observer.resume_token.is_empty());
+        state.disconnect_player(observer.player_id, observer.binding);
+        assert!(state.players.is_empty());
+    }
+
+    #[test]
+    fn validate_input_rate_limits_per_seat() {
+        use crate::lockstep::wire::InputRejectReason;
+        use crate::lockstep::{NoOp, PlayerInputEnum};
+
+        let mut state = LockstepState::new(0x1234);
+        state.input_limits.max_per_tick = 1;
+        state.input_limits.max_future_ticks = 10;
+        for id in [1, 2] {
+            state
+                .register_player(id, format!("p{id}"), JoinRoleEnum::Player)
+                .unwrap();
+        }
+        let noop = PlayerInput {
+            action: Some(PlayerInputEnum::NoOp(NoOp {})),
+        };
+        assert!(state.validate_input(1, 1, &noop).is_ok());
+        assert_eq!(
+            state.validate_input(1, 1, &noop).unwrap_err().reason,
+            InputRejectReason::RateLimitedTick
+        );
+        // 計數是每個座位各自的。
+        assert!(state.validate_input(2, 1, &noop).is_ok());
+        state.current_tick = 1;
+        assert_eq!(
+            state.validate_input(1, 12, &noop).unwrap_err().reason,
+            InputRejectReason::FarFuture
+        );
+    }
+
+    #[test]
+    fn authorize_input_binds_to_joined_seat() {
+        let mut state = LockstepState::new(0x1234);
+        let p1 = state
+            .join_player(1, "p1".into(), JoinRoleEnum::Player)
+            .unwrap();
+        state
+            .join_player(2, "p2".into(), JoinRoleEnum::Player)
+            .unwrap();
+        let obs = state
+            .join_player(0, "obs".into(), JoinRoleEnum::Observer)
+            .unwrap();
+
+        assert_eq!(state.authorize_input(Some((1, p1.binding)), 1), Ok(1));
+        // 觀察者用自己的 id 也不行。
+        assert!(state
+            .authorize_input(Some((obs.player_id, obs.binding)), obs.player_id)
+            .is_err());
+        // 冒用其他玩家的 id。
+        assert!(state.authorize_input(Some((1, p1.binding)), 2).is_err());
+        // 尚未加入的連線。
+        assert!(state.authorize_input(None, 1).is_err());
+
+        // 座位被新連線接回後，舊連線失效。
+        let resumed = state.resume_player(&p1.resume_token).unwrap();
+        assert!(state.authorize_input(Some((1, p1.binding)), 1).is_err());
+        assert_eq!(state.authorize_input(Some((1, resumed.binding)), 1), Ok(1));
+    }
+
+    #[test]
+    fn observers_get_own_ids_and_respect_cap() {
+        let mut state = LockstepState::new(0x1234);
+        state.max_observers = 2;
+        state
+            .join_player(1, "p1".into(), JoinRoleEnum::Player)
+            .unwrap();
+        let a = state
+            .join_player(0, "a".into(), JoinRoleEnum::Observer)
+            .unwrap();
+        let b = state
+            .join_player(0, "b".into(), JoinRoleEnum::Observer)
+            .unwrap();
+        assert_ne!(a.player_id, b.player_id);
+        assert!(a.player_id >= OBSERVER_ID_BASE);
+        assert!(state
+            .join_player(0, "c".into(), JoinRoleEnum::Observer)
+            .is_err());
+        assert_eq!(state.player_count(), 1);
+        assert_eq!(state.observer_count(), 2);
+
+        state.disconnect_player(a.player_id, a.binding);
+        assert!(state
+            .join_player(0, "c".into(), JoinRoleEnum::Observer)
+            .is_ok());
+    }
+
+    #[test]
+    fn seat_changes_queue_server_events() {
+        let mut state = LockstepState::new(0x1234);
+        state.resume_grace_ticks = 10;
+        let p1 = state
+            .join_player(1, "p1".into(), JoinRoleEnum::Player)
+            .unwrap();
+        let obs = state
+            .join_player(0, "obs".into(), JoinRoleEnum::Observer)
+            .unwrap();
+        assert_eq!(
+            state.take_server_events(),
+            vec![server_events::player_join(1, "p1")]
+        );
+
+        // 寬限中斷線、接回都不算離開；觀察者不產生事件。
+        state.disconnect_player(1, p1.binding);
+        let resumed = state.resume_player(&p1.resume_token).unwrap();
+        state.disconnect_player(obs.player_id, obs.binding);
+        assert!(state.take_server_events().is_empty());
+
+        state.disconnect_player(1, resumed.binding);
+        state.current_tick = 100;
+        state.expire_suspended();
+        assert_eq!(
+            state.take_server_events(),
+            vec![server_events::player_leave(1)]
+        );
+    }
+
+    #[test]
+    fn lagging_player_pauses_until_acks_catch_up() {
+        let mut state = LockstepState::new(0x1234);
+        state.lag_ack_budget_ticks = 10;
+        state.lag_policy = LagPolicy::Pause;
+        for (id, name) in [(1, "p1"), (2, "p2")] {
+            state
+                .register_player(id, name.into(), JoinRoleEnum::Player)
+                .unwrap();
+        }
+        state
+            .join_player(0, "obs".into(), JoinRoleEnum::Observer)
+            .unwrap();
+
+        // 沒送輸入但持續確認的玩家不算落後；觀察者不檢查。
+        state.current_tick = 11;
+        state.record_tick_ack(1, 9);
+        let report = state.check_lag();
+        assert_eq!(report.transitions.len(), 1);
+        assert_eq!(report.transitions[0].player_id, 2);
+        assert!(report.transitions[0].lagging);
+        assert_eq!(report.transitions[0].ack_gap_ticks, 11);
+        assert_eq!(
+            report.pause,
+            Some(LagPause {
+                paused: true,
+                tick: 11 + LAG_PAUSE_LEAD_TICKS
+            })
+        );
+
+        // 暫停期間刻度照常推進；玩家 2 還沒追上就不重複宣告。
+        state.current_tick = 40;
+        state.record_tick_ack(1, 40);
+        assert_eq!(state.check_lag(), LagReport::default());
+
+        state.record_tick_ack(2, 39);
+        let report = state.check_lag();
+        assert_eq!(report.transitions.len(), 1);
+        assert!(!report.transitions[0].lagging);
+        assert_eq!(
+            report.pause,
+            Some(LagPause {
+                paused: false,
+                tick: 40 + LAG_PAUSE_LEAD_TICKS
+            })
+        );
+    }
+
+    #[test]
+    fn ping_rtt_over_budget_drops_the_seat_to_ai() {
+        use crate::lockstep::lag::AI_INPUT_INTERVAL_TICKS;
+
+        let mut state = LockstepState::new(0x1234);
+        state.lag_rtt_budget_us = 200_000;
+        state.lag_policy = LagPolicy::DropToAi;
+        for (id, name) in [(1, "p1"), (2, "p2")] {
+            state
+                .register_player(id, name.into(), JoinRoleEnum::Player)
+                .unwrap();
+        }
+        state.record_ping_rtt(1, 50_000);
+        state.record_ping_rtt(2, 400_000);
+        assert_eq!(state.players[&2].latency.samples(), 1);
+
+        let report = state.check_lag();
+        assert_eq!(report.pause, None);
+        assert_eq!(report.transitions.len(), 1);
+        assert_eq!(report.transitions[0].player_id, 2);
+        assert_eq!(report.transitions[0].rtt_us, 400_000);
+        assert!(state.players[&2].ai_controlled);
+        assert!(state.ai_inputs(AI_INPUT_INTERVAL_TICKS - 1).is_empty());
+        let inputs = state.ai_inputs(AI_INPUT_INTERVAL_TICKS);
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].0, 2);
+
+        // RTT 回到預算內即恢復，不再代管。
+        for _ in 0..32 {
+            state.record_ping_rtt(2, 50_000);
+        }
+        let report = state.check_lag();
+        assert!(!report.transitions[0].lagging);
+        assert!(!state.players[&2].ai_controlled);
+        assert!(state.ai_inputs(AI_INPUT_INTERVAL_TICKS).is_empty());
+    }
+
+    #[test]
+    fn client_hash_matching_server_is_not_flagged() {
+        let mut state = LockstepState::new(0x1234);
+        state.current_tick = 1200;
+        assert_eq!(state.record_server_hash(1200, 0xAA), None);
+        assert_eq!(state.record_client_hash(1, 1200, 0xAA), None);
+        assert_eq!(state.desync_stats().reports_checked, 1);
+        assert_eq!(state.desync_stats().reports_mismatched, 0);
+        assert!(state.take_pending_desyncs().is_empty());
+    }
+
+    #[test]
+    fn early_client_reports_are_checked_when_server_hash_arrives() {
+        let mut state = LockstepState::new(0x1234);
+        state.current_tick = 1200;
+        assert_eq!(state.record_client_hash(1, 1200, 0xAA), None);
+        assert_eq!(state.record_client_hash(2, 1200, 0xBB), None);
+        let report = state
+            .record_server_hash(1200, 0xAA)
+            .expect("player 2 diverged");
+        assert_eq!(report.tick, 1200);
+        assert_eq!(report.server_hash, 0xAA);
+        assert_eq!(report.diverged, vec![(2, 0xBB)]);
+        assert_eq!(state.take_pending_desyncs(), vec![report]);
+    }
+
+    #[test]
+    fn chat_is_authorized_limited_and_scoped() {
+        use crate::lockstep::wire::MapPing;
+
+        let mut state = LockstepState::new(0x1234);
+        state.chat_limits = ChatLimits {
+            max_chars: 16,
+            max_per_window: 3,
+            window_ticks: 120,
+        };
+        let p1 = state
+            .join_player(1, "alice".into(), JoinRoleEnum::Player)
+            .unwrap();
+        let obs = state
+            .join_player(0, "obs".into(), JoinRoleEnum::Observer)
+            .unwrap();
+        let team_of = |name: &str| (name == "alice").then_some(2);
+        let say = |channel: ChatChannel, text: &str| ChatSend {
+            channel: channel as i32,
+            text: text.into(),
+            ping: None,
+        };
+        let reason = |r: Result<ChatMessage, ChatRejection>| r.unwrap_err().reason;
+        let seat = Some((1, p1.binding));
+
+        let sent = state
+            .submit_chat(seat, &say(ChatChannel::Team, "tower here"), team_of)
+            .unwrap();
+        assert_eq!(sent.scope, ChatScope::Team(2));
+        assert_eq!(sent.player_name, "alice");
+        assert_eq!(
+            reason(state.submit_chat(seat, &say(ChatChannel::Team, "gg"), |_: &str| None)),
+            ChatRejectReason::NoTeam
+        );
+        let ping = ChatSend {
+            ping: Some(MapPing { x: 10.0, y: 20.0 }),
+            ..say(ChatChannel::All, "")
+        };
+        assert_eq!(
+            state.submit_chat(seat, &ping, team_of).unwrap().ping,
+            Some((10.0, 20.0))
+        );
+        // 第 4 則超過窗口上限（被拒絕的也計數）。
+        assert_eq!(
+            reason(state.submit_chat(seat, &say(ChatChannel::All, "  "), team_of)),
+            ChatRejectReason::RateLimited
+        );
+        let queued = state.take_pending_chats();
+        assert_eq!(queued.len(), 2);
+        assert_eq!(queued[0], sent);
+        assert_eq!(queued[1].scope, ChatScope::All);
+        assert!(state.take_pending_chats().is_empty());
+
+        state.current_tick = 500;
+        assert_eq!(
+            reason(state.submit_chat(seat, &say(ChatChannel::All, "  "), team_of)),
+            ChatRejectReason::Empty
+        );
+        assert_eq!(
+            reason(state.submit_chat(
+                seat,
+                &say(ChatChannel::All, "this is far too long"),
+                team_of
+            )),
+            ChatRejectReason::TooLong
+        );
+        assert_eq!(
+            reason(state.submit_chat(
+                Some((obs.player_id, obs.binding)),
+                &say(ChatChannel::All, "hi"),
+                team_of
+            )),
+            ChatRejectReason::Unauthorized
+        );
+        assert_eq!(
+            reason(state.submit_chat(None, &say(ChatChannel::All, "hi"), team_of)),
+            ChatRejectReason::Unauthorized
+        );
+    }
+
+    #[test]
+    fn desynced_tick_is_counted_once() {
+        let mut state = LockstepState::new(0x1234);
+        state.current_tick = 1200;
+        state.record_server_hash(1200, 0xAA);
+        assert!(state.record_client_hash(1, 1200, 0x01).is_some());
+        // 同一刻度晚到的不一致回報只計入統計，不再產生第二份報告。
+        assert_eq!(state.record_client_hash(2, 1200, 0x02), None);
+        let stats = state.desync_stats();
+        assert_eq!(stats.reports_checked, 2);
+        assert_eq!(stats.reports_mismatched, 2);
+        assert_eq!(stats.desynced_ticks, 1);
+        assert_eq!(state.take_pending_desyncs().len(), 1);
+    }
+
+    #[test]
+    fn hash_history_is_bounded_per_player() {
+        let mut state = LockstepState::new(0x1234);
+        state.current_tick = 10_000;
+        for tick in 0..(STATE_HASH_HISTORY_LEN as u32 * 2) {
+            state.record_client_hash(1, tick, 1);
+        }
+        state.record_client_hash(2, 5, 1);
+        // 玩家 1 灌滿自己的歷史也不會擠掉玩家 2 的回報。
+        assert_eq!(state.client_hashes[&1].len(), STATE_HASH_HISTORY_LEN);
+        assert_eq!(state.client_hashes[&2].len(), 1);
+        for tick in 0..(STATE_HASH_HISTORY_LEN as u32 * 2) {
+            state.record_server_hash(10_000 + tick, 1);
+        }
+        assert_eq!(state.server_hashes.len(), STATE_HASH_HISTORY_LEN);
+    }
+
+    #[test]
+    fn client_hash_outside_the_server_window_is_rejected() {
+        let mut state = LockstepState::new(0x1234);
+        state.current_tick = 1200;
+        // 尚未廣播到的刻度。
+        assert_eq!(state.record_client_hash(1, 1201, 0xBB), None);
+        assert!(state.client_hashes.is_empty());
+        state.record_server_hash(600, 0xAA);
+        state.record_server_hash(1200, 0xAA);
+        // 早於最舊的伺服器雜湊，已無從比對。
+        assert_eq!(state.record_client_hash(1, 599, 0xBB), None);
+        assert_eq!(state.desync_stats().reports_rejected, 2);
+        assert_eq!(state.desync_stats().reports_checked, 0);
+        // 範圍內的回報照常比對。
+        assert!(state.record_client_hash(1, 600, 0xBB).is_some());
+    }
 }
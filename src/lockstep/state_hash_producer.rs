@@ -26,16 +26,34 @@
 //! 階段 3.4 僅對 `Pos` + `hp` 進行哈希處理。第 4+ 階段可能會增加「Facing」、「Vel」、
 //! 能力冷卻時間等 - 但添加字段會破壞固定，所以應該是
 //! 在一次遷移中完成。
+//!
+//! # 分解報告（階段 6.3）
+//!
+//! 單一 u64 只能說「有東西不同」。`compute_state_hash_report` 另外
+//! 依元件族（pos / vel / facing / hp / economy）與實體種類各算一組
+//! 子雜湊；`compute_entity_hashes` 產生逐實體雜湊表，兩端的表交給
+//! `diff_entity_hashes` 就能定位到「creep 4183 velocity」。報告只供
+//! 診斷使用，不影響線上 `StateHash` 的數值。
+//!
+//! 子雜湊要跨機器、跨版本比較（伺服器傾印對客戶端傾印），所以不用
+//! 每個 Rust 版本可能不同的 `DefaultHasher`，而用 `FnvHasher`
+//! （FNV-1a 64，整數一律以 little-endian 餵入）。
 
+use serde::Serialize;
 use specs::{Join, World, WorldExt};
 
 use omoba_sim::state_hash::hash_sorted_by_id;
+use std::collections::BTreeMap;
 use std::hash::{Hash, Hasher};
 
 use crate::comp::creep::CProperty;
 use crate::comp::facing::Facing;
+use crate::comp::hero::Hero;
 use crate::comp::phys::{Pos, Vel};
+use crate::comp::projectile::Projectile;
+use crate::comp::tower::Tower;
 use crate::comp::PlayerEconomy;
+use crate::lockstep::snapshot_producer::{classify_entity, EntityKindTag};
 
 /// 每個狀態哈希滴答的穩定子集進行哈希處理。 `#[derive(Hash)]` 訂單匹配
 /// 現場申報單；在不破壞協議的情況下不要重新安排
@@ -98,6 +116,290 @@ pub fn compute_state_hash(world: &World) -> u64 {
     hasher.finish()
 }
 
+/// 每個元件族的子雜湊。缺少元件的實體以 0 代入，與
+/// `compute_state_hash` 相同。
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ComponentHashes {
+    pub pos: u64,
+    pub vel: u64,
+    pub facing: u64,
+    pub hp: u64,
+}
+
+impl ComponentHashes {
+    /// 回傳數值不同的元件族名稱（`"pos"` / `"vel"` / `"facing"` / `"hp"`）。
+    pub fn differing_fields(&self, other: &ComponentHashes) -> Vec<&'static str> {
+        let mut out = Vec::new();
+        if self.pos != other.pos {
+            out.push("pos");
+        }
+        if self.vel != other.vel {
+            out.push("vel");
+        }
+        if self.facing != other.facing {
+            out.push("facing");
+        }
+        if self.hp != other.hp {
+            out.push("hp");
+        }
+        out
+    }
+}
+
+/// 結構化狀態雜湊報告。`total` 與 `compute_state_hash` 完全相同，
+/// 其餘欄位用來縮小不一致的範圍。
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct StateHashReport {
+    pub total: u64,
+    pub entity_count: u32,
+    /// 所有實體各元件族的子雜湊。
+    pub components: ComponentHashes,
+    /// `PlayerEconomy` 餘額雜湊；沒有該資源時為 0。
+    pub economy: u64,
+    /// 依實體種類分組的子雜湊（只含出現過的種類）。
+    pub by_kind: BTreeMap<EntityKindTag, ComponentHashes>,
+}
+
+/// 單一實體的雜湊列。
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityHash {
+    pub id: u32,
+    pub kind: EntityKindTag,
+    pub components: ComponentHashes,
+}
+
+/// 兩份實體雜湊表間的差異。`fields` 為空時代表實體只存在於其中一邊
+/// （見 `only_in`）。
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct EntityHashDiff {
+    pub id: u32,
+    pub kind: EntityKindTag,
+    pub fields: Vec<&'static str>,
+    /// `Some("left")` / `Some("right")`：實體只出現在該側。
+    pub only_in: Option<&'static str>,
+}
+
+/// FNV-1a 64。整數以 little-endian 位元組餵入、`usize` 擴成 `u64`，
+/// 結果與平台位元組序及指標寬度無關。
+struct FnvHasher(u64);
+
+impl FnvHasher {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ u64::from(b)).wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64);
+    }
+}
+
+/// 子雜湊的折疊單位：`(entity id, 該實體某元件族的雜湊)`。
+#[derive(std::hash::Hash)]
+struct IdHash {
+    id: u32,
+    hash: u64,
+}
+
+fn hash_one(value: impl Hash) -> u64 {
+    let mut hasher = FnvHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn economy_hash(world: &World) -> u64 {
+    match world.try_fetch::<PlayerEconomy>() {
+        Some(economy) => {
+            let mut hasher = FnvHasher::new();
+            "omoba-player-economy-v1".hash(&mut hasher);
+            economy.balances().hash(&mut hasher);
+            hasher.finish()
+        }
+        None => 0,
+    }
+}
+
+/// 逐實體收集與 `compute_state_hash` 相同的欄位並算出每元件雜湊，
+/// 依 entity id 排序。
+pub fn compute_entity_hashes(world: &World) -> Vec<EntityHash> {
+    let entities = world.entities();
+    let pos_storage = world.read_storage::<Pos>();
+    let vel_storage = world.read_storage::<Vel>();
+    let facing_storage = world.read_storage::<Facing>();
+    let cprop_storage = world.read_storage::<CProperty>();
+    let hero_storage = world.read_storage::<Hero>();
+    let tower_storage = world.read_storage::<Tower>();
+    let proj_storage = world.read_storage::<Projectile>();
+
+    let mut out: Vec<EntityHash> = (&entities, &pos_storage)
+        .join()
+        .map(|(e, pos)| {
+            let (vel_x_raw, vel_y_raw) = vel_storage
+                .get(e)
+                .map(|v| (v.0.x.raw(), v.0.y.raw()))
+                .unwrap_or((0, 0));
+            let facing_ticks = facing_storage.get(e).map(|f| f.0.ticks()).unwrap_or(0);
+            let hp_raw = cprop_storage.get(e).map(|c| c.hp.raw()).unwrap_or(0);
+            EntityHash {
+                id: e.id(),
+                kind: classify_entity(
+                    e,
+                    &hero_storage,
+                    &tower_storage,
+                    &proj_storage,
+                    &cprop_storage,
+                ),
+                components: ComponentHashes {
+                    pos: hash_one((pos.0.x.raw(), pos.0.y.raw())),
+                    vel: hash_one((vel_x_raw, vel_y_raw)),
+                    facing: hash_one(facing_ticks),
+                    hp: hash_one(hp_raw),
+                },
+            }
+        })
+        .collect();
+    out.sort_by_key(|h| h.id);
+    out
+}
+
+/// 產生分解報告。`total` 由 `compute_state_hash` 計算，保證與線上
+/// `StateHash` 可直接比較。
+pub fn compute_state_hash_report(world: &World) -> StateHashReport {
+    let rows = compute_entity_hashes(world);
+
+    let aggregate = |rows: &[&EntityHash]| -> ComponentHashes {
+        // 子雜湊同樣對 id 排序後折疊，與 ECS 連線順序無關。
+        let pairs = |f: fn(&ComponentHashes) -> u64| -> u64 {
+            let items: Vec<IdHash> = rows
+                .iter()
+                .map(|r| IdHash {
+                    id: r.id,
+                    hash: f(&r.components),
+                })
+                .collect();
+            hash_sorted_by_id(&items, |i| i.id)
+        };
+        ComponentHashes {
+            pos: pairs(|c| c.pos),
+            vel: pairs(|c| c.vel),
+            facing: pairs(|c| c.facing),
+            hp: pairs(|c| c.hp),
+        }
+    };
+
+    let all: Vec<&EntityHash> = rows.iter().collect();
+    let mut grouped: BTreeMap<EntityKindTag, Vec<&EntityHash>> = BTreeMap::new();
+    for row in &rows {
+        grouped.entry(row.kind).or_default().push(row);
+    }
+
+    StateHashReport {
+        total: compute_state_hash(world),
+        entity_count: rows.len() as u32,
+        components: aggregate(&all),
+        economy: economy_hash(world),
+        by_kind: grouped
+            .into_iter()
+            .map(|(kind, rows)| (kind, aggregate(&rows)))
+            .collect(),
+    }
+}
+
+/// 比較兩份（皆依 id 排序的）實體雜湊表，列出每個不一致的實體與
+/// 元件族。典型用法：伺服器與客戶端各自 `compute_entity_hashes`，
+/// 差異結果即為「creep 4183 vel」。
+pub fn diff_entity_hashes(left: &[EntityHash], right: &[EntityHash]) -> Vec<EntityHashDiff> {
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() || j < right.len() {
+        match (left.get(i), right.get(j)) {
+            (Some(l), Some(r)) if l.id == r.id => {
+                let fields = l.components.differing_fields(&r.components);
+                if !fields.is_empty() || l.kind != r.kind {
+                    out.push(EntityHashDiff {
+                        id: l.id,
+                        kind: l.kind,
+                        fields,
+                        only_in: None,
+                    });
+                }
+                i += 1;
+                j += 1;
+            }
+            (Some(l), r) if r.map_or(true, |r| l.id < r.id) => {
+                out.push(EntityHashDiff {
+                    id: l.id,
+                    kind: l.kind,
+                    fields: Vec::new(),
+                    only_in: Some("left"),
+                });
+                i += 1;
+            }
+            (_, Some(r)) => {
+                out.push(EntityHashDiff {
+                    id: r.id,
+                    kind: r.kind,
+                    fields: Vec::new(),
+                    only_in: Some("right"),
+                });
+                j += 1;
+            }
+            _ => break,
+        }
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -324,4 +626,114 @@ mod tests {
 
         assert_eq!(compute_state_hash(&w1), compute_state_hash(&w2));
     }
+
+    fn make_report_world() -> World {
+        let mut w = make_world();
+        w.register::<Hero>();
+        w.register::<Tower>();
+        w.register::<Projectile>();
+        w
+    }
+
+    fn vel_xy(x: i32, y: i32) -> Vel {
+        Vel(SimVec2 {
+            x: Fixed64::from_i32(x),
+            y: Fixed64::from_i32(y),
+        })
+    }
+
+    #[test]
+    fn report_total_matches_compute_state_hash() {
+        let mut w = make_report_world();
+        w.create_entity()
+            .with(pos_xy(1, 2))
+            .with(vel_xy(3, 4))
+            .with(cprop(50, 100))
+            .build();
+        w.create_entity().with(pos_xy(9, 9)).build();
+        let mut economy = PlayerEconomy::default();
+        economy.initialize(1, 650);
+        w.insert(economy);
+
+        let report = compute_state_hash_report(&w);
+        assert_eq!(report.total, compute_state_hash(&w));
+        assert_eq!(report.entity_count, 2);
+        assert_ne!(report.economy, 0);
+        assert_eq!(
+            report.by_kind.keys().copied().collect::<Vec<_>>(),
+            vec![EntityKindTag::Other, EntityKindTag::Creep]
+        );
+    }
+
+    #[test]
+    fn report_isolates_changed_component_family() {
+        let build = |vx: i32| {
+            let mut w = make_report_world();
+            w.create_entity()
+                .with(pos_xy(1, 2))
+                .with(vel_xy(vx, 0))
+                .with(cprop(50, 100))
+                .build();
+            compute_state_hash_report(&w)
+        };
+        let a = build(1);
+        let b = build(2);
+        assert_ne!(a.total, b.total);
+        assert_eq!(a.components.differing_fields(&b.components), vec!["vel"]);
+        assert_eq!(
+            a.by_kind[&EntityKindTag::Creep].differing_fields(&b.by_kind[&EntityKindTag::Creep]),
+            vec!["vel"]
+        );
+        assert_eq!(a.economy, b.economy);
+    }
+
+    #[test]
+    fn entity_diff_pinpoints_entity_and_field() {
+        let build = |hp: i32| {
+            let mut w = make_report_world();
+            w.create_entity().with(pos_xy(0, 0)).build();
+            w.create_entity()
+                .with(pos_xy(5, 5))
+                .with(cprop(hp, 100))
+                .build();
+            compute_entity_hashes(&w)
+        };
+        let left = build(100);
+        let right = build(99);
+        let diffs = diff_entity_hashes(&left, &right);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].id, left[1].id);
+        assert_eq!(diffs[0].kind, EntityKindTag::Creep);
+        assert_eq!(diffs[0].fields, vec!["hp"]);
+        assert_eq!(diffs[0].only_in, None);
+    }
+
+    #[test]
+    fn entity_diff_reports_one_sided_entities() {
+        let mut w1 = make_report_world();
+        w1.create_entity().with(pos_xy(0, 0)).build();
+        let mut w2 = make_report_world();
+        w2.create_entity().with(pos_xy(0, 0)).build();
+        w2.create_entity().with(pos_xy(1, 1)).build();
+
+        let diffs = diff_entity_hashes(&compute_entity_hashes(&w1), &compute_entity_hashes(&w2));
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].only_in, Some("right"));
+        assert!(
+            diff_entity_hashes(&compute_entity_hashes(&w2), &compute_entity_hashes(&w2)).is_empty()
+        );
+    }
+
+    #[test]
+    fn sub_hashes_use_fixed_fnv1a() {
+        // FNV-1a 64 標準測試向量。
+        let mut hasher = FnvHasher::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+        // 整數固定以 little-endian 餵入。
+        let mut bytes = FnvHasher::new();
+        bytes.write(&(-2i64).to_le_bytes());
+        assert_eq!(hash_one(-2i64), bytes.finish());
+        assert_eq!(hash_one(()), FnvHasher::OFFSET_BASIS);
+    }
 }
@@ -14,8 +14,7 @@
 //! 將其替換為 `omoba_sim::state_hash::hash_sorted_by_id`
 //! 真實的 ECS 狀態。
 //! - 類比調度程式與 broadcaster 使用相同 configured lockstep cadence。
-//! - 在第 2 階段，`server_events` 永遠為空；第5階段將注入
-//! player_join / wave_start / 等伺服器權威事件。
+//! - 在第 2 階段，`server_events` 永遠為空；階段 6.12 起見下方說明。
 //!
 //! 3.4階段狀態：
 //! - 可選的“state_hash_rx”通道由調度程序滴答循環提供
@@ -27,13 +26,44 @@
 //! 計算時間；廣播公司逐字轉寄「(tick, hash)」。
 //! - 當「state_hash_rx」為「None」（遺留/測試設定）時，廣播者
 //! 回退到“placeholder_state_hash”，以便現有測試繼續通過。
+//!
+//! 階段 6.4 不同步偵測：
+//! - 送出的 `StateHash` 也寫入 `LockstepState::record_server_hash`，
+//!   讓 kcp 收到的客戶端回報（0x19）可以比對。
+//! - 每刻取走 `LockstepState` 累積的 `DesyncReport`，寫入 replay 標記，
+//!   並在設定 `with_desync_dump` 時傾印最近的快照。
+//!
+//! 階段 6.7：設定 `with_tick_history` 時，每個送出的 `TickBatch` 也推入
+//! 共享的 `TickHistory`，供 kcp 傳輸回覆 `SnapshotReq` 補送。
+//!
+//! 階段 6.12：`server_events` 由 `LockstepState::take_server_events`
+//! （加入 / 離開，事件發生後的第一個批次）與可選的 `server_event_rx`
+//! （調度器的開波 / 對局結束）組成。調度器事件帶著發生時的調度器刻度，
+//! 固定排進刻度 `發生刻度 + SCHEDULER_EVENT_LEAD_TICKS` 的批次，與通道
+//! 何時被取出無關。
+//!
+//! 階段 6.13：每刻呼叫 `LockstepState::check_lag`，把落後 / 恢復轉換以
+//! `td/all/res` `lockstep`/`lag` 廣播給所有客戶端。`Pause` 策略的暫停 /
+//! 解除連同生效刻度以 `lockstep`/`pause` 廣播、寫進 replay，並經
+//! `with_lag_pause_tx` 通知調度器，雙方在同一刻度設定 `GamePause`。
+//! `DropToAi` 代管座位的伺服器輸入（`LockstepState::ai_inputs`）併入
+//! 該刻的輸入，同時送往調度器與 `TickBatch`（`input_id` 為 0）。
+//!
+//! 階段 6.24：每刻取走 `LockstepState` 暫存的玩家聊天，先寫進 replay，
+//! 再依頻道以 `All` / `Team` 策略送出。
 
 use crossbeam_channel::{Receiver, Sender};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use tokio::time::{interval, Duration, MissedTickBehavior};
 
+use crate::lockstep::input_buffer::BufferedPlayerInput;
+use crate::lockstep::replay::ReplayWriter;
+use crate::lockstep::tick_history::TickHistory;
 use crate::lockstep::{
-    InputBuffer, InputForPlayer, LockstepFrame, LockstepState, StateHash, TickBatch,
+    DesyncReport, InputBuffer, InputForPlayer, LagPause, LockstepFrame, LockstepState, ServerEvent,
+    StateHash, TickBatch,
 };
 use crate::transport::OutboundMsg;
 use omoba_core::lockstep_timing::LockstepTiming;
@@ -44,6 +74,14 @@ use omoba_core::lockstep_timing::LockstepTiming;
 /// 廣播公司自己的 `LockstepState.current_tick`。
 pub type StateHashSample = (u32, u64);
 
+/// 階段 6.12：調度器發布的伺服器事件與發生時的調度器刻度。
+pub type ScheduledServerEvent = (u32, ServerEvent);
+
+/// 階段 6.12：調度器事件放進刻度 `發生刻度 + SCHEDULER_EVENT_LEAD_TICKS`
+/// 的批次。調度器與廣播器各自計時，調度器通常只差一兩刻；留一點提前量
+/// 讓事件在目標批次送出前就已到達。
+pub const SCHEDULER_EVENT_LEAD_TICKS: u32 = 4;
+
 #[derive(Clone, Copy, Debug)]
 pub struct TickBroadcasterConfig {
     /// 以微秒為單位的刻度週期。預設由 `LOCKSTEP_TPS` 推導。
@@ -93,6 +131,18 @@ pub struct TickBroadcaster {
     /// 所以主機端遊戲狀態（例如 `CurrentCreepWave.is_running`）永遠不會
     /// 翻轉開始回合。
     host_input_tx: Option<crossbeam_channel::Sender<Vec<(u32, crate::lockstep::PlayerInput)>>>,
+    /// 階段 6.1：可選的 replay 錄製器。`None` 時不錄製。
+    replay: Option<Arc<Mutex<ReplayWriter>>>,
+    /// 階段 6.4：不同步時傾印快照的 `(目錄, SnapshotStore)`。`None` 時不傾印。
+    desync_dump: Option<(PathBuf, Arc<Mutex<crate::comp::SnapshotStore>>)>,
+    /// 階段 6.7：最近批次的共享環形緩衝區。`None` 時不保留。
+    tick_history: Option<Arc<Mutex<TickHistory>>>,
+    /// 階段 6.12：調度器發布的伺服器事件。`None` 時只送加入 / 離開。
+    server_event_rx: Option<Receiver<ScheduledServerEvent>>,
+    /// 階段 6.12：已收到、尚未到目標批次的調度器事件（目標刻度 → 事件）。
+    scheduled_events: Mutex<BTreeMap<u32, Vec<ServerEvent>>>,
+    /// 階段 6.13：落後暫停 / 解除送往 `State::set_lag_pause_rx`。
+    lag_pause_tx: Option<Sender<LagPause>>,
 }
 
 impl TickBroadcaster {
@@ -109,6 +159,12 @@ impl TickBroadcaster {
             out_tx,
             state_hash_rx: None,
             host_input_tx: None,
+            replay: None,
+            desync_dump: None,
+            tick_history: None,
+            server_event_rx: None,
+            scheduled_events: Mutex::new(BTreeMap::new()),
+            lag_pause_tx: None,
         }
     }
 
@@ -130,6 +186,41 @@ impl TickBroadcaster {
         self
     }
 
+    /// 階段 6.1：附加 replay 錄製器。每個送出的 `TickBatch` / `StateHash`
+    /// 都會在進入出站通道前追加寫入。
+    pub fn with_replay_writer(mut self, writer: Arc<Mutex<ReplayWriter>>) -> Self {
+        self.replay = Some(writer);
+        self
+    }
+
+    /// 階段 6.4：偵測到不同步時，把 `store` 目前的快照寫到 `dir`。
+    pub fn with_desync_dump(
+        mut self,
+        dir: impl Into<PathBuf>,
+        store: Arc<Mutex<crate::comp::SnapshotStore>>,
+    ) -> Self {
+        self.desync_dump = Some((dir.into(), store));
+        self
+    }
+
+    /// 階段 6.7：附加 `TickHistory`。與 kcp 傳輸共用同一個 Arc。
+    pub fn with_tick_history(mut self, history: Arc<Mutex<TickHistory>>) -> Self {
+        self.tick_history = Some(history);
+        self
+    }
+
+    /// 階段 6.12：附加調度器端的伺服器事件來源（`State::set_server_event_tx`）。
+    pub fn with_server_event_rx(mut self, rx: Receiver<ScheduledServerEvent>) -> Self {
+        self.server_event_rx = Some(rx);
+        self
+    }
+
+    /// 階段 6.13：附加落後暫停通道。
+    pub fn with_lag_pause_tx(mut self, tx: Sender<LagPause>) -> Self {
+        self.lag_pause_tx = Some(tx);
+        self
+    }
+
     /// 產生 configured-cadence 滴答循環。運行直到“out_tx”關閉（通道
     /// 作為發送錯誤斷開表面，然後我們記錄+退出）。
     pub async fn run(self) {
@@ -164,18 +255,129 @@ impl TickBroadcaster {
         }
     }
 
+    /// 階段 6.13：廣播落後轉換與暫停變化，暫停變化另外寫進 replay 並
+    /// 轉送調度器。
+    fn publish_lag(&self, tick: u32, lag: crate::lockstep::LagReport) {
+        for t in &lag.transitions {
+            if t.lagging {
+                log::warn!(
+                    "lockstep player_id={} lagging at tick {} (ack gap {} ticks, srtt {}us), policy={}",
+                    t.player_id,
+                    tick,
+                    t.ack_gap_ticks,
+                    t.rtt_us,
+                    t.policy.as_str()
+                );
+            } else {
+                log::info!("lockstep player_id={} recovered at tick {}", t.player_id, tick);
+            }
+            let msg = OutboundMsg::new_s_all("td/all/res", "lockstep", "lag", t.to_json(tick));
+            if let Err(e) = self.out_tx.send(msg) {
+                log::warn!("TickBroadcaster: lag notice send failed: {e}");
+            }
+        }
+        let Some(pause) = lag.pause else {
+            return;
+        };
+        if pause.paused {
+            log::warn!(
+                "lockstep match pausing from tick {}: player lagging",
+                pause.tick
+            );
+        } else {
+            log::info!("lockstep match resuming from tick {}", pause.tick);
+        }
+        self.record_replay(|w| w.record_lag_pause(&pause));
+        let msg = OutboundMsg::new_s_all("td/all/res", "lockstep", "pause", pause.to_json());
+        if let Err(e) = self.out_tx.send(msg) {
+            log::warn!("TickBroadcaster: pause notice send failed: {e}");
+        }
+        if let Some(tx) = self.lag_pause_tx.as_ref() {
+            if let Err(e) = tx.send(pause) {
+                log::warn!("TickBroadcaster: lag_pause_tx send failed: {e}");
+            }
+        }
+    }
+
+    /// 階段 6.12：收下調度器事件並取出目標刻度已到的部分。晚於目標批次
+    /// 才到的事件（調度器落後超過提前量）改放本刻並記錄警告。
+    fn take_scheduled_events(&self, tick: u32) -> Vec<ServerEvent> {
+        let mut pending = self.scheduled_events.lock().unwrap();
+        if let Some(rx) = self.server_event_rx.as_ref() {
+            for (event_tick, event) in rx.try_iter() {
+                let target = event_tick.wrapping_add(SCHEDULER_EVENT_LEAD_TICKS);
+                if target < tick {
+                    log::warn!(
+                        "TickBroadcaster: server event from scheduler tick {} missed batch {}, sending with batch {}",
+                        event_tick,
+                        target,
+                        tick
+                    );
+                }
+                pending.entry(target.max(tick)).or_default().push(event);
+            }
+        }
+        let later = pending.split_off(&tick.saturating_add(1));
+        std::mem::replace(&mut *pending, later)
+            .into_values()
+            .flatten()
+            .collect()
+    }
+
+    /// 階段 6.24：錄製並送出暫存的聊天。
+    fn publish_chats(&self) {
+        let chats = self.state.lock().unwrap().take_pending_chats();
+        for chat in &chats {
+            self.record_replay(|w| w.record_chat(chat));
+            if let Err(e) = self.out_tx.send(chat.to_outbound()) {
+                log::warn!("TickBroadcaster: chat send failed: {e}");
+            }
+        }
+    }
+
+    /// 階段 6.20：不經計時器手動推進一刻，供 loopback 傳輸、無頭執行與
+    /// 測試逐刻驅動。回傳值同 `run` 的結束條件。
+    pub fn step(&self) -> bool {
+        self.fire_one_tick()
+    }
+
     /// 激發一滴。如果出站通道關閉則回傳 false
     /// （表示傳輸已關閉 - 呼叫者退出循環）。
     fn fire_one_tick(&self) -> bool {
         // 提前刻度計數器。
-        let tick = {
+        let (tick, mut server_events, lag, ai_inputs) = {
             let mut s = self.state.lock().unwrap();
             s.current_tick = s.current_tick.wrapping_add(1);
-            s.current_tick
+            // 階段 6.9：寬限已過仍未接回的座位在此移除。
+            for player_id in s.expire_suspended() {
+                log::info!(
+                    "lockstep player_id={} did not reconnect within grace; seat released",
+                    player_id
+                );
+            }
+            let lag = s.check_lag();
+            let tick = s.current_tick;
+            (tick, s.take_server_events(), lag, s.ai_inputs(tick))
         };
+        self.publish_lag(tick, lag);
+        server_events.extend(self.take_scheduled_events(tick));
 
         // 針對此刻度的漏極輸入。
-        let inputs = self.input_buffer.lock().unwrap().drain_for_tick(tick);
+        let mut inputs = self.input_buffer.lock().unwrap().drain_for_tick(tick);
+        // 階段 6.13：代管座位的輸入排在該玩家自己的輸入之後（穩定排序）。
+        if !ai_inputs.is_empty() {
+            let now = std::time::Instant::now();
+            inputs.extend(ai_inputs.into_iter().map(|(player_id, input)| {
+                let buffered = BufferedPlayerInput {
+                    input,
+                    input_id: 0,
+                    server_receive_tick: tick,
+                    server_receive_instant: now,
+                };
+                (player_id, buffered)
+            }));
+            inputs.sort_by_key(|(player_id, _)| *player_id);
+        }
 
         // 階段 5.x：將耗盡的輸入鏡像到主機調度程式（State::tick
         // 透過橫樑接收器讀取）。在使用“輸入”之前發送
@@ -211,8 +413,7 @@ impl TickBroadcaster {
         let batch = TickBatch {
             tick,
             inputs: inputs_proto,
-            // 第2階段：空；第 5+ 階段注入 PlayerJoin/WaveStart/等。
-            server_events: vec![],
+            server_events,
             lua_content_generation: omoba_template_ids::runtime_lua_content_generation()
                 .ok()
                 .flatten()
@@ -223,11 +424,17 @@ impl TickBroadcaster {
                 .unwrap_or_default(),
         };
 
+        self.record_replay(|w| w.record_tick_batch(&batch));
+        if let Some(history) = self.tick_history.as_ref() {
+            history.lock().unwrap().push(batch.clone());
+        }
+
         let msg = OutboundMsg::lockstep_frame(LockstepFrame::TickBatch(batch));
         if let Err(e) = self.out_tx.send(msg) {
             log::warn!("TickBroadcaster failed to send TickBatch: {e}");
             return false;
         }
+        self.publish_chats();
 
         // 週期性狀態哈希。第 3.4 階段的雜湊值來源為
         // `state_hash_rx`（調度程序）可用時；否則就會回落
@@ -238,6 +445,14 @@ impl TickBroadcaster {
                 tick: hash_tick,
                 hash,
             };
+            self.record_replay(|w| w.record_state_hash(&sh));
+            // hash=0 代表沒有調度器樣本，不能拿來比對客戶端。
+            if hash != 0 {
+                self.state
+                    .lock()
+                    .unwrap()
+                    .record_server_hash(hash_tick, hash);
+            }
             let msg = OutboundMsg::lockstep_frame(LockstepFrame::StateHash(sh));
             if let Err(e) = self.out_tx.send(msg) {
                 log::warn!("TickBroadcaster failed to send StateHash: {e}");
@@ -245,6 +460,23 @@ impl TickBroadcaster {
             }
         }
 
+        let (desyncs, stats) = {
+            let mut s = self.state.lock().unwrap();
+            (s.take_pending_desyncs(), s.desync_stats())
+        };
+        for report in &desyncs {
+            log::warn!(
+                "lockstep desync at tick {}: server_hash={:#018x} diverged={:?} (mismatched {}/{} reports, {} desynced ticks)",
+                report.tick,
+                report.server_hash,
+                report.diverged,
+                stats.reports_mismatched,
+                stats.reports_checked,
+                stats.desynced_ticks
+            );
+            self.handle_desync(report);
+        }
+
         // 定期清理過時的未來輸入（例如提交的內容）
         // 引用了我們已經通過的勾號，因為玩家是
         // 斷開連接並重新連接）。
@@ -258,6 +490,55 @@ impl TickBroadcaster {
         true
     }
 
+    /// 階段 6.1：對 replay 寫入器執行一次寫入；未附加時為 no-op。
+    fn record_replay(&self, f: impl FnOnce(&mut ReplayWriter) -> std::io::Result<()>) {
+        if let Some(replay) = self.replay.as_ref() {
+            let mut writer = replay.lock().unwrap();
+            if let Err(e) = f(&mut writer) {
+                log::warn!(
+                    "TickBroadcaster: replay write to {} failed: {e}",
+                    writer.path().display()
+                );
+            }
+        }
+    }
+
+    /// 階段 6.4：寫入 replay 標記並（可選）傾印最近的快照。
+    fn handle_desync(&self, report: &DesyncReport) {
+        self.record_replay(|w| w.record_desync_marker(report));
+        let Some((dir, store)) = self.desync_dump.as_ref() else {
+            return;
+        };
+        let (snapshot_tick, bytes) = {
+            let store = store.lock().expect("SnapshotStore mutex poisoned");
+            (store.tick, store.bytes.clone())
+        };
+        if bytes.is_empty() {
+            log::warn!(
+                "TickBroadcaster: desync at tick {} but no snapshot has been taken yet",
+                report.tick
+            );
+            return;
+        }
+        let path = dir.join(format!(
+            "desync_t{}_snap{}.bin",
+            report.tick, snapshot_tick
+        ));
+        let result = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&path, &bytes));
+        match result {
+            Ok(()) => log::info!(
+                "TickBroadcaster: desync at tick {} — dumped snapshot tick {} to {}",
+                report.tick,
+                snapshot_tick,
+                path.display()
+            ),
+            Err(e) => log::warn!(
+                "TickBroadcaster: failed to dump desync snapshot to {}: {e}",
+                path.display()
+            ),
+        }
+    }
+
     /// 第 3 階段：替換為真正的 `omoba_sim::state_hash::hash_sorted_by_id`
     /// 超過權威的 ECS 狀態。佔位符是確定性的
     /// 因此可以在第二階段整合測試中使用線路路徑。
@@ -309,6 +590,7 @@ mod tests {
     //! 透過跳過“run()”並呼叫“fire_one_tick”來運行時依賴
     //! 同步地。
     use super::*;
+    use crate::lockstep::replay::{ReplayHeader, ReplayReader, ReplayRecord};
     use crate::lockstep::{NoOp, PlayerInput, PlayerInputEnum};
     use crossbeam_channel::unbounded;
 
@@ -344,6 +626,49 @@ mod tests {
         (bc, buf, state, rx)
     }
 
+    /// replay 測試共用的暫存目錄與 `match.omrp`；離開作用域時刪除目錄。
+    struct TempReplay {
+        dir: std::path::PathBuf,
+        path: std::path::PathBuf,
+    }
+
+    impl TempReplay {
+        fn new(label: &str) -> Self {
+            let stamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos();
+            let dir = std::env::temp_dir().join(format!("omoba_broadcaster_{label}_{stamp}"));
+            let path = dir.join("match.omrp");
+            Self { dir, path }
+        }
+
+        /// 以 `make_broadcaster` 的種子與預設刻度率建立 writer。
+        fn writer(&self) -> Arc<Mutex<ReplayWriter>> {
+            let header = ReplayHeader {
+                master_seed: 0xCAFE_BABE_DEAD_BEEF,
+                step_fps: LockstepTiming::DEFAULT.step_fps(),
+                start_tick: 0,
+                lua_content_hash: String::new(),
+                story: "TD_1".to_string(),
+                recorded_at_unix_ms: 0,
+            };
+            Arc::new(Mutex::new(
+                ReplayWriter::create(&self.path, &header, 1).unwrap(),
+            ))
+        }
+
+        fn records(&self) -> Vec<ReplayRecord> {
+            ReplayReader::open(&self.path).unwrap().records().collect()
+        }
+    }
+
+    impl Drop for TempReplay {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
     #[test]
     fn fires_tick_batches_with_buffered_inputs() {
         let cfg = TickBroadcasterConfig {
@@ -579,4 +904,322 @@ mod tests {
             frames
         );
     }
+
+    /// 階段 6.1：附加 replay 寫入器時，每個送出的 TickBatch 與 StateHash
+    /// 都要出現在 replay 檔中，順序與出站通道相同。
+    #[test]
+    fn records_tick_batches_and_state_hashes_to_replay() {
+        let cfg = TickBroadcasterConfig {
+            tick_period_us: LockstepTiming::DEFAULT.tick_period_us(),
+            step_fps: LockstepTiming::DEFAULT.step_fps(),
+            state_hash_interval: 2,
+            input_evict_interval: LockstepTiming::DEFAULT.ticks_for_seconds(1),
+            input_retention_ticks: LockstepTiming::DEFAULT.ticks_for_seconds(2),
+        };
+        let replay = TempReplay::new("replay");
+        let (bc, buf, _state, _rx) = make_broadcaster(cfg);
+        let bc = bc.with_replay_writer(replay.writer());
+        buf.lock().unwrap().submit(0, 9, 3, noop_input(), 0);
+        for _ in 0..4 {
+            assert!(bc.fire_one_tick());
+        }
+
+        let kinds: Vec<String> = replay
+            .records()
+            .into_iter()
+            .map(|r| match r {
+                ReplayRecord::TickBatch(b) => format!("batch{}:{}", b.tick, b.inputs.len()),
+                ReplayRecord::StateHash(sh) => format!("hash{}", sh.tick),
+                ReplayRecord::DesyncMarker(d) => format!("desync{}", d.tick),
+                ReplayRecord::Chat(c) => format!("chat{}", c.tick),
+                ReplayRecord::LagPause(p) => format!("pause{}", p.tick),
+                ReplayRecord::Unknown { kind } => format!("unknown{kind}"),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec!["batch1:0", "batch2:0", "hash2", "batch3:1", "batch4:0", "hash4"]
+        );
+    }
+
+    /// 階段 6.7：附加 `TickHistory` 時，送出的批次依序進入環形緩衝區。
+    #[test]
+    fn pushes_tick_batches_into_history() {
+        let (bc, buf, _state, _rx) = make_broadcaster(TickBroadcasterConfig::default());
+        let history = Arc::new(Mutex::new(TickHistory::new(3)));
+        let bc = bc.with_tick_history(history.clone());
+        buf.lock().unwrap().submit(0, 9, 4, noop_input(), 0);
+        for _ in 0..5 {
+            assert!(bc.fire_one_tick());
+        }
+
+        let h = history.lock().unwrap();
+        assert_eq!(h.oldest_tick(), Some(3));
+        assert_eq!(h.newest_tick(), Some(5));
+        assert_eq!(h.get(4).map(|b| b.inputs.len()), Some(1));
+    }
+
+    /// 階段 6.12：加入 / 離開進入下一個批次；調度器事件依發生刻度固定
+    /// 落在 `SCHEDULER_EVENT_LEAD_TICKS` 之後的批次，且只出現一次。
+    #[test]
+    fn server_events_ride_the_next_tick_batch() {
+        use crate::lockstep::{server_events, JoinRoleEnum};
+
+        let (bc, _buf, state, rx) = make_broadcaster(TickBroadcasterConfig::default());
+        let (event_tx, event_rx) = unbounded();
+        let bc = bc.with_server_event_rx(event_rx);
+        assert!(bc.fire_one_tick());
+
+        state
+            .lock()
+            .unwrap()
+            .join_player(4, "p4".into(), JoinRoleEnum::Player)
+            .unwrap();
+        // 調度器在第 1 刻開波；事件排進第 1 + SCHEDULER_EVENT_LEAD_TICKS 刻。
+        event_tx.send((1, server_events::wave_start(0))).unwrap();
+        for _ in 2..=(SCHEDULER_EVENT_LEAD_TICKS + 2) {
+            assert!(bc.fire_one_tick());
+        }
+        // 晚於目標批次才到的事件改放下一個批次。
+        event_tx.send((0, server_events::game_end("left"))).unwrap();
+        assert!(bc.fire_one_tick());
+
+        let events: Vec<(u32, Vec<ServerEvent>)> = drain_frames(&rx)
+            .into_iter()
+            .filter_map(|f| match f {
+                LockstepFrame::TickBatch(b) => Some((b.tick, b.server_events)),
+                _ => None,
+            })
+            .filter(|(_, events)| !events.is_empty())
+            .collect();
+        let wave_tick = 1 + SCHEDULER_EVENT_LEAD_TICKS;
+        assert_eq!(
+            events,
+            vec![
+                (2, vec![server_events::player_join(4, "p4")]),
+                (wave_tick, vec![server_events::wave_start(0)]),
+                (wave_tick + 2, vec![server_events::game_end("left")]),
+            ]
+        );
+    }
+
+    /// 階段 6.13：落後轉換與暫停以 JSON 通知廣播；暫停 / 解除提前
+    /// `LAG_PAUSE_LEAD_TICKS` 宣告，送往調度器並寫進 replay，刻度照常推進。
+    #[test]
+    fn lag_pause_is_announced_ahead_and_ticks_keep_advancing() {
+        use crate::lockstep::lag::LAG_PAUSE_LEAD_TICKS;
+        use crate::lockstep::{JoinRoleEnum, LagPolicy};
+
+        let replay = TempReplay::new("lag_pause");
+        let (bc, _buf, state, rx) = make_broadcaster(TickBroadcasterConfig::default());
+        let (pause_tx, pause_rx) = unbounded();
+        let bc = bc
+            .with_lag_pause_tx(pause_tx)
+            .with_replay_writer(replay.writer());
+        {
+            let mut s = state.lock().unwrap();
+            s.lag_ack_budget_ticks = 2;
+            s.lag_policy = LagPolicy::Pause;
+            s.register_player(5, "p5".into(), JoinRoleEnum::Player)
+                .unwrap();
+        }
+        for _ in 0..10 {
+            assert!(bc.fire_one_tick());
+        }
+        state.lock().unwrap().record_tick_ack(5, 10);
+        assert!(bc.fire_one_tick());
+
+        let expected = vec![
+            LagPause {
+                paused: true,
+                tick: 3 + LAG_PAUSE_LEAD_TICKS,
+            },
+            LagPause {
+                paused: false,
+                tick: 11 + LAG_PAUSE_LEAD_TICKS,
+            },
+        ];
+        assert_eq!(pause_rx.try_iter().collect::<Vec<_>>(), expected);
+        let recorded: Vec<LagPause> = replay
+            .records()
+            .into_iter()
+            .filter_map(|r| match r {
+                ReplayRecord::LagPause(p) => Some(p),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(recorded, expected);
+
+        let (batches, notices): (Vec<_>, Vec<_>) =
+            rx.try_iter().partition(|m| m.lockstep_frame.is_some());
+        let ticks: Vec<u32> = batches
+            .into_iter()
+            .filter_map(|m| match m.lockstep_frame {
+                Some(LockstepFrame::TickBatch(b)) => Some(b.tick),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ticks, (1..=11).collect::<Vec<_>>());
+        let notices: Vec<String> = notices.into_iter().map(|m| m.msg).collect();
+        assert_eq!(notices.len(), 4);
+        assert!(notices[0].contains("\"lagging\"") && notices[0].contains("\"tick\":3"));
+        assert!(notices[1].contains("\"paused\""));
+        assert!(notices[2].contains("\"recovered\""));
+        assert!(notices[3].contains("\"resumed\""));
+    }
+
+    /// 階段 6.13：`DropToAi` 代管座位的伺服器輸入與玩家輸入一起送往
+    /// 調度器與 `TickBatch`。
+    #[test]
+    fn ai_controlled_seat_inputs_reach_host_and_batch() {
+        use crate::lockstep::lag::{ai_input, AI_INPUT_INTERVAL_TICKS};
+        use crate::lockstep::{JoinRoleEnum, LagPolicy};
+
+        let (bc, buf, state, rx) = make_broadcaster(TickBroadcasterConfig::default());
+        let (host_tx, host_rx) = unbounded();
+        let bc = bc.with_host_input_tx(host_tx);
+        {
+            let mut s = state.lock().unwrap();
+            s.lag_ack_budget_ticks = 2;
+            s.lag_policy = LagPolicy::DropToAi;
+            s.register_player(5, "p5".into(), JoinRoleEnum::Player)
+                .unwrap();
+        }
+        let ai_tick = AI_INPUT_INTERVAL_TICKS;
+        buf.lock().unwrap().submit(0, 5, ai_tick, noop_input(), 8);
+        for _ in 0..ai_tick {
+            assert!(bc.fire_one_tick());
+        }
+
+        let expected = vec![(5, noop_input()), (5, ai_input(ai_tick).unwrap())];
+        assert_eq!(
+            host_rx.try_iter().collect::<Vec<_>>(),
+            vec![expected.clone()]
+        );
+        let batch = drain_frames(&rx)
+            .into_iter()
+            .find_map(|f| match f {
+                LockstepFrame::TickBatch(b) if b.tick == ai_tick => Some(b),
+                _ => None,
+            })
+            .unwrap();
+        let inputs: Vec<(u32, u32, PlayerInput)> = batch
+            .inputs
+            .into_iter()
+            .map(|i| (i.player_id, i.input_id, i.input.unwrap()))
+            .collect();
+        assert_eq!(
+            inputs,
+            vec![(5, 8, expected[0].1.clone()), (5, 0, expected[1].1.clone())]
+        );
+    }
+
+    /// 階段 6.24：暫存的聊天在下一刻先寫進 replay，再依頻道策略送出。
+    #[test]
+    fn chats_are_recorded_then_routed_by_channel() {
+        use crate::lockstep::wire::{ChatChannel, ChatSend};
+        use crate::lockstep::{ChatScope, JoinRoleEnum};
+        use crate::transport::BroadcastPolicy;
+
+        let replay = TempReplay::new("chat");
+        let (bc, _buf, state, rx) = make_broadcaster(TickBroadcasterConfig::default());
+        let bc = bc.with_replay_writer(replay.writer());
+
+        {
+            let mut s = state.lock().unwrap();
+            let seat = s
+                .join_player(3, "carol".into(), JoinRoleEnum::Player)
+                .unwrap();
+            let session = Some((seat.player_id, seat.binding));
+            for (channel, text) in [
+                (ChatChannel::All, "gl hf"),
+                (ChatChannel::Team, "slow left"),
+            ] {
+                let send = ChatSend {
+                    channel: channel as i32,
+                    text: text.into(),
+                    ping: None,
+                };
+                s.submit_chat(session, &send, |_: &str| Some(1)).unwrap();
+            }
+        }
+        assert!(bc.fire_one_tick());
+
+        let policies: Vec<_> = rx
+            .try_iter()
+            .filter(|m| m.lockstep_frame.is_none())
+            .map(|m| m.policy)
+            .collect();
+        assert!(matches!(
+            policies.as_slice(),
+            [Some(BroadcastPolicy::All), Some(BroadcastPolicy::Team(1))]
+        ));
+        let recorded: Vec<_> = replay
+            .records()
+            .into_iter()
+            .filter_map(|r| match r {
+                ReplayRecord::Chat(c) => Some((c.scope, c.text)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            recorded,
+            vec![
+                (ChatScope::All, "gl hf".to_string()),
+                (ChatScope::Team(1), "slow left".to_string())
+            ]
+        );
+        assert!(state.lock().unwrap().take_pending_chats().is_empty());
+    }
+
+    /// 階段 6.4：客戶端回報與廣播雜湊不一致時，寫入 replay 標記並
+    /// 傾印最近的快照。
+    #[test]
+    fn desync_report_writes_replay_marker_and_snapshot_dump() {
+        let cfg = TickBroadcasterConfig {
+            tick_period_us: LockstepTiming::DEFAULT.tick_period_us(),
+            step_fps: LockstepTiming::DEFAULT.step_fps(),
+            state_hash_interval: 2,
+            input_evict_interval: LockstepTiming::DEFAULT.ticks_for_seconds(1),
+            input_retention_ticks: LockstepTiming::DEFAULT.ticks_for_seconds(2),
+        };
+        let replay = TempReplay::new("desync");
+        let store = Arc::new(Mutex::new(crate::comp::SnapshotStore {
+            tick: 1,
+            bytes: vec![1, 2, 3],
+        }));
+
+        let (bc, _buf, state, _rx) = make_broadcaster(cfg);
+        let bc = bc
+            .with_replay_writer(replay.writer())
+            .with_desync_dump(replay.dir.join("dumps"), store);
+        // 廣播 tick 2 的雜湊後，玩家 7 回報錯誤的 tick 2 雜湊；下一刻
+        // 取走不同步紀錄。
+        for _ in 0..2 {
+            assert!(bc.fire_one_tick());
+        }
+        assert!(state
+            .lock()
+            .unwrap()
+            .record_client_hash(7, 2, 0xBAD)
+            .is_some());
+        assert!(bc.fire_one_tick());
+
+        let markers: Vec<_> = replay
+            .records()
+            .into_iter()
+            .filter_map(|r| match r {
+                ReplayRecord::DesyncMarker(d) => Some(d),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].tick, 2);
+        assert_eq!(markers[0].diverged, vec![(7, 0xBAD)]);
+        assert_eq!(
+            std::fs::read(replay.dir.join("dumps").join("desync_t2_snap1.bin")).unwrap(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(state.lock().unwrap().desync_stats().desynced_ticks, 1);
+    }
 }
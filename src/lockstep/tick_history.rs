@@ -0,0 +1,237 @@
+//! 階段 6.7：最近 `TickBatch` 的環形緩衝區。
+//!
+//! `TickBroadcaster` 每刻把送出的批次推入共享的
+//! `Arc<Mutex<TickHistory>>`（與 `SnapshotStore` 並列）。kcp 傳輸收到
+//! `SnapshotReq{from_tick}` 時以 `plan_catch_up` 決定回覆內容：
+//!
+//! - 缺的批次仍在緩衝區內 → 只補送批次；
+//! - 否則若最近快照之後的批次都還在 → 快照 + 向前滾動所需的批次；
+//! - 兩者皆不可行 → 無法補齊（客戶端需重新加入）。
+//!
+//! 快照刻度（調度器 `local_tick`）與廣播器刻度在正式伺服器上同時起跑、
+//! 同一節奏前進，因此這裡直接視為同一時間軸，與 bootstrap 快照相同。
+//!
+//! 階段 6.8：同一緩衝區也用於補送單一會話漏掉的批次（出站佇列滿、
+//! 客戶端 `TickAck` NACK），見 `resend_range`。
+
+use std::collections::VecDeque;
+
+use crate::lockstep::TickBatch;
+
+pub struct TickHistory {
+    capacity: usize,
+    batches: VecDeque<TickBatch>,
+}
+
+/// `SnapshotReq` 的補送計畫。
+#[derive(Debug, Clone, PartialEq)]
+pub enum CatchUpPlan {
+    /// `from_tick` 尚未廣播，沒有東西可補。
+    UpToDate,
+    /// 只需補送這些批次（依刻度遞增）。
+    Batches(Vec<TickBatch>),
+    /// 先送最近的快照，再送快照之後的這些批次。
+    SnapshotThenBatches(Vec<TickBatch>),
+    /// 缺口早於緩衝區且沒有可銜接的快照。
+    Unavailable,
+}
+
+impl TickHistory {
+    /// `capacity` 為保留的批次數；0 視為 1。
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            batches: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.batches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    /// 推入新批次；超過容量時丟棄最舊者。刻度不連續（例如重設）時
+    /// 先清空，確保緩衝區永遠是連續區段。
+    pub fn push(&mut self, batch: TickBatch) {
+        if let Some(newest) = self.newest_tick() {
+            if batch.tick != newest.wrapping_add(1) {
+                self.batches.clear();
+            }
+        }
+        if self.batches.len() == self.capacity {
+            self.batches.pop_front();
+        }
+        self.batches.push_back(batch);
+    }
+
+    /// 清空緩衝區（快照還原換時間軸時使用）。
+    pub fn clear(&mut self) {
+        self.batches.clear();
+    }
+
+    pub fn oldest_tick(&self) -> Option<u32> {
+        self.batches.front().map(|b| b.tick)
+    }
+
+    pub fn newest_tick(&self) -> Option<u32> {
+        self.batches.back().map(|b| b.tick)
+    }
+
+    pub fn get(&self, tick: u32) -> Option<&TickBatch> {
+        let oldest = self.oldest_tick()?;
+        let idx = tick.checked_sub(oldest)? as usize;
+        self.batches.get(idx)
+    }
+
+    /// `from_tick` 起（含）到最新的批次。`from_tick` 早於緩衝區時回傳
+    /// `None`，以免呼叫者誤以為已補齊。
+    pub fn range_from(&self, from_tick: u32) -> Option<Vec<TickBatch>> {
+        let oldest = self.oldest_tick()?;
+        if from_tick < oldest {
+            return None;
+        }
+        let skip = (from_tick - oldest) as usize;
+        Some(self.batches.iter().skip(skip).cloned().collect())
+    }
+
+    /// 階段 6.8：`from..=through` 的批次，供落後會話補送。`from` 已被
+    /// 擠出緩衝區時回傳 `None`（只能靠快照或重連修復）。
+    pub fn resend_range(&self, from: u32, through: u32) -> Option<Vec<TickBatch>> {
+        let oldest = self.oldest_tick()?;
+        if from < oldest {
+            return None;
+        }
+        Some(
+            self.batches
+                .iter()
+                .skip((from - oldest) as usize)
+                .take_while(|b| b.tick <= through)
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/// 依緩衝區與最近快照決定如何回覆 `SnapshotReq{from_tick}`。
+/// `from_tick` 是客戶端缺的第一個刻度；`snapshot_tick` 為 `None` 表示
+/// 目前沒有快照可用。
+pub fn plan_catch_up(
+    history: &TickHistory,
+    snapshot_tick: Option<u32>,
+    from_tick: u32,
+) -> CatchUpPlan {
+    if let Some(newest) = history.newest_tick() {
+        if from_tick > newest {
+            return CatchUpPlan::UpToDate;
+        }
+    }
+    if let Some(batches) = history.range_from(from_tick) {
+        return CatchUpPlan::Batches(batches);
+    }
+    let Some(snap_tick) = snapshot_tick else {
+        return CatchUpPlan::Unavailable;
+    };
+    let resume = snap_tick.wrapping_add(1);
+    match (history.oldest_tick(), history.newest_tick()) {
+        // 快照比緩衝區還新（調度器略超前）：快照本身就是最新狀態。
+        (_, Some(newest)) if resume > newest => CatchUpPlan::SnapshotThenBatches(Vec::new()),
+        (Some(_), Some(_)) => match history.range_from(resume) {
+            Some(batches) => CatchUpPlan::SnapshotThenBatches(batches),
+            None => CatchUpPlan::Unavailable,
+        },
+        _ => CatchUpPlan::SnapshotThenBatches(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(tick: u32) -> TickBatch {
+        TickBatch {
+            tick,
+            inputs: vec![],
+            server_events: vec![],
+            lua_content_generation: 0,
+            lua_content_hash: String::new(),
+        }
+    }
+
+    fn filled(capacity: usize, ticks: std::ops::RangeInclusive<u32>) -> TickHistory {
+        let mut h = TickHistory::new(capacity);
+        for t in ticks {
+            h.push(batch(t));
+        }
+        h
+    }
+
+    fn ticks(batches: &[TickBatch]) -> Vec<u32> {
+        batches.iter().map(|b| b.tick).collect()
+    }
+
+    #[test]
+    fn ring_buffer_drops_oldest_beyond_capacity() {
+        let h = filled(4, 1..=10);
+        assert_eq!(h.len(), 4);
+        assert_eq!(h.oldest_tick(), Some(7));
+        assert_eq!(h.newest_tick(), Some(10));
+        assert_eq!(h.get(8).map(|b| b.tick), Some(8));
+        assert!(h.get(6).is_none());
+        assert!(h.get(11).is_none());
+        assert!(h.range_from(6).is_none());
+        assert_eq!(ticks(&h.range_from(9).unwrap()), vec![9, 10]);
+    }
+
+    #[test]
+    fn resend_range_is_bounded_by_window() {
+        let h = filled(4, 1..=10);
+        assert_eq!(ticks(&h.resend_range(8, 9).unwrap()), vec![8, 9]);
+        assert_eq!(ticks(&h.resend_range(9, 20).unwrap()), vec![9, 10]);
+        assert!(h.resend_range(11, 12).unwrap().is_empty());
+        assert!(h.resend_range(6, 8).is_none());
+    }
+
+    #[test]
+    fn non_contiguous_push_resets_window() {
+        let mut h = filled(8, 1..=5);
+        h.push(batch(20));
+        assert_eq!(h.len(), 1);
+        assert_eq!(h.oldest_tick(), Some(20));
+    }
+
+    #[test]
+    fn plan_prefers_batches_when_gap_is_buffered() {
+        let h = filled(8, 11..=18);
+        match plan_catch_up(&h, Some(12), 15) {
+            CatchUpPlan::Batches(b) => assert_eq!(ticks(&b), vec![15, 16, 17, 18]),
+            other => panic!("unexpected plan: {:?}", other),
+        }
+        assert_eq!(plan_catch_up(&h, Some(12), 19), CatchUpPlan::UpToDate);
+    }
+
+    #[test]
+    fn plan_rolls_forward_from_snapshot_when_gap_predates_buffer() {
+        let h = filled(8, 11..=18);
+        match plan_catch_up(&h, Some(14), 3) {
+            CatchUpPlan::SnapshotThenBatches(b) => assert_eq!(ticks(&b), vec![15, 16, 17, 18]),
+            other => panic!("unexpected plan: {:?}", other),
+        }
+        // 快照早於緩衝區：無法銜接。
+        assert_eq!(plan_catch_up(&h, Some(5), 3), CatchUpPlan::Unavailable);
+        // 沒有快照。
+        assert_eq!(plan_catch_up(&h, None, 3), CatchUpPlan::Unavailable);
+        // 快照比最新批次還新：只送快照。
+        assert_eq!(
+            plan_catch_up(&h, Some(18), 3),
+            CatchUpPlan::SnapshotThenBatches(Vec::new())
+        );
+    }
+}
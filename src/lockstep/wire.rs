@@ -0,0 +1,359 @@
+//! 階段 6.x：omb 端新增、尚未進入 `omoba-core` game.proto 的 lockstep
+//! 線上訊息。
+//!
+//! 以 prost derive 手寫，欄位編號即線上格式；日後搬進 proto 時必須
+//! 保持相同編號，客戶端才不需要同步升版。
+
+/// 客戶端回報自己在 `tick` 算出的 state hash（標籤 0x19，C→S）。
+/// `tick` 與伺服器廣播的 `StateHash.tick` 相同（調度器刻度）。
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ClientStateHash {
+    #[prost(uint32, tag = "1")]
+    pub player_id: u32,
+    #[prost(uint32, tag = "2")]
+    pub tick: u32,
+    #[prost(uint64, tag = "3")]
+    pub hash: u64,
+}
+
+/// 客戶端回報 TickBatch 接收進度（標籤 0x1A，C→S）。
+///
+/// `acked_tick` 為已連續收到的最後一個刻度（累積確認，只增不減）；
+/// `missing_ticks` 為之後觀察到的缺口，伺服器從 `TickHistory` 補送。
+/// 兩者可以分開送：純確認時 `missing_ticks` 為空。
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TickAck {
+    #[prost(uint32, tag = "1")]
+    pub acked_tick: u32,
+    #[prost(uint32, repeated, tag = "2")]
+    pub missing_ticks: Vec<u32>,
+}
+
+/// 階段 6.9：重連 token 擴充欄位。不單獨成幀，而是把編碼結果直接接在
+/// `GameStart`（S→C）或 `JoinRequest`（C→S）的編碼後面 — protobuf 串接
+/// 等同合併，舊版對端只會忽略未知的欄位 15。兩個訊息都不得再使用 15 號。
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ResumeToken {
+    #[prost(string, tag = "15")]
+    pub resume_token: String,
+}
+
+/// 階段 6.14：建議輸入提前量，接在 `PingResponse`（S→C）的編碼後面。
+/// 客戶端以 `目前伺服器刻度 + recommended_lead_ticks` 當作 `target_tick`。
+/// 佔用 11–15 號欄位，`PingResponse` 不得再使用這些編號。
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InputLeadHint {
+    #[prost(uint32, tag = "15")]
+    pub recommended_lead_ticks: u32,
+    #[prost(uint32, tag = "14")]
+    pub srtt_us: u32,
+    #[prost(uint32, tag = "13")]
+    pub jitter_us: u32,
+    #[prost(uint32, tag = "12")]
+    pub retargeted_inputs: u32,
+    #[prost(uint32, tag = "11")]
+    pub rejected_late_inputs: u32,
+}
+
+/// 階段 6.13 / 6.14：伺服器量 RTT 用的探針，接在 `PingResponse`（S→C）
+/// 的編碼後面。客戶端收到非 0 的 `probe_id` 時立即送一個接著同樣探針的
+/// `PingRequest`（C→S），伺服器以往返時間當作 RTT 樣本；帶探針的
+/// `PingRequest` 不會再得到新探針。佔用 10 號欄位，兩個訊息都不得再使用。
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PingProbe {
+    #[prost(uint64, tag = "10")]
+    pub probe_id: u64,
+}
+
+/// 階段 6.15：`InputRejected.reason`。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum InputRejectReason {
+    Unspecified = 0,
+    /// 同一刻送出的輸入超過上限。
+    RateLimitedTick = 1,
+    /// 一秒內送出的輸入超過上限。
+    RateLimitedSecond = 2,
+    /// 座標超出地圖範圍。
+    OutOfBounds = 3,
+    /// 技能 / 物品格索引不合法。
+    InvalidSlot = 4,
+    /// `target_tick` 超前太多。
+    FarFuture = 5,
+    /// 晚到超過寬限而被丟棄。
+    Late = 6,
+    /// 連線未加入該座位（觀察者、冒用 id、舊連線）。
+    Unauthorized = 7,
+}
+
+/// 階段 6.15：輸入被拒絕的回覆（標籤 0x1B，S→C，只回給送出者）。
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InputRejected {
+    #[prost(uint32, tag = "1")]
+    pub input_id: u32,
+    #[prost(uint32, tag = "2")]
+    pub target_tick: u32,
+    #[prost(enumeration = "InputRejectReason", tag = "3")]
+    pub reason: i32,
+    #[prost(string, tag = "4")]
+    pub detail: String,
+}
+
+/// 階段 6.19：gRPC `omoba.lockstep.Lockstep/Play` 串流的客戶端訊息（C→S）。
+/// 每個變體對應一個 KCP 幀標籤；KCP 以串接方式附帶的擴充欄位
+/// （`ResumeToken`、`PingProbe`）在這裡是信封上的獨立欄位。
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct LockstepClientFrame {
+    #[prost(oneof = "lockstep_client_frame::Frame", tags = "1, 2, 3, 4, 5, 6, 7")]
+    pub frame: Option<lockstep_client_frame::Frame>,
+    /// 只用於 `Ping`：帶回的 `PingProbe.probe_id`，0 表示沒有。
+    #[prost(uint64, tag = "13")]
+    pub ping_probe_id: u64,
+    /// 只用於 `Join`。
+    #[prost(string, tag = "14")]
+    pub resume_token: String,
+}
+
+pub mod lockstep_client_frame {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Frame {
+        /// 標籤 0x13。
+        #[prost(message, tag = "1")]
+        Join(crate::lockstep::JoinRequest),
+        /// 標籤 0x10。
+        #[prost(message, tag = "2")]
+        Input(crate::lockstep::InputSubmit),
+        /// 標籤 0x15。
+        #[prost(message, tag = "3")]
+        SnapshotReq(crate::lockstep::SnapshotReq),
+        /// 標籤 0x17。
+        #[prost(message, tag = "4")]
+        Ping(omoba_core::game_proto::PingRequest),
+        /// 標籤 0x19。
+        #[prost(message, tag = "5")]
+        ClientStateHash(super::ClientStateHash),
+        /// 標籤 0x1A。
+        #[prost(message, tag = "6")]
+        TickAck(super::TickAck),
+        /// 標籤 0x1C。
+        #[prost(message, tag = "7")]
+        Chat(super::ChatSend),
+    }
+}
+
+/// 階段 6.19：gRPC lockstep 串流的伺服器訊息（S→C），做法同
+/// `LockstepClientFrame`。舊 GameEvent 路徑不經這條串流，改用
+/// `GameService.SubscribeEvents`。
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct LockstepServerFrame {
+    #[prost(oneof = "lockstep_server_frame::Frame", tags = "1, 2, 3, 4, 5, 6, 7")]
+    pub frame: Option<lockstep_server_frame::Frame>,
+    /// 只用於 `Ping`：`PingProbe.probe_id`，0 表示沒有。
+    #[prost(uint64, tag = "13")]
+    pub ping_probe_id: u64,
+    /// 只用於 `GameStart`。
+    #[prost(string, tag = "14")]
+    pub resume_token: String,
+    /// 只用於 `Ping`。
+    #[prost(message, optional, tag = "15")]
+    pub lead_hint: Option<InputLeadHint>,
+}
+
+pub mod lockstep_server_frame {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Frame {
+        /// 標籤 0x14。
+        #[prost(message, tag = "1")]
+        GameStart(crate::lockstep::GameStart),
+        /// 標籤 0x11。
+        #[prost(message, tag = "2")]
+        TickBatch(crate::lockstep::TickBatch),
+        /// 標籤 0x12。
+        #[prost(message, tag = "3")]
+        StateHash(crate::lockstep::StateHash),
+        /// 標籤 0x16。
+        #[prost(message, tag = "4")]
+        SnapshotResp(crate::lockstep::SnapshotResp),
+        /// 標籤 0x18。
+        #[prost(message, tag = "5")]
+        Ping(omoba_core::game_proto::PingResponse),
+        /// 標籤 0x1B。
+        #[prost(message, tag = "6")]
+        InputRejected(super::InputRejected),
+        /// 標籤 0x1D。
+        #[prost(message, tag = "7")]
+        ChatRejected(super::ChatRejected),
+    }
+}
+
+/// 階段 6.24：聊天頻道。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ChatChannel {
+    /// 所有連線（含觀察者）。
+    All = 0,
+    /// 只送給同隊（`TeamRoster`）的連線。
+    Team = 1,
+}
+
+/// 階段 6.24：對局內聊天 / 地圖標記（標籤 0x1C，C→S）。只接受已加入
+/// lockstep 座位的玩家；`ping` 有值時為地圖標記，`text` 可為空。
+/// 伺服器在下一個刻度以 `td/all/res` `chat`/`msg`（或 `chat`/`ping`）
+/// 轉送，並寫進 replay。
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChatSend {
+    #[prost(enumeration = "ChatChannel", tag = "1")]
+    pub channel: i32,
+    #[prost(string, tag = "2")]
+    pub text: String,
+    #[prost(message, optional, tag = "3")]
+    pub ping: Option<MapPing>,
+}
+
+/// 地圖標記位置，世界座標。
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MapPing {
+    #[prost(float, tag = "1")]
+    pub x: f32,
+    #[prost(float, tag = "2")]
+    pub y: f32,
+}
+
+/// 階段 6.24：`ChatRejected.reason`。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ChatRejectReason {
+    Unspecified = 0,
+    /// 送出頻率超過上限。
+    RateLimited = 1,
+    /// 文字超過長度上限。
+    TooLong = 2,
+    /// 沒有文字也沒有標記。
+    Empty = 3,
+    /// 隊伍頻道，但送出者不在任何隊伍。
+    NoTeam = 4,
+    /// 標記位置超出地圖範圍。
+    OutOfBounds = 5,
+    /// 連線未加入座位或是觀察者。
+    Unauthorized = 6,
+}
+
+/// 階段 6.24：聊天被拒絕的回覆（標籤 0x1D，S→C，只回給送出者）。
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChatRejected {
+    #[prost(enumeration = "ChatRejectReason", tag = "1")]
+    pub reason: i32,
+    #[prost(string, tag = "2")]
+    pub detail: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    fn chat_send_round_trips_ping() {
+        let msg = ChatSend {
+            channel: ChatChannel::Team as i32,
+            text: "tower here".into(),
+            ping: Some(MapPing { x: 320.0, y: -48.5 }),
+        };
+        let decoded = ChatSend::decode(msg.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, msg);
+        assert_eq!(decoded.channel(), ChatChannel::Team);
+    }
+
+    #[test]
+    fn client_state_hash_round_trips() {
+        let msg = ClientStateHash {
+            player_id: 3,
+            tick: 1200,
+            hash: 0xDEAD_BEEF_CAFE_F00D,
+        };
+        let decoded = ClientStateHash::decode(msg.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn tick_ack_round_trips() {
+        let msg = TickAck {
+            acked_tick: 500,
+            missing_ticks: vec![503, 504, 507],
+        };
+        let decoded = TickAck::decode(msg.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn resume_token_rides_on_join_request_and_game_start() {
+        use crate::lockstep::{GameStart, JoinRequest};
+
+        let token = ResumeToken {
+            resume_token: "abc123".into(),
+        };
+        let mut join = JoinRequest {
+            player_name: "alice".into(),
+            role: 1,
+            player_id: 7,
+        }
+        .encode_to_vec();
+        join.extend(token.encode_to_vec());
+        assert_eq!(JoinRequest::decode(join.as_slice()).unwrap().player_id, 7);
+        assert_eq!(ResumeToken::decode(join.as_slice()).unwrap(), token);
+
+        let mut start = GameStart {
+            player_id: 7,
+            start_tick: 900,
+            master_seed: 42,
+            initial_state: None,
+            step_fps: 60,
+        }
+        .encode_to_vec();
+        start.extend(token.encode_to_vec());
+        assert_eq!(GameStart::decode(start.as_slice()).unwrap().start_tick, 900);
+        assert_eq!(ResumeToken::decode(start.as_slice()).unwrap(), token);
+
+        // 沒有擴充欄位的舊版 JoinRequest 解出空 token。
+        let plain = JoinRequest::default().encode_to_vec();
+        assert!(ResumeToken::decode(plain.as_slice())
+            .unwrap()
+            .resume_token
+            .is_empty());
+    }
+
+    #[test]
+    fn input_rejected_round_trips_reason() {
+        let msg = InputRejected {
+            input_id: 41,
+            target_tick: 900,
+            reason: InputRejectReason::OutOfBounds as i32,
+            detail: "position (-1, 0) is outside the map".into(),
+        };
+        let decoded = InputRejected::decode(msg.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, msg);
+        assert_eq!(decoded.reason(), InputRejectReason::OutOfBounds);
+    }
+
+    #[test]
+    fn input_lead_hint_rides_on_ping_response() {
+        use omoba_core::game_proto::PingResponse;
+
+        let hint = InputLeadHint {
+            recommended_lead_ticks: 9,
+            srtt_us: 120_000,
+            jitter_us: 8_000,
+            retargeted_inputs: 3,
+            rejected_late_inputs: 1,
+        };
+        let mut pong = PingResponse {
+            client_send_us: 77,
+        }
+        .encode_to_vec();
+        pong.extend(hint.encode_to_vec());
+        pong.extend(PingProbe { probe_id: 5 }.encode_to_vec());
+        assert_eq!(PingResponse::decode(pong.as_slice()).unwrap().client_send_us, 77);
+        assert_eq!(InputLeadHint::decode(pong.as_slice()).unwrap(), hint);
+        assert_eq!(PingProbe::decode(pong.as_slice()).unwrap().probe_id, 5);
+    }
+}
@@ -115,16 +115,6 @@ async fn main() -> std::result::Result<(), Error> {
     let server_port = CONFIG.SERVER_PORT.clone();
     let client_id = CONFIG.CLIENT_ID.clone();
 
-    #[cfg(feature = "mqtt")]
-    let handle = transport::mqtt_transport::start(
-        server_addr.clone(),
-        server_port.clone(),
-        client_id.clone(),
-    )?;
-
-    #[cfg(feature = "grpc")]
-    let handle = transport::grpc_transport::start(server_addr.clone(), server_port.clone()).await?;
-
     // 步驟 2 鎖定步驟：預先建立共用狀態，以便我們可以將其傳遞給
     // kcp 傳輸（任務 2.3 — 處理 0x10/0x13/0x15）和
     // TickBroadcaster（在下面生成）。 MasterSeed::default() 傳回相同的結果
@@ -134,26 +124,111 @@ async fn main() -> std::result::Result<(), Error> {
     // 階段 5.3 新增了第三個 Arc<Mutex<>> — SnapshotStore — 由以下人員編寫
     // 調度程式每 30 秒循環一次並由 kcp 傳輸讀取
     // 0x16 SnapshotResp 處理程序。
+    //
+    // 階段 6.7：第四個 Arc — TickHistory — 由 TickBroadcaster 每刻寫入，
+    // kcp 傳輸以它和 SnapshotStore 回覆 SnapshotReq 補送。
+    // 階段 6.9：斷線座位寬限由 `[lockstep] resume_grace_seconds` 換算成刻度。
+    // 階段 6.11：觀察者延遲與上限同樣掛在 LockstepState 上。
+    // 階段 6.13：落後預算與策略亦同。
+    // 階段 6.15：輸入驗證上限亦同（地圖範圍待 State 建立後補上）。
     #[cfg(feature = "kcp")]
-    let (lockstep_state_handle, input_buffer_handle, snapshot_store_handle) = {
-        use crate::lockstep::{InputBuffer, LockstepState};
+    let (lockstep_state_handle, input_buffer_handle, snapshot_store_handle, tick_history_handle) = {
+        use crate::lockstep::{InputBuffer, LockstepState, TickHistory};
         use std::sync::{Arc, Mutex as StdMutex};
+        let lockstep_setting = crate::config::server_config::read_lockstep_setting();
         let master_seed = crate::comp::MasterSeed::default().0;
-        let lockstep_state = Arc::new(StdMutex::new(LockstepState::new(master_seed)));
+        let mut lockstep_state = LockstepState::new(master_seed);
+        lockstep_state.resume_grace_ticks =
+            lockstep_timing.ticks_for_seconds(lockstep_setting.resume_grace_seconds);
+        lockstep_state.spectator_delay_ticks =
+            lockstep_timing.ticks_for_seconds(lockstep_setting.spectator_delay_seconds);
+        lockstep_state.max_observers = lockstep_setting.max_observers;
+        lockstep_state.lag_ack_budget_ticks =
+            lockstep_timing.ticks_for_seconds(lockstep_setting.lag_ack_budget_seconds);
+        lockstep_state.lag_rtt_budget_us = lockstep_setting.lag_rtt_budget_ms.saturating_mul(1000);
+        lockstep_state.lag_policy = crate::lockstep::LagPolicy::parse(&lockstep_setting.lag_policy)
+            .unwrap_or_else(|| {
+                log::warn!(
+                    "unknown [lockstep] lag_policy {:?}; falling back to warn",
+                    lockstep_setting.lag_policy
+                );
+                crate::lockstep::LagPolicy::Warn
+            });
+        lockstep_state.input_limits = crate::lockstep::InputLimits {
+            max_per_tick: lockstep_setting.max_inputs_per_tick,
+            max_per_second: lockstep_setting.max_inputs_per_second,
+            second_ticks: lockstep_timing.step_fps(),
+            max_future_ticks: lockstep_timing
+                .ticks_for_seconds(lockstep_setting.max_input_lead_seconds),
+            map_bounds: lockstep_setting
+                .map_bounds
+                .map(|[min_x, min_y, max_x, max_y]| {
+                    crate::lockstep::MapBounds::from_world(min_x, min_y, max_x, max_y)
+                }),
+            ..Default::default()
+        };
+        lockstep_state.chat_limits = crate::lockstep::ChatLimits {
+            max_chars: lockstep_setting.chat_max_chars,
+            max_per_window: lockstep_setting.chat_max_messages,
+            window_ticks: lockstep_timing.ticks_for_seconds(lockstep_setting.chat_window_seconds),
+        };
+        let lockstep_state = Arc::new(StdMutex::new(lockstep_state));
         let input_buffer = Arc::new(StdMutex::new(InputBuffer::new()));
         let snapshot_store = Arc::new(StdMutex::new(crate::comp::SnapshotStore::default()));
-        (lockstep_state, input_buffer, snapshot_store)
+        // 觀察者的補送窗口落後延遲秒數，緩衝區要一併涵蓋。
+        let history_ticks = lockstep_timing.ticks_for_seconds(
+            lockstep_setting.tick_history_seconds + lockstep_setting.spectator_delay_seconds,
+        );
+        let tick_history = Arc::new(StdMutex::new(TickHistory::new(history_ticks as usize)));
+        (lockstep_state, input_buffer, snapshot_store, tick_history)
     };
 
+    // 階段 6.18：所有啟用的傳輸同時運行，由 `transport::start_all` 合併成
+    // 一個 TransportHandle。KCP 與 WebSocket 共用同一個 `LockstepShared`，
+    // 原生與瀏覽器客戶端因此在同一場對局；MQTT broker 埠見 `MQTT_PORT`。
+    let mut transports: Vec<Box<dyn transport::Transport>> = Vec::new();
+    #[cfg(feature = "mqtt")]
+    transports.push(Box::new(transport::mqtt_transport::MqttTransport {
+        server_addr: server_addr.clone(),
+        server_port: CONFIG.mqtt_port(),
+        client_id: client_id.clone(),
+    }));
     #[cfg(feature = "kcp")]
-    let handle = transport::kcp_transport::start(
-        server_addr.clone(),
-        server_port.clone(),
+    let lockstep_shared = transport::kcp_transport::LockstepShared::new(
         input_buffer_handle.clone(),
         lockstep_state_handle.clone(),
         snapshot_store_handle.clone(),
-    )
-    .await?;
+        tick_history_handle.clone(),
+    );
+    // 階段 6.23：`game.toml` 的分隊設定。
+    #[cfg(feature = "kcp")]
+    {
+        let mut roster = lockstep_shared.teams.write().unwrap();
+        for (player, team) in &CONFIG.TEAMS {
+            roster.assign(player.clone(), *team);
+        }
+    }
+    // 階段 6.19：啟用 kcp 時 gRPC 一併提供 lockstep 串流（HTTP/2 上的 bot / 工具）。
+    #[cfg(feature = "grpc")]
+    transports.push(Box::new(transport::grpc_transport::GrpcTransport {
+        server_addr: server_addr.clone(),
+        server_port: server_port.clone(),
+        #[cfg(feature = "kcp")]
+        shared: lockstep_shared.clone(),
+    }));
+    #[cfg(feature = "kcp")]
+    transports.push(Box::new(transport::kcp_transport::KcpTransport {
+        server_addr: server_addr.clone(),
+        server_port: server_port.clone(),
+        shared: lockstep_shared.clone(),
+    }));
+    #[cfg(feature = "websocket")]
+    transports.push(Box::new(transport::websocket_transport::WebSocketTransport {
+        server_addr: server_addr.clone(),
+        server_port: CONFIG.WS_PORT.clone(),
+        shared: lockstep_shared.clone(),
+    }));
+    let handle = transport::start_all(transports).await?;
 
     // === TEMP：P7 檢查點轉儲器 — 測量後恢復 ===
     // 顯示 per-window delta（不是累積！）— 修正前一版誤導。
@@ -206,14 +281,6 @@ async fn main() -> std::result::Result<(), Error> {
     }
     // === 結束溫度 ===
 
-    // 防止同時啟用多個傳輸功能
-    #[cfg(all(feature = "mqtt", feature = "grpc"))]
-    compile_error!("Cannot enable both 'mqtt' and 'grpc' features simultaneously");
-    #[cfg(all(feature = "mqtt", feature = "kcp"))]
-    compile_error!("Cannot enable both 'mqtt' and 'kcp' features simultaneously");
-    #[cfg(all(feature = "grpc", feature = "kcp"))]
-    compile_error!("Cannot enable both 'grpc' and 'kcp' features simultaneously");
-
     thread::sleep(Duration::from_millis(500));
 
     // 初始化 ECS
@@ -237,6 +304,26 @@ async fn main() -> std::result::Result<(), Error> {
     // 從提供 0x16 SnapshotResp 時讀取。
     #[cfg(feature = "kcp")]
     state.attach_snapshot_store(snapshot_store_handle.clone());
+    // 階段 6.6：`[lockstep] resume_snapshot` 指定快照時，世界與 lockstep
+    // 時間軸都從快照刻度繼續（須在 TickBroadcaster 啟動前）。
+    #[cfg(feature = "kcp")]
+    {
+        let resume = crate::config::server_config::read_lockstep_setting().resume_snapshot;
+        if !resume.is_empty() {
+            match std::fs::read(&resume)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| state.restore_snapshot(&bytes))
+            {
+                Ok(report) => crate::lockstep::rewind_lockstep(
+                    report.tick,
+                    &lockstep_state_handle,
+                    &input_buffer_handle,
+                    &tick_history_handle,
+                ),
+                Err(e) => log::error!("Failed to resume from snapshot {}: {}", resume, e),
+            }
+        }
+    }
     // 階段 5.x 橋接器：與 TickBroadcaster 的 host_input_tx 配對的接收器
     // （連線如下）。 State::tick Drains 每個tick 排出的輸入批次，並且
     // 將它們鏡像到調度程式的 PendingPlayerInputs 中。
@@ -265,6 +352,12 @@ async fn main() -> std::result::Result<(), Error> {
         use crate::lockstep::{TickBroadcaster, TickBroadcasterConfig};
         let (state_hash_tx, state_hash_rx) = crossbeam_channel::unbounded();
         state.set_state_hash_tx(state_hash_tx);
+        // 階段 6.12：開波 / 對局結束事件放進 TickBatch.server_events。
+        let (server_event_tx, server_event_rx) = crossbeam_channel::unbounded();
+        state.set_server_event_tx(server_event_tx);
+        // 階段 6.13：落後策略的暫停 / 解除。
+        let (lag_pause_tx, lag_pause_rx) = crossbeam_channel::unbounded();
+        state.set_lag_pause_rx(lag_pause_rx);
         let broadcaster = TickBroadcaster::new(
             TickBroadcasterConfig::from_timing(lockstep_timing),
             input_buffer_handle.clone(),
@@ -272,7 +365,57 @@ async fn main() -> std::result::Result<(), Error> {
             handle.lockstep_tx.clone(),
         )
         .with_state_hash_rx(state_hash_rx)
-        .with_host_input_tx(host_input_tx.clone());
+        .with_host_input_tx(host_input_tx.clone())
+        .with_tick_history(tick_history_handle.clone())
+        .with_server_event_rx(server_event_rx)
+        .with_lag_pause_tx(lag_pause_tx);
+        // 階段 6.1：依 `[lockstep] replay_enabled` 錄製 replay。建立失敗
+        // 只記錄錯誤，不阻擋對局。
+        let lockstep_setting = crate::config::server_config::read_lockstep_setting();
+        let broadcaster = if lockstep_setting.replay_enabled {
+            use crate::lockstep::replay::{default_replay_file_name, ReplayHeader, ReplayWriter};
+            let (master_seed, start_tick) = {
+                let s = lockstep_state_handle.lock().unwrap();
+                (s.master_seed, s.current_tick)
+            };
+            let header = ReplayHeader {
+                master_seed,
+                step_fps: lockstep_timing.step_fps(),
+                start_tick,
+                lua_content_hash: omoba_template_ids::runtime_lua_content_hash()
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default(),
+                story: CONFIG.STORY.clone(),
+                recorded_at_unix_ms: SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|d| d.as_millis() as u64)
+                    .unwrap_or(0),
+            };
+            let path = std::path::Path::new(&lockstep_setting.replay_dir)
+                .join(default_replay_file_name(&header));
+            match ReplayWriter::create(&path, &header, lockstep_timing.ticks_for_seconds(1)) {
+                Ok(writer) => {
+                    log::info!("Lockstep replay recording to {}", path.display());
+                    broadcaster.with_replay_writer(Arc::new(std::sync::Mutex::new(writer)))
+                }
+                Err(e) => {
+                    log::error!("Failed to create replay file {}: {}", path.display(), e);
+                    broadcaster
+                }
+            }
+        } else {
+            broadcaster
+        };
+        // 階段 6.4：客戶端回報不同步時傾印最近的快照。
+        let broadcaster = if lockstep_setting.desync_dump_snapshot {
+            broadcaster.with_desync_dump(
+                lockstep_setting.desync_dump_dir.clone(),
+                snapshot_store_handle.clone(),
+            )
+        } else {
+            broadcaster
+        };
         tokio::spawn(broadcaster.run());
         log::info!(
             "Lockstep TickBroadcaster spawned at {}Hz (period {}us, state_hash every {} ticks)",
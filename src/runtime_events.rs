@@ -29,8 +29,10 @@ pub fn runtime_event_to_outbound(event: RuntimeEvent) -> OutboundMsg {
     };
 
     #[cfg(any(feature = "grpc", feature = "kcp"))]
-    if let Some(policy) = broadcast {
-        msg = msg.with_policy(runtime_broadcast_to_policy(policy));
+    if let Some(policy) =
+        runtime_topic_to_policy(&topic).or_else(|| broadcast.map(runtime_broadcast_to_policy))
+    {
+        msg = msg.with_policy(policy);
     }
 
     msg
@@ -71,7 +73,7 @@ fn typed_runtime_event_to_outbound(event: &RuntimeEvent) -> Option<OutboundMsg>
 }
 
 #[cfg(feature = "kcp")]
-fn game_end_winner(data: &Value) -> String {
+pub(crate) fn game_end_winner(data: &Value) -> String {
     data.get("winner")
         .or_else(|| data.get("result"))
         .and_then(|value| value.as_str())
@@ -94,3 +96,67 @@ pub fn runtime_broadcast_to_policy(policy: RuntimeBroadcast) -> BroadcastPolicy
         RuntimeBroadcast::PlayerOnly(player) => BroadcastPolicy::PlayerOnly(player),
     }
 }
+
+/// 階段 6.23：omoba-core 的 `RuntimeBroadcast` 沒有隊伍 / 排除對象，改由
+/// 事件主題指定：`td/team/{隊伍編號}/res` 轉成 `BroadcastPolicy::Team`，
+/// `td/except/{玩家}/res` 轉成 `BroadcastPolicy::AllExcept`。主題指定的對象
+/// 優先於 `RuntimeBroadcast`，不會被放寬成全體廣播；其他主題回傳 `None`。
+#[cfg(any(feature = "grpc", feature = "kcp"))]
+pub fn runtime_topic_to_policy(topic: &str) -> Option<BroadcastPolicy> {
+    let audience = topic.strip_prefix("td/")?.strip_suffix("/res")?;
+    match audience.split_once('/')? {
+        ("team", team) => match team.parse() {
+            Ok(team) => Some(BroadcastPolicy::Team(team)),
+            Err(_) => {
+                log::warn!("runtime event topic '{}' has no valid team id", topic);
+                None
+            }
+        },
+        ("except", player) if !player.is_empty() => {
+            Some(BroadcastPolicy::AllExcept(player.to_string()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(all(test, any(feature = "grpc", feature = "kcp")))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(topic: &str, broadcast: Option<RuntimeBroadcast>) -> RuntimeEvent {
+        RuntimeEvent {
+            topic: topic.to_string(),
+            kind: "team".to_string(),
+            action: "ping".to_string(),
+            data: json!({ "x": 1 }),
+            entity_pos: None,
+            broadcast,
+        }
+    }
+
+    #[test]
+    fn team_and_except_topics_become_policies() {
+        let team = runtime_event_to_outbound(event("td/team/2/res", Some(RuntimeBroadcast::All)));
+        assert!(matches!(team.policy, Some(BroadcastPolicy::Team(2))));
+
+        let except = runtime_event_to_outbound(event("td/except/alice/res", None));
+        assert!(matches!(
+            &except.policy,
+            Some(BroadcastPolicy::AllExcept(name)) if name == "alice"
+        ));
+
+        let player = runtime_event_to_outbound(event(
+            "td/bob/res",
+            Some(RuntimeBroadcast::PlayerOnly("bob".to_string())),
+        ));
+        assert!(matches!(
+            &player.policy,
+            Some(BroadcastPolicy::PlayerOnly(name)) if name == "bob"
+        ));
+
+        assert!(runtime_topic_to_policy("td/team/red/res").is_none());
+        assert!(runtime_topic_to_policy("td/except//res").is_none());
+        assert!(runtime_topic_to_policy("td/all/res").is_none());
+    }
+}
@@ -0,0 +1,190 @@
+//! 階段 6.25：玩家命令（`tower` / `player`）的處理結果。
+//!
+//! `ResourceManager` 的每個命令 handler 回傳 `CommandResult`，由
+//! `handle_tower_request` / `handle_player_request` 放進既有的
+//! `tower`/`R`、`player`/`R` 回覆：成功為 `status: "completed"`；被拒絕為
+//! `status: "rejected"`，另帶 `code`（穩定的 snake_case 代碼，前端據此
+//! 顯示「金幣不足」等文字）與 `detail`（給開發者看的說明）。
+//!
+//! KCP 的 `CommandAck` 在命令排入 `State` 佇列時就回覆，只代表「已收到」；
+//! 命令實際是否執行以這則回覆為準。
+
+use serde_json::json;
+
+use crate::comp::tower_upgrade_rules::UpgradeRejection;
+
+/// 拒絕代碼。`as_str` 的字串是對外協定的一部分，只能新增不能改名。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectCode {
+    /// 命令在目前的遊戲模式（非 TD）下不適用。
+    WrongMode,
+    /// payload 缺少必要欄位。
+    MissingField,
+    /// 技能 / 背包 slot 無效。
+    InvalidSlot,
+    /// 未知的塔種類或裝備 id。
+    UnknownKind,
+    /// 未知的命令 action。
+    UnknownAction,
+    /// 找不到玩家英雄。
+    NoHero,
+    /// 找不到目標（塔 id、entity id）。
+    NotFound,
+    InsufficientGold,
+    /// 蓋塔位置壓到禁建 region。
+    RegionCollision,
+    /// 蓋塔位置壓到出怪路徑。
+    PathCollision,
+    /// 蓋塔位置與其他塔重疊。
+    TowerOverlap,
+    /// 波次正在進行或關卡已結束。
+    RoundUnavailable,
+    /// 升級 path 不在 0..=2。
+    InvalidPath,
+    AlreadyMaxed,
+    TwoPrimaryPaths,
+    TwoSecondaryPaths,
+    ThirdPathLocked,
+    /// 該 path / 等級沒有升級定義。
+    NoUpgrade,
+    /// slot 沒有綁定技能。
+    NoAbility,
+    NotLearned,
+    OnCooldown,
+    NoSkillPoints,
+    MaxLevel,
+    /// 不在基地購買範圍內。
+    OutOfRange,
+    /// 合成缺少組件。
+    MissingComponents,
+    InventoryFull,
+    /// 背包 slot 是空的。
+    EmptySlot,
+    /// 裝備沒有主動效果。
+    NoActive,
+    /// 伺服器端資料不一致（缺 metadata、spawn 失敗）。
+    Internal,
+}
+
+impl RejectCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectCode::WrongMode => "wrong_mode",
+            RejectCode::MissingField => "missing_field",
+            RejectCode::InvalidSlot => "invalid_slot",
+            RejectCode::UnknownKind => "unknown_kind",
+            RejectCode::UnknownAction => "unknown_action",
+            RejectCode::NoHero => "no_hero",
+            RejectCode::NotFound => "not_found",
+            RejectCode::InsufficientGold => "insufficient_gold",
+            RejectCode::RegionCollision => "region_collision",
+            RejectCode::PathCollision => "path_collision",
+            RejectCode::TowerOverlap => "tower_overlap",
+            RejectCode::RoundUnavailable => "round_unavailable",
+            RejectCode::InvalidPath => "invalid_path",
+            RejectCode::AlreadyMaxed => "already_maxed",
+            RejectCode::TwoPrimaryPaths => "two_primary_paths",
+            RejectCode::TwoSecondaryPaths => "two_secondary_paths",
+            RejectCode::ThirdPathLocked => "third_path_locked",
+            RejectCode::NoUpgrade => "no_upgrade",
+            RejectCode::NoAbility => "no_ability",
+            RejectCode::NotLearned => "not_learned",
+            RejectCode::OnCooldown => "on_cooldown",
+            RejectCode::NoSkillPoints => "no_skill_points",
+            RejectCode::MaxLevel => "max_level",
+            RejectCode::OutOfRange => "out_of_range",
+            RejectCode::MissingComponents => "missing_components",
+            RejectCode::InventoryFull => "inventory_full",
+            RejectCode::EmptySlot => "empty_slot",
+            RejectCode::NoActive => "no_active",
+            RejectCode::Internal => "internal",
+        }
+    }
+}
+
+impl From<UpgradeRejection> for RejectCode {
+    fn from(rej: UpgradeRejection) -> Self {
+        match rej {
+            UpgradeRejection::AlreadyMaxed => RejectCode::AlreadyMaxed,
+            UpgradeRejection::TwoPrimaryPaths => RejectCode::TwoPrimaryPaths,
+            UpgradeRejection::TwoSecondaryPaths => RejectCode::TwoSecondaryPaths,
+            UpgradeRejection::ThirdPathLocked => RejectCode::ThirdPathLocked,
+        }
+    }
+}
+
+/// 單一命令的結果。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandResult {
+    Ok,
+    Rejected { code: RejectCode, detail: String },
+}
+
+impl CommandResult {
+    pub fn rejected(code: RejectCode, detail: impl Into<String>) -> Self {
+        CommandResult::Rejected {
+            code,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        matches!(self, CommandResult::Ok)
+    }
+
+    /// `tower`/`R`、`player`/`R` 回覆的 `d`。
+    pub fn response(&self, action: &str, player: &str) -> serde_json::Value {
+        match self {
+            CommandResult::Ok => json!({
+                "action": action,
+                "status": "completed",
+                "player": player,
+            }),
+            CommandResult::Rejected { code, detail } => json!({
+                "action": action,
+                "status": "rejected",
+                "code": code.as_str(),
+                "detail": detail,
+                "player": player,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completed_response_keeps_the_old_shape() {
+        let body = CommandResult::Ok.response("create", "alice");
+        assert_eq!(
+            body,
+            json!({ "action": "create", "status": "completed", "player": "alice" })
+        );
+    }
+
+    #[test]
+    fn rejection_carries_code_and_detail() {
+        let result = CommandResult::rejected(RejectCode::InsufficientGold, "need 120, have 80");
+        assert!(!result.is_ok());
+        let body = result.response("buy_item", "bob");
+        assert_eq!(body["status"], "rejected");
+        assert_eq!(body["code"], "insufficient_gold");
+        assert_eq!(body["detail"], "need 120, have 80");
+        assert_eq!(body["player"], "bob");
+    }
+
+    #[test]
+    fn upgrade_rule_codes_match_upgrade_reject_reasons() {
+        let cases = [
+            (UpgradeRejection::AlreadyMaxed, "already_maxed"),
+            (UpgradeRejection::TwoPrimaryPaths, "two_primary_paths"),
+            (UpgradeRejection::TwoSecondaryPaths, "two_secondary_paths"),
+            (UpgradeRejection::ThirdPathLocked, "third_path_locked"),
+        ];
+        for (rej, reason) in cases {
+            assert_eq!(RejectCode::from(rej).as_str(), reason);
+        }
+    }
+}
@@ -20,7 +20,7 @@ use std::collections::BTreeMap;
 #[cfg(any(feature = "grpc", feature = "kcp"))]
 use std::collections::{HashMap, HashSet};
 
-use super::{ResourceManager, StateInitializer, SystemDispatcher, TimeManager};
+use super::{CommandResult, ResourceManager, StateInitializer, SystemDispatcher, TimeManager};
 
 /// 遊戲核心狀態
 pub struct State {
@@ -81,7 +81,7 @@ pub struct State {
     lockstep_timing: LockstepTiming,
     /// Last observed `player_profile.json` modified time for live hero knowledge reloads.
     hero_knowledge_profile_modified: Option<SystemTime>,
-    /// 上次執行可見度差異時「local_tick」的值
+    /// 上次執行可見度差異 / AOI 興趣更新（階段 6.22）時「local_tick」的值
     last_visibility_tick: u64,
     /// 載入的本機腳本 DLL（H1 — 進程生命週期，從不重新載入）。
     script_registry: ScriptRegistry,
@@ -92,6 +92,8 @@ pub struct State {
     /// 預先收集的（id，pos）傳遞已經使用的心跳。運輸
     /// 廣播線程讀取它以進行“BroadcastPolicy::AoiEntity”查找。
     /// 對於非 kcp 構建，“無”（mqtt/grpc 不驅動 AOI Broadphase）。
+    /// 階段 6.22：由 `update_aoi_interest` 每 `AOI_INTEREST_INTERVAL_TICKS`
+    /// 刻重建，同時更新每位玩家的興趣集合。
     #[cfg(feature = "kcp")]
     aoi_grid: Option<std::sync::Arc<std::sync::Mutex<crate::aoi::AoiGrid>>>,
     /// 階段 3.4：可選的出站通道，發布新計算的結果
@@ -116,6 +118,24 @@ pub struct State {
     /// 120Hz，但仍排空所有可用批次以便短暫 stall 後追上。
     #[cfg(feature = "kcp")]
     host_input_rx: Option<crossbeam_channel::Receiver<Vec<(u32, crate::lockstep::PlayerInput)>>>,
+    /// 階段 6.12：開波 / 對局結束等伺服器權威事件送往
+    /// `TickBroadcaster::with_server_event_rx`，附上發生時的 `local_tick`。
+    /// `None` 時不發布。
+    #[cfg(feature = "kcp")]
+    server_event_tx:
+        Option<crossbeam_channel::Sender<crate::lockstep::tick_broadcaster::ScheduledServerEvent>>,
+    /// 階段 6.12：上一刻的 `CurrentCreepWave.is_running`，用來偵測開波。
+    #[cfg(feature = "kcp")]
+    wave_was_running: bool,
+    /// 階段 6.13：`TickBroadcaster::with_lag_pause_tx` 的接收端。
+    #[cfg(feature = "kcp")]
+    lag_pause_rx: Option<crossbeam_channel::Receiver<crate::lockstep::LagPause>>,
+    /// 階段 6.13：已收到、尚未到生效刻度的落後暫停 / 解除。
+    #[cfg(feature = "kcp")]
+    pending_lag_pauses: std::collections::VecDeque<crate::lockstep::LagPause>,
+    /// 階段 6.13：`GamePause` 是由落後暫停設定的；解除時只清掉自己設的。
+    #[cfg(feature = "kcp")]
+    paused_by_lag: bool,
 }
 
 #[cfg(test)]
@@ -386,6 +406,16 @@ impl State {
             snapshot_store: None,
             #[cfg(feature = "kcp")]
             host_input_rx: None,
+            #[cfg(feature = "kcp")]
+            server_event_tx: None,
+            #[cfg(feature = "kcp")]
+            wave_was_running: false,
+            #[cfg(feature = "kcp")]
+            lag_pause_rx: None,
+            #[cfg(feature = "kcp")]
+            pending_lag_pauses: std::collections::VecDeque::new(),
+            #[cfg(feature = "kcp")]
+            paused_by_lag: false,
         };
 
         state.load_item_registry();
@@ -720,6 +750,16 @@ impl State {
             snapshot_store: None,
             #[cfg(feature = "kcp")]
             host_input_rx: None,
+            #[cfg(feature = "kcp")]
+            server_event_tx: None,
+            #[cfg(feature = "kcp")]
+            wave_was_running: false,
+            #[cfg(feature = "kcp")]
+            lag_pause_rx: None,
+            #[cfg(feature = "kcp")]
+            pending_lag_pauses: std::collections::VecDeque::new(),
+            #[cfg(feature = "kcp")]
+            paused_by_lag: false,
         };
 
         state.load_item_registry();
@@ -739,6 +779,11 @@ impl State {
         self.local_tick = self.local_tick.wrapping_add(1);
         let dt_fixed_raw = self.lockstep_timing.fixed_raw_for_tick(self.local_tick);
 
+        // 階段 6.13：落後暫停 / 解除在宣告的刻度設定 / 清除 GamePause，
+        // 與客戶端同一刻生效。
+        #[cfg(feature = "kcp")]
+        self.apply_lag_pauses();
+
         // 更新時間管理。暫停中仍會繼續收 lockstep input，但 gameplay time 不前進。
         let was_paused = self.ecs.read_resource::<crate::comp::GamePause>().is_paused;
         if was_paused {
@@ -893,6 +938,10 @@ impl State {
         // 維護 ECS
         self.ecs.maintain();
 
+        // 階段 6.22：重建 AOI 網格並更新每位玩家的興趣集合。
+        #[cfg(feature = "kcp")]
+        self.update_aoi_interest();
+
         // 階段 3.4：每隔一段時間發布一個確定性的 ECS 狀態哈希
         // STATE_HASH_INTERVAL_TICKS 調度程式滴答聲（120Hz cadence）。這
         // 120Hz 鎖步 TickBroadcaster 在其上提取最新樣本
@@ -911,6 +960,11 @@ impl State {
             }
         }
 
+        // 階段 6.12：`CurrentCreepWave.is_running` 由 false 轉 true 即為開波，
+        // 在發生的這一刻送出 WaveStart。
+        #[cfg(feature = "kcp")]
+        self.publish_wave_start();
+
         // 階段 5.3：序列化新的世界快照以供觀察者重新加入
         // 每個 SNAPSHOT_INTERVAL_TICKS 排程器滴答（= 30 s @ 120Hz）。
         // 跳過刻度 0 — 第一個調度刻度可能會在所有刻度之前運行
@@ -962,6 +1016,10 @@ impl State {
             {
                 log::info!("[hero_knowledge] 偵測到 game_end 事件，data={}", event.data);
                 self.award_kp_on_game_end(&self.ecs, &event.data);
+                #[cfg(feature = "kcp")]
+                self.publish_server_event(crate::lockstep::server_events::game_end(
+                    &crate::runtime_events::game_end_winner(&event.data),
+                ));
             }
         }
         for msg in crate::runtime_events::runtime_events_to_outbound(events) {
@@ -969,6 +1027,67 @@ impl State {
         }
     }
 
+    #[cfg(feature = "kcp")]
+    fn publish_wave_start(&mut self) {
+        let (running, wave) = {
+            let ccw = self.ecs.read_resource::<omoba_core::comp::CurrentCreepWave>();
+            (ccw.is_running, ccw.wave as u32)
+        };
+        if running && !self.wave_was_running {
+            self.publish_server_event(crate::lockstep::server_events::wave_start(wave));
+        }
+        self.wave_was_running = running;
+    }
+
+    /// 階段 6.13：套用生效刻度已到的落後暫停 / 解除。暫停前 `GamePause`
+    /// 已經設定（例如玩家自己暫停）時不接手，解除時也不清掉。
+    #[cfg(feature = "kcp")]
+    fn apply_lag_pauses(&mut self) {
+        if let Some(rx) = self.lag_pause_rx.as_ref() {
+            self.pending_lag_pauses.extend(rx.try_iter());
+        }
+        while let Some(pause) = self.pending_lag_pauses.front().copied() {
+            if pause.tick as u64 > self.local_tick {
+                break;
+            }
+            self.pending_lag_pauses.pop_front();
+            if (pause.tick as u64) < self.local_tick {
+                log::warn!(
+                    "[lockstep] lag pause for tick {} applied late at local tick {}",
+                    pause.tick,
+                    self.local_tick
+                );
+            }
+            let mut game_pause = self.ecs.write_resource::<crate::comp::GamePause>();
+            if pause.paused {
+                log::warn!(
+                    "[lockstep] match pausing from tick {}: player lagging",
+                    pause.tick
+                );
+                if !game_pause.is_paused {
+                    game_pause.is_paused = true;
+                    self.paused_by_lag = true;
+                }
+            } else {
+                log::info!("[lockstep] match resuming from tick {}", pause.tick);
+                if self.paused_by_lag {
+                    game_pause.is_paused = false;
+                    self.paused_by_lag = false;
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "kcp")]
+    fn publish_server_event(&self, event: crate::lockstep::ServerEvent) {
+        if let Some(tx) = &self.server_event_tx {
+            // u32 包裝與 TickBatch.tick 欄位相符。
+            if let Err(e) = tx.send((self.local_tick as u32, event)) {
+                log::warn!("State: failed to publish server event: {e}");
+            }
+        }
+    }
+
     fn award_kp_on_game_end(&self, world: &World, data: &serde_json::Value) {
         use crate::config::server_config::read_hero_knowledge_setting;
         use crate::knowledge::kp_reward::{award_kp, KpRewardConfig};
@@ -1046,17 +1165,113 @@ impl State {
                     // 實體 → 全部包括在內）。
                     self.hb_last_hp_sent.remove(&player_name);
                     self.hb_last_full_send.remove(&player_name);
+                    #[cfg(feature = "kcp")]
+                    self.drop_aoi_interest(&player_name);
                 }
             }
         }
     }
 
+    /// 階段 6.22：每 `AOI_INTEREST_INTERVAL_TICKS` 刻以英雄 / 小兵 / 塔 /
+    /// 投射物的位置重建共用 `AoiGrid`，再以每位玩家的視口更新興趣集合，
+    /// 把進入（帶完整狀態）與離開的實體送給該玩家。
+    #[cfg(feature = "kcp")]
+    fn update_aoi_interest(&mut self) {
+        use super::interest::{interest_messages, AOI_INTEREST_INTERVAL_TICKS};
+        use crate::aoi::{AoiEntry, AOI_HYSTERESIS_MARGIN};
+
+        if self.local_tick.wrapping_sub(self.last_visibility_tick) < AOI_INTEREST_INTERVAL_TICKS {
+            return;
+        }
+        let Some(grid) = self.aoi_grid.clone() else {
+            return;
+        };
+        self.last_visibility_tick = self.local_tick;
+
+        let views = super::query::collect_view_entities(&self.ecs, |_, _| true);
+        let mut msgs = Vec::new();
+        {
+            let Ok(mut grid) = grid.lock() else {
+                return;
+            };
+            grid.rebuild(views.iter().map(|v| AoiEntry {
+                entity_id: u64::from(v.id),
+                pos: (v.x, v.y),
+            }));
+            if self.client_viewports.is_empty() {
+                return;
+            }
+            let by_id: HashMap<u64, &super::query::ViewEntity> =
+                views.iter().map(|v| (u64::from(v.id), v)).collect();
+            for (player, vp) in &self.client_viewports {
+                let changes = grid.update_interest(
+                    player,
+                    (vp.cx, vp.cy),
+                    (vp.padded_hw, vp.padded_hh),
+                    AOI_HYSTERESIS_MARGIN,
+                );
+                msgs.extend(interest_messages(player, &changes, &by_id));
+            }
+        }
+        for msg in msgs {
+            let _ = self.mqtx.try_send(msg);
+        }
+    }
+
+    /// 丟棄玩家的 AOI 興趣集合；下次更新時視口內的實體重新以完整狀態進入。
+    #[cfg(feature = "kcp")]
+    fn drop_aoi_interest(&self, player_name: &str) {
+        if let Some(grid) = &self.aoi_grid {
+            if let Ok(mut grid) = grid.lock() {
+                grid.drop_interest(player_name);
+            }
+        }
+    }
+
+    /// 測試用：放入 `player_name` 的心跳差異快取與可見集合。
+    #[cfg(all(test, feature = "kcp"))]
+    pub(crate) fn seed_view_caches_for_test(&mut self, player_name: &str) {
+        self.client_visibility
+            .insert(player_name.to_string(), VisSet::default());
+        self.hb_last_hp_sent
+            .insert(player_name.to_string(), HashMap::from([(1, 100)]));
+        self.hb_last_full_send.insert(player_name.to_string(), 0.0);
+    }
+
+    /// 測試用：`player_name` 是否仍留有心跳差異快取或可見集合。
+    #[cfg(all(test, feature = "kcp"))]
+    pub(crate) fn has_view_caches_for_test(&self, player_name: &str) -> bool {
+        self.client_visibility.contains_key(player_name)
+            || self.hb_last_hp_sent.contains_key(player_name)
+            || self.hb_last_full_send.contains_key(player_name)
+    }
+
     /// 處理來自 MCP server 的查詢請求
     #[cfg(any(feature = "grpc", feature = "kcp"))]
-    fn process_queries(&self) {
+    fn process_queries(&mut self) {
         use super::query;
         while let Ok(req) = self.query_rx.try_recv() {
-            let response = match req.query_type.as_str() {
+            // 帶參數的查詢以 `類型:參數` 編碼在 query_type（例如
+            // `state_hash_entities:tower`），不佔用 `player_name`。
+            let (query_type, query_arg) = req
+                .query_type
+                .split_once(':')
+                .unwrap_or((req.query_type.as_str(), ""));
+            let response = match query_type {
+                // 階段 6.21：KCP 會話的 seq-gap 重新同步，`player_name` 為該
+                // 會話訂閱時的名稱。回覆即完整視圖，清掉心跳差異快取與
+                // 可見集合，之後的心跳與可見性差異從頭計算。
+                #[cfg(feature = "kcp")]
+                "seq-gap" => {
+                    self.client_visibility.remove(&req.player_name);
+                    self.hb_last_hp_sent.remove(&req.player_name);
+                    self.hb_last_full_send.remove(&req.player_name);
+                    self.drop_aoi_interest(&req.player_name);
+                    query::query_seq_gap_view(
+                        &self.ecs,
+                        self.client_viewports.get(&req.player_name),
+                    )
+                }
                 "list_players" => query::query_list_players(&self.ecs),
                 "inspect_player_view" => {
                     query::query_inspect_player_view(&self.ecs, &req.player_name)
@@ -1065,6 +1280,10 @@ impl State {
                 "get_ability_detail" => {
                     query::query_get_ability_detail(&self.ecs, &req.player_name)
                 }
+                #[cfg(feature = "kcp")]
+                "state_hash_report" => query::query_state_hash_report(&self.ecs),
+                #[cfg(feature = "kcp")]
+                "state_hash_entities" => query::query_state_hash_entities(&self.ecs, query_arg),
                 other => crate::transport::QueryResponse {
                     success: false,
                     error: format!("Unknown query_type: {}", other),
@@ -1096,6 +1315,24 @@ impl State {
         self.state_hash_tx = Some(tx);
     }
 
+    /// 階段 6.12：註冊調度程式 → 廣播程式伺服器事件通道（開波 / 對局結束）。
+    #[cfg(feature = "kcp")]
+    pub fn set_server_event_tx(
+        &mut self,
+        tx: crossbeam_channel::Sender<crate::lockstep::tick_broadcaster::ScheduledServerEvent>,
+    ) {
+        self.server_event_tx = Some(tx);
+    }
+
+    /// 階段 6.13：註冊落後暫停通道。
+    #[cfg(feature = "kcp")]
+    pub fn set_lag_pause_rx(
+        &mut self,
+        rx: crossbeam_channel::Receiver<crate::lockstep::LagPause>,
+    ) {
+        self.lag_pause_rx = Some(rx);
+    }
+
     /// 階段 5.3：註冊共享快照儲存。調度員勾選
     /// 循環會將其週期性的“serialize_snapshot”輸出鏡像到此
     /// `Arc<Mutex<>>` 因此 KCP 傳輸的 0x16 SnapshotResp 處理程序
@@ -1110,6 +1347,42 @@ impl State {
         self.snapshot_store = Some(store);
     }
 
+    /// 階段 6.6：從快照恢復（當機復原 / 存讀檔 / 從中盤開始測試）。
+    /// 快照內的刻度（調度器刻度）即 `RestoreReport.tick`，之後的 `tick()`
+    /// 從該刻度 + 1 繼續，讓 state hash / 快照節奏與原對局對齊。快照也會
+    /// 寫回 `SnapshotStore`，讓 KCP 的 bootstrap 立即能提供。lockstep
+    /// 端的刻度、輸入緩衝與批次歷史由呼叫端以 `lockstep::rewind_lockstep`
+    /// 對齊。
+    #[cfg(feature = "kcp")]
+    pub fn restore_snapshot(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<crate::lockstep::RestoreReport, String> {
+        let report = crate::lockstep::restore_snapshot(&mut self.ecs, bytes)?;
+        let tick = report.tick;
+        self.local_tick = u64::from(tick);
+        {
+            let mut store = self.ecs.write_resource::<crate::comp::SnapshotStore>();
+            store.tick = tick;
+            store.bytes = bytes.to_vec();
+        }
+        if let Some(shared) = &self.snapshot_store {
+            let mut guard = shared.lock().expect("SnapshotStore mutex poisoned");
+            guard.tick = tick;
+            guard.bytes = bytes.to_vec();
+        }
+        log::info!(
+            "[snapshot] restored tick={} (schema v{}, {} entities, {} deleted, {} remapped, {} unresolved)",
+            tick,
+            report.schema_version,
+            report.updated,
+            report.deleted,
+            report.remapped.len(),
+            report.unresolved.len()
+        );
+        Ok(report)
+    }
+
     /// 階段 5.x 橋接器：註冊與配對的主機輸入接收器
     /// `TickBroadcaster::with_host_input_tx`。每個 `tick()` 都會耗盡待處理的內容
     /// 每個刻度輸入 vecs 並將它們寫入 ECS `PendingPlayerInputs`
@@ -1172,13 +1445,13 @@ impl State {
     }
 
     /// 處理塔相關請求
-    pub fn handle_tower(&mut self, pd: InboundMsg) -> Result<(), Error> {
+    pub fn handle_tower(&mut self, pd: InboundMsg) -> Result<CommandResult, Error> {
         self.resource_manager
             .handle_tower_request(&mut self.ecs, pd)
     }
 
     /// 處理玩家相關請求
-    pub fn handle_player(&mut self, pd: InboundMsg) -> Result<(), Error> {
+    pub fn handle_player(&mut self, pd: InboundMsg) -> Result<CommandResult, Error> {
         self.resource_manager
             .handle_player_request(&mut self.ecs, pd)
     }
@@ -0,0 +1,93 @@
+//! 階段 6.22：AOI 興趣集合的進入 / 離開訊息。
+//!
+//! `State` 每 `AOI_INTEREST_INTERVAL_TICKS` 刻重建共用的 `AoiGrid`，並以
+//! 每位玩家的視口更新其 `InterestSet`（含 `AOI_HYSTERESIS_MARGIN` 遲滯）。
+//! 進入的實體以 `entity.enter` 帶完整狀態（位置、HP、buff）送給該玩家，
+//! 離開的以 `entity.leave` 送出 id。之後 `BroadcastPolicy::AoiEntity` 的
+//! 更新只送給興趣集合包含該實體的會話。
+
+use serde_json::json;
+use std::collections::HashMap;
+
+use crate::aoi::InterestChanges;
+use crate::state::query::ViewEntity;
+use crate::transport::{BroadcastPolicy, OutboundMsg};
+
+/// 興趣更新間隔。120Hz 下約 33ms，與傳輸的批次視窗一致。
+pub(crate) const AOI_INTEREST_INTERVAL_TICKS: u64 = 4;
+
+/// 把一位玩家的興趣變化轉成出站訊息；沒有變化時回傳空。`views` 為本次
+/// 重建時收集的實體狀態，以實體 id 為鍵。
+pub(crate) fn interest_messages(
+    player: &str,
+    changes: &InterestChanges,
+    views: &HashMap<u64, &ViewEntity>,
+) -> Vec<OutboundMsg> {
+    let topic = format!("td/{}/res", player);
+    let mut msgs = Vec::new();
+    if !changes.entered.is_empty() {
+        let entities: Vec<serde_json::Value> = changes
+            .entered
+            .iter()
+            .filter_map(|id| views.get(id).copied())
+            .map(ViewEntity::to_json)
+            .collect();
+        msgs.push(
+            OutboundMsg::new_s(&topic, "entity", "enter", json!({ "entities": entities }))
+                .with_policy(BroadcastPolicy::PlayerOnly(player.to_string())),
+        );
+    }
+    if !changes.left.is_empty() {
+        msgs.push(
+            OutboundMsg::new_s(&topic, "entity", "leave", json!({ "ids": changes.left }))
+                .with_policy(BroadcastPolicy::PlayerOnly(player.to_string())),
+        );
+    }
+    msgs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::query::ViewBuff;
+
+    #[test]
+    fn enter_carries_full_state_and_leave_carries_ids() {
+        let hero = ViewEntity {
+            id: 7,
+            kind: 1,
+            x: 10.0,
+            y: 20.0,
+            hp: 300.0,
+            max_hp: 500.0,
+            buffs: vec![ViewBuff {
+                buff_id: "slow".into(),
+                remaining: 2.0,
+            }],
+        };
+        let views: HashMap<u64, &ViewEntity> = [(7u64, &hero)].into_iter().collect();
+        let changes = InterestChanges {
+            entered: vec![7],
+            left: vec![3, 4],
+        };
+
+        let msgs = interest_messages("alice", &changes, &views);
+        assert_eq!(msgs.len(), 2);
+        for msg in &msgs {
+            assert_eq!(msg.topic, "td/alice/res");
+            assert!(matches!(
+                &msg.policy,
+                Some(BroadcastPolicy::PlayerOnly(name)) if name == "alice"
+            ));
+        }
+        let enter: serde_json::Value = serde_json::from_str(&msgs[0].msg).unwrap();
+        assert_eq!(enter["a"], "enter");
+        assert_eq!(enter["d"]["entities"][0]["hp"], 300.0);
+        assert_eq!(enter["d"]["entities"][0]["buffs"][0]["id"], "slow");
+        let leave: serde_json::Value = serde_json::from_str(&msgs[1].msg).unwrap();
+        assert_eq!(leave["a"], "leave");
+        assert_eq!(leave["d"]["ids"], json!([3, 4]));
+
+        assert!(interest_messages("alice", &InterestChanges::default(), &views).is_empty());
+    }
+}
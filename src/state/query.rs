@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use specs::{Entity, Join, World, WorldExt};
 /// ECS 狀態查詢模塊
@@ -241,3 +242,187 @@ pub fn query_inspect_player_view(world: &World, player_name: &str) -> QueryRespo
         data_json: serde_json::to_vec(&data).unwrap_or_default(),
     }
 }
+
+/// 狀態雜湊分解報告（不同步診斷用）。`total` 與廣播的 `StateHash` 相同，
+/// 其餘欄位為各元件族 / 實體種類的子雜湊。
+#[cfg(feature = "kcp")]
+pub fn query_state_hash_report(world: &World) -> QueryResponse {
+    let report = crate::lockstep::compute_state_hash_report(world);
+    let data = json!({
+        "tick": world.read_resource::<Tick>().0,
+        "report": report,
+    });
+    QueryResponse {
+        success: true,
+        error: String::new(),
+        data_json: serde_json::to_vec(&data).unwrap_or_default(),
+    }
+}
+
+/// 逐實體雜湊表。實體種類篩選（"hero" / "tower" / "creep" / ...）編碼在
+/// query_type 的參數部分（`state_hash_entities:tower`），空字串代表全部，
+/// 無法辨識的名稱回覆錯誤。
+#[cfg(feature = "kcp")]
+pub fn query_state_hash_entities(world: &World, kind_filter: &str) -> QueryResponse {
+    use crate::lockstep::snapshot_producer::EntityKindTag;
+
+    let kind = match kind_filter {
+        "" => None,
+        name => match EntityKindTag::parse(name) {
+            Some(kind) => Some(kind),
+            None => {
+                return QueryResponse {
+                    success: false,
+                    error: format!("Unknown entity kind '{}'", name),
+                    data_json: Vec::new(),
+                }
+            }
+        },
+    };
+    let rows: Vec<_> = crate::lockstep::compute_entity_hashes(world)
+        .into_iter()
+        .filter(|row| kind.map_or(true, |kind| row.kind == kind))
+        .collect();
+    let data = json!({
+        "tick": world.read_resource::<Tick>().0,
+        "entities": rows,
+    });
+    QueryResponse {
+        success: true,
+        error: String::new(),
+        data_json: serde_json::to_vec(&data).unwrap_or_default(),
+    }
+}
+
+/// 階段 6.21：舊 GameEvent 路徑的 seq-gap 重新同步快照。客戶端偵測到
+/// `GameEvent.sequence` 缺口時送 `GameStateRequest{query_type:"seq-gap"}`，
+/// 伺服器在 `GameStateResponse.data_json` 回覆 `SeqGapView` 的 JSON。
+/// `entities` 只含 `viewport` 內（`None` 為全地圖）的實體。
+///
+/// 回覆之前傳輸層已丟棄佇列中的舊 GameEvent，並把會話的 `sequence`
+/// 歸零，所以回覆之後的第一個 GameEvent 序號為 0。
+#[cfg(feature = "kcp")]
+pub fn query_seq_gap_view(
+    world: &World,
+    viewport: Option<&crate::transport::Viewport>,
+) -> QueryResponse {
+    let view = SeqGapView {
+        tick: world.read_resource::<Tick>().0 as u32,
+        game_time: world.read_resource::<TimeOfDay>().0,
+        entities: collect_view_entities(world, |x, y| {
+            viewport.map_or(true, |vp| vp.contains(x, y))
+        }),
+    };
+    QueryResponse {
+        success: true,
+        error: String::new(),
+        data_json: serde_json::to_vec(&view).unwrap_or_default(),
+    }
+}
+
+/// seq-gap 回覆 `data_json` 的格式（客戶端契約）：
+///
+/// ```json
+/// {"tick": 1200, "game_time": 40.0,
+///  "entities": [{"id": 7, "kind": 1, "x": 10.0, "y": 20.0, "hp": 300.0,
+///                "max_hp": 500.0, "buffs": [{"id": "slow", "remaining": 2.0}]}]}
+/// ```
+///
+/// `entities` 的元素與 AOI `entity.enter` 訊息相同（`ViewEntity`）。
+#[cfg(feature = "kcp")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeqGapView {
+    pub tick: u32,
+    /// `TimeOfDay`（秒）。
+    pub game_time: f64,
+    pub entities: Vec<ViewEntity>,
+}
+
+/// seq-gap 快照與 AOI 進入訊息中的一個實體。座標與 HP 為渲染用 f32
+/// （與心跳相同，不參與 lockstep 決定論）。
+#[cfg(feature = "kcp")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewEntity {
+    pub id: u32,
+    /// `EntityKindTag` 的判別值（1 英雄、2 塔、3 小兵、4 投射物）。
+    pub kind: u32,
+    pub x: f32,
+    pub y: f32,
+    pub hp: f32,
+    pub max_hp: f32,
+    /// 依 `buff_id` 排序。
+    pub buffs: Vec<ViewBuff>,
+}
+
+#[cfg(feature = "kcp")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewBuff {
+    #[serde(rename = "id")]
+    pub buff_id: String,
+    /// 剩餘秒數。
+    pub remaining: f32,
+}
+
+#[cfg(feature = "kcp")]
+impl ViewEntity {
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+/// 收集位置符合 `keep(x, y)` 的英雄、小兵、塔與投射物的完整視圖狀態
+/// （seq-gap 快照與階段 6.22 的 AOI 進入訊息共用）。
+#[cfg(feature = "kcp")]
+pub(crate) fn collect_view_entities(
+    world: &World,
+    mut keep: impl FnMut(f32, f32) -> bool,
+) -> Vec<ViewEntity> {
+    use crate::comp::projectile::Projectile;
+    use crate::lockstep::snapshot_producer::classify_entity;
+    use crate::lockstep::EntityKindTag;
+    use omoba_core::runtime::ability_runtime::BuffStore;
+
+    let entities = world.entities();
+    let positions = world.read_storage::<Pos>();
+    let properties = world.read_storage::<CProperty>();
+    let heroes = world.read_storage::<Hero>();
+    let towers = world.read_storage::<Tower>();
+    let projectiles = world.read_storage::<Projectile>();
+    let buff_store = world.try_fetch::<BuffStore>();
+
+    let mut view = Vec::new();
+    for (ent, pos) in (&entities, &positions).join() {
+        let kind = classify_entity(ent, &heroes, &towers, &projectiles, &properties);
+        if kind == EntityKindTag::Other {
+            continue;
+        }
+        let (x, y) = (pos.0.x.to_f32_for_render(), pos.0.y.to_f32_for_render());
+        if !keep(x, y) {
+            continue;
+        }
+        let prop = properties.get(ent);
+        let mut buffs: Vec<ViewBuff> = buff_store
+            .as_ref()
+            .map(|store| {
+                store
+                    .iter_for(ent)
+                    .map(|(buff_id, entry)| ViewBuff {
+                        buff_id: buff_id.to_string(),
+                        remaining: entry.remaining.to_f32_for_render(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        buffs.sort_by(|a, b| a.buff_id.cmp(&b.buff_id));
+        view.push(ViewEntity {
+            id: ent.id(),
+            kind: kind as u32,
+            x,
+            y,
+            hp: prop.map(|p| p.hp.to_f32_for_render()).unwrap_or(0.0),
+            max_hp: prop.map(|p| p.mhp.to_f32_for_render()).unwrap_or(0.0),
+            buffs,
+        });
+    }
+    view
+}
@@ -4,6 +4,7 @@ use failure::Error;
 use specs::{Entity, Join, LendJoin, World, WorldExt};
 
 use crate::comp::*;
+use crate::state::command_result::{CommandResult, RejectCode};
 use crate::transport::{InboundMsg, OutboundMsg};
 use crate::Outcome;
 
@@ -128,87 +129,87 @@ impl ResourceManager {
         Ok(())
     }
 
-    /// 處理塔相關請求
-    pub fn handle_tower_request(&self, world: &mut World, pd: InboundMsg) -> Result<(), Error> {
-        use serde_json::json;
-
-        match pd.a.as_str() {
+    /// 處理塔相關請求。結果（含拒絕代碼）以 `tower`/`R` 回覆並回傳給呼叫端。
+    pub fn handle_tower_request(
+        &self,
+        world: &mut World,
+        pd: InboundMsg,
+    ) -> Result<CommandResult, Error> {
+        let result = match pd.a.as_str() {
             "create" => {
-                self.create_tower(world, &pd)?;
+                let result = self.create_tower(world, &pd)?;
                 log::info!("創建塔: 玩家 {}", pd.name);
+                result
             }
             "upgrade" => {
-                self.upgrade_tower(world, &pd)?;
+                let result = self.upgrade_tower(world, &pd)?;
                 log::info!("升級塔: 玩家 {}", pd.name);
+                result
             }
             "sell" => {
-                self.sell_tower(world, &pd)?;
+                let result = self.sell_tower(world, &pd)?;
                 log::info!("出售塔: 玩家 {}", pd.name);
+                result
             }
             _ => {
                 log::warn!("未知的塔操作: {}", pd.a);
+                CommandResult::rejected(
+                    RejectCode::UnknownAction,
+                    format!("unknown tower action '{}'", pd.a),
+                )
             }
-        }
+        };
 
-        // 發送確認消息
-        let response = json!({
-            "action": pd.a,
-            "status": "completed",
-            "player": pd.name
-        });
+        // 發送結果
+        let response = result.response(&pd.a, &pd.name);
         self.mqtx
             .send(OutboundMsg::new_s("td/all/res", "tower", "R", response))?;
 
-        Ok(())
+        Ok(result)
     }
 
-    /// 處理玩家相關請求
-    pub fn handle_player_request(&self, world: &mut World, pd: InboundMsg) -> Result<(), Error> {
-        use serde_json::json;
-
-        match pd.a.as_str() {
+    /// 處理玩家相關請求。結果（含拒絕代碼）以 `player`/`R` 回覆並回傳給呼叫端。
+    pub fn handle_player_request(
+        &self,
+        world: &mut World,
+        pd: InboundMsg,
+    ) -> Result<CommandResult, Error> {
+        let result = match pd.a.as_str() {
             "move" => {
-                self.move_player(world, &pd)?;
+                let result = self.move_player(world, &pd)?;
                 log::info!("移動玩家: {}", pd.name);
+                result
             }
             "attack" => {
-                self.player_attack(world, &pd)?;
+                let result = self.player_attack(world, &pd)?;
                 log::info!("玩家攻擊: {}", pd.name);
+                result
             }
             "skill" | "cast_ability" => {
-                self.use_skill(world, &pd)?;
+                let result = self.use_skill(world, &pd)?;
                 log::info!("使用技能: 玩家 {}", pd.name);
+                result
             }
-            "upgrade_skill" => {
-                self.upgrade_skill(world, &pd)?;
-            }
-            "buy_item" => {
-                self.buy_item(world, &pd)?;
-            }
-            "sell_item" => {
-                self.sell_item(world, &pd)?;
-            }
-            "use_item" => {
-                self.use_item(world, &pd)?;
-            }
-            "start_round" => {
-                self.start_round(world)?;
-            }
+            "upgrade_skill" => self.upgrade_skill(world, &pd)?,
+            "buy_item" => self.buy_item(world, &pd)?,
+            "sell_item" => self.sell_item(world, &pd)?,
+            "use_item" => self.use_item(world, &pd)?,
+            "start_round" => self.start_round(world)?,
             _ => {
                 log::warn!("未知的玩家操作: {}", pd.a);
+                CommandResult::rejected(
+                    RejectCode::UnknownAction,
+                    format!("unknown player action '{}'", pd.a),
+                )
             }
-        }
+        };
 
-        // 發送確認消息
-        let response = json!({
-            "action": pd.a,
-            "status": "completed",
-            "player": pd.name
-        });
+        // 發送結果
+        let response = result.response(&pd.a, &pd.name);
         self.mqtx
             .send(OutboundMsg::new_s("td/all/res", "player", "R", response))?;
 
-        Ok(())
+        Ok(result)
     }
 
     /// 處理畫面請求
@@ -241,7 +242,7 @@ impl ResourceManager {
     }
 
     // 私有實現方法
-    fn create_tower(&self, world: &mut World, pd: &InboundMsg) -> Result<(), Error> {
+    fn create_tower(&self, world: &mut World, pd: &InboundMsg) -> Result<CommandResult, Error> {
         use specs::{Builder, Join, WorldExt};
         use vek::Vec2;
 
@@ -280,7 +281,7 @@ impl ResourceManager {
                     tatk: tower_attack,
                 },
             });
-            return Ok(());
+            return Ok(CommandResult::Ok);
         }
 
         // ===== TD 模式：unit_id + cost + 碰撞檢查 =====
@@ -292,7 +293,10 @@ impl ResourceManager {
         };
         let Some(tpl) = tpl else {
             log::warn!("未知塔 unit_id '{}'，放棄建造", kind_str);
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::UnknownKind,
+                format!("unknown tower kind '{}'", kind_str),
+            ));
         };
 
         // 找到玩家英雄（TD 地圖保證只有一個）
@@ -311,17 +315,23 @@ impl ResourceManager {
         };
         let Some(hero_entity) = hero_entity else {
             log::warn!("TD 蓋塔：找不到玩家英雄");
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::NoHero,
+                "no player hero",
+            ));
         };
 
         // 金幣檢查
-        let has_gold = {
+        let gold = {
             let golds = world.read_storage::<Gold>();
-            golds.get(hero_entity).map(|g| g.0).unwrap_or(0) >= tpl.cost
+            golds.get(hero_entity).map(|g| g.0).unwrap_or(0)
         };
-        if !has_gold {
+        if gold < tpl.cost {
             log::info!("TD 蓋塔：金幣不足（需要 {}）", tpl.cost);
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::InsufficientGold,
+                format!("'{}' costs {}, have {}", tpl.unit_id, tpl.cost, gold),
+            ));
         }
 
         let placement_radius = td_tower_placement_radius(&tpl);
@@ -337,7 +347,10 @@ impl ResourceManager {
                         pos.y,
                         r.name
                     );
-                    return Ok(());
+                    return Ok(CommandResult::rejected(
+                        RejectCode::RegionCollision,
+                        format!("({:.0},{:.0}) overlaps region '{}'", pos.x, pos.y, r.name),
+                    ));
                 }
             }
         }
@@ -361,7 +374,10 @@ impl ResourceManager {
                             pos.y,
                             name
                         );
-                        return Ok(());
+                        return Ok(CommandResult::rejected(
+                            RejectCode::PathCollision,
+                            format!("({:.0},{:.0}) overlaps path '{}'", pos.x, pos.y, name),
+                        ));
                     }
                 }
             }
@@ -386,12 +402,18 @@ impl ResourceManager {
                     .map(td_tower_placement_radius)
                 else {
                     log::warn!("TD 蓋塔：既有塔缺少 script-owned placement_radius metadata");
-                    return Ok(());
+                    return Ok(CommandResult::rejected(
+                        RejectCode::Internal,
+                        "existing tower has no placement metadata",
+                    ));
                 };
                 let min_d = placement_radius + existing_radius;
                 if d_sq < min_d * min_d {
                     log::info!("TD 蓋塔：位置 ({:.0},{:.0}) 與其他塔重疊", pos.x, pos.y);
-                    return Ok(());
+                    return Ok(CommandResult::rejected(
+                        RejectCode::TowerOverlap,
+                        format!("({:.0},{:.0}) overlaps another tower", pos.x, pos.y),
+                    ));
                 }
             }
         }
@@ -408,7 +430,10 @@ impl ResourceManager {
                 Some(e) => e,
                 None => {
                     log::warn!("spawn_td_tower 失敗 unit_id={}", tpl.unit_id);
-                    return Ok(());
+                    return Ok(CommandResult::rejected(
+                        RejectCode::Internal,
+                        format!("failed to spawn '{}'", tpl.unit_id),
+                    ));
                 }
             };
         world.get_mut::<Searcher>().unwrap().tower.mark_dirty();
@@ -426,19 +451,22 @@ impl ResourceManager {
         // 新 spawn 的 Tower entity，render-side TD build menu 從
         // tower_templates Arc 拿 metadata（sim_runner.rs:88）
 
-        Ok(())
+        Ok(CommandResult::Ok)
     }
 
     /// 處理 TD 模式的 `player/start_round` 指令：把 CurrentCreepWave.is_running
     /// 切成 true、記錄 wave_start_time = totaltime，並廣播 `game/round` 告訴前端。
     /// 非 TD 模式忽略（記 log 但不做事）。
-    fn start_round(&self, world: &mut World) -> Result<(), Error> {
+    fn start_round(&self, world: &mut World) -> Result<CommandResult, Error> {
         use serde_json::json;
 
         let is_td = world.read_resource::<GameMode>().is_td();
         if !is_td {
             log::warn!("start_round 指令在非 TD 模式下被忽略");
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::WrongMode,
+                "start_round is only available in TD mode",
+            ));
         }
         let totaltime = world.read_resource::<Time>().0;
         let (round, total, already) = {
@@ -454,17 +482,20 @@ impl ResourceManager {
         };
         if already {
             log::info!("start_round 忽略：波已在跑或關卡已結束");
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::RoundUnavailable,
+                "a wave is already running or the level is finished",
+            ));
         }
         log::info!("▶️ TD 開始第 {}/{} 波 @ t={:.1}s", round, total, totaltime);
 
         // GameRound broadcast 已砍 — omfx HUD 從 SimWorldSnapshot.round /
         // total_rounds / round_is_running 讀取（sim_runner.rs:57-67）
         let _ = (round, total);
-        Ok(())
+        Ok(CommandResult::Ok)
     }
 
-    fn upgrade_tower(&self, world: &mut World, pd: &InboundMsg) -> Result<(), Error> {
+    fn upgrade_tower(&self, world: &mut World, pd: &InboundMsg) -> Result<CommandResult, Error> {
         use omoba_core::tower_meta::{StatOp, UpgradeEffect};
         use serde_json::json;
         use specs::{Join, WorldExt};
@@ -473,7 +504,10 @@ impl ResourceManager {
         let is_td = world.read_resource::<GameMode>().is_td();
         if !is_td {
             log::warn!("upgrade_tower 指令在非 TD 模式下被忽略");
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::WrongMode,
+                "tower upgrades are only available in TD mode",
+            ));
         }
 
         // 2. 解析 tower_id + path
@@ -481,14 +515,20 @@ impl ResourceManager {
             Some(v) => v as u32,
             None => {
                 log::warn!("TD 升級：payload 缺少 tower_id");
-                return Ok(());
+                return Ok(CommandResult::rejected(
+                    RejectCode::MissingField,
+                    "missing tower_id",
+                ));
             }
         };
         let path = match pd.d.get("path").and_then(|v| v.as_u64()) {
             Some(v) => v as u8,
             None => {
                 log::warn!("TD 升級：payload 缺少 path");
-                return Ok(());
+                return Ok(CommandResult::rejected(
+                    RejectCode::MissingField,
+                    "missing path",
+                ));
             }
         };
         if path >= 3 {
@@ -500,10 +540,13 @@ impl ResourceManager {
                 json!({
                     "tower_id": tower_id_u32,
                     "path": path,
-                    "reason": "invalid_path",
+                    "reason": RejectCode::InvalidPath.as_str(),
                 }),
             ));
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::InvalidPath,
+                format!("path {} is not 0..=2", path),
+            ));
         }
 
         // 3. 找塔 entity 並取 levels + unit_id
@@ -522,7 +565,10 @@ impl ResourceManager {
         };
         let Some((tower_entity, levels, unit_id)) = tower_info else {
             log::warn!("TD 升級：找不到塔 id={}", tower_id_u32);
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::NotFound,
+                format!("no tower with id {}", tower_id_u32),
+            ));
         };
 
         // 4. 規則驗證
@@ -534,18 +580,7 @@ impl ResourceManager {
                 levels,
                 rej
             );
-            let reason = match rej {
-                crate::comp::tower_upgrade_rules::UpgradeRejection::AlreadyMaxed => "already_maxed",
-                crate::comp::tower_upgrade_rules::UpgradeRejection::TwoPrimaryPaths => {
-                    "two_primary_paths"
-                }
-                crate::comp::tower_upgrade_rules::UpgradeRejection::TwoSecondaryPaths => {
-                    "two_secondary_paths"
-                }
-                crate::comp::tower_upgrade_rules::UpgradeRejection::ThirdPathLocked => {
-                    "third_path_locked"
-                }
-            };
+            let code = RejectCode::from(rej);
             let _ = self.mqtx.send(OutboundMsg::new_s(
                 "td/all/res",
                 "tower",
@@ -553,10 +588,13 @@ impl ResourceManager {
                 json!({
                     "tower_id": tower_id_u32,
                     "path": path,
-                    "reason": reason,
+                    "reason": code.as_str(),
                 }),
             ));
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                code,
+                format!("path {} not allowed at levels {:?}", path, levels),
+            ));
         }
         let next_level = levels[path as usize] + 1;
 
@@ -573,7 +611,13 @@ impl ResourceManager {
                 path,
                 next_level
             );
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::NoUpgrade,
+                format!(
+                    "'{}' has no path {} level {} upgrade",
+                    unit_id, path, next_level
+                ),
+            ));
         };
 
         // 6. 找英雄 + 金幣檢查
@@ -592,14 +636,17 @@ impl ResourceManager {
         };
         let Some(hero_entity) = hero_entity else {
             log::warn!("TD 升級：找不到玩家英雄");
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::NoHero,
+                "no player hero",
+            ));
         };
 
-        let has_gold = {
+        let gold = {
             let golds = world.read_storage::<Gold>();
-            golds.get(hero_entity).map(|g| g.0).unwrap_or(0) >= def.cost
+            golds.get(hero_entity).map(|g| g.0).unwrap_or(0)
         };
-        if !has_gold {
+        if gold < def.cost {
             log::info!("TD 升級：金幣不足（需要 {}）", def.cost);
             let _ = self.mqtx.send(OutboundMsg::new_s(
                 "td/all/res",
@@ -608,11 +655,14 @@ impl ResourceManager {
                 json!({
                     "tower_id": tower_id_u32,
                     "path": path,
-                    "reason": "insufficient_gold",
+                    "reason": RejectCode::InsufficientGold.as_str(),
                     "cost": def.cost,
                 }),
             ));
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::InsufficientGold,
+                format!("upgrade costs {}, have {}", def.cost, gold),
+            ));
         }
 
         // 7. 扣錢
@@ -674,25 +724,31 @@ impl ResourceManager {
             def.cost
         );
 
-        Ok(())
+        Ok(CommandResult::Ok)
     }
 
     /// TD 模式賣塔：退 85% 建造費、刪掉塔 entity、廣播 delete。
-    fn sell_tower(&self, world: &mut World, pd: &InboundMsg) -> Result<(), Error> {
+    fn sell_tower(&self, world: &mut World, pd: &InboundMsg) -> Result<CommandResult, Error> {
         use serde_json::json;
         use specs::{Join, WorldExt};
 
         let is_td = world.read_resource::<GameMode>().is_td();
         if !is_td {
             log::warn!("sell_tower 指令在非 TD 模式下被忽略");
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::WrongMode,
+                "selling towers is only available in TD mode",
+            ));
         }
 
         let tower_id_u32 = match pd.d.get("tower_id").and_then(|v| v.as_u64()) {
             Some(v) => v as u32,
             None => {
                 log::warn!("TD 賣塔：payload 缺少 tower_id");
-                return Ok(());
+                return Ok(CommandResult::rejected(
+                    RejectCode::MissingField,
+                    "missing tower_id",
+                ));
             }
         };
 
@@ -711,7 +767,10 @@ impl ResourceManager {
         };
         let Some(target_entity) = target_entity else {
             log::warn!("TD 賣塔：找不到塔 id={}", tower_id_u32);
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::NotFound,
+                format!("no tower with id {}", tower_id_u32),
+            ));
         };
 
         // 依 ScriptUnitTag → TowerTemplateRegistry.cost 算退款（85% base + 75% 升級費）
@@ -782,10 +841,10 @@ impl ResourceManager {
         );
 
         log::info!("🏚 TD 賣塔 id={} 退款 {}", tower_id_u32, refund);
-        Ok(())
+        Ok(CommandResult::Ok)
     }
 
-    fn move_player(&self, world: &mut World, pd: &InboundMsg) -> Result<(), Error> {
+    fn move_player(&self, world: &mut World, pd: &InboundMsg) -> Result<CommandResult, Error> {
         use vek::Vec2;
 
         // 解析目標位置
@@ -830,27 +889,38 @@ impl ResourceManager {
         };
 
         // 設定 MoveTarget
-        if let Some(entity) = target_entity {
-            let mut move_targets = world.write_storage::<MoveTarget>();
-            let _ = move_targets.insert(entity, MoveTarget::from_xy_f32(x, y));
-            if let Some(eid) = explicit_id {
-                log::info!("設定 entity {} 移動目標: ({}, {})", eid, x, y);
-            } else {
-                log::info!("設定英雄移動目標: ({}, {})", x, y);
-            }
-        } else if explicit_id.is_none() {
-            log::warn!("找不到英雄實體: {}", pd.name);
+        let Some(entity) = target_entity else {
+            return Ok(match explicit_id {
+                Some(eid) => CommandResult::rejected(
+                    RejectCode::NotFound,
+                    format!("entity {} does not exist", eid),
+                ),
+                None => {
+                    log::warn!("找不到英雄實體: {}", pd.name);
+                    CommandResult::rejected(
+                        RejectCode::NoHero,
+                        format!("no hero for '{}'", pd.name),
+                    )
+                }
+            });
+        };
+        let mut move_targets = world.write_storage::<MoveTarget>();
+        let _ = move_targets.insert(entity, MoveTarget::from_xy_f32(x, y));
+        if let Some(eid) = explicit_id {
+            log::info!("設定 entity {} 移動目標: ({}, {})", eid, x, y);
+        } else {
+            log::info!("設定英雄移動目標: ({}, {})", x, y);
         }
 
-        Ok(())
+        Ok(CommandResult::Ok)
     }
 
-    fn player_attack(&self, _world: &mut World, _pd: &InboundMsg) -> Result<(), Error> {
+    fn player_attack(&self, _world: &mut World, _pd: &InboundMsg) -> Result<CommandResult, Error> {
         // 實現玩家攻擊邏輯
-        Ok(())
+        Ok(CommandResult::Ok)
     }
 
-    fn use_skill(&self, world: &mut World, pd: &InboundMsg) -> Result<(), Error> {
+    fn use_skill(&self, world: &mut World, pd: &InboundMsg) -> Result<CommandResult, Error> {
         use crate::scripting::event::{ScriptEvent, ScriptEventQueue, SkillTarget};
 
         // 插槽 Q/W/E/R → 索引 0..3
@@ -859,7 +929,10 @@ impl ResourceManager {
             Some(i) => i,
             None => {
                 log::warn!("[cast_ability] invalid slot '{}'", slot);
-                return Ok(());
+                return Ok(CommandResult::rejected(
+                    RejectCode::InvalidSlot,
+                    format!("invalid slot '{}'", slot),
+                ));
             }
         };
 
@@ -867,7 +940,10 @@ impl ResourceManager {
             Some(e) => e,
             None => {
                 log::warn!("[cast_ability] no hero for '{}'", pd.name);
-                return Ok(());
+                return Ok(CommandResult::rejected(
+                    RejectCode::NoHero,
+                    format!("no hero for '{}'", pd.name),
+                ));
             }
         };
 
@@ -884,7 +960,10 @@ impl ResourceManager {
                 pd.name,
                 slot
             );
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::NoAbility,
+                format!("slot {} has no ability", slot),
+            ));
         }
 
         // Gate：必須學過 + 不在 CD（防止 client 繞過 UI 直接送命令）
@@ -892,7 +971,12 @@ impl ResourceManager {
             let heroes = world.read_storage::<Hero>();
             let h = match heroes.get(caster) {
                 Some(h) => h,
-                None => return Ok(()),
+                None => {
+                    return Ok(CommandResult::rejected(
+                        RejectCode::NoHero,
+                        format!("no hero for '{}'", pd.name),
+                    ))
+                }
             };
             if !h.can_use_ability(&ability_id) {
                 log::warn!(
@@ -901,14 +985,20 @@ impl ResourceManager {
                     slot,
                     ability_id
                 );
-                return Ok(());
+                return Ok(CommandResult::rejected(
+                    RejectCode::NotLearned,
+                    format!("'{}' has not been learned", ability_id),
+                ));
             }
             if h.is_on_cooldown(&ability_id) {
                 log::warn!(
                     "[cast_ability] hero '{}' slot {} ability '{}' still on cooldown ({:.2}s remaining)",
                     pd.name, slot, ability_id, h.get_cooldown(&ability_id).to_f32_for_render()
                 );
-                return Ok(());
+                return Ok(CommandResult::rejected(
+                    RejectCode::OnCooldown,
+                    format!("'{}' is on cooldown", ability_id),
+                ));
             }
         }
 
@@ -951,7 +1041,7 @@ impl ResourceManager {
                 skill_id: ability_id,
                 target,
             });
-        Ok(())
+        Ok(CommandResult::Ok)
     }
 
     // ===== MVP LoL: skill/item 管理 =====
@@ -986,18 +1076,26 @@ impl ResourceManager {
 
     /// Hero broadcast 已砍 — omfx 從 SimWorldSnapshot.entities[].hero_ext
     /// 讀完整 HeroStatsExt（armor/atk/range/msd/asd/inventory/ability_levels
-    fn upgrade_skill(&self, world: &mut World, pd: &InboundMsg) -> Result<(), Error> {
+    fn upgrade_skill(&self, world: &mut World, pd: &InboundMsg) -> Result<CommandResult, Error> {
         let slot = pd.d.get("slot").and_then(|v| v.as_str()).unwrap_or("");
         let idx = match Self::slot_to_index(slot) {
             Some(i) => i,
             None => {
                 log::warn!("upgrade_skill: 未知 slot '{}'", slot);
-                return Ok(());
+                return Ok(CommandResult::rejected(
+                    RejectCode::InvalidSlot,
+                    format!("invalid slot '{}'", slot),
+                ));
             }
         };
         let hero_e = match self.find_hero_entity(world, &pd.name) {
             Some(e) => e,
-            None => return Ok(()),
+            None => {
+                return Ok(CommandResult::rejected(
+                    RejectCode::NoHero,
+                    format!("no hero for '{}'", pd.name),
+                ))
+            }
         };
         // 成功升級後要 push SkillLearn event；先用 Option 暫存
         let mut learn_info: Option<(String, u8)> = None;
@@ -1006,13 +1104,19 @@ impl ResourceManager {
             if let Some(hero) = heroes.get_mut(hero_e) {
                 if hero.skill_points <= 0 {
                     log::info!("upgrade_skill: 無可用技能點 (slot {})", slot);
-                    return Ok(());
+                    return Ok(CommandResult::rejected(
+                        RejectCode::NoSkillPoints,
+                        "no skill points left",
+                    ));
                 }
                 let ability_id = match hero.abilities.get(idx) {
                     Some(a) => a.clone(),
                     None => {
                         log::warn!("upgrade_skill: 英雄無 slot {} 技能", slot);
-                        return Ok(());
+                        return Ok(CommandResult::rejected(
+                            RejectCode::NoAbility,
+                            format!("slot {} has no ability", slot),
+                        ));
                     }
                 };
                 let cur = hero.ability_levels.get(&ability_id).copied().unwrap_or(0);
@@ -1020,7 +1124,10 @@ impl ResourceManager {
                 // 但 skill_points 還是被扣 1（無實際效果）。改成已滿級就拒絕。
                 if cur >= 5 {
                     log::info!("upgrade_skill: slot {} ({}) 已達滿級 5", slot, ability_id);
-                    return Ok(());
+                    return Ok(CommandResult::rejected(
+                        RejectCode::MaxLevel,
+                        format!("'{}' is already level 5", ability_id),
+                    ));
                 }
                 let new_lvl = cur + 1;
                 hero.ability_levels.insert(ability_id.clone(), new_lvl);
@@ -1044,21 +1151,29 @@ impl ResourceManager {
                 new_level,
             });
         }
-        Ok(())
+        Ok(CommandResult::Ok)
     }
 
-    fn buy_item(&self, world: &mut World, pd: &InboundMsg) -> Result<(), Error> {
+    fn buy_item(&self, world: &mut World, pd: &InboundMsg) -> Result<CommandResult, Error> {
         let item_id =
             pd.d.get("item_id")
                 .and_then(|v| v.as_str())
                 .unwrap_or("")
                 .to_string();
         if item_id.is_empty() {
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::MissingField,
+                "missing item_id",
+            ));
         }
         let hero_e = match self.find_hero_entity(world, &pd.name) {
             Some(e) => e,
-            None => return Ok(()),
+            None => {
+                return Ok(CommandResult::rejected(
+                    RejectCode::NoHero,
+                    format!("no hero for '{}'", pd.name),
+                ))
+            }
         };
         // 取出 item config（Arc clone）
         let item_cfg = {
@@ -1067,7 +1182,10 @@ impl ResourceManager {
                 Some(c) => c,
                 None => {
                     log::warn!("buy_item: 未知 item_id '{}'", item_id);
-                    return Ok(());
+                    return Ok(CommandResult::rejected(
+                        RejectCode::UnknownKind,
+                        format!("unknown item '{}'", item_id),
+                    ));
                 }
             }
         };
@@ -1079,7 +1197,10 @@ impl ResourceManager {
                 let (px, py) = p.xy_f32();
                 if px * px + py * py > 800.0 * 800.0 {
                     log::info!("buy_item: 不在基地範圍內");
-                    return Ok(());
+                    return Ok(CommandResult::rejected(
+                        RejectCode::OutOfRange,
+                        "items can only be bought at the base",
+                    ));
                 }
             }
         }
@@ -1090,67 +1211,96 @@ impl ResourceManager {
         let gold_entry = golds.get_mut(hero_e);
         let inv = invs.get_mut(hero_e);
         let eff = effs.get_mut(hero_e);
-        if let (Some(gold), Some(inv), Some(eff)) = (gold_entry, inv, eff) {
-            // 先尋找並消耗組件槽（若 recipe 不為空）
-            let mut to_consume: Vec<usize> = Vec::new();
-            for req_id in item_cfg.recipe.iter() {
-                if let Some(slot_i) = inv.slots.iter().enumerate().find_map(|(i, s)| match s {
-                    Some(inst) if inst.item_id == *req_id && !to_consume.contains(&i) => Some(i),
-                    _ => None,
-                }) {
-                    to_consume.push(slot_i);
-                }
-            }
-            let recipe_satisfied = to_consume.len() == item_cfg.recipe.len();
-            if !recipe_satisfied {
-                log::info!(
-                    "buy_item: 組件不足 ({} 需要 {:?})",
-                    item_cfg.id,
-                    item_cfg.recipe
-                );
-                return Ok(());
-            }
-            if gold.0 < item_cfg.cost {
-                log::info!("buy_item: 金錢不足 ({}/{})", gold.0, item_cfg.cost);
-                return Ok(());
-            }
-            for idx in to_consume {
-                inv.slots[idx] = None;
+        let (Some(gold), Some(inv), Some(eff)) = (gold_entry, inv, eff) else {
+            return Ok(CommandResult::rejected(
+                RejectCode::Internal,
+                "hero has no gold or inventory",
+            ));
+        };
+        // 先尋找並消耗組件槽（若 recipe 不為空）
+        let mut to_consume: Vec<usize> = Vec::new();
+        for req_id in item_cfg.recipe.iter() {
+            if let Some(slot_i) = inv.slots.iter().enumerate().find_map(|(i, s)| match s {
+                Some(inst) if inst.item_id == *req_id && !to_consume.contains(&i) => Some(i),
+                _ => None,
+            }) {
+                to_consume.push(slot_i);
             }
-            let slot_i = match inv.first_free_slot() {
-                Some(i) => i,
-                None => {
-                    log::info!("buy_item: 背包已滿");
-                    return Ok(());
-                }
-            };
-            gold.0 -= item_cfg.cost;
-            inv.slots[slot_i] = Some(ItemInstance {
-                item_id: item_cfg.id.clone(),
-                cooldown_remaining: 0.0,
-            });
-            eff.dirty = true;
+        }
+        let recipe_satisfied = to_consume.len() == item_cfg.recipe.len();
+        if !recipe_satisfied {
             log::info!(
-                "🛒 買入 {} (slot {}) — 剩餘金錢 {}",
-                item_cfg.name,
-                slot_i,
-                gold.0
+                "buy_item: 組件不足 ({} 需要 {:?})",
+                item_cfg.id,
+                item_cfg.recipe
             );
+            return Ok(CommandResult::rejected(
+                RejectCode::MissingComponents,
+                format!("'{}' needs {:?}", item_cfg.id, item_cfg.recipe),
+            ));
+        }
+        if gold.0 < item_cfg.cost {
+            log::info!("buy_item: 金錢不足 ({}/{})", gold.0, item_cfg.cost);
+            return Ok(CommandResult::rejected(
+                RejectCode::InsufficientGold,
+                format!("'{}' costs {}, have {}", item_cfg.id, item_cfg.cost, gold.0),
+            ));
+        }
+        // 先確定放得下再消耗組件：被消耗的組件槽也算空位，
+        // 否則背包已滿時組件會被吃掉卻買不到。
+        let slot_i = match inv
+            .slots
+            .iter()
+            .enumerate()
+            .find(|(i, s)| s.is_none() || to_consume.contains(i))
+            .map(|(i, _)| i)
+        {
+            Some(i) => i,
+            None => {
+                log::info!("buy_item: 背包已滿");
+                return Ok(CommandResult::rejected(
+                    RejectCode::InventoryFull,
+                    "no free inventory slot",
+                ));
+            }
+        };
+        for idx in to_consume {
+            inv.slots[idx] = None;
         }
+        gold.0 -= item_cfg.cost;
+        inv.slots[slot_i] = Some(ItemInstance {
+            item_id: item_cfg.id.clone(),
+            cooldown_remaining: 0.0,
+        });
+        eff.dirty = true;
+        log::info!(
+            "🛒 買入 {} (slot {}) — 剩餘金錢 {}",
+            item_cfg.name,
+            slot_i,
+            gold.0
+        );
         drop(golds);
         drop(invs);
         drop(effs);
-        Ok(())
+        Ok(CommandResult::Ok)
     }
 
-    fn sell_item(&self, world: &mut World, pd: &InboundMsg) -> Result<(), Error> {
+    fn sell_item(&self, world: &mut World, pd: &InboundMsg) -> Result<CommandResult, Error> {
         let slot_i = pd.d.get("slot").and_then(|v| v.as_u64()).unwrap_or(99) as usize;
         if slot_i >= INVENTORY_SLOTS {
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::InvalidSlot,
+                format!("invalid inventory slot {}", slot_i),
+            ));
         }
         let hero_e = match self.find_hero_entity(world, &pd.name) {
             Some(e) => e,
-            None => return Ok(()),
+            None => {
+                return Ok(CommandResult::rejected(
+                    RejectCode::NoHero,
+                    format!("no hero for '{}'", pd.name),
+                ))
+            }
         };
         let refund = {
             let invs = world.read_storage::<Inventory>();
@@ -1166,32 +1316,44 @@ impl ResourceManager {
                 None
             }
         };
-        if let Some(refund) = refund {
-            let mut golds = world.write_storage::<Gold>();
-            let mut invs = world.write_storage::<Inventory>();
-            let mut effs = world.write_storage::<ItemEffects>();
-            if let (Some(g), Some(inv), Some(eff)) = (
-                golds.get_mut(hero_e),
-                invs.get_mut(hero_e),
-                effs.get_mut(hero_e),
-            ) {
-                inv.slots[slot_i] = None;
-                g.0 += refund;
-                eff.dirty = true;
-                log::info!("💰 賣出 slot {}，退還 {} 金錢，餘 {}", slot_i, refund, g.0);
-            }
+        let Some(refund) = refund else {
+            return Ok(CommandResult::rejected(
+                RejectCode::EmptySlot,
+                format!("inventory slot {} is empty", slot_i),
+            ));
+        };
+        let mut golds = world.write_storage::<Gold>();
+        let mut invs = world.write_storage::<Inventory>();
+        let mut effs = world.write_storage::<ItemEffects>();
+        if let (Some(g), Some(inv), Some(eff)) = (
+            golds.get_mut(hero_e),
+            invs.get_mut(hero_e),
+            effs.get_mut(hero_e),
+        ) {
+            inv.slots[slot_i] = None;
+            g.0 += refund;
+            eff.dirty = true;
+            log::info!("💰 賣出 slot {}，退還 {} 金錢，餘 {}", slot_i, refund, g.0);
         }
-        Ok(())
+        Ok(CommandResult::Ok)
     }
 
-    fn use_item(&self, world: &mut World, pd: &InboundMsg) -> Result<(), Error> {
+    fn use_item(&self, world: &mut World, pd: &InboundMsg) -> Result<CommandResult, Error> {
         let slot_i = pd.d.get("slot").and_then(|v| v.as_u64()).unwrap_or(99) as usize;
         if slot_i >= INVENTORY_SLOTS {
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::InvalidSlot,
+                format!("invalid inventory slot {}", slot_i),
+            ));
         }
         let hero_e = match self.find_hero_entity(world, &pd.name) {
             Some(e) => e,
-            None => return Ok(()),
+            None => {
+                return Ok(CommandResult::rejected(
+                    RejectCode::NoHero,
+                    format!("no hero for '{}'", pd.name),
+                ))
+            }
         };
 
         // 取出裝備 config（需要 active）
@@ -1212,17 +1374,28 @@ impl ResourceManager {
         };
         let cfg = match item_cfg {
             Some(c) => c,
-            None => return Ok(()),
+            None => {
+                return Ok(CommandResult::rejected(
+                    RejectCode::EmptySlot,
+                    format!("inventory slot {} is empty", slot_i),
+                ))
+            }
         };
         if !can_use {
             log::info!("use_item: slot {} CD 中", slot_i);
-            return Ok(());
+            return Ok(CommandResult::rejected(
+                RejectCode::OnCooldown,
+                format!("'{}' is on cooldown", cfg.id),
+            ));
         }
         let active = match &cfg.active {
             Some(a) => a.clone(),
             None => {
                 log::info!("use_item: slot {} 裝備無主動效果", slot_i);
-                return Ok(());
+                return Ok(CommandResult::rejected(
+                    RejectCode::NoActive,
+                    format!("'{}' has no active effect", cfg.id),
+                ));
             }
         };
 
@@ -1276,7 +1449,7 @@ impl ResourceManager {
                 }
             }
         }
-        Ok(())
+        Ok(CommandResult::Ok)
     }
 
     fn get_screen_area_data(
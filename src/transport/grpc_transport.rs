@@ -5,11 +5,31 @@ use log::*;
 use std::thread;
 use tokio::sync::broadcast;
 use tokio_stream::wrappers::BroadcastStream;
+use tonic::transport::server::Router;
 use tonic::{transport::Server, Request, Response, Status};
 
 use super::types::{
     InboundMsg, OutboundMsg, QueryRequest, QueryResponse, TransportHandle, ViewportMsg,
 };
+#[cfg(feature = "kcp")]
+use super::kcp_transport::{
+    client_envelope_frame, read_framed, server_envelope, spawn_session_server, LockstepShared,
+    SessionServer,
+};
+#[cfg(feature = "kcp")]
+use crate::lockstep::wire::{LockstepClientFrame, LockstepServerFrame};
+#[cfg(feature = "kcp")]
+use lockstep_proto::lockstep_server::{Lockstep, LockstepServer};
+#[cfg(feature = "kcp")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "kcp")]
+use tokio::io::AsyncWriteExt;
+#[cfg(feature = "kcp")]
+use tonic::Streaming;
+
+/// 階段 6.19：lockstep 串流橋接用 duplex 緩衝區大小（同 WebSocket）。
+#[cfg(feature = "kcp")]
+const BRIDGE_BUFFER_BYTES: usize = 256 * 1024;
 
 // 包含生成的原始程式碼
 pub mod game_proto {
@@ -19,6 +39,13 @@ pub mod game_proto {
 use game_proto::game_service_server::{GameService, GameServiceServer};
 use game_proto::*;
 
+/// 階段 6.19：lockstep 串流服務 `omoba.lockstep.Lockstep`。由 build.rs 以
+/// `tonic_build::manual` 產生，訊息是 `lockstep::wire` 的手寫信封。
+#[cfg(feature = "kcp")]
+pub mod lockstep_proto {
+    include!(concat!(env!("OUT_DIR"), "/omoba.lockstep.Lockstep.rs"));
+}
+
 /// gRPC服務實現
 pub struct GameServiceImpl {
     /// 將入站訊息（來自玩家）傳送到遊戲邏輯的通道
@@ -158,11 +185,62 @@ impl GameService for GameServiceImpl {
     }
 }
 
-/// 啟動 gRPC 傳輸層。
+/// 啟動 gRPC 傳輸層（只有舊的 command/event/query 服務）。
 ///
 /// 傳回一個“TransportHandle”，其“tx”提供出站訊息
 /// 其“rx”產生入站玩家命令。
 pub async fn start(server_addr: String, server_port: String) -> Result<TransportHandle, Error> {
+    let addr = bind_addr(&server_addr, &server_port)?;
+    let (service, handle) = game_service();
+
+    info!("Starting gRPC server on {}", addr);
+    spawn_server(
+        Server::builder().add_service(GameServiceServer::new(service)),
+        addr,
+    );
+    Ok(handle)
+}
+
+/// 階段 6.19：啟動 gRPC 傳輸層並掛上 lockstep 串流服務。
+///
+/// 每條 `Lockstep/Play` 串流被橋接成一條位元組串流交給 KCP 的
+/// `SessionServer`（做法同 WebSocket），所以 JoinRequest / InputSubmit /
+/// SnapshotReq / Ping 的處理、驗證與 `InputBuffer` / `LockstepState` 都與
+/// KCP 共用；與 `KcpTransport` 共用同一個 `LockstepShared` 時，工具與 bot
+/// 以 HTTP/2 加入同一場對局。
+#[cfg(feature = "kcp")]
+pub async fn start_shared(
+    server_addr: String,
+    server_port: String,
+    shared: LockstepShared,
+) -> Result<TransportHandle, Error> {
+    let addr = bind_addr(&server_addr, &server_port)?;
+    let (service, mut handle) = game_service();
+    let (session, server) = spawn_session_server(shared);
+    // 會話伺服器的舊事件與查詢入口不會被 lockstep 串流用到（舊路徑走
+    // GameService）；視口移除通知沿用會話伺服器的通道。
+    handle.lockstep_tx = session.lockstep_tx;
+    handle.viewport_rx = session.viewport_rx;
+    handle.counter = session.counter;
+    handle.aoi = session.aoi;
+    let lockstep = LockstepServiceImpl {
+        server,
+        _legacy_tx: session.tx,
+        next_stream: AtomicU64::new(0),
+    };
+
+    info!("Starting gRPC server (with lockstep stream) on {}", addr);
+    spawn_server(
+        Server::builder()
+            .add_service(GameServiceServer::new(service))
+            .add_service(LockstepServer::new(lockstep)),
+        addr,
+    );
+    Ok(handle)
+}
+
+/// 建立舊的 `GameService` 與對應的 `TransportHandle`。
+fn game_service() -> (GameServiceImpl, TransportHandle) {
     let (out_tx, out_rx): (Sender<OutboundMsg>, Receiver<OutboundMsg>) = bounded(10000);
     let (in_tx, in_rx): (Sender<InboundMsg>, Receiver<InboundMsg>) = bounded(10000);
 
@@ -194,37 +272,155 @@ pub async fn start(server_addr: String, server_port: String) -> Result<Transport
         query_tx,
     };
 
-    // 將主機名稱解析為可綁定的 SocketAddr（例如“localhost”→“0.0.0.0”）
-    let bind_ip = match server_addr.as_str() {
-        "localhost" | "127.0.0.1" => "0.0.0.0".to_string(),
-        other => other.to_string(),
-    };
-    let addr = format!("{}:{}", bind_ip, server_port);
-    let addr: std::net::SocketAddr = addr
-        .parse()
-        .map_err(|e| failure::err_msg(format!("Invalid address '{}': {}", addr, e)))?;
-
-    info!("Starting gRPC server on {}", addr);
-
-    tokio::spawn(async move {
-        if let Err(e) = Server::builder()
-            .add_service(GameServiceServer::new(service))
-            .serve(addr)
-            .await
-        {
-            error!("gRPC server error: {}", e);
-        }
-    });
-
     // gRPC 不實現視口更新；提供始終為空的通道
     // 因此 State API 與 KCP 傳輸保持一致。
     let (_viewport_tx, viewport_rx): (Sender<ViewportMsg>, Receiver<ViewportMsg>) = bounded(1);
     drop(_viewport_tx);
 
-    Ok(TransportHandle {
+    let handle = TransportHandle {
         tx: out_tx,
+        // 階段 6.18：未掛 lockstep 服務時只補齊欄位；`start_shared` 會換成
+        // 會話伺服器的通道。
+        #[cfg(feature = "kcp")]
+        lockstep_tx: super::types::detached_sender(),
         rx: in_rx,
         query_rx,
         viewport_rx,
-    })
+        #[cfg(feature = "kcp")]
+        counter: std::sync::Arc::new(super::KcpBytesCounter::new()),
+        #[cfg(feature = "kcp")]
+        aoi: std::sync::Arc::new(std::sync::Mutex::new(crate::aoi::AoiGrid::new())),
+    };
+    (service, handle)
+}
+
+/// 將主機名稱解析為可綁定的 SocketAddr（例如“localhost”→“0.0.0.0”）
+fn bind_addr(server_addr: &str, server_port: &str) -> Result<std::net::SocketAddr, Error> {
+    let bind_ip = match server_addr {
+        "localhost" | "127.0.0.1" => "0.0.0.0",
+        other => other,
+    };
+    let addr = format!("{}:{}", bind_ip, server_port);
+    addr.parse()
+        .map_err(|e| failure::err_msg(format!("Invalid address '{}': {}", addr, e)))
+}
+
+fn spawn_server(router: Router, addr: std::net::SocketAddr) {
+    tokio::spawn(async move {
+        if let Err(e) = router.serve(addr).await {
+            error!("gRPC server error: {}", e);
+        }
+    });
+}
+
+/// 階段 6.19：`Lockstep` 服務。
+#[cfg(feature = "kcp")]
+struct LockstepServiceImpl {
+    server: SessionServer,
+    /// 會話伺服器的舊事件通道。lockstep 串流不轉送舊事件，但廣播線程在
+    /// 此通道關閉時結束，所以由服務持有。
+    _legacy_tx: Sender<OutboundMsg>,
+    /// 同一條 HTTP/2 連線可以開多條串流，會話 id 以序號區分。
+    next_stream: AtomicU64,
+}
+
+#[cfg(feature = "kcp")]
+#[tonic::async_trait]
+impl Lockstep for LockstepServiceImpl {
+    type PlayStream = std::pin::Pin<
+        Box<dyn tokio_stream::Stream<Item = Result<LockstepServerFrame, Status>> + Send>,
+    >;
+
+    async fn play(
+        &self,
+        request: Request<Streaming<LockstepClientFrame>>,
+    ) -> Result<Response<Self::PlayStream>, Status> {
+        let peer = request
+            .remote_addr()
+            .map(|a| a.to_string())
+            .unwrap_or_else(|| "unknown".into());
+        let session_id = format!(
+            "grpc_{}#{}",
+            peer,
+            self.next_stream.fetch_add(1, Ordering::Relaxed)
+        );
+        info!("gRPC lockstep stream opened: {}", session_id);
+
+        let (session_io, bridge_io) = tokio::io::duplex(BRIDGE_BUFFER_BYTES);
+        self.server.spawn_client(session_io, session_id.clone());
+        let (mut io_rx, mut io_tx) = tokio::io::split(bridge_io);
+
+        // 入站：信封 → 幀位元組。串流結束時關閉寫端，會話隨之清理。
+        let mut inbound = request.into_inner();
+        tokio::spawn(async move {
+            loop {
+                match inbound.message().await {
+                    Ok(Some(envelope)) => {
+                        let Some(frame) = client_envelope_frame(&envelope) else {
+                            continue;
+                        };
+                        if io_tx.write_all(&frame).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(status) => {
+                        debug!("gRPC lockstep stream {} ended: {}", session_id, status);
+                        break;
+                    }
+                }
+            }
+            let _ = io_tx.shutdown().await;
+        });
+
+        // 出站：會話寫出的幀 → 信封。
+        let output = stream! {
+            loop {
+                match read_framed(&mut io_rx).await {
+                    Ok(Some((tag, payload, _))) => match server_envelope(tag, &payload) {
+                        Ok(Some(envelope)) => yield Ok(envelope),
+                        Ok(None) => {}
+                        Err(e) => warn!("gRPC lockstep: failed to decode frame 0x{:02x}: {}", tag, e),
+                    },
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!("gRPC lockstep outbound frame error: {}", e);
+                        break;
+                    }
+                }
+            }
+        };
+        Ok(Response::new(Box::pin(output)))
+    }
+}
+
+/// 階段 6.18：`Transport` 實作，供 `transport::start_all` 與其他傳輸並行。
+/// 階段 6.19：啟用 `kcp` 時一併掛上 lockstep 串流服務。
+pub struct GrpcTransport {
+    pub server_addr: String,
+    pub server_port: String,
+    #[cfg(feature = "kcp")]
+    pub shared: LockstepShared,
+}
+
+#[cfg(feature = "kcp")]
+impl super::Transport for GrpcTransport {
+    fn kind(&self) -> super::TransportKind {
+        super::TransportKind::Grpc
+    }
+
+    fn start(self: Box<Self>) -> super::StartFuture {
+        Box::pin(start_shared(self.server_addr, self.server_port, self.shared))
+    }
+}
+
+#[cfg(not(feature = "kcp"))]
+impl super::Transport for GrpcTransport {
+    fn kind(&self) -> super::TransportKind {
+        super::TransportKind::Grpc
+    }
+
+    fn start(self: Box<Self>) -> super::StartFuture {
+        Box::pin(start(self.server_addr, self.server_port))
+    }
 }
@@ -7,11 +7,11 @@ use std::thread;
 use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio::sync::Mutex;
 
-use tokio_kcp::{KcpConfig, KcpListener, KcpNoDelayConfig, KcpStream};
+use tokio_kcp::{KcpConfig, KcpListener, KcpNoDelayConfig};
 
 use super::metrics::KcpBytesCounter;
 use super::types::{
-    urgency, BroadcastPolicy, InboundMsg, OutboundMsg, QueryRequest, QueryResponse,
+    urgency, BroadcastPolicy, InboundMsg, OutboundMsg, QueryRequest, QueryResponse, TeamRoster,
     TransportHandle, TypedOutbound, Urgency, Viewport, ViewportMsg,
 };
 use crate::aoi::AoiGrid;
@@ -40,6 +40,21 @@ const TAG_SNAPSHOT_REQ: u8 = 0x15;
 const TAG_SNAPSHOT_RESP: u8 = 0x16;
 const TAG_PING_REQ: u8 = 0x17;
 const TAG_PING_RESP: u8 = 0x18;
+// 階段 6.4：客戶端 state hash 回報（訊息定義見 `lockstep::wire`）。
+const TAG_CLIENT_STATE_HASH: u8 = 0x19;
+// 階段 6.8：TickBatch 累積確認 + NACK（訊息定義見 `lockstep::wire`）。
+const TAG_TICK_ACK: u8 = 0x1A;
+// 階段 6.15：輸入被拒絕的原因回覆（訊息定義見 `lockstep::wire`）。
+const TAG_INPUT_REJECTED: u8 = 0x1B;
+// 階段 6.24：對局內聊天 / 地圖標記與其拒絕回覆（訊息定義見 `lockstep::wire`）。
+const TAG_CHAT_SEND: u8 = 0x1C;
+const TAG_CHAT_REJECTED: u8 = 0x1D;
+/// 單一 TickAck 最多補送的批次數，避免惡意/錯亂的 NACK 塞爆連線。
+const MAX_NACK_RESEND_TICKS: usize = 256;
+/// 單一 SnapshotReq 最多補送的批次數。補送寫在會話的讀取迴圈內，一次寫完
+/// 整個 TickHistory（預設 40 秒）會讓該會話的輸入處理卡住；客戶端收到
+/// 最後一批後以下一刻再送 SnapshotReq 即可接續。
+const MAX_CATCH_UP_RESEND_TICKS: usize = 256;
 const LATE_INPUT_GRACE_MS: u32 = 64;
 
 /// 標籤的高位元 — 當幀有效負載經過 LZ4 壓縮時設定。
@@ -49,13 +64,28 @@ const COMPRESSION_FLAG: u8 = 0x80;
 /// 在嘗試 LZ4 壓縮之前，最小有效負載大小。
 const LZ4_THRESHOLD: usize = 128;
 
-fn late_input_grace_ticks(step_fps: u32) -> u32 {
+pub(crate) fn late_input_grace_ticks(step_fps: u32) -> u32 {
     step_fps
         .saturating_mul(LATE_INPUT_GRACE_MS)
         .saturating_add(999)
         / 1000
 }
 
+/// 階段 6.15：`InputRejected` 回覆的編碼。
+fn input_rejected_payload(
+    input_id: u32,
+    target_tick: u32,
+    rejection: &crate::lockstep::InputRejection,
+) -> Vec<u8> {
+    crate::lockstep::wire::InputRejected {
+        input_id,
+        target_tick,
+        reason: rejection.reason as i32,
+        detail: rejection.detail.clone(),
+    }
+    .encode_to_vec()
+}
+
 /// 寫入幀訊息：[1 位元組標籤][4 位元組 len (big-endian)][N 位元組有效負載]
 /// 當有效負載≥LZ4_THRESHOLD並且LZ4縮小它時，有效負載被替換為
 /// 大小前置的 LZ4 區塊和 COMPRESSION_FLAG 與標籤進行「或」運算。
@@ -99,7 +129,7 @@ async fn write_framed<W: AsyncWriteExt + Unpin>(
 /// 傳回的標籤已移除標誌（呼叫者只能看到 0x01~0x07）。
 /// `wire_bytes` = 1（標籤）+ 4（長度）+ N（原始線上位元組）。
 /// 與 omoba-core::kcp::framing::read_framed 保持同步。
-async fn read_framed<R: AsyncReadExt + Unpin>(
+pub(crate) async fn read_framed<R: AsyncReadExt + Unpin>(
     reader: &mut R,
 ) -> std::io::Result<Option<(u8, Vec<u8>, usize)>> {
     let tag_raw = match reader.read_u8().await {
@@ -150,6 +180,82 @@ fn build_framed_bytes(tag: u8, payload: &[u8]) -> Vec<u8> {
     frame
 }
 
+/// 階段 6.19：gRPC lockstep 信封 → 與 KCP 相同的幀位元組，交給
+/// `SessionServer` 的會話串流。擴充欄位照 KCP 的做法串接在編碼後面。
+/// 空信封回傳 `None`。
+#[cfg(feature = "grpc")]
+pub(crate) fn client_envelope_frame(
+    envelope: &crate::lockstep::wire::LockstepClientFrame,
+) -> Option<Vec<u8>> {
+    use crate::lockstep::wire::lockstep_client_frame::Frame;
+
+    let (tag, mut payload) = match envelope.frame.as_ref()? {
+        Frame::Join(m) => (TAG_JOIN_REQUEST, m.encode_to_vec()),
+        Frame::Input(m) => (TAG_INPUT_SUBMIT, m.encode_to_vec()),
+        Frame::SnapshotReq(m) => (TAG_SNAPSHOT_REQ, m.encode_to_vec()),
+        Frame::Ping(m) => (TAG_PING_REQ, m.encode_to_vec()),
+        Frame::ClientStateHash(m) => (TAG_CLIENT_STATE_HASH, m.encode_to_vec()),
+        Frame::TickAck(m) => (TAG_TICK_ACK, m.encode_to_vec()),
+        Frame::Chat(m) => (TAG_CHAT_SEND, m.encode_to_vec()),
+    };
+    if tag == TAG_JOIN_REQUEST && !envelope.resume_token.is_empty() {
+        payload.extend(
+            crate::lockstep::wire::ResumeToken {
+                resume_token: envelope.resume_token.clone(),
+            }
+            .encode_to_vec(),
+        );
+    }
+    if tag == TAG_PING_REQ && envelope.ping_probe_id != 0 {
+        payload.extend(
+            crate::lockstep::wire::PingProbe {
+                probe_id: envelope.ping_probe_id,
+            }
+            .encode_to_vec(),
+        );
+    }
+    Some(build_framed_bytes(tag, &payload))
+}
+
+/// 階段 6.19：會話送出的幀（已解壓）→ gRPC lockstep 信封。非 lockstep
+/// 標籤（舊 GameEvent、查詢回覆等）回傳 `Ok(None)`。
+#[cfg(feature = "grpc")]
+pub(crate) fn server_envelope(
+    tag: u8,
+    payload: &[u8],
+) -> Result<Option<crate::lockstep::wire::LockstepServerFrame>, prost::DecodeError> {
+    use crate::lockstep::wire::lockstep_server_frame::Frame;
+
+    let mut envelope = crate::lockstep::wire::LockstepServerFrame::default();
+    let frame = match tag {
+        TAG_GAME_START => {
+            envelope.resume_token =
+                crate::lockstep::wire::ResumeToken::decode(payload)?.resume_token;
+            Frame::GameStart(GameStart::decode(payload)?)
+        }
+        TAG_TICK_BATCH => Frame::TickBatch(TickBatch::decode(payload)?),
+        TAG_STATE_HASH => Frame::StateHash(StateHash::decode(payload)?),
+        TAG_SNAPSHOT_RESP => Frame::SnapshotResp(SnapshotResp::decode(payload)?),
+        TAG_PING_RESP => {
+            let hint = crate::lockstep::wire::InputLeadHint::decode(payload)?;
+            if hint.recommended_lead_ticks > 0 {
+                envelope.lead_hint = Some(hint);
+            }
+            envelope.ping_probe_id = crate::lockstep::wire::PingProbe::decode(payload)?.probe_id;
+            Frame::Ping(PingResponse::decode(payload)?)
+        }
+        TAG_INPUT_REJECTED => {
+            Frame::InputRejected(crate::lockstep::wire::InputRejected::decode(payload)?)
+        }
+        TAG_CHAT_REJECTED => {
+            Frame::ChatRejected(crate::lockstep::wire::ChatRejected::decode(payload)?)
+        }
+        _ => return Ok(None),
+    };
+    envelope.frame = Some(frame);
+    Ok(Some(envelope))
+}
+
 /// 每個客戶端會話：持有一個發送者來推送出站事件。
 ///
 /// P5：通道有效負載為「Arc<[u8]>」—廣播線程編碼+壓縮
@@ -173,13 +279,137 @@ struct ClientSession {
     /// 具有此標誌的會話 - 舊 GameEvent 路徑上的用戶端
     /// （第 2 階段過渡期間的 omb-mcp、omfx）不會看到鎖步流量。
     lockstep_joined: bool,
+    /// 階段 6.8：客戶端 `TickAck` 回報的最後連續刻度（只增不減）。
+    lockstep_acked_tick: u32,
+    /// 階段 6.8：出站佇列滿而漏送的第一個刻度。`Some` 時廣播線程
+    /// 先從 `TickHistory` 補送到目前刻度，而不是移除會話。
+    lockstep_resend_from: Option<u32>,
+    /// 階段 6.10：此連線 JoinRequest 拿到的 player_id。InputSubmit 以它
+    /// 為準，不信任 payload 自報的 id。
+    lockstep_player_id: Option<u32>,
+    /// 階段 6.11：觀察者會話。TickBatch / StateHash / bootstrap 快照改走
+    /// `SpectatorDelay` 延遲釋出。
+    lockstep_observer: bool,
+}
+
+/// 階段 6.11：把 `SpectatorDelay` 到期的幀送給觀察者會話。目標為 `None`
+/// 的幀送給所有已加入的觀察者。佇列滿就丟（觀察者可以在可見窗口內
+/// NACK），回傳已斷線的會話 id。
+fn deliver_spectator_frames(
+    sessions: &HashMap<String, ClientSession>,
+    due: Vec<(Option<String>, Arc<[u8]>)>,
+) -> Vec<String> {
+    use tokio::sync::mpsc::error::TrySendError;
+
+    let mut closed = Vec::new();
+    for (target, frame) in due {
+        for (sid, session) in sessions.iter() {
+            if !session.lockstep_joined || !session.lockstep_observer {
+                continue;
+            }
+            if target.as_ref().is_some_and(|t| t != sid) {
+                continue;
+            }
+            if let Err(TrySendError::Closed(_)) = session.event_tx.try_send(frame.clone()) {
+                if !closed.contains(sid) {
+                    closed.push(sid.clone());
+                }
+            }
+        }
+    }
+    closed
+}
+
+/// 階段 6.8：`deliver_tick_batch` 對單一會話的結果。
+#[derive(Debug, PartialEq, Eq)]
+enum TickDelivery {
+    /// 目前刻度（與任何待補送的刻度）都已進入佇列。
+    Sent,
+    /// 佇列滿；記下第一個沒送出的刻度，下個刻度再補。
+    Lagging(u32),
+    /// 待補的刻度已被擠出 `TickHistory`；游標已清除，客戶端需以
+    /// `SnapshotReq` 自行補齊。
+    GapTooOld(u32),
+    /// 客戶端已斷線（接收端關閉）。
+    Closed,
+}
+
+/// 階段 6.8：把 `batch_tick` 的 TickBatch 送給單一會話。落後中的會話先
+/// 從 `history` 依序補送 `resend_from..=batch_tick`，佇列再滿就停下來
+/// 等下一刻。只有接收端關閉才回報 `Closed`。
+fn deliver_tick_batch(
+    session: &mut ClientSession,
+    frame: &Arc<[u8]>,
+    batch_tick: u32,
+    history: &crate::lockstep::TickHistory,
+) -> TickDelivery {
+    use tokio::sync::mpsc::error::TrySendError;
+
+    let Some(from) = session.lockstep_resend_from else {
+        return match session.event_tx.try_send(frame.clone()) {
+            Ok(()) => TickDelivery::Sent,
+            Err(TrySendError::Full(_)) => {
+                session.lockstep_resend_from = Some(batch_tick);
+                TickDelivery::Lagging(batch_tick)
+            }
+            Err(TrySendError::Closed(_)) => TickDelivery::Closed,
+        };
+    };
+    // 客戶端已確認（例如經 NACK 補到）的刻度不必再送。
+    let from = from.max(session.lockstep_acked_tick.wrapping_add(1));
+    let Some(batches) = history.resend_range(from, batch_tick) else {
+        session.lockstep_resend_from = None;
+        return TickDelivery::GapTooOld(from);
+    };
+    let mut last_sent = None;
+    for batch in &batches {
+        let bytes = build_framed_bytes(TAG_TICK_BATCH, &batch.encode_to_vec());
+        match session.event_tx.try_send(Arc::from(bytes.into_boxed_slice())) {
+            Ok(()) => last_sent = Some(batch.tick),
+            Err(TrySendError::Full(_)) => {
+                session.lockstep_resend_from = Some(batch.tick);
+                return TickDelivery::Lagging(batch.tick);
+            }
+            Err(TrySendError::Closed(_)) => return TickDelivery::Closed,
+        }
+    }
+    // 未附加 TickHistory 的設定下緩衝區沒有目前刻度，改送現成的幀。
+    if last_sent.map_or(true, |t| t < batch_tick) && from <= batch_tick {
+        match session.event_tx.try_send(frame.clone()) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                session.lockstep_resend_from = Some(batch_tick);
+                return TickDelivery::Lagging(batch_tick);
+            }
+            Err(TrySendError::Closed(_)) => return TickDelivery::Closed,
+        }
+    }
+    session.lockstep_resend_from = None;
+    TickDelivery::Sent
+}
+
+/// 階段 6.22：`BroadcastPolicy::AoiEntity` 是否送給某個會話。`interested`
+/// 為該玩家的興趣集合判斷（`AoiGrid::interested`），有結果時以它為準；
+/// 沒有興趣集合時退回以實體位置做視口過濾，位置未知或尚無視口時送出。
+fn aoi_entity_hit(
+    interested: Option<bool>,
+    pos: Option<(f32, f32)>,
+    viewport: Option<&Viewport>,
+) -> bool {
+    match (interested, pos, viewport) {
+        (Some(hit), _, _) => hit,
+        (None, Some((x, y)), Some(vp)) => vp.contains(x, y),
+        _ => true,
+    }
 }
 
 /// 廣播線程和單元使用的純函數策略調度
 /// 測試。傳回應接收訊框的會話 ID 清單。
 ///
 /// `sessions` 是即時會話映射的借用； `aoi_lookup` 是一個回呼
-/// 廣播線程連接到“AoiGrid::lookup_pos”（測試可以對其進行存根）。
+/// 廣播線程連接到“AoiGrid::lookup_pos”（測試可以對其進行存根），
+/// `aoi_interest` 對應 `AoiGrid::interested`（玩家名稱、實體 id），
+/// `team_of` 對應 `TeamRoster::team_of`。
 /// 這讓我們可以在不啟動 KCP / tokio 的情況下對調度規則進行單元測試。
 #[cfg(test)]
 fn select_targets_for_policy(
@@ -188,6 +418,8 @@ fn select_targets_for_policy(
     entity_pos: Option<(f32, f32)>,
     sessions: &std::collections::BTreeMap<String, (String, Option<Viewport>)>,
     aoi_lookup: &dyn Fn(u64) -> Option<(f32, f32)>,
+    aoi_interest: &dyn Fn(&str, u64) -> Option<bool>,
+    team_of: &dyn Fn(&str) -> Option<u32>,
 ) -> Vec<String> {
     match policy {
         Some(BroadcastPolicy::All) => sessions.keys().cloned().collect(),
@@ -196,6 +428,16 @@ fn select_targets_for_policy(
             .filter(|(_, (player_name, _))| player_name == name)
             .map(|(id, _)| id.clone())
             .collect(),
+        Some(BroadcastPolicy::Team(team)) => sessions
+            .iter()
+            .filter(|(_, (player_name, _))| team_of(player_name) == Some(*team))
+            .map(|(id, _)| id.clone())
+            .collect(),
+        Some(BroadcastPolicy::AllExcept(name)) => sessions
+            .iter()
+            .filter(|(_, (player_name, _))| player_name != name)
+            .map(|(id, _)| id.clone())
+            .collect(),
         Some(BroadcastPolicy::AoiPoint(x, y)) => sessions
             .iter()
             .filter(|(_, (_, vp))| match vp {
@@ -204,17 +446,16 @@ fn select_targets_for_policy(
             })
             .map(|(id, _)| id.clone())
             .collect(),
-        Some(BroadcastPolicy::AoiEntity(eid)) => match aoi_lookup(*eid) {
-            Some((x, y)) => sessions
+        Some(BroadcastPolicy::AoiEntity(eid)) => {
+            let pos = aoi_lookup(*eid);
+            sessions
                 .iter()
-                .filter(|(_, (_, vp))| match vp {
-                    Some(v) => v.contains(x, y),
-                    None => true,
+                .filter(|(_, (player_name, vp))| {
+                    aoi_entity_hit(aoi_interest(player_name, *eid), pos, vp.as_ref())
                 })
                 .map(|(id, _)| id.clone())
-                .collect(),
-            None => sessions.keys().cloned().collect(),
-        },
+                .collect()
+        }
         None => {
             let is_broadcast = topic.contains("/all/");
             sessions
@@ -459,6 +700,62 @@ fn peek_kind_and_id(payload: &str) -> (String, String, Option<u64>) {
     (t, a, id)
 }
 
+/// 階段 6.21：seq-gap 重新同步時取走會話佇列中已排隊的幀。GameEvent
+/// （含壓縮旗標）被回覆的視圖快照取代而丟棄；其餘幀（lockstep）原順序
+/// 回傳，由呼叫者先行送出。回傳 `(保留的幀, 丟棄數)`。
+fn take_stale_events(
+    rx: &mut tokio::sync::mpsc::Receiver<Arc<[u8]>>,
+) -> (Vec<Arc<[u8]>>, usize) {
+    let mut kept = Vec::new();
+    let mut dropped = 0;
+    while let Ok(frame) = rx.try_recv() {
+        match frame.first() {
+            Some(tag) if tag & !COMPRESSION_FLAG == TAG_GAME_EVENT => dropped += 1,
+            _ => kept.push(frame),
+        }
+    }
+    (kept, dropped)
+}
+
+/// 階段 6.11：補送（SnapshotReq / NACK）可送出的最大刻度。觀察者受
+/// `spectator_delay_ticks` 限制，玩家不限。
+fn observer_visible_limit(
+    observer: bool,
+    lockstep_state: &Arc<std::sync::Mutex<crate::lockstep::LockstepState>>,
+) -> u32 {
+    if !observer {
+        return u32::MAX;
+    }
+    let s = lockstep_state.lock().unwrap();
+    s.current_tick.saturating_sub(s.spectator_delay_ticks)
+}
+
+/// 階段 6.8：挑出 TickAck 的 NACK 要補送的批次。只補 `acked_tick` 之後、
+/// `visible_limit`（見 `observer_visible_limit`）以內的刻度，依刻度遞增、
+/// 最多 `MAX_NACK_RESEND_TICKS` 個；已被擠出 `history` 的刻度另外回傳，
+/// 由客戶端改以 `SnapshotReq` 補齊。回傳 `(補送批次, 無法補送的刻度)`。
+fn nack_resend_batches(
+    ack: &crate::lockstep::wire::TickAck,
+    visible_limit: u32,
+    history: &crate::lockstep::TickHistory,
+) -> (Vec<TickBatch>, Vec<u32>) {
+    let missing: std::collections::BTreeSet<u32> = ack
+        .missing_ticks
+        .iter()
+        .copied()
+        .filter(|&t| t > ack.acked_tick && t <= visible_limit)
+        .collect();
+    let mut resend = Vec::new();
+    let mut unavailable = Vec::new();
+    for tick in missing.into_iter().take(MAX_NACK_RESEND_TICKS) {
+        match history.get(tick) {
+            Some(batch) => resend.push(batch.clone()),
+            None => unavailable.push(tick),
+        }
+    }
+    (resend, unavailable)
+}
+
 fn snapshot_resp_from_store(store: &crate::comp::SnapshotStore) -> SnapshotResp {
     SnapshotResp {
         tick: store.tick,
@@ -526,7 +823,175 @@ pub async fn start(
     lockstep_input_buffer: Arc<std::sync::Mutex<crate::lockstep::InputBuffer>>,
     lockstep_state: Arc<std::sync::Mutex<crate::lockstep::LockstepState>>,
     lockstep_snapshot_store: Arc<std::sync::Mutex<crate::comp::SnapshotStore>>,
+    lockstep_tick_history: Arc<std::sync::Mutex<crate::lockstep::TickHistory>>,
+) -> Result<TransportHandle, Error> {
+    let shared = LockstepShared::new(
+        lockstep_input_buffer,
+        lockstep_state,
+        lockstep_snapshot_store,
+        lockstep_tick_history,
+    );
+    start_shared(server_addr, server_port, shared).await
+}
+
+/// 階段 6.18：lockstep 傳輸共用的狀態。同一個 `LockstepShared` 交給
+/// KCP 與 WebSocket 時，兩邊的玩家加入同一場對局；AOI 網格與位元組
+/// 計數器也只有一份，`State` 只需掛一次。
+#[derive(Clone)]
+pub struct LockstepShared {
+    pub input_buffer: Arc<std::sync::Mutex<crate::lockstep::InputBuffer>>,
+    pub state: Arc<std::sync::Mutex<crate::lockstep::LockstepState>>,
+    pub snapshot_store: Arc<std::sync::Mutex<crate::comp::SnapshotStore>>,
+    pub tick_history: Arc<std::sync::Mutex<crate::lockstep::TickHistory>>,
+    pub counter: Arc<KcpBytesCounter>,
+    pub aoi: Arc<std::sync::Mutex<AoiGrid>>,
+    /// 階段 6.23：`BroadcastPolicy::Team` 的分隊表，所有共用此狀態的傳輸
+    /// 看到同一份。
+    pub teams: Arc<std::sync::RwLock<TeamRoster>>,
+}
+
+impl LockstepShared {
+    pub fn new(
+        input_buffer: Arc<std::sync::Mutex<crate::lockstep::InputBuffer>>,
+        state: Arc<std::sync::Mutex<crate::lockstep::LockstepState>>,
+        snapshot_store: Arc<std::sync::Mutex<crate::comp::SnapshotStore>>,
+        tick_history: Arc<std::sync::Mutex<crate::lockstep::TickHistory>>,
+    ) -> Self {
+        Self {
+            input_buffer,
+            state,
+            snapshot_store,
+            tick_history,
+            counter: Arc::new(KcpBytesCounter::new()),
+            aoi: Arc::new(std::sync::Mutex::new(AoiGrid::new())),
+            teams: Arc::new(std::sync::RwLock::new(TeamRoster::default())),
+        }
+    }
+}
+
+/// 階段 6.18：`Transport` 實作，供 `transport::start_all` 與其他傳輸並行。
+pub struct KcpTransport {
+    pub server_addr: String,
+    pub server_port: String,
+    pub shared: LockstepShared,
+}
+
+impl super::Transport for KcpTransport {
+    fn kind(&self) -> super::TransportKind {
+        super::TransportKind::Kcp
+    }
+
+    fn start(self: Box<Self>) -> super::StartFuture {
+        Box::pin(start_shared(self.server_addr, self.server_port, self.shared))
+    }
+}
+
+/// 以既有的 `LockstepShared` 啟動 KCP 傳輸。
+pub async fn start_shared(
+    server_addr: String,
+    server_port: String,
+    shared: LockstepShared,
 ) -> Result<TransportHandle, Error> {
+    // 解析綁定位址
+    let bind_ip = match server_addr.as_str() {
+        "localhost" | "127.0.0.1" => "0.0.0.0".to_string(),
+        other => other.to_string(),
+    };
+    let addr = format!("{}:{}", bind_ip, server_port);
+    let addr: std::net::SocketAddr = addr
+        .parse()
+        .map_err(|e| failure::err_msg(format!("Invalid address '{}': {}", addr, e)))?;
+
+    let mut config = KcpConfig::default();
+    config.nodelay = KcpNoDelayConfig::fastest();
+
+    info!("Starting KCP server on {}", addr);
+
+    // 同步綁定，因此如果連接埠被過時的實例佔用，啟動會快速失敗。
+    let mut listener = KcpListener::bind(config, addr)
+        .await
+        .map_err(|e| failure::err_msg(format!("Failed to bind KCP listener on {}: {}", addr, e)))?;
+
+    let (handle, server) = spawn_session_server(shared);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer_addr) = match listener.accept().await {
+                Ok(s) => s,
+                Err(e) => {
+                    error!("KCP accept error: {}", e);
+                    continue;
+                }
+            };
+
+            info!("KCP client connected from {}", peer_addr);
+            server.spawn_client(stream, format!("kcp_{}", peer_addr));
+        }
+    });
+
+    Ok(handle)
+}
+
+/// 階段 6.17：與傳輸無關的會話伺服器。KCP 與 WebSocket 共用同一張
+/// 會話表、同一條廣播線程與同一套幀處理（`handle_client`）；各傳輸
+/// 只負責接受連線並交出一條承載 `[tag][len][payload]` 幀的位元組串流。
+#[derive(Clone)]
+pub(crate) struct SessionServer {
+    sessions: Arc<Mutex<HashMap<String, ClientSession>>>,
+    in_tx: Sender<InboundMsg>,
+    query_tx: Sender<QueryRequest>,
+    viewport_tx: Sender<ViewportMsg>,
+    lockstep_tx: Sender<OutboundMsg>,
+    lockstep_input_buffer: Arc<std::sync::Mutex<crate::lockstep::InputBuffer>>,
+    lockstep_state: Arc<std::sync::Mutex<crate::lockstep::LockstepState>>,
+    lockstep_snapshot_store: Arc<std::sync::Mutex<crate::comp::SnapshotStore>>,
+    lockstep_tick_history: Arc<std::sync::Mutex<crate::lockstep::TickHistory>>,
+    teams: Arc<std::sync::RwLock<TeamRoster>>,
+}
+
+impl SessionServer {
+    /// 為一條新連線產生讀寫任務。`session_id` 需全域唯一（以傳輸前綴 +
+    /// 對端位址組成，例如 `kcp_1.2.3.4:5678`）。
+    pub(crate) fn spawn_client<S>(&self, stream: S, session_id: String)
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + 'static,
+    {
+        let server = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(
+                stream,
+                session_id.clone(),
+                server.sessions,
+                server.in_tx,
+                server.query_tx,
+                server.viewport_tx,
+                server.lockstep_tx,
+                server.lockstep_input_buffer,
+                server.lockstep_state,
+                server.lockstep_snapshot_store,
+                server.lockstep_tick_history,
+                server.teams,
+            )
+            .await
+            {
+                warn!("Client handler error (session={}): {}", session_id, e);
+            }
+        });
+    }
+}
+
+/// 建立出入站通道與廣播線程，回傳交給 `State` 的 `TransportHandle` 與
+/// 供傳輸接受迴圈使用的 `SessionServer`。
+pub(crate) fn spawn_session_server(shared: LockstepShared) -> (TransportHandle, SessionServer) {
+    let LockstepShared {
+        input_buffer: lockstep_input_buffer,
+        state: lockstep_state,
+        snapshot_store: lockstep_snapshot_store,
+        tick_history: lockstep_tick_history,
+        counter,
+        aoi,
+        teams,
+    } = shared;
     // 階段 5.x 反壓修復：在 TD_STRESS 下，主機滴答系統仍然存在
     // 發出遺留的每個實體事件（creep.M / Creep.H /Entity.F / Projectile.C
     // — 第 5 階段設計希望副本用戶端在本地計算這些數據，但
@@ -545,20 +1010,21 @@ pub async fn start(
 
     let sessions: Arc<Mutex<HashMap<String, ClientSession>>> = Arc::new(Mutex::new(HashMap::new()));
 
-    // 每個事件位元組/訊息計數器。與廣播線程共享以便測試
-    // 遊戲循環可以快照/重置觀察到的線量。
-    let counter: Arc<KcpBytesCounter> = Arc::new(KcpBytesCounter::new());
-
-    // P5：共享 AOI 寬相網格。遊戲循環每刻重建一次，傳輸
-    // 執行緒讀取 `BroadcastPolicy::AoiEntity` 查找。 `std::sync::互斥體`
-    // （不是`tokio::sync::Mutex`）因為兩個接觸點都是同步的
+    // 每個事件位元組/訊息計數器（`LockstepShared::counter`）。與廣播線程
+    // 共享以便測試/遊戲循環可以快照/重置觀察到的線量。
+    //
+    // P5：共享 AOI 寬相網格（`LockstepShared::aoi`）。遊戲循環每刻重建
+    // 一次，傳輸執行緒讀取 `BroadcastPolicy::AoiEntity` 查找。
+    // `std::sync::Mutex`（不是`tokio::sync::Mutex`）因為兩個接觸點都是同步的
     // 代碼保持鎖定微秒——鎖定時沒有“.await”。
-    let aoi: Arc<std::sync::Mutex<AoiGrid>> = Arc::new(std::sync::Mutex::new(AoiGrid::new()));
 
     // 後台執行緒：從out_rx讀取並廣播到所有會話
     let sessions_broadcast = sessions.clone();
     let counter_broadcast = counter.clone();
     let aoi_broadcast = aoi.clone();
+    let teams_broadcast = teams.clone();
+    let tick_history_broadcast = lockstep_tick_history.clone();
+    let spectator_delay_ticks = lockstep_state.lock().unwrap().spectator_delay_ticks;
     thread::spawn(move || {
         let rt = tokio::runtime::Builder::new_current_thread()
             .enable_all()
@@ -587,6 +1053,11 @@ pub async fn start(
             const MIN_BATCH: Duration = Duration::from_millis(10);
             const MAX_BATCH: Duration = Duration::from_millis(33);
 
+            // 階段 6.11：觀察者幀的延遲佇列，以最近一個 TickBatch 刻度為時鐘。
+            let mut spectator =
+                crate::lockstep::SpectatorDelay::<Arc<[u8]>>::new(spectator_delay_ticks);
+            let mut last_tick: u32 = 0;
+
             'outer: loop {
                 // 等第一筆訊息（阻塞）。Lockstep frames have a separate priority
                 // channel so ordinary TD_STRESS GameEvent backlog cannot place a
@@ -616,25 +1087,49 @@ pub async fn start(
                     if let Some(frame) = msg.lockstep_frame.clone() {
                         match frame {
                             crate::lockstep::LockstepFrame::TickBatch(batch_msg) => {
+                                // 階段 6.8：佇列滿不再移除會話，改記游標並
+                                // 從 TickHistory 補送；只有斷線才移除。
                                 let payload = batch_msg.encode_to_vec();
                                 let frame_bytes = build_framed_bytes(TAG_TICK_BATCH, &payload);
                                 let frame_arc: Arc<[u8]> = Arc::from(frame_bytes.into_boxed_slice());
-                                let sessions = sessions_broadcast.lock().await;
+                                let mut sessions = sessions_broadcast.lock().await;
+                                let history = tick_history_broadcast.lock().unwrap();
                                 let mut to_remove = Vec::new();
-                                for (sid, session) in sessions.iter() {
-                                    if !session.lockstep_joined { continue; }
-                                    if session.event_tx.try_send(frame_arc.clone()).is_err() {
-                                        to_remove.push(sid.clone());
+                                for (sid, session) in sessions.iter_mut() {
+                                    if !session.lockstep_joined || session.lockstep_observer { continue; }
+                                    let was_lagging = session.lockstep_resend_from.is_some();
+                                    match deliver_tick_batch(session, &frame_arc, batch_msg.tick, &history) {
+                                        TickDelivery::Sent => {
+                                            if was_lagging {
+                                                info!("KCP session {} caught up at tick {}", sid, batch_msg.tick);
+                                            }
+                                        }
+                                        TickDelivery::Lagging(from) => {
+                                            if !was_lagging {
+                                                warn!("KCP session {} outbound queue full at tick {}; resending from TickHistory", sid, from);
+                                            }
+                                        }
+                                        TickDelivery::GapTooOld(from) => {
+                                            warn!(
+                                                "KCP session {} missed ticks from {} beyond TickHistory (oldest={:?}); client must SnapshotReq",
+                                                sid, from, history.oldest_tick()
+                                            );
+                                        }
+                                        TickDelivery::Closed => to_remove.push(sid.clone()),
                                     }
                                 }
-                                drop(sessions);
-                                if !to_remove.is_empty() {
-                                    let mut sessions = sessions_broadcast.lock().await;
-                                    for id in to_remove {
-                                        sessions.remove(&id);
-                                        info!("Removed disconnected KCP session: {}", id);
+                                drop(history);
+                                last_tick = batch_msg.tick;
+                                spectator.push_broadcast(last_tick, frame_arc.clone());
+                                for id in deliver_spectator_frames(&sessions, spectator.release(last_tick)) {
+                                    if !to_remove.contains(&id) {
+                                        to_remove.push(id);
                                     }
                                 }
+                                for id in to_remove {
+                                    sessions.remove(&id);
+                                    info!("Removed disconnected KCP session: {}", id);
+                                }
                             }
                             crate::lockstep::LockstepFrame::StateHash(sh) => {
                                 let payload = sh.encode_to_vec();
@@ -642,9 +1137,14 @@ pub async fn start(
                                 let frame_arc: Arc<[u8]> = Arc::from(frame_bytes.into_boxed_slice());
                                 let sessions = sessions_broadcast.lock().await;
                                 let mut to_remove = Vec::new();
+                                spectator.push_broadcast(last_tick, frame_arc.clone());
                                 for (sid, session) in sessions.iter() {
-                                    if !session.lockstep_joined { continue; }
-                                    if session.event_tx.try_send(frame_arc.clone()).is_err() {
+                                    if !session.lockstep_joined || session.lockstep_observer { continue; }
+                                    // 階段 6.8：StateHash 只是探測，佇列滿時略過
+                                    // 這一筆即可；斷線才移除。
+                                    if let Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) =
+                                        session.event_tx.try_send(frame_arc.clone())
+                                    {
                                         to_remove.push(sid.clone());
                                     }
                                 }
@@ -657,8 +1157,13 @@ pub async fn start(
                                     }
                                 }
                             }
-                            crate::lockstep::LockstepFrame::GameStart { client_session_id, msg: gs } => {
-                                let payload = gs.encode_to_vec();
+                            crate::lockstep::LockstepFrame::GameStart { client_session_id, msg: gs, resume_token } => {
+                                let mut payload = gs.encode_to_vec();
+                                if !resume_token.is_empty() {
+                                    payload.extend(
+                                        crate::lockstep::wire::ResumeToken { resume_token }.encode_to_vec(),
+                                    );
+                                }
                                 let frame_bytes = build_framed_bytes(TAG_GAME_START, &payload);
                                 let frame_arc: Arc<[u8]> = Arc::from(frame_bytes.into_boxed_slice());
                                 let sessions = sessions_broadcast.lock().await;
@@ -673,10 +1178,18 @@ pub async fn start(
                                 let frame_bytes = build_framed_bytes(TAG_SNAPSHOT_RESP, &payload);
                                 let frame_arc: Arc<[u8]> = Arc::from(frame_bytes.into_boxed_slice());
                                 let sessions = sessions_broadcast.lock().await;
-                                if let Some(session) = sessions.get(&client_session_id) {
-                                    let _ = session.event_tx.try_send(frame_arc);
-                                } else {
-                                    warn!("SnapshotResp unicast: session '{}' not found", client_session_id);
+                                match sessions.get(&client_session_id) {
+                                    // 階段 6.11：觀察者的快照排到延遲串流播到
+                                    // 該刻度時才送，之後的延遲批次正好銜接。
+                                    Some(session) if session.lockstep_observer => {
+                                        spectator.push_unicast(sr.tick, client_session_id, frame_arc);
+                                    }
+                                    Some(session) => {
+                                        let _ = session.event_tx.try_send(frame_arc);
+                                    }
+                                    None => {
+                                        warn!("SnapshotResp unicast: session '{}' not found", client_session_id);
+                                    }
                                 }
                             }
                         }
@@ -761,24 +1274,39 @@ pub async fn start(
                                     .map(|(id, _)| id.clone())
                                     .collect()
                             }
+                            Some(BroadcastPolicy::Team(team)) => {
+                                // 階段 6.23：只送給分隊表中同隊的玩家；
+                                // 讀不到分隊表時寧可不送，避免洩漏給對手。
+                                let roster = teams_broadcast.read().ok();
+                                sessions.iter()
+                                    .filter(|(_, s)| {
+                                        roster.as_ref().and_then(|r| r.team_of(&s.player_name)) == Some(*team)
+                                    })
+                                    .map(|(id, _)| id.clone())
+                                    .collect()
+                            }
+                            Some(BroadcastPolicy::AllExcept(name)) => {
+                                sessions.iter()
+                                    .filter(|(_, s)| &s.player_name != name)
+                                    .map(|(id, _)| id.clone())
+                                    .collect()
+                            }
                             Some(BroadcastPolicy::AoiEntity(eid)) => {
-                                // 透過AoiGrid解析entity_id → pos。如果
-                                // 網格不知道這個實體（產生這個
-                                // 重建後勾選，或已死亡），掉落
+                                // 階段 6.22：玩家有興趣集合時以集合判斷；
+                                // 否則透過AoiGrid解析entity_id → pos 做視口
+                                // 過濾。網格不知道這個實體（產生這個
+                                // 重建後勾選，或已死亡）時掉落
                                 // 返回廣播以避免無聲掉線。
-                                let pos_opt = aoi_broadcast.lock()
-                                    .ok()
-                                    .and_then(|g| g.lookup_pos(*eid));
-                                match pos_opt {
-                                    Some((x, y)) => sessions.iter()
-                                        .filter(|(_, s)| match &s.viewport {
-                                            Some(vp) => vp.contains(x, y),
-                                            None => true,
-                                        })
-                                        .map(|(id, _)| id.clone())
-                                        .collect(),
-                                    None => sessions.keys().cloned().collect(),
-                                }
+                                let grid = aoi_broadcast.lock().ok();
+                                let pos_opt = grid.as_ref().and_then(|g| g.lookup_pos(*eid));
+                                sessions.iter()
+                                    .filter(|(_, s)| aoi_entity_hit(
+                                        grid.as_ref().and_then(|g| g.interested(&s.player_name, *eid)),
+                                        pos_opt,
+                                        s.viewport.as_ref(),
+                                    ))
+                                    .map(|(id, _)| id.clone())
+                                    .collect()
                             }
                             None => {
                                 // 傳統的基於主題的路由。 “/all/”⇒廣播；
@@ -816,6 +1344,11 @@ pub async fn start(
                         // 已經可以接受了。
                         for target in &targets {
                             if let Some(session) = sessions.get(target) {
+                                // 階段 6.11：觀察者只看 `SpectatorDelay` 延遲後的
+                                // 鎖步串流；即時的舊版事件會提前洩漏戰況。
+                                if session.lockstep_observer {
+                                    continue;
+                                }
                                 // 標記每個會話序列（單調，
                                 // 無間隙－客戶使用這些來檢測損失
                                 // 即使 AOI 可能會丟棄事件預標記）。
@@ -878,91 +1411,32 @@ pub async fn start(
         });
     });
 
-    // 解析綁定位址
-    let bind_ip = match server_addr.as_str() {
-        "localhost" | "127.0.0.1" => "0.0.0.0".to_string(),
-        other => other.to_string(),
-    };
-    let addr = format!("{}:{}", bind_ip, server_port);
-    let addr: std::net::SocketAddr = addr
-        .parse()
-        .map_err(|e| failure::err_msg(format!("Invalid address '{}': {}", addr, e)))?;
-
-    let mut config = KcpConfig::default();
-    config.nodelay = KcpNoDelayConfig::fastest();
-
-    info!("Starting KCP server on {}", addr);
-
-    let sessions_accept = sessions.clone();
-    let in_tx_accept = in_tx.clone();
-    let query_tx_accept = query_tx.clone();
-    let viewport_tx_accept = viewport_tx.clone();
-    let lockstep_tx_accept = lockstep_tx.clone();
-    let lockstep_input_buffer_accept = lockstep_input_buffer.clone();
-    let lockstep_state_accept = lockstep_state.clone();
-    let lockstep_snapshot_store_accept = lockstep_snapshot_store.clone();
-
-    // 同步綁定，因此如果連接埠被過時的實例佔用，啟動會快速失敗。
-    let mut listener = KcpListener::bind(config, addr)
-        .await
-        .map_err(|e| failure::err_msg(format!("Failed to bind KCP listener on {}: {}", addr, e)))?;
-
-    tokio::spawn(async move {
-        loop {
-            let (stream, peer_addr) = match listener.accept().await {
-                Ok(s) => s,
-                Err(e) => {
-                    error!("KCP accept error: {}", e);
-                    continue;
-                }
-            };
-
-            info!("KCP client connected from {}", peer_addr);
-
-            let sessions = sessions_accept.clone();
-            let in_tx = in_tx_accept.clone();
-            let query_tx = query_tx_accept.clone();
-            let viewport_tx = viewport_tx_accept.clone();
-            let lockstep_tx = lockstep_tx_accept.clone();
-            let lockstep_input_buffer = lockstep_input_buffer_accept.clone();
-            let lockstep_state = lockstep_state_accept.clone();
-            let lockstep_snapshot_store = lockstep_snapshot_store_accept.clone();
-            let session_id = format!("kcp_{}", peer_addr);
-
-            tokio::spawn(async move {
-                if let Err(e) = handle_client(
-                    stream,
-                    session_id,
-                    sessions,
-                    in_tx,
-                    query_tx,
-                    viewport_tx,
-                    lockstep_tx,
-                    lockstep_input_buffer,
-                    lockstep_state,
-                    lockstep_snapshot_store,
-                )
-                .await
-                {
-                    warn!("KCP client handler error: {}", e);
-                }
-            });
-        }
-    });
-
-    Ok(TransportHandle {
+    let handle = TransportHandle {
         tx: out_tx,
-        lockstep_tx,
+        lockstep_tx: lockstep_tx.clone(),
         rx: in_rx,
         query_rx,
         viewport_rx,
         counter,
         aoi,
-    })
+    };
+    let server = SessionServer {
+        sessions,
+        in_tx,
+        query_tx,
+        viewport_tx,
+        lockstep_tx,
+        lockstep_input_buffer,
+        lockstep_state,
+        lockstep_snapshot_store,
+        lockstep_tick_history,
+        teams,
+    };
+    (handle, server)
 }
 
-async fn handle_client(
-    stream: KcpStream,
+async fn handle_client<S>(
+    stream: S,
     session_id: String,
     sessions: Arc<Mutex<HashMap<String, ClientSession>>>,
     in_tx: Sender<InboundMsg>,
@@ -972,7 +1446,12 @@ async fn handle_client(
     lockstep_input_buffer: Arc<std::sync::Mutex<crate::lockstep::InputBuffer>>,
     lockstep_state: Arc<std::sync::Mutex<crate::lockstep::LockstepState>>,
     lockstep_snapshot_store: Arc<std::sync::Mutex<crate::comp::SnapshotStore>>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    lockstep_tick_history: Arc<std::sync::Mutex<crate::lockstep::TickHistory>>,
+    teams: Arc<std::sync::RwLock<TeamRoster>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + 'static,
+{
     let (mut reader, mut writer) = tokio::io::split(stream);
 
     // 每個會話的出站通道（惰性 — 僅在 SubscribeRequest 之後使用）。
@@ -984,6 +1463,13 @@ async fn handle_client(
     // 追蹤訂閱的player_name，以便我們可以在斷開連接時發送刪除
     let mut player_name: Option<String> = None;
     let mut joined_player_id: Option<u32> = None;
+    // 階段 6.9：本連線在座位上的代數，斷線時交給 `disconnect_player`。
+    let mut joined_binding: u32 = 0;
+    // 階段 6.11：觀察者連線（延遲串流、補送只到可見刻度）。
+    let mut joined_observer = false;
+    // 階段 6.14：尚未帶回的 `PingProbe`（id, 送出時間）與下一個 id。
+    let mut ping_probe: Option<(u64, std::time::Instant)> = None;
+    let mut next_probe_id: u64 = 0;
 
     // 主循環：從客戶端讀取，可選擇寫入出站事件
     loop {
@@ -1016,6 +1502,10 @@ async fn handle_client(
                                             // 鎖步流直到發送
                                             // 加入請求 (0x13)。
                                             lockstep_joined: false,
+                                            lockstep_acked_tick: 0,
+                                            lockstep_resend_from: None,
+                                            lockstep_player_id: None,
+                                            lockstep_observer: false,
                                         },
                                     );
                                 }
@@ -1038,7 +1528,9 @@ async fn handle_client(
 
                                     let _ = in_tx.send(inbound);
 
-                                    // 發送確認
+                                    // 發送確認：只代表已排入 State 佇列。
+                                    // 實際結果（含拒絕代碼）由 tower/player 的
+                                    // `R` 回覆帶回（階段 6.25 `CommandResult`）。
                                     let ack = CommandAck {
                                         ok: true,
                                         message: "Command accepted".into(),
@@ -1049,32 +1541,68 @@ async fn handle_client(
                             }
                             TAG_GAME_STATE_REQUEST => {
                                 if let Ok(req) = GameStateRequest::decode(payload.as_slice()) {
-                                    // P6：處理客戶端發起的“seq-gap”
-                                    // 重新同步。 player_name 帶有
-                                    // 最後已知的 seq 作為十進位字串（到
-                                    // 避免原型模式碰撞）。現在我們
-                                    // 只是 LOG + ACK — 完整狀態快照
-                                    // 答覆推遲到後續行動。
-                                    // 發現差距的客戶將重試
-                                    // 定期請求，直到伺服器
-                                    // 有機地趕上。
+                                    // P6 / 階段 6.21：客戶端偵測到 GameEvent
+                                    // 序號缺口時的重新同步。player_name 帶有
+                                    // 最後已知的 seq（十進位字串，避免改動
+                                    // proto），只用於記錄；查詢改用本會話
+                                    // 訂閱時的名稱。先在 sessions 鎖內把 seq
+                                    // 歸零並丟棄佇列中的舊 GameEvent（廣播執行緒
+                                    // 持同一把鎖蓋序號），再向 State 要視圖快照：
+                                    // 期間新產生的事件從 0 起算、排在回覆之後，
+                                    // 不會遺漏，最多與快照重複。
                                     if req.query_type == "seq-gap" {
                                         warn!(
                                             "⚠️ seq-gap resync request from session={} last_seq={:?}",
                                             session_id, req.player_name
                                         );
-                                        // 存根：ACK，以便客戶端知道伺服器
-                                        // 收到請求。未來的補丁
-                                        // 應該建立一個完整的視圖快照
-                                        // （AOI 中的英雄/小兵/塔）和船隻
-                                        // 它作為批量重播返回。
-                                        let resp = GameStateResponse {
-                                            success: true,
-                                            error: String::new(),
-                                            data_json: b"{\"stub\":true,\"note\":\"seq-gap snapshot not yet implemented - server logged request\"}".to_vec(),
+                                        let (Some(name), Some(rx)) = (player_name.clone(), event_rx.as_mut()) else {
+                                            let resp = GameStateResponse {
+                                                success: false,
+                                                error: "seq-gap resync requires a subscribed session".to_string(),
+                                                data_json: Vec::new(),
+                                            };
+                                            let _ = write_framed(&mut writer, TAG_GAME_STATE_RESPONSE, &resp.encode_to_vec()).await;
+                                            continue;
                                         };
-                                        let resp_payload = resp.encode_to_vec();
-                                        let _ = write_framed(&mut writer, TAG_GAME_STATE_RESPONSE, &resp_payload).await;
+                                        let kept = {
+                                            let sess = sessions.lock().await;
+                                            if let Some(s) = sess.get(&session_id) {
+                                                s.seq.store(0, Ordering::Relaxed);
+                                            }
+                                            let (kept, dropped) = take_stale_events(rx);
+                                            debug!("seq-gap resync for '{}': dropped {} queued GameEvents", name, dropped);
+                                            kept
+                                        };
+                                        // 非 GameEvent 幀（lockstep）照原順序先送出。
+                                        let mut write_failed = false;
+                                        for frame in kept {
+                                            if writer.write_all(&frame).await.is_err() {
+                                                write_failed = true;
+                                                break;
+                                            }
+                                        }
+                                        if write_failed {
+                                            break;
+                                        }
+                                        let (resp_tx, resp_rx) = tokio::sync::oneshot::channel();
+                                        let _ = query_tx.send(QueryRequest {
+                                            query_type: req.query_type,
+                                            player_name: name,
+                                            response_tx: resp_tx,
+                                        });
+                                        let resp = match resp_rx.await {
+                                            Ok(response) => GameStateResponse {
+                                                success: response.success,
+                                                error: response.error,
+                                                data_json: response.data_json,
+                                            },
+                                            Err(_) => GameStateResponse {
+                                                success: false,
+                                                error: "state dropped the seq-gap query".to_string(),
+                                                data_json: Vec::new(),
+                                            },
+                                        };
+                                        let _ = write_framed(&mut writer, TAG_GAME_STATE_RESPONSE, &resp.encode_to_vec()).await;
                                         continue;
                                     }
                                     let (resp_tx, resp_rx) = tokio::sync::oneshot::channel();
@@ -1132,38 +1660,72 @@ async fn handle_client(
                             TAG_INPUT_SUBMIT => {
                                 match InputSubmit::decode(payload.as_slice()) {
                                     Ok(req) => {
-                                        let current_tick = lockstep_state.lock().unwrap().current_tick;
-                                        let player_id = req.player_id;
-                                        if !lockstep_state
-                                            .lock()
-                                            .unwrap()
-                                            .players
-                                            .contains_key(&player_id)
-                                        {
-                                            warn!(
-                                                "InputSubmit rejected from unknown player_id={} input_id={} session={}",
-                                                player_id,
-                                                req.input_id,
-                                                session_id
-                                            );
-                                            continue;
-                                        }
-                                        let target_tick = req.target_tick;
-                                        let input_id = req.input_id;
-                                        let input = req.input.unwrap_or_default();
+                                        // 階段 6.10：只接受加入該座位的連線送來的
+                                        // 輸入（觀察者、冒用他人 id、已被接回的舊
+                                        // 連線都拒絕）。
+                                        // 階段 6.14：晚到寬限依該玩家的抖動估計放寬。
+                                        // 階段 6.15：通過授權後再做速率與語意檢查，
+                                        // 拒絕時以 InputRejected 回覆原因。
                                         let step_fps =
                                             crate::config::server_config::CONFIG.STEP_FPS;
-                                        let result = lockstep_input_buffer
-                                            .lock()
-                                            .unwrap()
-                                            .submit_with_late_grace(
+                                        let target_tick = req.target_tick;
+                                        let input_id = req.input_id;
+                                        let input = req.input.clone().unwrap_or_default();
+                                        let admitted = {
+                                            let mut s = lockstep_state.lock().unwrap();
+                                            s.authorize_input(
+                                                joined_player_id.map(|pid| (pid, joined_binding)),
+                                                req.player_id,
+                                            )
+                                            .map_err(|reason| {
+                                                crate::lockstep::InputRejection::new(
+                                                    crate::lockstep::wire::InputRejectReason::Unauthorized,
+                                                    reason,
+                                                )
+                                            })
+                                            .and_then(|pid| {
+                                                s.validate_input(pid, target_tick, &input)?;
+                                                let grace = s.late_grace_ticks(
+                                                    pid,
+                                                    step_fps,
+                                                    late_input_grace_ticks(step_fps),
+                                                );
+                                                Ok((pid, s.current_tick, grace))
+                                            })
+                                        };
+                                        let (player_id, current_tick, late_grace) = match admitted {
+                                            Ok(v) => v,
+                                            Err(rejection) => {
+                                                warn!(
+                                                    "InputSubmit rejected player_id={} input_id={} session={}: {:?} {}",
+                                                    req.player_id,
+                                                    input_id,
+                                                    session_id,
+                                                    rejection.reason,
+                                                    rejection.detail
+                                                );
+                                                let reply = input_rejected_payload(input_id, target_tick, &rejection);
+                                                let _ = write_framed(&mut writer, TAG_INPUT_REJECTED, &reply).await;
+                                                continue;
+                                            }
+                                        };
+                                        let result = lockstep_input_buffer
+                                            .lock()
+                                            .unwrap()
+                                            .submit_with_late_grace(
                                                 current_tick,
                                                 player_id,
                                                 target_tick,
                                                 input,
                                                 input_id,
-                                                late_input_grace_ticks(step_fps),
+                                                late_grace,
                                             );
+                                        // 階段 6.13 / 6.14：生效刻度供落後偵測，
+                                        // retarget / 拒絕計數供建議提前量。
+                                        lockstep_state
+                                            .lock()
+                                            .unwrap()
+                                            .record_input_result(player_id, &result);
                                         match result {
                                             crate::lockstep::InputSubmitResult::Accepted { .. } => {}
                                             crate::lockstep::InputSubmitResult::Retargeted {
@@ -1183,14 +1745,25 @@ async fn handle_client(
                                             crate::lockstep::InputSubmitResult::RejectedLate {
                                                 original_tick,
                                                 current_tick,
-                                            } => warn!(
-                                                "late InputSubmit from player {} input_id={} target_tick={} current_tick={} step_fps={}",
-                                                player_id,
-                                                input_id,
-                                                original_tick,
-                                                current_tick,
-                                                step_fps
-                                            ),
+                                            } => {
+                                                warn!(
+                                                    "late InputSubmit from player {} input_id={} target_tick={} current_tick={} step_fps={}",
+                                                    player_id,
+                                                    input_id,
+                                                    original_tick,
+                                                    current_tick,
+                                                    step_fps
+                                                );
+                                                let rejection = crate::lockstep::InputRejection::new(
+                                                    crate::lockstep::wire::InputRejectReason::Late,
+                                                    format!(
+                                                        "target_tick {} is past the late grace at tick {}",
+                                                        original_tick, current_tick
+                                                    ),
+                                                );
+                                                let reply = input_rejected_payload(input_id, original_tick, &rejection);
+                                                let _ = write_framed(&mut writer, TAG_INPUT_REJECTED, &reply).await;
+                                            }
                                         }
                                     }
                                     Err(e) => warn!("Failed to decode InputSubmit: {}", e),
@@ -1204,16 +1777,33 @@ async fn handle_client(
                                             _ => crate::lockstep::JoinRoleEnum::Player,
                                         };
                                         let declared_player_id = req.player_id;
+                                        // 階段 6.9：JoinRequest 後面可能接著
+                                        // `ResumeToken`（欄位 15），有 token 就接回
+                                        // 原座位，不看自報的 player_id。
+                                        let resume_token = crate::lockstep::wire::ResumeToken::decode(payload.as_slice())
+                                            .map(|t| t.resume_token)
+                                            .unwrap_or_default();
+                                        let resuming = !resume_token.is_empty();
                                         let registered = {
                                             let mut s = lockstep_state.lock().unwrap();
-                                            let result = s.register_player(
-                                                declared_player_id,
-                                                req.player_name.clone(),
-                                                role,
-                                            );
-                                            result.map(|pid| (pid, s.master_seed, s.current_tick))
+                                            let result = if resuming {
+                                                s.resume_player(&resume_token)
+                                            } else {
+                                                s.join_player(
+                                                    declared_player_id,
+                                                    req.player_name.clone(),
+                                                    role,
+                                                )
+                                            };
+                                            // 階段 6.11：觀察者從延遲後的可見刻度開始。
+                                            let start_tick = if !resuming && role == crate::lockstep::JoinRoleEnum::Observer {
+                                                s.current_tick.saturating_sub(s.spectator_delay_ticks)
+                                            } else {
+                                                s.current_tick
+                                            };
+                                            result.map(|grant| (grant, s.master_seed, start_tick))
                                         };
-                                        let (player_id, master_seed, start_tick) = match registered {
+                                        let (grant, master_seed, start_tick) = match registered {
                                             Ok(v) => v,
                                             Err(reason) => {
                                                 warn!(
@@ -1227,7 +1817,10 @@ async fn handle_client(
                                                 break;
                                             }
                                         };
+                                        let player_id = grant.player_id;
                                         joined_player_id = Some(player_id);
+                                        joined_binding = grant.binding;
+                                        joined_observer = !resuming && role == crate::lockstep::JoinRoleEnum::Observer;
                                         // 將此會話標記為已加入
                                         // 鎖步流因此未來 TickBatch /
                                         // StateHash 廣播到達它。
@@ -1235,6 +1828,8 @@ async fn handle_client(
                                             let mut sess = sessions.lock().await;
                                             if let Some(s) = sess.get_mut(&session_id) {
                                                 s.lockstep_joined = true;
+                                                s.lockstep_player_id = Some(player_id);
+                                                s.lockstep_observer = joined_observer;
                                                 if s.player_name.is_empty() {
                                                     s.player_name = req.player_name.clone();
                                                 }
@@ -1256,13 +1851,17 @@ async fn handle_client(
                                                         viewport: None,
                                                         seq: Arc::new(AtomicU64::new(0)),
                                                         lockstep_joined: true,
+                                                        lockstep_acked_tick: 0,
+                                                        lockstep_resend_from: None,
+                                                        lockstep_player_id: Some(player_id),
+                                                        lockstep_observer: joined_observer,
                                                     },
                                                 );
                                             }
                                         }
                                         info!(
-                                            "🎮 KCP lockstep JoinRequest player='{}' role={:?} accepted player_id={} (session={})",
-                                            req.player_name, role, player_id, session_id
+                                            "🎮 KCP lockstep JoinRequest player='{}' role={:?} accepted player_id={} resumed={} (session={})",
+                                            req.player_name, role, player_id, resuming, session_id
                                         );
                                         // 透過單播方式傳送 GameStart
                                         // 廣播線程（所以它通過
@@ -1274,13 +1873,14 @@ async fn handle_client(
                                             master_seed,
                                             initial_state: Some(SimSnapshot {
                                                 world_bytes: vec![],
-                                                schema_version: 1,
+                                                schema_version: crate::lockstep::SNAPSHOT_SCHEMA_VERSION,
                                             }),
                                             step_fps: crate::config::server_config::CONFIG.STEP_FPS,
                                         };
                                         let frame = crate::lockstep::LockstepFrame::GameStart {
                                             client_session_id: session_id.clone(),
                                             msg: game_start,
+                                            resume_token: grant.resume_token,
                                         };
                                         if let Err(e) = lockstep_tx.send(OutboundMsg::lockstep_frame(frame)) {
                                             warn!("Failed to enqueue GameStart: {}", e);
@@ -1296,13 +1896,61 @@ async fn handle_client(
                                 }
                             }
                             TAG_SNAPSHOT_REQ => {
+                                // 階段 6.7：以最近快照 + TickHistory 補送。
+                                // 直接寫入（與 PingResponse 相同），補送批次可能
+                                // 與廣播線程的即時批次交錯，客戶端依刻度去重。
                                 match SnapshotReq::decode(payload.as_slice()) {
                                     Ok(req) => {
-                                        let current_tick = lockstep_state.lock().unwrap().current_tick;
-                                        warn!(
-                                            "Ignoring SnapshotReq from {} for from_tick={} (current_tick={}): snapshots are bootstrap-only",
-                                            session_id, req.from_tick, current_tick
+                                        // 階段 6.11：觀察者只能補到延遲後的可見刻度。
+                                        let visible_limit = observer_visible_limit(joined_observer, &lockstep_state);
+                                        let (snapshot, plan) = {
+                                            let store = lockstep_snapshot_store
+                                                .lock()
+                                                .expect("SnapshotStore mutex poisoned");
+                                            let snapshot = (!store.bytes.is_empty() && store.tick <= visible_limit)
+                                                .then(|| snapshot_resp_from_store(&store));
+                                            let history = lockstep_tick_history.lock().unwrap();
+                                            let plan = crate::lockstep::plan_catch_up(
+                                                &history,
+                                                snapshot.as_ref().map(|s| s.tick),
+                                                req.from_tick,
+                                            );
+                                            (snapshot, plan)
+                                        };
+                                        let mut batches = match plan {
+                                            crate::lockstep::CatchUpPlan::UpToDate => Vec::new(),
+                                            crate::lockstep::CatchUpPlan::Batches(batches) => batches,
+                                            crate::lockstep::CatchUpPlan::SnapshotThenBatches(batches) => {
+                                                if let Some(resp) = snapshot.as_ref() {
+                                                    let _ = write_framed(&mut writer, TAG_SNAPSHOT_RESP, &resp.encode_to_vec()).await;
+                                                }
+                                                batches
+                                            }
+                                            crate::lockstep::CatchUpPlan::Unavailable => {
+                                                let current_tick = lockstep_state.lock().unwrap().current_tick;
+                                                warn!(
+                                                    "SnapshotReq from {} for from_tick={} cannot be served (current_tick={}): gap predates tick history and latest snapshot",
+                                                    session_id, req.from_tick, current_tick
+                                                );
+                                                Vec::new()
+                                            }
+                                        };
+                                        batches.retain(|b| b.tick <= visible_limit);
+                                        let pending = batches.len();
+                                        batches.truncate(MAX_CATCH_UP_RESEND_TICKS);
+                                        info!(
+                                            "📸 SnapshotReq from {} from_tick={}: snapshot={} batches={} (of {})",
+                                            session_id,
+                                            req.from_tick,
+                                            snapshot.as_ref().map(|s| s.tick).unwrap_or(0),
+                                            batches.len(),
+                                            pending
                                         );
+                                        for batch in &batches {
+                                            if write_framed(&mut writer, TAG_TICK_BATCH, &batch.encode_to_vec()).await.is_err() {
+                                                break;
+                                            }
+                                        }
                                     }
                                     Err(e) => warn!("Failed to decode SnapshotReq: {}", e),
                                 }
@@ -1315,15 +1963,142 @@ async fn handle_client(
                                 // 污染 RTT 測量）。
                                 match PingRequest::decode(payload.as_slice()) {
                                     Ok(req) => {
+                                        // 階段 6.14：RTT 由伺服器自己量。帶回探針的
+                                        // PingRequest 產生一個樣本；其他的回覆附上新
+                                        // 探針，客戶端會立即帶回。
+                                        let echoed = crate::lockstep::wire::PingProbe::decode(payload.as_slice())
+                                            .map(|p| p.probe_id)
+                                            .unwrap_or(0);
+                                        let mut new_probe = None;
+                                        let hint = joined_player_id.and_then(|player_id| {
+                                            let mut state = lockstep_state.lock().unwrap();
+                                            if echoed == 0 {
+                                                next_probe_id += 1;
+                                                new_probe = Some(next_probe_id);
+                                            } else if let Some((_, sent_at)) =
+                                                ping_probe.filter(|(id, _)| *id == echoed)
+                                            {
+                                                ping_probe = None;
+                                                let rtt_us = sent_at.elapsed().as_micros();
+                                                state.record_ping_rtt(player_id, rtt_us.min(u32::MAX as u128) as u32);
+                                            }
+                                            // 回覆附上建議的輸入提前量。
+                                            state.next_lead_hint(
+                                                player_id,
+                                                crate::config::server_config::CONFIG.STEP_FPS,
+                                            )
+                                        });
                                         let resp = PingResponse {
                                             client_send_us: req.client_send_us,
                                         };
-                                        let resp_payload = resp.encode_to_vec();
+                                        let mut resp_payload = resp.encode_to_vec();
+                                        if let Some(hint) = hint {
+                                            resp_payload.extend(hint.encode_to_vec());
+                                        }
+                                        if let Some(probe_id) = new_probe {
+                                            resp_payload.extend(crate::lockstep::wire::PingProbe { probe_id }.encode_to_vec());
+                                            ping_probe = Some((probe_id, std::time::Instant::now()));
+                                        }
                                         let _ = write_framed(&mut writer, TAG_PING_RESP, &resp_payload).await;
                                     }
                                     Err(e) => warn!("Failed to decode PingRequest: {}", e),
                                 }
                             }
+                            TAG_CLIENT_STATE_HASH => {
+                                match crate::lockstep::wire::ClientStateHash::decode(payload.as_slice()) {
+                                    Ok(report) => {
+                                        // 以會話加入時分配的 player_id 為準，
+                                        // 不信任 payload 自報的 id。
+                                        let Some(player_id) = joined_player_id else {
+                                            warn!(
+                                                "Ignoring ClientStateHash from {} before JoinRequest",
+                                                session_id
+                                            );
+                                            continue;
+                                        };
+                                        if report.player_id != player_id {
+                                            warn!(
+                                                "ClientStateHash from {} claims player_id={} but session joined as {}",
+                                                session_id, report.player_id, player_id
+                                            );
+                                        }
+                                        // 不一致時由 TickBroadcaster 取走並記錄
+                                        // （log / replay 標記 / 快照傾印）。
+                                        lockstep_state
+                                            .lock()
+                                            .unwrap()
+                                            .record_client_hash(player_id, report.tick, report.hash);
+                                    }
+                                    Err(e) => warn!("Failed to decode ClientStateHash: {}", e),
+                                }
+                            }
+                            TAG_TICK_ACK => {
+                                // 階段 6.8：推進確認游標，並直接補送 NACK 的
+                                // 刻度（與 SnapshotReq 補送相同，只回給請求者）。
+                                match crate::lockstep::wire::TickAck::decode(payload.as_slice()) {
+                                    Ok(ack) => {
+                                        {
+                                            let mut sess = sessions.lock().await;
+                                            if let Some(s) = sess.get_mut(&session_id) {
+                                                s.lockstep_acked_tick = s.lockstep_acked_tick.max(ack.acked_tick);
+                                            }
+                                        }
+                                        // 階段 6.13：確認進度即伺服器觀察到的存活訊號，供落後偵測。
+                                        if let Some(player_id) = joined_player_id {
+                                            lockstep_state
+                                                .lock()
+                                                .unwrap()
+                                                .record_tick_ack(player_id, ack.acked_tick);
+                                        }
+                                        if ack.missing_ticks.is_empty() {
+                                            continue;
+                                        }
+                                        let visible_limit = observer_visible_limit(joined_observer, &lockstep_state);
+                                        let (resend, unavailable) = nack_resend_batches(
+                                            &ack,
+                                            visible_limit,
+                                            &lockstep_tick_history.lock().unwrap(),
+                                        );
+                                        if !unavailable.is_empty() {
+                                            warn!(
+                                                "TickAck NACK from {} for ticks {:?} outside TickHistory; client must SnapshotReq",
+                                                session_id, unavailable
+                                            );
+                                        }
+                                        for batch in &resend {
+                                            if write_framed(&mut writer, TAG_TICK_BATCH, &batch.encode_to_vec()).await.is_err() {
+                                                break;
+                                            }
+                                        }
+                                    }
+                                    Err(e) => warn!("Failed to decode TickAck: {}", e),
+                                }
+                            }
+                            TAG_CHAT_SEND => {
+                                // 階段 6.24：驗證後暫存，由 TickBroadcaster 在下一刻
+                                // 寫進 replay 並依頻道送出；拒絕只回給送出者。
+                                match crate::lockstep::wire::ChatSend::decode(payload.as_slice()) {
+                                    Ok(send) => {
+                                        let result = lockstep_state.lock().unwrap().submit_chat(
+                                            joined_player_id.map(|pid| (pid, joined_binding)),
+                                            &send,
+                                            |name| teams.read().ok().and_then(|r| r.team_of(name)),
+                                        );
+                                        if let Err(rejection) = result {
+                                            debug!(
+                                                "Chat from {} rejected: {:?} {}",
+                                                session_id, rejection.reason, rejection.detail
+                                            );
+                                            let reply = crate::lockstep::wire::ChatRejected {
+                                                reason: rejection.reason as i32,
+                                                detail: rejection.detail,
+                                            };
+                                            let _ = write_framed(&mut writer, TAG_CHAT_REJECTED, &reply.encode_to_vec()).await;
+                                        }
+                                    }
+                                    Err(e) => warn!("Failed to decode ChatSend: {}", e),
+                                }
+                            }
                             _ => {
                                 warn!("Unknown tag from client: 0x{:02x}", tag);
                             }
@@ -1367,8 +2142,12 @@ async fn handle_client(
     if let Some(name) = player_name {
         let _ = viewport_tx.send(ViewportMsg::Remove { player_name: name });
     }
+    // 階段 6.9：玩家座位進入寬限狀態等待重連，而不是立即移除。
     if let Some(player_id) = joined_player_id {
-        lockstep_state.lock().unwrap().unregister_player(player_id);
+        lockstep_state
+            .lock()
+            .unwrap()
+            .disconnect_player(player_id, joined_binding);
     }
     info!("KCP session cleaned up: {}", session_id);
     Ok(())
@@ -1502,6 +2281,8 @@ mod tests {
             None,
             &sessions,
             &|_| None,
+            &|_, _| None,
+            &|_| None,
         );
         assert_eq!(targets.len(), 3);
     }
@@ -1515,6 +2296,8 @@ mod tests {
             None,
             &sessions,
             &|_| None,
+            &|_, _| None,
+            &|_| None,
         );
         assert_eq!(targets, vec!["s2".to_string()]);
     }
@@ -1539,6 +2322,8 @@ mod tests {
             None,
             &sessions,
             &|_| None,
+            &|_, _| None,
+            &|_| None,
         );
         let mut sorted = targets.clone();
         sorted.sort();
@@ -1569,6 +2354,39 @@ mod tests {
             None,
             &sessions,
             &lookup,
+            &|_, _| None,
+            &|_| None,
+        );
+        assert_eq!(targets, vec!["s1".to_string()]);
+    }
+
+    #[test]
+    fn policy_aoi_entity_follows_interest_sets() {
+        let sessions = mk_sessions(&[
+            ("s1", "alice", Some(Viewport::new(0.0, 0.0, 100.0, 100.0))),
+            ("s2", "bob", Some(Viewport::new(0.0, 0.0, 100.0, 100.0))),
+            (
+                "s3",
+                "carol",
+                Some(Viewport::new(1000.0, 1000.0, 100.0, 100.0)),
+            ),
+        ]);
+        // 實體 42 在三人視口外，但仍在 alice 的興趣集合（遲滯範圍內）；
+        // bob 的集合不含它；carol 尚無集合 → 退回視口判斷。
+        let lookup = |_: u64| Some((-120.0f32, 0.0f32));
+        let interest = |player: &str, _: u64| match player {
+            "alice" => Some(true),
+            "bob" => Some(false),
+            _ => None,
+        };
+        let targets = select_targets_for_policy(
+            Some(&BroadcastPolicy::AoiEntity(42)),
+            "td/all/res",
+            None,
+            &sessions,
+            &lookup,
+            &interest,
+            &|_| None,
         );
         assert_eq!(targets, vec!["s1".to_string()]);
     }
@@ -1590,10 +2408,56 @@ mod tests {
             None,
             &sessions,
             &|_| None,
+            &|_, _| None,
+            &|_| None,
         );
         assert_eq!(targets.len(), 2);
     }
 
+    #[test]
+    fn policy_team_reaches_only_teammates() {
+        let sessions = mk_sessions(&[
+            ("s1", "alice", None),
+            ("s2", "bob", None),
+            ("s3", "carol", None),
+            ("s4", "spectator", None),
+        ]);
+        let mut roster = TeamRoster::default();
+        roster.assign("alice", 1);
+        roster.assign("carol", 1);
+        roster.assign("bob", 2);
+        let targets = select_targets_for_policy(
+            Some(&BroadcastPolicy::Team(1)),
+            "td/all/res",
+            None,
+            &sessions,
+            &|_| None,
+            &|_, _| None,
+            &|name| roster.team_of(name),
+        );
+        // 未分隊的會話（觀察者）也不會收到。
+        assert_eq!(targets, vec!["s1".to_string(), "s3".to_string()]);
+    }
+
+    #[test]
+    fn policy_all_except_skips_only_that_player() {
+        let sessions = mk_sessions(&[
+            ("s1", "alice", None),
+            ("s2", "bob", Some(Viewport::new(0.0, 0.0, 100.0, 100.0))),
+            ("s3", "carol", None),
+        ]);
+        let targets = select_targets_for_policy(
+            Some(&BroadcastPolicy::AllExcept("bob".into())),
+            "td/all/res",
+            None,
+            &sessions,
+            &|_| None,
+            &|_, _| None,
+            &|_| None,
+        );
+        assert_eq!(targets, vec!["s1".to_string(), "s3".to_string()]);
+    }
+
     #[test]
     fn policy_none_preserves_legacy_topic_routing() {
         let sessions = mk_sessions(&[
@@ -1607,11 +2471,11 @@ mod tests {
         // 舊版 /all/ topic +entity_pos → 應用視窗過濾器。
         // (0, 0) 處的事件 — alice 包含，bob 不包含。
         let targets =
-            select_targets_for_policy(None, "td/all/res", Some((0.0, 0.0)), &sessions, &|_| None);
+            select_targets_for_policy(None, "td/all/res", Some((0.0, 0.0)), &sessions, &|_| None, &|_, _| None, &|_| None);
         assert_eq!(targets, vec!["s1".to_string()]);
 
         // 每個玩家的主題「td/bob/res」 → 僅 bob 的會話。
-        let targets = select_targets_for_policy(None, "td/bob/res", None, &sessions, &|_| None);
+        let targets = select_targets_for_policy(None, "td/bob/res", None, &sessions, &|_| None, &|_, _| None, &|_| None);
         assert_eq!(targets, vec!["s2".to_string()]);
     }
 
@@ -1626,7 +2490,7 @@ mod tests {
             ),
         ]);
         // /all/ topic + 無entity_pos → 每個會話都通過。
-        let targets = select_targets_for_policy(None, "td/all/res", None, &sessions, &|_| None);
+        let targets = select_targets_for_policy(None, "td/all/res", None, &sessions, &|_| None, &|_, _| None, &|_| None);
         assert_eq!(targets.len(), 2);
     }
 
@@ -1930,6 +2794,520 @@ mod tests {
         }
     }
 
+    fn lockstep_session(capacity: usize) -> (ClientSession, tokio::sync::mpsc::Receiver<Arc<[u8]>>) {
+        let (event_tx, rx) = tokio::sync::mpsc::channel::<Arc<[u8]>>(capacity);
+        let session = ClientSession {
+            player_name: "p1".to_string(),
+            event_tx,
+            viewport: None,
+            seq: Arc::new(AtomicU64::new(0)),
+            lockstep_joined: true,
+            lockstep_acked_tick: 0,
+            lockstep_resend_from: None,
+            lockstep_player_id: Some(1),
+            lockstep_observer: false,
+        };
+        (session, rx)
+    }
+
+    fn tick_batch(tick: u32) -> TickBatch {
+        TickBatch {
+            tick,
+            inputs: vec![],
+            server_events: vec![],
+            lua_content_generation: 0,
+            lua_content_hash: String::new(),
+        }
+    }
+
+    fn tick_frame(tick: u32) -> Arc<[u8]> {
+        let bytes = build_framed_bytes(TAG_TICK_BATCH, &tick_batch(tick).encode_to_vec());
+        Arc::from(bytes.into_boxed_slice())
+    }
+
+    fn history_with(ticks: std::ops::RangeInclusive<u32>) -> crate::lockstep::TickHistory {
+        let mut history = crate::lockstep::TickHistory::new(16);
+        for tick in ticks {
+            history.push(tick_batch(tick));
+        }
+        history
+    }
+
+    /// 取出佇列中所有 TickBatch 幀的刻度（負載小於 LZ4 門檻，未壓縮）。
+    fn queued_ticks(rx: &mut tokio::sync::mpsc::Receiver<Arc<[u8]>>) -> Vec<u32> {
+        let mut ticks = Vec::new();
+        while let Ok(frame) = rx.try_recv() {
+            assert_eq!(frame[0], TAG_TICK_BATCH);
+            ticks.push(TickBatch::decode(&frame[5..]).unwrap().tick);
+        }
+        ticks
+    }
+
+    #[test]
+    fn full_queue_keeps_session_and_resends_from_history() {
+        let (mut session, mut rx) = lockstep_session(2);
+        let history = history_with(1..=4);
+
+        assert_eq!(deliver_tick_batch(&mut session, &tick_frame(1), 1, &history), TickDelivery::Sent);
+        assert_eq!(deliver_tick_batch(&mut session, &tick_frame(2), 2, &history), TickDelivery::Sent);
+        assert_eq!(
+            deliver_tick_batch(&mut session, &tick_frame(3), 3, &history),
+            TickDelivery::Lagging(3)
+        );
+        assert_eq!(session.lockstep_resend_from, Some(3));
+        assert_eq!(queued_ticks(&mut rx), vec![1, 2]);
+
+        assert_eq!(deliver_tick_batch(&mut session, &tick_frame(4), 4, &history), TickDelivery::Sent);
+        assert_eq!(session.lockstep_resend_from, None);
+        assert_eq!(queued_ticks(&mut rx), vec![3, 4]);
+    }
+
+    #[test]
+    fn resend_skips_acked_ticks_and_gives_up_beyond_history() {
+        let (mut session, mut rx) = lockstep_session(8);
+        let history = history_with(5..=9);
+
+        session.lockstep_resend_from = Some(6);
+        session.lockstep_acked_tick = 7;
+        assert_eq!(deliver_tick_batch(&mut session, &tick_frame(9), 9, &history), TickDelivery::Sent);
+        assert_eq!(queued_ticks(&mut rx), vec![8, 9]);
+
+        session.lockstep_resend_from = Some(2);
+        session.lockstep_acked_tick = 0;
+        assert_eq!(
+            deliver_tick_batch(&mut session, &tick_frame(9), 9, &history),
+            TickDelivery::GapTooOld(2)
+        );
+        assert_eq!(session.lockstep_resend_from, None);
+        assert!(queued_ticks(&mut rx).is_empty());
+    }
+
+    #[test]
+    fn closed_receiver_reports_closed() {
+        let (mut session, rx) = lockstep_session(2);
+        drop(rx);
+        let history = history_with(1..=1);
+        assert_eq!(
+            deliver_tick_batch(&mut session, &tick_frame(1), 1, &history),
+            TickDelivery::Closed
+        );
+    }
+
+    #[test]
+    fn spectator_frames_reach_only_observers() {
+        let (player, mut player_rx) = lockstep_session(4);
+        let (mut obs_a, mut obs_a_rx) = lockstep_session(4);
+        let (mut obs_b, mut obs_b_rx) = lockstep_session(4);
+        obs_a.lockstep_observer = true;
+        obs_b.lockstep_observer = true;
+        let mut sessions = HashMap::new();
+        sessions.insert("player".to_string(), player);
+        sessions.insert("obs_a".to_string(), obs_a);
+        sessions.insert("obs_b".to_string(), obs_b);
+
+        let closed = deliver_spectator_frames(
+            &sessions,
+            vec![(None, tick_frame(1)), (Some("obs_b".to_string()), tick_frame(2))],
+        );
+        assert!(closed.is_empty());
+        assert!(queued_ticks(&mut player_rx).is_empty());
+        assert_eq!(queued_ticks(&mut obs_a_rx), vec![1]);
+        assert_eq!(queued_ticks(&mut obs_b_rx), vec![1, 2]);
+
+        drop(obs_a_rx);
+        let closed = deliver_spectator_frames(&sessions, vec![(None, tick_frame(3))]);
+        assert_eq!(closed, vec!["obs_a".to_string()]);
+    }
+
+    fn history_through(last: u32) -> crate::lockstep::TickHistory {
+        let mut history = crate::lockstep::TickHistory::new(1024);
+        for tick in 1..=last {
+            history.push(TickBatch {
+                tick,
+                ..Default::default()
+            });
+        }
+        history
+    }
+
+    fn nack(acked_tick: u32, missing_ticks: Vec<u32>) -> crate::lockstep::wire::TickAck {
+        crate::lockstep::wire::TickAck {
+            acked_tick,
+            missing_ticks,
+        }
+    }
+
+    #[test]
+    fn nack_resends_missing_ticks_after_the_ack_from_history() {
+        let history = history_through(50);
+        let (resend, unavailable) = nack_resend_batches(
+            &nack(10, vec![12, 9, 10, 11, 12, 60, 40]),
+            u32::MAX,
+            &history,
+        );
+        // 已確認的刻度略過、重複合併、依刻度遞增。
+        let ticks: Vec<u32> = resend.iter().map(|b| b.tick).collect();
+        assert_eq!(ticks, vec![11, 12, 40]);
+        // 尚未產生 / 已擠出歷史的刻度交給 SnapshotReq。
+        assert_eq!(unavailable, vec![60]);
+    }
+
+    #[test]
+    fn nack_resend_is_capped_per_ack() {
+        let history = history_through(600);
+        let (resend, unavailable) =
+            nack_resend_batches(&nack(0, (1..=600).collect()), u32::MAX, &history);
+        assert_eq!(resend.len(), MAX_NACK_RESEND_TICKS);
+        assert_eq!(resend.first().map(|b| b.tick), Some(1));
+        assert_eq!(
+            resend.last().map(|b| b.tick),
+            Some(MAX_NACK_RESEND_TICKS as u32)
+        );
+        assert!(unavailable.is_empty());
+    }
+
+    #[test]
+    fn nack_resend_stops_at_the_observer_visible_limit() {
+        let state = Arc::new(std::sync::Mutex::new(crate::lockstep::LockstepState::new(
+            0x1234,
+        )));
+        {
+            let mut s = state.lock().unwrap();
+            s.current_tick = 50;
+            s.spectator_delay_ticks = 20;
+        }
+        assert_eq!(observer_visible_limit(false, &state), u32::MAX);
+        let limit = observer_visible_limit(true, &state);
+        assert_eq!(limit, 30);
+
+        let history = history_through(50);
+        let ack = nack(25, vec![28, 30, 31, 45]);
+        let (resend, unavailable) = nack_resend_batches(&ack, limit, &history);
+        // 觀察者不能藉 NACK 拿到延遲窗口內的刻度；這些也不算無法補送。
+        assert_eq!(
+            resend.iter().map(|b| b.tick).collect::<Vec<_>>(),
+            vec![28, 30]
+        );
+        assert!(unavailable.is_empty());
+        let (resend, _) = nack_resend_batches(&ack, u32::MAX, &history);
+        assert_eq!(resend.len(), 4);
+    }
+
+    /// 以 duplex 串流接上 `SessionServer` 的測試會話（與 WebSocket / gRPC
+    /// 橋接相同的接法）。
+    fn test_session_server() -> (TransportHandle, SessionServer, LockstepShared) {
+        let shared = LockstepShared::new(
+            Arc::new(std::sync::Mutex::new(crate::lockstep::InputBuffer::new())),
+            Arc::new(std::sync::Mutex::new(crate::lockstep::LockstepState::new(
+                7,
+            ))),
+            Arc::new(std::sync::Mutex::new(crate::comp::SnapshotStore::default())),
+            Arc::new(std::sync::Mutex::new(crate::lockstep::TickHistory::new(64))),
+        );
+        let (handle, server) = spawn_session_server(shared.clone());
+        (handle, server, shared)
+    }
+
+    fn connect(server: &SessionServer, session_id: &str) -> tokio::io::DuplexStream {
+        let (client, session_io) = tokio::io::duplex(64 * 1024);
+        server.spawn_client(session_io, session_id.to_string());
+        client
+    }
+
+    async fn send_frame(client: &mut tokio::io::DuplexStream, tag: u8, payload: &[u8]) {
+        write_framed(client, tag, payload).await.unwrap();
+    }
+
+    /// 讀到標籤為 `tag` 的幀為止，略過其他幀；`skipped` 收集略過的標籤。
+    async fn expect_frame_skipping(
+        client: &mut tokio::io::DuplexStream,
+        tag: u8,
+        skipped: &mut Vec<u8>,
+    ) -> Vec<u8> {
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                let (got, payload, _) = read_framed(client).await.unwrap().expect("session closed");
+                if got == tag {
+                    return payload;
+                }
+                skipped.push(got);
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("no frame with tag 0x{:02x}", tag))
+    }
+
+    async fn expect_frame(client: &mut tokio::io::DuplexStream, tag: u8) -> Vec<u8> {
+        expect_frame_skipping(client, tag, &mut Vec::new()).await
+    }
+
+    /// 送出 JoinRequest（可附 resume token）並等 GameStart，回傳分配的
+    /// player_id 與新的 resume token。
+    async fn join_session(
+        client: &mut tokio::io::DuplexStream,
+        player_id: u32,
+        role: JoinRole,
+        resume_token: &str,
+    ) -> (u32, String) {
+        let mut payload = JoinRequest {
+            player_name: format!("p{}", player_id),
+            role: role as i32,
+            player_id,
+        }
+        .encode_to_vec();
+        if !resume_token.is_empty() {
+            payload.extend(
+                crate::lockstep::wire::ResumeToken {
+                    resume_token: resume_token.to_string(),
+                }
+                .encode_to_vec(),
+            );
+        }
+        send_frame(client, TAG_JOIN_REQUEST, &payload).await;
+        let start = expect_frame(client, TAG_GAME_START).await;
+        let token = crate::lockstep::wire::ResumeToken::decode(start.as_slice())
+            .unwrap()
+            .resume_token;
+        (
+            GameStart::decode(start.as_slice()).unwrap().player_id,
+            token,
+        )
+    }
+
+    /// 以 Ping 來回確認會話已處理完先前送出的幀。
+    async fn round_trip(client: &mut tokio::io::DuplexStream) -> Vec<u8> {
+        send_frame(
+            client,
+            TAG_PING_REQ,
+            &PingRequest { client_send_us: 1 }.encode_to_vec(),
+        )
+        .await;
+        expect_frame(client, TAG_PING_RESP).await
+    }
+
+    fn input_submit(
+        player_id: u32,
+        target_tick: u32,
+        input_id: u32,
+        action: player_input::Action,
+    ) -> Vec<u8> {
+        InputSubmit {
+            player_id,
+            target_tick,
+            input: Some(PlayerInput {
+                action: Some(action),
+            }),
+            input_id,
+        }
+        .encode_to_vec()
+    }
+
+    fn noop_submit(player_id: u32, target_tick: u32, input_id: u32) -> Vec<u8> {
+        input_submit(
+            player_id,
+            target_tick,
+            input_id,
+            player_input::Action::NoOp(NoOp {}),
+        )
+    }
+
+    async fn expect_rejected(
+        client: &mut tokio::io::DuplexStream,
+    ) -> crate::lockstep::wire::InputRejected {
+        let payload = expect_frame(client, TAG_INPUT_REJECTED).await;
+        crate::lockstep::wire::InputRejected::decode(payload.as_slice()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn legacy_fan_out_skips_observers() {
+        let (handle, server, _shared) = test_session_server();
+        let mut player = connect(&server, "player");
+        let mut observer = connect(&server, "observer");
+        join_session(&mut player, 1, JoinRole::RolePlayer, "").await;
+        join_session(&mut observer, 0, JoinRole::RoleObserver, "").await;
+
+        handle
+            .tx
+            .send(OutboundMsg::new_s_all(
+                "td/all/res",
+                "game",
+                "lives",
+                json!({"lives": 3}),
+            ))
+            .unwrap();
+        let event = expect_frame(&mut player, TAG_GAME_EVENT).await;
+        assert_eq!(GameEvent::decode(event.as_slice()).unwrap().sequence, 0);
+
+        // 廣播線程在同一輪把事件排進所有目標會話；玩家收到時觀察者的佇列
+        // 已定案。觀察者沒被標序號，Ping 回覆前也沒有任何 GameEvent。
+        {
+            let sessions = server.sessions.lock().await;
+            assert_eq!(sessions["player"].seq.load(Ordering::Relaxed), 1);
+            assert_eq!(sessions["observer"].seq.load(Ordering::Relaxed), 0);
+        }
+        let mut skipped = Vec::new();
+        send_frame(
+            &mut observer,
+            TAG_PING_REQ,
+            &PingRequest { client_send_us: 1 }.encode_to_vec(),
+        )
+        .await;
+        expect_frame_skipping(&mut observer, TAG_PING_RESP, &mut skipped).await;
+        assert!(!skipped.contains(&TAG_GAME_EVENT));
+    }
+
+    #[tokio::test]
+    async fn input_submit_from_spoofed_stale_or_observer_sessions_is_rejected() {
+        use crate::lockstep::wire::InputRejectReason;
+
+        let (_handle, server, shared) = test_session_server();
+        let mut first = connect(&server, "first");
+        let (player_id, token) = join_session(&mut first, 1, JoinRole::RolePlayer, "").await;
+        assert_eq!(player_id, 1);
+
+        // 冒用其他玩家的 id。
+        send_frame(&mut first, TAG_INPUT_SUBMIT, &noop_submit(2, 5, 10)).await;
+        let rejected = expect_rejected(&mut first).await;
+        assert_eq!(rejected.input_id, 10);
+        assert_eq!(rejected.reason, InputRejectReason::Unauthorized as i32);
+
+        // 觀察者不能提交，即使自報的是自己拿到的 id。
+        let mut observer = connect(&server, "observer");
+        let (observer_id, _) = join_session(&mut observer, 0, JoinRole::RoleObserver, "").await;
+        send_frame(
+            &mut observer,
+            TAG_INPUT_SUBMIT,
+            &noop_submit(observer_id, 5, 11),
+        )
+        .await;
+        assert_eq!(
+            expect_rejected(&mut observer).await.reason,
+            InputRejectReason::Unauthorized as i32
+        );
+
+        // 座位被新連線以 token 接回後，舊連線的輸入一律拒絕。
+        let mut second = connect(&server, "second");
+        assert_eq!(
+            join_session(&mut second, 1, JoinRole::RolePlayer, &token)
+                .await
+                .0,
+            1
+        );
+        send_frame(&mut first, TAG_INPUT_SUBMIT, &noop_submit(1, 5, 12)).await;
+        let stale = expect_rejected(&mut first).await;
+        assert_eq!(stale.input_id, 12);
+        assert_eq!(stale.reason, InputRejectReason::Unauthorized as i32);
+        assert_eq!(shared.input_buffer.lock().unwrap().pending_count(), 0);
+
+        // 新連線以自己的座位提交則進入緩衝區（同一會話依序處理，Ping 回覆
+        // 之前的輸入都已處理完）。
+        send_frame(&mut second, TAG_INPUT_SUBMIT, &noop_submit(1, 5, 13)).await;
+        round_trip(&mut second).await;
+        assert_eq!(shared.input_buffer.lock().unwrap().pending_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn ping_probe_samples_drive_the_lead_hint_on_ping_response() {
+        use crate::lockstep::latency::MIN_LEAD_TICKS;
+        use crate::lockstep::wire::{InputLeadHint, PingProbe};
+        use crate::lockstep::InputSubmitResult;
+
+        let (_handle, server, shared) = test_session_server();
+        let mut client = connect(&server, "player");
+        let (player_id, _) = join_session(&mut client, 3, JoinRole::RolePlayer, "").await;
+
+        // 一般 Ping 的回覆附上探針；帶回後伺服器記一個 RTT 樣本，不再發新探針。
+        for _ in 0..3 {
+            let pong = round_trip(&mut client).await;
+            let probe_id = PingProbe::decode(pong.as_slice()).unwrap().probe_id;
+            assert_ne!(probe_id, 0);
+            let mut echo = PingRequest { client_send_us: 2 }.encode_to_vec();
+            echo.extend(PingProbe { probe_id }.encode_to_vec());
+            send_frame(&mut client, TAG_PING_REQ, &echo).await;
+            let pong = expect_frame(&mut client, TAG_PING_RESP).await;
+            assert_eq!(PingProbe::decode(pong.as_slice()).unwrap().probe_id, 0);
+        }
+        // 不認得的探針不產生樣本。
+        let mut forged = PingRequest { client_send_us: 2 }.encode_to_vec();
+        forged.extend(PingProbe { probe_id: 999 }.encode_to_vec());
+        send_frame(&mut client, TAG_PING_REQ, &forged).await;
+        expect_frame(&mut client, TAG_PING_RESP).await;
+
+        let latency = {
+            let mut state = shared.state.lock().unwrap();
+            state.record_input_result(
+                player_id,
+                &InputSubmitResult::Retargeted {
+                    original_tick: 1,
+                    effective_tick: 2,
+                },
+            );
+            state.players[&player_id].latency.clone()
+        };
+        assert_eq!(latency.samples(), 3);
+
+        let pong = round_trip(&mut client).await;
+        assert_eq!(
+            PingResponse::decode(pong.as_slice())
+                .unwrap()
+                .client_send_us,
+            1
+        );
+        let hint = InputLeadHint::decode(pong.as_slice()).unwrap();
+        assert_eq!(hint.srtt_us, latency.srtt_us());
+        assert_eq!(hint.jitter_us, latency.jitter_us());
+        assert_eq!(hint.retargeted_inputs, 1);
+        assert_eq!(hint.rejected_late_inputs, 0);
+        assert!(hint.recommended_lead_ticks >= MIN_LEAD_TICKS);
+    }
+
+    #[tokio::test]
+    async fn invalid_inputs_are_rejected_with_reasons_and_never_buffered() {
+        use crate::lockstep::wire::InputRejectReason;
+
+        let (_handle, server, shared) = test_session_server();
+        shared.state.lock().unwrap().input_limits = crate::lockstep::InputLimits {
+            max_per_tick: 2,
+            max_future_ticks: 10,
+            item_slots: 6,
+            ..Default::default()
+        };
+        let mut client = connect(&server, "player");
+        join_session(&mut client, 1, JoinRole::RolePlayer, "").await;
+
+        let submissions = [
+            (noop_submit(1, 50, 1), InputRejectReason::FarFuture),
+            (
+                input_submit(
+                    1,
+                    5,
+                    2,
+                    player_input::Action::ItemUse(ItemUse {
+                        slot: 6,
+                        ..Default::default()
+                    }),
+                ),
+                InputRejectReason::InvalidSlot,
+            ),
+            // 前兩筆雖被拒絕仍計入本刻的速率。
+            (noop_submit(1, 5, 3), InputRejectReason::RateLimitedTick),
+        ];
+        for (input_id, (payload, reason)) in (1..).zip(submissions) {
+            send_frame(&mut client, TAG_INPUT_SUBMIT, &payload).await;
+            let rejected = expect_rejected(&mut client).await;
+            assert_eq!(rejected.input_id, input_id);
+            assert_eq!(rejected.reason, reason as i32, "{}", rejected.detail);
+        }
+        round_trip(&mut client).await;
+        assert_eq!(shared.input_buffer.lock().unwrap().pending_count(), 0);
+
+        // 下一刻速率窗口重新計數，合法的輸入照常進入緩衝區。
+        shared.state.lock().unwrap().current_tick = 1;
+        send_frame(&mut client, TAG_INPUT_SUBMIT, &noop_submit(1, 5, 4)).await;
+        round_trip(&mut client).await;
+        assert_eq!(shared.input_buffer.lock().unwrap().pending_count(), 1);
+    }
+
     #[test]
     fn input_submit_arm_does_not_touch_snapshot_delivery() {
         let source = include_str!("kcp_transport.rs");
@@ -1953,14 +3331,166 @@ mod tests {
     }
 
     #[test]
-    fn explicit_snapshot_req_arm_does_not_send_snapshot_response() {
+    fn explicit_snapshot_req_arm_serves_catch_up_directly() {
         let source = include_str!("kcp_transport.rs");
         let arm = source_between(source, "TAG_SNAPSHOT_REQ =>", "TAG_PING_REQ =>");
 
-        assert!(!arm.contains("lockstep_snapshot_store"));
+        assert!(arm.contains("plan_catch_up"));
+        assert!(arm.contains("snapshot_resp_from_store"));
+        assert!(arm.contains("TAG_TICK_BATCH"));
+        // 觀察者不能藉補送繞過延遲。
+        assert!(arm.contains("observer_visible_limit"));
+        // 每次請求的補送量有上限，不會一次寫完整個歷史。
+        assert!(arm.contains("MAX_CATCH_UP_RESEND_TICKS"));
+        // 補送只回給請求者，不經廣播通道。
         assert!(!arm.contains("OutboundMsg::lockstep_frame"));
-        assert!(!arm.contains("SnapshotResp"));
-        assert!(!arm.contains("snapshot_resp_from_store"));
+        assert!(!arm.contains("bootstrap_snapshot"));
+    }
+
+    #[tokio::test]
+    async fn seq_gap_resync_drops_stale_events_and_serves_the_viewport() {
+        use crate::comp::{CProperty, Pos};
+        use crate::state::query::SeqGapView;
+        use crate::state::State;
+        use omoba_sim::{Fixed64, Vec2 as SimVec2};
+        use specs::{Builder, WorldExt};
+
+        let (handle, server, _shared) = test_session_server();
+        let campaign = crate::ue4::import_campaign::load_generated("TD_1").expect("TD_1 campaign");
+        let mut state = State::new_with_campaign(
+            campaign,
+            handle.tx.clone(),
+            handle.rx,
+            handle.query_rx,
+            handle.viewport_rx,
+        );
+        // 類小兵實體（有 CProperty，不是英雄 / 塔 / 投射物），視口內外各一。
+        let mut creep_at = |x: i32, y: i32| {
+            state
+                .ecs_mut()
+                .create_entity()
+                .with(Pos(SimVec2 {
+                    x: Fixed64::from_i32(x),
+                    y: Fixed64::from_i32(y),
+                }))
+                .with(CProperty {
+                    hp: Fixed64::from_i32(50),
+                    mhp: Fixed64::from_i32(80),
+                    msd: Fixed64::ZERO,
+                    def_physic: Fixed64::ZERO,
+                    def_magic: Fixed64::ZERO,
+                })
+                .build()
+                .id()
+        };
+        let near = creep_at(100, 100);
+        let far = creep_at(20_000, 20_000);
+
+        let mut client = connect(&server, "gap");
+        send_frame(
+            &mut client,
+            TAG_SUBSCRIBE_REQUEST,
+            &SubscribeRequest {
+                player_name: "alice".into(),
+            }
+            .encode_to_vec(),
+        )
+        .await;
+        send_frame(
+            &mut client,
+            TAG_VIEWPORT_UPDATE,
+            &ViewportUpdate {
+                center_x: 100.0,
+                center_y: 100.0,
+                half_width: 200.0,
+                half_height: 200.0,
+            }
+            .encode_to_vec(),
+        )
+        .await;
+        let lives = || OutboundMsg::new_s_all("td/all/res", "game", "lives", json!({"lives": 3}));
+        for expected in 0..2 {
+            handle.tx.send(lives()).unwrap();
+            let event = expect_frame(&mut client, TAG_GAME_EVENT).await;
+            assert_eq!(
+                GameEvent::decode(event.as_slice()).unwrap().sequence,
+                expected
+            );
+        }
+        state.seed_view_caches_for_test("alice");
+
+        // 持住 sessions 鎖：會話讀到請求後停在鎖前，這時排進佇列的是舊
+        // 序號的 GameEvent 與一個 lockstep 幀。
+        {
+            let sessions = server.sessions.lock().await;
+            send_frame(
+                &mut client,
+                TAG_GAME_STATE_REQUEST,
+                &GameStateRequest {
+                    query_type: "seq-gap".into(),
+                    player_name: "1".into(),
+                }
+                .encode_to_vec(),
+            )
+            .await;
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            let stale = GameEvent {
+                sequence: 2,
+                ..Default::default()
+            };
+            let stale = build_framed_bytes(TAG_GAME_EVENT, &stale.encode_to_vec());
+            let tx = &sessions["gap"].event_tx;
+            tx.try_send(Arc::from(stale.into_boxed_slice())).unwrap();
+            tx.try_send(tick_frame(9)).unwrap();
+        }
+        // 等會話把查詢交給 State，再跑一刻（先吸收視口，再處理查詢）。
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        state.tick(std::time::Duration::from_millis(33)).unwrap();
+
+        let mut skipped = Vec::new();
+        let resp = expect_frame_skipping(&mut client, TAG_GAME_STATE_RESPONSE, &mut skipped).await;
+        assert_eq!(skipped, vec![TAG_TICK_BATCH]);
+        let resp = GameStateResponse::decode(resp.as_slice()).unwrap();
+        assert!(resp.success, "{}", resp.error);
+        let view: SeqGapView = serde_json::from_slice(&resp.data_json).unwrap();
+        let viewport = Viewport::new(100.0, 100.0, 200.0, 200.0);
+        assert!(view.entities.iter().all(|e| viewport.contains(e.x, e.y)));
+        let near_view = view
+            .entities
+            .iter()
+            .find(|e| e.id == near)
+            .expect("near creep");
+        assert_eq!(
+            (near_view.kind, near_view.hp, near_view.max_hp),
+            (3, 50.0, 80.0)
+        );
+        assert!(view.entities.iter().all(|e| e.id != far));
+        assert!(!state.has_view_caches_for_test("alice"));
+
+        // 回覆之後的第一個 GameEvent 從 0 起算。
+        handle.tx.send(lives()).unwrap();
+        let event = expect_frame(&mut client, TAG_GAME_EVENT).await;
+        assert_eq!(GameEvent::decode(event.as_slice()).unwrap().sequence, 0);
+    }
+
+    #[test]
+    fn stale_game_events_are_dropped_and_lockstep_frames_kept() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<Arc<[u8]>>(8);
+        let frames: [&[u8]; 4] = [
+            &[TAG_GAME_EVENT, 0, 0, 0, 0],
+            &[TAG_TICK_BATCH, 0, 0, 0, 0],
+            &[TAG_GAME_EVENT | COMPRESSION_FLAG, 0, 0, 0, 0],
+            &[TAG_STATE_HASH, 0, 0, 0, 0],
+        ];
+        for frame in frames {
+            tx.try_send(Arc::from(frame)).unwrap();
+        }
+
+        let (kept, dropped) = take_stale_events(&mut rx);
+        assert_eq!(dropped, 2);
+        let tags: Vec<u8> = kept.iter().map(|f| f[0]).collect();
+        assert_eq!(tags, vec![TAG_TICK_BATCH, TAG_STATE_HASH]);
+        assert!(rx.try_recv().is_err());
     }
 
     #[test]
@@ -2034,4 +3564,62 @@ mod tests {
             frame.len()
         );
     }
+
+    #[cfg(feature = "grpc")]
+    #[tokio::test]
+    async fn grpc_envelopes_carry_extension_fields() {
+        use crate::lockstep::wire::{
+            lockstep_client_frame, lockstep_server_frame, InputLeadHint, LockstepClientFrame,
+            PingProbe, ResumeToken,
+        };
+
+        // C→S：resume token 串接在 JoinRequest 後面，與 KCP 客戶端相同。
+        let join = LockstepClientFrame {
+            frame: Some(lockstep_client_frame::Frame::Join(JoinRequest {
+                player_name: "bot".into(),
+                role: 1,
+                player_id: 4,
+            })),
+            resume_token: "tok".into(),
+            ..Default::default()
+        };
+        let bytes = client_envelope_frame(&join).unwrap();
+        let (tag, payload, _) = read_framed(&mut bytes.as_slice()).await.unwrap().unwrap();
+        assert_eq!(tag, TAG_JOIN_REQUEST);
+        assert_eq!(JoinRequest::decode(payload.as_slice()).unwrap().player_id, 4);
+        assert_eq!(ResumeToken::decode(payload.as_slice()).unwrap().resume_token, "tok");
+
+        let ping = LockstepClientFrame {
+            frame: Some(lockstep_client_frame::Frame::Ping(PingRequest {
+                client_send_us: 77,
+            })),
+            ping_probe_id: 5,
+            ..Default::default()
+        };
+        let bytes = client_envelope_frame(&ping).unwrap();
+        let (tag, payload, _) = read_framed(&mut bytes.as_slice()).await.unwrap().unwrap();
+        assert_eq!(tag, TAG_PING_REQ);
+        assert_eq!(PingRequest::decode(payload.as_slice()).unwrap().client_send_us, 77);
+        assert_eq!(PingProbe::decode(payload.as_slice()).unwrap().probe_id, 5);
+        assert!(client_envelope_frame(&LockstepClientFrame::default()).is_none());
+
+        // S→C：PingResponse 後面的 InputLeadHint / PingProbe 拆成信封欄位。
+        let hint = InputLeadHint {
+            recommended_lead_ticks: 9,
+            ..Default::default()
+        };
+        let mut payload = PingResponse { client_send_us: 77 }.encode_to_vec();
+        payload.extend(hint.encode_to_vec());
+        payload.extend(PingProbe { probe_id: 6 }.encode_to_vec());
+        let envelope = server_envelope(TAG_PING_RESP, &payload).unwrap().unwrap();
+        assert_eq!(envelope.lead_hint, Some(hint));
+        assert_eq!(envelope.ping_probe_id, 6);
+        assert!(matches!(
+            envelope.frame,
+            Some(lockstep_server_frame::Frame::Ping(PingResponse { client_send_us: 77 }))
+        ));
+
+        // 舊 GameEvent 不經 lockstep 串流。
+        assert!(server_envelope(TAG_GAME_EVENT, &[]).unwrap().is_none());
+    }
 }
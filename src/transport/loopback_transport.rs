@@ -0,0 +1,430 @@
+//! 階段 6.20：進程內 loopback 傳輸。
+//!
+//! `pair()` 回傳一個只由 crossbeam 通道組成的 `TransportHandle` 與對應的
+//! `LoopbackClient`：客戶端送出的 `InboundMsg` / 查詢 / 視口直接出現在
+//! `State` 讀取的接收端，`State` 與 `TickBroadcaster` 送出的 `OutboundMsg`
+//! 與 lockstep 幀直接出現在客戶端。沒有 socket、沒有背景執行緒，所以單元
+//! 測試、無頭平衡測試與進程內 AI bot 可以逐刻推進 `State::tick`
+//! （搭配 `TickBroadcaster::step`）並得到確定的結果。
+//!
+//! 啟用 `kcp` 時，`pair_with_lockstep` 讓客戶端以與 KCP 會話相同的規則
+//! （授權、輸入驗證、晚到寬限）加入座位並提交輸入。客戶端的出站通道
+//! 不設上限，必須持續取走，否則訊息會一直累積。
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use failure::Error;
+use std::time::Duration;
+
+#[cfg(feature = "kcp")]
+use super::kcp_transport::LockstepShared;
+use super::types::{InboundMsg, OutboundMsg, TransportHandle};
+#[cfg(any(feature = "grpc", feature = "kcp"))]
+use super::types::{QueryRequest, QueryResponse, ViewportMsg};
+#[cfg(feature = "kcp")]
+use crate::lockstep::{
+    ChatMessage, ChatRejection, GameStart, InputRejection, InputSubmitResult, JoinRoleEnum,
+    LockstepFrame, PlayerInput, SimSnapshot,
+};
+
+/// 建立沒有 lockstep 狀態的 loopback 傳輸。
+pub fn pair() -> (TransportHandle, LoopbackClient) {
+    build(Default::default())
+}
+
+/// 建立與 `shared` 共用 `InputBuffer` / `LockstepState` 的 loopback 傳輸。
+/// 同一個 `LockstepShared` 交給 `KcpTransport` 時，bot 與遠端玩家在同一場
+/// 對局（經 `transport::start_all` 合併）。
+#[cfg(feature = "kcp")]
+pub fn pair_with_lockstep(shared: LockstepShared) -> (TransportHandle, LoopbackClient) {
+    build(Some(shared))
+}
+
+/// 客戶端可選的 lockstep 狀態；未啟用 `kcp` 時沒有 lockstep。
+#[cfg(feature = "kcp")]
+type SharedLockstep = Option<LockstepShared>;
+#[cfg(not(feature = "kcp"))]
+type SharedLockstep = ();
+
+fn build(
+    #[cfg_attr(not(feature = "kcp"), allow(unused_variables))] shared: SharedLockstep,
+) -> (TransportHandle, LoopbackClient) {
+    let (out_tx, out_rx) = unbounded::<OutboundMsg>();
+    let (in_tx, in_rx) = unbounded::<InboundMsg>();
+    #[cfg(any(feature = "grpc", feature = "kcp"))]
+    let (query_tx, query_rx) = unbounded::<QueryRequest>();
+    #[cfg(any(feature = "grpc", feature = "kcp"))]
+    let (viewport_tx, viewport_rx) = unbounded::<ViewportMsg>();
+    #[cfg(feature = "kcp")]
+    let (lockstep_tx, lockstep_rx) = unbounded::<OutboundMsg>();
+    #[cfg(feature = "kcp")]
+    let (counter, aoi) = match shared.as_ref() {
+        Some(s) => (s.counter.clone(), s.aoi.clone()),
+        None => (
+            std::sync::Arc::new(super::KcpBytesCounter::new()),
+            std::sync::Arc::new(std::sync::Mutex::new(crate::aoi::AoiGrid::new())),
+        ),
+    };
+
+    let handle = TransportHandle {
+        tx: out_tx,
+        #[cfg(feature = "kcp")]
+        lockstep_tx,
+        rx: in_rx,
+        #[cfg(any(feature = "grpc", feature = "kcp"))]
+        query_rx,
+        #[cfg(any(feature = "grpc", feature = "kcp"))]
+        viewport_rx,
+        #[cfg(feature = "kcp")]
+        counter,
+        #[cfg(feature = "kcp")]
+        aoi,
+    };
+    let client = LoopbackClient {
+        in_tx,
+        out_rx,
+        #[cfg(any(feature = "grpc", feature = "kcp"))]
+        query_tx,
+        #[cfg(any(feature = "grpc", feature = "kcp"))]
+        viewport_tx,
+        #[cfg(feature = "kcp")]
+        lockstep_rx,
+        #[cfg(feature = "kcp")]
+        shared,
+    };
+    (handle, client)
+}
+
+/// 以 `Transport` 的形式交給 `transport::start_all`，與其他傳輸並行。
+pub struct LoopbackTransport(pub TransportHandle);
+
+impl super::Transport for LoopbackTransport {
+    fn kind(&self) -> super::TransportKind {
+        super::TransportKind::Loopback
+    }
+
+    fn start(self: Box<Self>) -> super::StartFuture {
+        Box::pin(async move { Ok(self.0) })
+    }
+}
+
+/// loopback 傳輸的客戶端一側。
+pub struct LoopbackClient {
+    in_tx: Sender<InboundMsg>,
+    out_rx: Receiver<OutboundMsg>,
+    #[cfg(any(feature = "grpc", feature = "kcp"))]
+    query_tx: Sender<QueryRequest>,
+    #[cfg(any(feature = "grpc", feature = "kcp"))]
+    viewport_tx: Sender<ViewportMsg>,
+    #[cfg(feature = "kcp")]
+    lockstep_rx: Receiver<OutboundMsg>,
+    #[cfg(feature = "kcp")]
+    shared: SharedLockstep,
+}
+
+/// `LoopbackClient::join` 拿到的座位。提交輸入時以它授權，
+/// 與 KCP 會話的 `(player_id, binding)` 相同。
+#[cfg(feature = "kcp")]
+#[derive(Debug, Clone)]
+pub struct LoopbackSeat {
+    pub player_id: u32,
+    pub binding: u32,
+    pub resume_token: String,
+    pub game_start: GameStart,
+}
+
+impl LoopbackClient {
+    pub fn send(&self, msg: InboundMsg) -> Result<(), Error> {
+        self.in_tx
+            .send(msg)
+            .map_err(|_| failure::err_msg("loopback transport closed"))
+    }
+
+    /// 送出一個舊路徑的玩家命令（同 MQTT / KCP 的 `PlayerCommand`）。
+    pub fn command(&self, name: &str, t: &str, a: &str, d: serde_json::Value) -> Result<(), Error> {
+        self.send(InboundMsg {
+            name: name.to_string(),
+            t: t.to_string(),
+            a: a.to_string(),
+            d,
+        })
+    }
+
+    pub fn try_recv(&self) -> Option<OutboundMsg> {
+        self.out_rx.try_recv().ok()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<OutboundMsg> {
+        self.out_rx.recv_timeout(timeout).ok()
+    }
+
+    /// 取走目前所有的出站訊息。
+    pub fn drain(&self) -> Vec<OutboundMsg> {
+        self.out_rx.try_iter().collect()
+    }
+
+    /// 送出查詢；`State` 處理查詢後回覆會出現在回傳的 oneshot。
+    #[cfg(any(feature = "grpc", feature = "kcp"))]
+    pub fn query(
+        &self,
+        query_type: &str,
+        player_name: &str,
+    ) -> Result<tokio::sync::oneshot::Receiver<QueryResponse>, Error> {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        self.query_tx
+            .send(QueryRequest {
+                query_type: query_type.to_string(),
+                player_name: player_name.to_string(),
+                response_tx,
+            })
+            .map_err(|_| failure::err_msg("loopback transport closed"))?;
+        Ok(response_rx)
+    }
+
+    #[cfg(any(feature = "grpc", feature = "kcp"))]
+    pub fn viewport(&self, msg: ViewportMsg) -> Result<(), Error> {
+        self.viewport_tx
+            .send(msg)
+            .map_err(|_| failure::err_msg("loopback transport closed"))
+    }
+
+    /// 取走 lockstep 通道上目前所有的訊息，包含非幀訊息（例如落後通知）。
+    #[cfg(feature = "kcp")]
+    pub fn drain_lockstep(&self) -> Vec<OutboundMsg> {
+        self.lockstep_rx.try_iter().collect()
+    }
+
+    /// 同 `drain_lockstep`，只留下 lockstep 幀（TickBatch / StateHash / 單播幀）。
+    #[cfg(feature = "kcp")]
+    pub fn drain_frames(&self) -> Vec<LockstepFrame> {
+        self.drain_lockstep()
+            .into_iter()
+            .filter_map(|msg| msg.lockstep_frame)
+            .collect()
+    }
+
+    /// 以與 KCP `JoinRequest` 相同的規則加入座位。觀察者的 `player_id`
+    /// 被忽略。loopback 沒有觀察者延遲，幀一律即時送達。
+    #[cfg(feature = "kcp")]
+    pub fn join(
+        &self,
+        player_id: u32,
+        name: &str,
+        role: JoinRoleEnum,
+    ) -> Result<LoopbackSeat, String> {
+        let shared = self.lockstep()?;
+        let mut state = shared.state.lock().unwrap();
+        let grant = state.join_player(player_id, name.to_string(), role)?;
+        let game_start = GameStart {
+            player_id: grant.player_id,
+            start_tick: state.current_tick,
+            master_seed: state.master_seed,
+            initial_state: Some(SimSnapshot {
+                world_bytes: vec![],
+                schema_version: crate::lockstep::SNAPSHOT_SCHEMA_VERSION,
+            }),
+            step_fps: crate::config::server_config::CONFIG.STEP_FPS,
+        };
+        Ok(LoopbackSeat {
+            player_id: grant.player_id,
+            binding: grant.binding,
+            resume_token: grant.resume_token,
+            game_start,
+        })
+    }
+
+    /// 離開座位（同 KCP 斷線；有重連寬限時座位保留到寬限結束）。
+    #[cfg(feature = "kcp")]
+    pub fn leave(&self, seat: &LoopbackSeat) -> Result<(), String> {
+        let shared = self.lockstep()?;
+        shared
+            .state
+            .lock()
+            .unwrap()
+            .disconnect_player(seat.player_id, seat.binding);
+        Ok(())
+    }
+
+    /// 提交一筆輸入。依序做授權、速率與語意驗證、晚到寬限，與 KCP 的
+    /// `InputSubmit` 處理相同；被拒絕時回傳原因。
+    #[cfg(feature = "kcp")]
+    pub fn submit(
+        &self,
+        seat: &LoopbackSeat,
+        target_tick: u32,
+        input: PlayerInput,
+        input_id: u32,
+    ) -> Result<InputSubmitResult, InputRejection> {
+        use crate::lockstep::wire::InputRejectReason;
+
+        let shared = self
+            .lockstep()
+            .map_err(|e| InputRejection::new(InputRejectReason::Unauthorized, e))?;
+        let step_fps = crate::config::server_config::CONFIG.STEP_FPS;
+        let (player_id, current_tick, late_grace) = {
+            let mut s = shared.state.lock().unwrap();
+            let player_id = s
+                .authorize_input(Some((seat.player_id, seat.binding)), seat.player_id)
+                .map_err(|e| InputRejection::new(InputRejectReason::Unauthorized, e))?;
+            s.validate_input(player_id, target_tick, &input)?;
+            let grace = s.late_grace_ticks(
+                player_id,
+                step_fps,
+                super::kcp_transport::late_input_grace_ticks(step_fps),
+            );
+            (player_id, s.current_tick, grace)
+        };
+        let result = shared.input_buffer.lock().unwrap().submit_with_late_grace(
+            current_tick,
+            player_id,
+            target_tick,
+            input,
+            input_id,
+            late_grace,
+        );
+        shared
+            .state
+            .lock()
+            .unwrap()
+            .record_input_result(player_id, &result);
+        if let InputSubmitResult::RejectedLate {
+            original_tick,
+            current_tick,
+        } = result
+        {
+            return Err(InputRejection::new(
+                InputRejectReason::Late,
+                format!(
+                    "target_tick {} is past the late grace at tick {}",
+                    original_tick, current_tick
+                ),
+            ));
+        }
+        Ok(result)
+    }
+
+    /// 階段 6.24：送出聊天 / 地圖標記，規則同 KCP 的 `ChatSend`（隊伍
+    /// 頻道以共用的 `TeamRoster` 查隊伍）。通過後在下一刻送出。
+    #[cfg(feature = "kcp")]
+    pub fn chat(
+        &self,
+        seat: &LoopbackSeat,
+        send: &crate::lockstep::wire::ChatSend,
+    ) -> Result<ChatMessage, ChatRejection> {
+        use crate::lockstep::wire::ChatRejectReason;
+
+        let shared = self
+            .lockstep()
+            .map_err(|e| ChatRejection::new(ChatRejectReason::Unauthorized, e))?;
+        let teams = shared.teams.clone();
+        let mut state = shared.state.lock().unwrap();
+        state.submit_chat(Some((seat.player_id, seat.binding)), send, |name| {
+            teams.read().ok().and_then(|r| r.team_of(name))
+        })
+    }
+
+    #[cfg(feature = "kcp")]
+    fn lockstep(&self) -> Result<&LockstepShared, String> {
+        self.shared
+            .as_ref()
+            .ok_or_else(|| "loopback transport was created without lockstep state".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn inbound_and_outbound_pass_straight_through() {
+        let (handle, client) = pair();
+        client
+            .command("bot", "player", "move", json!({"x": 1}))
+            .unwrap();
+        let msg = handle.rx.try_recv().unwrap();
+        assert_eq!((msg.name.as_str(), msg.a.as_str()), ("bot", "move"));
+
+        handle
+            .tx
+            .send(OutboundMsg::new_s_all(
+                "td/all/res",
+                "game",
+                "lives",
+                json!({"lives": 3}),
+            ))
+            .unwrap();
+        let out = client.drain();
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].topic, "td/all/res");
+        assert!(client.try_recv().is_none());
+    }
+
+    #[cfg(feature = "kcp")]
+    #[test]
+    fn lockstep_inputs_reach_the_broadcaster_deterministically() {
+        use crate::lockstep::{
+            InputBuffer, LockstepState, NoOp, PlayerInputEnum, TickBroadcaster,
+            TickBroadcasterConfig,
+        };
+        use std::sync::{Arc, Mutex};
+
+        let state = Arc::new(Mutex::new(LockstepState::new(7)));
+        let input_buffer = Arc::new(Mutex::new(InputBuffer::new()));
+        let shared = LockstepShared::new(
+            input_buffer.clone(),
+            state.clone(),
+            Arc::new(Mutex::new(crate::comp::SnapshotStore::default())),
+            Arc::new(Mutex::new(crate::lockstep::TickHistory::new(64))),
+        );
+        let (handle, client) = pair_with_lockstep(shared);
+        let broadcaster = TickBroadcaster::new(
+            TickBroadcasterConfig::default(),
+            input_buffer,
+            state,
+            handle.lockstep_tx.clone(),
+        );
+
+        let seat = client.join(3, "bot", JoinRoleEnum::Player).unwrap();
+        assert_eq!(seat.game_start.player_id, 3);
+        let noop = PlayerInput {
+            action: Some(PlayerInputEnum::NoOp(NoOp {})),
+        };
+        client.submit(&seat, 2, noop.clone(), 11).unwrap();
+
+        assert!(broadcaster.step());
+        assert!(broadcaster.step());
+        let batches: Vec<_> = client
+            .drain_frames()
+            .into_iter()
+            .filter_map(|f| match f {
+                LockstepFrame::TickBatch(b) => Some(b),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            batches.iter().map(|b| b.tick).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert!(batches[0].inputs.is_empty());
+        assert_eq!(batches[1].inputs[0].player_id, 3);
+        assert_eq!(batches[1].inputs[0].input_id, 11);
+
+        // 未加入的座位不能提交。
+        let stranger = LoopbackSeat {
+            player_id: 9,
+            ..seat
+        };
+        let rejected = client.submit(&stranger, 5, noop, 12).unwrap_err();
+        assert_eq!(
+            rejected.reason,
+            crate::lockstep::wire::InputRejectReason::Unauthorized
+        );
+    }
+
+    #[cfg(feature = "kcp")]
+    #[test]
+    fn lockstep_calls_without_state_are_refused() {
+        let (_handle, client) = pair();
+        assert!(client.join(1, "bot", JoinRoleEnum::Player).is_err());
+    }
+}
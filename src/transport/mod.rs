@@ -9,6 +9,11 @@ pub mod grpc_transport;
 #[cfg(feature = "kcp")]
 pub mod kcp_transport;
 
+#[cfg(feature = "websocket")]
+pub mod websocket_transport;
+
+pub mod loopback_transport;
+
 #[cfg(feature = "kcp")]
 pub mod metrics;
 
@@ -17,5 +22,11 @@ pub use metrics::{KcpBytesCounter, KcpCounterSnapshot};
 #[cfg(feature = "kcp")]
 pub use types::TypedOutbound;
 #[cfg(any(feature = "grpc", feature = "kcp"))]
-pub use types::{BroadcastPolicy, QueryRequest, QueryResponse, Viewport, ViewportMsg};
+pub use types::{
+    BroadcastPolicy, QueryRequest, QueryResponse, TeamRoster, Viewport, ViewportMsg,
+};
 pub use types::{InboundMsg, OutboundMsg, TransportHandle};
+
+// 階段 6.18：多傳輸並行。
+pub mod mux;
+pub use mux::{start_all, StartFuture, Transport, TransportKind, TransportMux};
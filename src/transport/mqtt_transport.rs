@@ -183,6 +183,36 @@ pub fn start(
 
     Ok(TransportHandle {
         tx: out_tx,
+        // 階段 6.18：與 gRPC / KCP 並行時補齊其他 feature 的欄位。MQTT 沒有
+        // 查詢、視口與 lockstep。
+        #[cfg(feature = "kcp")]
+        lockstep_tx: super::types::detached_sender(),
         rx: in_rx,
+        #[cfg(any(feature = "grpc", feature = "kcp"))]
+        query_rx: crossbeam_channel::never(),
+        #[cfg(any(feature = "grpc", feature = "kcp"))]
+        viewport_rx: crossbeam_channel::never(),
+        #[cfg(feature = "kcp")]
+        counter: std::sync::Arc::new(super::KcpBytesCounter::new()),
+        #[cfg(feature = "kcp")]
+        aoi: std::sync::Arc::new(std::sync::Mutex::new(crate::aoi::AoiGrid::new())),
     })
 }
+
+/// 階段 6.18：`Transport` 實作，供 `transport::start_all` 與其他傳輸並行。
+pub struct MqttTransport {
+    pub server_addr: String,
+    pub server_port: String,
+    pub client_id: String,
+}
+
+impl super::Transport for MqttTransport {
+    fn kind(&self) -> super::TransportKind {
+        super::TransportKind::Mqtt
+    }
+
+    fn start(self: Box<Self>) -> super::StartFuture {
+        let this = *self;
+        Box::pin(async move { start(this.server_addr, this.server_port, this.client_id) })
+    }
+}
@@ -0,0 +1,399 @@
+//! 階段 6.18：多個傳輸同時運行。
+//!
+//! 每個傳輸實作 `Transport`，由 `start_all` 啟動。只有一個傳輸時直接回傳
+//! 它的 `TransportHandle`；多個時由 `TransportMux` 合併成一個：
+//!
+//! - 入站 `InboundMsg` / `QueryRequest` / `ViewportMsg` 從各傳輸轉送到同一組
+//!   通道，`State` 照舊讀取；
+//! - 出站 `OutboundMsg` 扇出到所有傳輸（各自依自己的會話路由）；
+//! - lockstep 通道上的 `TickBatch` / `StateHash` 廣播到所有承載 lockstep 的
+//!   傳輸，`GameStart` / `SnapshotResp` 依會話 id 前綴只送到擁有該會話的傳輸。
+//!
+//! 原生 KCP、瀏覽器 WebSocket、gRPC 工具與 MQTT 測試腳本因此可以連上同一場
+//! 對局。lockstep 傳輸（KCP、WebSocket，以及階段 6.19 起的 gRPC lockstep
+//! 串流）需共用同一個 `kcp_transport::LockstepShared`。
+
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+use failure::Error;
+use log::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::thread;
+
+use super::types::{InboundMsg, OutboundMsg, TransportHandle};
+#[cfg(any(feature = "grpc", feature = "kcp"))]
+use super::types::{QueryRequest, ViewportMsg};
+
+pub type StartFuture = Pin<Box<dyn Future<Output = Result<TransportHandle, Error>> + Send>>;
+
+/// 一種可啟動的傳輸。實作通常是帶位址設定的小結構
+/// （例如 `kcp_transport::KcpTransport`）。
+pub trait Transport: Send {
+    fn kind(&self) -> TransportKind;
+    fn start(self: Box<Self>) -> StartFuture;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKind {
+    Mqtt,
+    Grpc,
+    Kcp,
+    WebSocket,
+    /// 階段 6.20：進程內 loopback（測試、無頭執行、內嵌 bot）。
+    Loopback,
+}
+
+impl TransportKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mqtt => "mqtt",
+            Self::Grpc => "grpc",
+            Self::Kcp => "kcp",
+            Self::WebSocket => "websocket",
+            Self::Loopback => "loopback",
+        }
+    }
+
+    /// 是否處理 `lockstep_tx` 上的幀。
+    pub fn carries_lockstep(self) -> bool {
+        matches!(
+            self,
+            Self::Kcp | Self::WebSocket | Self::Grpc | Self::Loopback
+        )
+    }
+
+    /// 該傳輸會話 id 的前綴（`LockstepFrame` 單播的 `client_session_id`）。
+    pub fn session_prefix(self) -> Option<&'static str> {
+        match self {
+            Self::Kcp => Some("kcp_"),
+            Self::WebSocket => Some("ws_"),
+            Self::Grpc => Some("grpc_"),
+            // loopback 不經 `LockstepFrame` 單播（加入時直接拿到 GameStart）。
+            Self::Mqtt | Self::Loopback => None,
+        }
+    }
+}
+
+/// 依序啟動所有傳輸；任一啟動失敗即回傳錯誤。
+pub async fn start_all(transports: Vec<Box<dyn Transport>>) -> Result<TransportHandle, Error> {
+    let mut started = Vec::with_capacity(transports.len());
+    for transport in transports {
+        let kind = transport.kind();
+        let handle = transport.start().await.map_err(|e| {
+            failure::err_msg(format!("failed to start {} transport: {}", kind.name(), e))
+        })?;
+        info!("{} transport started", kind.name());
+        started.push((kind, handle));
+    }
+    match started.len() {
+        0 => Err(failure::err_msg("no transport enabled")),
+        1 => Ok(started.pop().unwrap().1),
+        _ => Ok(TransportMux::spawn(started)),
+    }
+}
+
+pub struct TransportMux;
+
+impl TransportMux {
+    /// 把多個已啟動的傳輸合併成一個 `TransportHandle`。
+    pub fn spawn(handles: Vec<(TransportKind, TransportHandle)>) -> TransportHandle {
+        let names: Vec<&str> = handles.iter().map(|(k, _)| k.name()).collect();
+        info!("TransportMux: multiplexing {:?}", names);
+
+        let (out_tx, out_rx) = bounded::<OutboundMsg>(100_000);
+        let (in_tx, in_rx) = bounded::<InboundMsg>(10_000);
+        #[cfg(any(feature = "grpc", feature = "kcp"))]
+        let (query_tx, query_rx) = bounded::<QueryRequest>(100);
+        #[cfg(any(feature = "grpc", feature = "kcp"))]
+        let (viewport_tx, viewport_rx) = bounded::<ViewportMsg>(1024);
+        #[cfg(feature = "kcp")]
+        let (lockstep_tx, lockstep_rx) = bounded::<OutboundMsg>(10_000);
+
+        // lockstep 傳輸共用 `LockstepShared`，counter / aoi 是同一個 Arc；
+        // 沒有 lockstep 傳輸時取第一個。
+        #[cfg(feature = "kcp")]
+        let (counter, aoi) = {
+            let (_, first) = handles
+                .iter()
+                .find(|(k, _)| k.carries_lockstep())
+                .unwrap_or(&handles[0]);
+            (first.counter.clone(), first.aoi.clone())
+        };
+
+        let mut outputs = Vec::with_capacity(handles.len());
+        for (kind, handle) in handles {
+            forward(kind, "inbound", handle.rx, in_tx.clone());
+            #[cfg(any(feature = "grpc", feature = "kcp"))]
+            {
+                forward(kind, "query", handle.query_rx, query_tx.clone());
+                forward(kind, "viewport", handle.viewport_rx, viewport_tx.clone());
+            }
+            outputs.push(MuxOutput {
+                kind,
+                tx: handle.tx,
+                #[cfg(feature = "kcp")]
+                lockstep_tx: handle.lockstep_tx,
+            });
+        }
+
+        #[cfg(feature = "kcp")]
+        {
+            let outputs = outputs.clone();
+            thread::Builder::new()
+                .name("transport-mux-lockstep".into())
+                .spawn(move || {
+                    for msg in lockstep_rx.iter() {
+                        fan_out_lockstep(&outputs, msg);
+                    }
+                    info!("TransportMux: lockstep channel closed");
+                })
+                .expect("spawn transport mux thread");
+        }
+        thread::Builder::new()
+            .name("transport-mux-out".into())
+            .spawn(move || {
+                let mut dropped = 0u64;
+                for msg in out_rx.iter() {
+                    for out in &outputs {
+                        try_send_counted(out, msg.clone(), &mut dropped);
+                    }
+                }
+                info!("TransportMux: outbound channel closed");
+            })
+            .expect("spawn transport mux thread");
+
+        TransportHandle {
+            tx: out_tx,
+            #[cfg(feature = "kcp")]
+            lockstep_tx,
+            rx: in_rx,
+            #[cfg(any(feature = "grpc", feature = "kcp"))]
+            query_rx,
+            #[cfg(any(feature = "grpc", feature = "kcp"))]
+            viewport_rx,
+            #[cfg(feature = "kcp")]
+            counter,
+            #[cfg(feature = "kcp")]
+            aoi,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct MuxOutput {
+    kind: TransportKind,
+    tx: Sender<OutboundMsg>,
+    #[cfg(feature = "kcp")]
+    lockstep_tx: Sender<OutboundMsg>,
+}
+
+/// 把一個傳輸的接收端轉送到合併後的通道，直到任一端關閉。
+fn forward<T: Send + 'static>(
+    kind: TransportKind,
+    what: &'static str,
+    rx: Receiver<T>,
+    tx: Sender<T>,
+) {
+    thread::Builder::new()
+        .name(format!("transport-mux-{}-{}", kind.name(), what))
+        .spawn(move || {
+            for item in rx.iter() {
+                if tx.send(item).is_err() {
+                    break;
+                }
+            }
+            debug!("TransportMux: {} {} forwarder stopped", kind.name(), what);
+        })
+        .expect("spawn transport mux thread");
+}
+
+/// 舊事件路徑：佇列滿就丟，避免一個卡住的傳輸拖住其他傳輸。
+fn try_send_counted(out: &MuxOutput, msg: OutboundMsg, dropped: &mut u64) {
+    if let Err(TrySendError::Full(_)) = out.tx.try_send(msg) {
+        *dropped += 1;
+        if *dropped == 1 || *dropped % 1000 == 0 {
+            warn!(
+                "TransportMux: {} outbound queue full, {} messages dropped so far",
+                out.kind.name(),
+                dropped
+            );
+        }
+    }
+}
+
+/// lockstep 幀不能丟：以阻塞送出。非幀訊息（例如落後通知）送往 lockstep
+/// 傳輸的 lockstep 通道與其他傳輸的一般通道。
+#[cfg(feature = "kcp")]
+fn fan_out_lockstep(outputs: &[MuxOutput], msg: OutboundMsg) {
+    use crate::lockstep::LockstepFrame;
+
+    let unicast = match msg.lockstep_frame.as_ref() {
+        Some(LockstepFrame::GameStart {
+            client_session_id, ..
+        })
+        | Some(LockstepFrame::SnapshotResp {
+            client_session_id, ..
+        }) => Some(client_session_id.as_str()),
+        _ => None,
+    };
+    for out in outputs {
+        let lockstep = out.kind.carries_lockstep();
+        if msg.lockstep_frame.is_some() && !lockstep {
+            continue;
+        }
+        if let Some(session_id) = unicast {
+            let owns = out
+                .kind
+                .session_prefix()
+                .is_some_and(|p| session_id.starts_with(p));
+            if !owns {
+                continue;
+            }
+        }
+        let tx = if lockstep { &out.lockstep_tx } else { &out.tx };
+        if let Err(e) = tx.send(msg.clone()) {
+            warn!(
+                "TransportMux: {} lockstep send failed: {}",
+                out.kind.name(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    struct Fake {
+        handle: TransportHandle,
+        out_rx: Receiver<OutboundMsg>,
+        #[cfg(feature = "kcp")]
+        lockstep_rx: Receiver<OutboundMsg>,
+        in_tx: Sender<InboundMsg>,
+    }
+
+    fn fake() -> Fake {
+        let (out_tx, out_rx) = bounded(16);
+        let (in_tx, in_rx) = bounded(16);
+        #[cfg(feature = "kcp")]
+        let (lockstep_tx, lockstep_rx) = bounded(16);
+        let handle = TransportHandle {
+            tx: out_tx,
+            #[cfg(feature = "kcp")]
+            lockstep_tx,
+            rx: in_rx,
+            #[cfg(any(feature = "grpc", feature = "kcp"))]
+            query_rx: crossbeam_channel::never(),
+            #[cfg(any(feature = "grpc", feature = "kcp"))]
+            viewport_rx: crossbeam_channel::never(),
+            #[cfg(feature = "kcp")]
+            counter: std::sync::Arc::new(crate::transport::KcpBytesCounter::new()),
+            #[cfg(feature = "kcp")]
+            aoi: std::sync::Arc::new(std::sync::Mutex::new(crate::aoi::AoiGrid::new())),
+        };
+        Fake {
+            handle,
+            out_rx,
+            #[cfg(feature = "kcp")]
+            lockstep_rx,
+            in_tx,
+        }
+    }
+
+    #[test]
+    fn inbound_is_merged_and_outbound_fans_out() {
+        let a = fake();
+        let b = fake();
+        let mux = TransportMux::spawn(vec![
+            (TransportKind::Mqtt, a.handle),
+            (TransportKind::Grpc, b.handle),
+        ]);
+
+        for (tx, name) in [(&a.in_tx, "mqtt_player"), (&b.in_tx, "grpc_player")] {
+            tx.send(InboundMsg {
+                name: name.into(),
+                t: "player".into(),
+                a: "join".into(),
+                d: json!({}),
+            })
+            .unwrap();
+        }
+        let mut names: Vec<String> = (0..2)
+            .map(|_| mux.rx.recv_timeout(Duration::from_secs(1)).unwrap().name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["grpc_player", "mqtt_player"]);
+
+        mux.tx
+            .send(OutboundMsg::new_s_all(
+                "td/all/res",
+                "game",
+                "lives",
+                json!({"lives": 3}),
+            ))
+            .unwrap();
+        for rx in [&a.out_rx, &b.out_rx] {
+            let msg = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+            assert_eq!(msg.topic, "td/all/res");
+        }
+    }
+
+    #[cfg(feature = "kcp")]
+    #[test]
+    fn lockstep_unicast_goes_to_the_owning_transport_only() {
+        use crate::lockstep::{GameStart, LockstepFrame, TickBatch};
+
+        let kcp = fake();
+        let ws = fake();
+        let mqtt = fake();
+        let mux = TransportMux::spawn(vec![
+            (TransportKind::Kcp, kcp.handle),
+            (TransportKind::WebSocket, ws.handle),
+            (TransportKind::Mqtt, mqtt.handle),
+        ]);
+
+        mux.lockstep_tx
+            .send(OutboundMsg::lockstep_frame(LockstepFrame::GameStart {
+                client_session_id: "ws_127.0.0.1:9000".into(),
+                msg: GameStart::default(),
+                resume_token: String::new(),
+            }))
+            .unwrap();
+        mux.lockstep_tx
+            .send(OutboundMsg::lockstep_frame(LockstepFrame::TickBatch(
+                TickBatch {
+                    tick: 7,
+                    ..Default::default()
+                },
+            )))
+            .unwrap();
+
+        let recv = |rx: &Receiver<OutboundMsg>| rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(matches!(
+            recv(&ws.lockstep_rx).lockstep_frame,
+            Some(LockstepFrame::GameStart { .. })
+        ));
+        for rx in [&kcp.lockstep_rx, &ws.lockstep_rx] {
+            assert!(matches!(
+                recv(rx).lockstep_frame,
+                Some(LockstepFrame::TickBatch(TickBatch { tick: 7, .. }))
+            ));
+        }
+        // MQTT 不承載 lockstep 幀。
+        assert!(mqtt.lockstep_rx.try_recv().is_err());
+        assert!(mqtt.out_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn session_prefixes_identify_lockstep_owners() {
+        assert_eq!(TransportKind::Kcp.session_prefix(), Some("kcp_"));
+        assert_eq!(TransportKind::WebSocket.session_prefix(), Some("ws_"));
+        assert_eq!(TransportKind::Grpc.session_prefix(), Some("grpc_"));
+        assert!(!TransportKind::Mqtt.carries_lockstep());
+        assert!(TransportKind::Grpc.carries_lockstep());
+        assert!(TransportKind::WebSocket.carries_lockstep());
+    }
+}
@@ -49,6 +49,34 @@ pub enum BroadcastPolicy {
     /// 單一目標 - 特定於玩家的事件，例如 Hero.inventory、
     /// 蠕動可見度差異（目前 `td/{player}/res` 主題）。
     PlayerOnly(String),
+    /// 階段 6.23：`TeamRoster` 中屬於該隊伍（陣營）的玩家。隊友標記、
+    /// 共享金錢、隊伍聊天等不能讓對手收到，未分隊的會話一律不送。
+    Team(u32),
+    /// 階段 6.23：除了該玩家以外的所有人（例如已在本地預測的自身操作）。
+    AllExcept(String),
+}
+
+/// 階段 6.23：玩家名稱 → 隊伍編號，`BroadcastPolicy::Team` 依此選擇會話。
+/// 由伺服器指定（`game.toml` 的 `TEAMS` 或遊戲模式），不採信客戶端。
+#[cfg(any(feature = "grpc", feature = "kcp"))]
+#[derive(Default, Debug, Clone)]
+pub struct TeamRoster {
+    teams: std::collections::HashMap<String, u32>,
+}
+
+#[cfg(any(feature = "grpc", feature = "kcp"))]
+impl TeamRoster {
+    pub fn assign(&mut self, player_name: impl Into<String>, team: u32) {
+        self.teams.insert(player_name.into(), team);
+    }
+
+    pub fn remove(&mut self, player_name: &str) -> Option<u32> {
+        self.teams.remove(player_name)
+    }
+
+    pub fn team_of(&self, player_name: &str) -> Option<u32> {
+        self.teams.get(player_name).copied()
+    }
 }
 
 /// 從遊戲邏輯到傳輸層的出站訊息。
@@ -350,6 +378,7 @@ pub enum Urgency {
 /// - `creep.stall` — 一次性碰撞回饋。
 /// - `tower.create/.upgrade` — 很少見，但對使用者體驗至關重要。
 /// - `buff.*` — 新增/刪除：玩家必須看到 buff 清晰地出現/消失。
+/// - `entity.enter/.leave` — 階段 6.22 AOI 進出視野，與產生/死亡同級。
 ///
 /// 其他所有內容（creep.M/.H/.S、entity.F、hero.hot、heartbeat.tick）都會下降
 /// 到“正常”並享受重複資料刪除視窗。
@@ -366,6 +395,8 @@ pub fn urgency(msg_type: &str, action: &str) -> Urgency {
         ("tower", "upgrade") => Urgency::Urgent,
         ("creep", "stall") => Urgency::Urgent,
         ("buff", _) => Urgency::Urgent,
+        ("entity", "enter" | "leave") => Urgency::Urgent,
+        ("chat", _) => Urgency::Urgent,
         _ => Urgency::Normal,
     }
 }
@@ -461,6 +492,13 @@ pub struct TransportHandle {
     pub aoi: Arc<std::sync::Mutex<crate::aoi::AoiGrid>>,
 }
 
+/// 階段 6.18：接收端已關閉的發送端。給不承載 lockstep 的傳輸（gRPC /
+/// MQTT）填 `TransportHandle::lockstep_tx`；`TransportMux` 不會往這裡送。
+#[cfg(feature = "kcp")]
+pub(crate) fn detached_sender<T>() -> Sender<T> {
+    crossbeam_channel::bounded(1).0
+}
+
 #[cfg(test)]
 #[cfg(any(feature = "grpc", feature = "kcp"))]
 mod urgency_tests {
@@ -503,6 +541,18 @@ mod urgency_tests {
         assert_eq!(urgency("buff", "buff_add"), Urgency::Urgent);
     }
 
+    #[test]
+    fn aoi_enter_leave_urgent() {
+        assert_eq!(urgency("entity", "enter"), Urgency::Urgent);
+        assert_eq!(urgency("entity", "leave"), Urgency::Urgent);
+    }
+
+    #[test]
+    fn chat_and_pings_urgent() {
+        assert_eq!(urgency("chat", "msg"), Urgency::Urgent);
+        assert_eq!(urgency("chat", "ping"), Urgency::Urgent);
+    }
+
     #[test]
     fn tower_upgrade_urgent() {
         assert_eq!(urgency("tower", "upgrade"), Urgency::Urgent);
@@ -0,0 +1,219 @@
+//! 階段 6.17：瀏覽器用的 WebSocket 傳輸。
+//!
+//! 瀏覽器無法使用 KCP（UDP），所以以 WebSocket 承載與 KCP 完全相同的
+//! `[1B tag][4B BE len][payload]` 幀（`0x80` 為 LZ4 旗標）：每個 binary
+//! 訊息放一個或多個完整的幀。連線被橋接成一條位元組串流後交給 KCP 的
+//! `SessionServer`，因此 lockstep（JoinRequest / InputSubmit / TickBatch /
+//! StateHash / GameStart / SnapshotResp）與舊 GameEvent 路徑都與 KCP
+//! 客戶端行為一致，回傳的 `TransportHandle` 也相同，`State` 不需要知道
+//! 使用的是哪一種傳輸。
+
+use failure::Error;
+use futures_util::{SinkExt, StreamExt};
+use log::*;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use super::kcp_transport::{spawn_session_server, LockstepShared, SessionServer};
+use super::types::TransportHandle;
+
+/// 橋接用 duplex 緩衝區大小。
+const BRIDGE_BUFFER_BYTES: usize = 256 * 1024;
+
+/// 啟動 WebSocket 傳輸層。參數與 `kcp_transport::start` 相同。
+pub async fn start(
+    server_addr: String,
+    server_port: String,
+    lockstep_input_buffer: Arc<std::sync::Mutex<crate::lockstep::InputBuffer>>,
+    lockstep_state: Arc<std::sync::Mutex<crate::lockstep::LockstepState>>,
+    lockstep_snapshot_store: Arc<std::sync::Mutex<crate::comp::SnapshotStore>>,
+    lockstep_tick_history: Arc<std::sync::Mutex<crate::lockstep::TickHistory>>,
+) -> Result<TransportHandle, Error> {
+    let shared = LockstepShared::new(
+        lockstep_input_buffer,
+        lockstep_state,
+        lockstep_snapshot_store,
+        lockstep_tick_history,
+    );
+    start_shared(server_addr, server_port, shared).await
+}
+
+/// 階段 6.18：`Transport` 實作。與 `KcpTransport` 共用同一個
+/// `LockstepShared` 時，瀏覽器與原生客戶端在同一場對局。
+pub struct WebSocketTransport {
+    pub server_addr: String,
+    pub server_port: String,
+    pub shared: LockstepShared,
+}
+
+impl super::Transport for WebSocketTransport {
+    fn kind(&self) -> super::TransportKind {
+        super::TransportKind::WebSocket
+    }
+
+    fn start(self: Box<Self>) -> super::StartFuture {
+        Box::pin(start_shared(self.server_addr, self.server_port, self.shared))
+    }
+}
+
+/// 以既有的 `LockstepShared` 啟動 WebSocket 傳輸。
+pub async fn start_shared(
+    server_addr: String,
+    server_port: String,
+    shared: LockstepShared,
+) -> Result<TransportHandle, Error> {
+    let bind_ip = match server_addr.as_str() {
+        "localhost" | "127.0.0.1" => "0.0.0.0".to_string(),
+        other => other.to_string(),
+    };
+    let addr = format!("{}:{}", bind_ip, server_port);
+    let addr: std::net::SocketAddr = addr
+        .parse()
+        .map_err(|e| failure::err_msg(format!("Invalid address '{}': {}", addr, e)))?;
+
+    info!("Starting WebSocket server on {}", addr);
+    let listener = TcpListener::bind(addr).await.map_err(|e| {
+        failure::err_msg(format!("Failed to bind WebSocket listener on {}: {}", addr, e))
+    })?;
+
+    let (handle, server) = spawn_session_server(shared);
+    tokio::spawn(accept_loop(listener, server));
+    Ok(handle)
+}
+
+async fn accept_loop(listener: TcpListener, server: SessionServer) {
+    loop {
+        let (tcp, peer_addr) = match listener.accept().await {
+            Ok(s) => s,
+            Err(e) => {
+                error!("WebSocket accept error: {}", e);
+                continue;
+            }
+        };
+        let server = server.clone();
+        tokio::spawn(async move {
+            // 幀都很小且講求延遲，關掉 Nagle。
+            let _ = tcp.set_nodelay(true);
+            let ws = match tokio_tungstenite::accept_async(tcp).await {
+                Ok(ws) => ws,
+                Err(e) => {
+                    warn!("WebSocket handshake with {} failed: {}", peer_addr, e);
+                    return;
+                }
+            };
+            info!("WebSocket client connected from {}", peer_addr);
+            let (session_io, bridge_io) = tokio::io::duplex(BRIDGE_BUFFER_BYTES);
+            server.spawn_client(session_io, format!("ws_{}", peer_addr));
+            bridge(ws, bridge_io).await;
+            info!("WebSocket client {} disconnected", peer_addr);
+        });
+    }
+}
+
+/// 在 WebSocket 訊息與會話的位元組串流之間搬運資料。任一方向結束
+/// （客戶端關閉或會話處理結束）即整條連線結束。
+async fn bridge(ws: WebSocketStream<TcpStream>, io: DuplexStream) {
+    let (mut ws_tx, mut ws_rx) = ws.split();
+    let (mut io_rx, mut io_tx) = tokio::io::split(io);
+
+    let inbound = async {
+        while let Some(msg) = ws_rx.next().await {
+            match msg {
+                Ok(Message::Binary(bytes)) => {
+                    if io_tx.write_all(&bytes).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(Message::Close(_)) => break,
+                // 協定只用 binary；ping/pong 由 tungstenite 處理。
+                Ok(_) => {}
+                Err(e) => {
+                    debug!("WebSocket read error: {}", e);
+                    break;
+                }
+            }
+        }
+        let _ = io_tx.shutdown().await;
+    };
+    let outbound = async {
+        loop {
+            match read_frame_bytes(&mut io_rx).await {
+                Ok(Some(frame)) => {
+                    if ws_tx.send(Message::Binary(frame)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("WebSocket outbound frame error: {}", e);
+                    break;
+                }
+            }
+        }
+        let _ = ws_tx.close().await;
+    };
+    tokio::select! {
+        _ = inbound => {}
+        _ = outbound => {}
+    }
+}
+
+/// 從會話串流讀出一個完整的幀（含標頭，壓縮旗標原樣保留），讓每個
+/// 出站幀對應一個 WebSocket 訊息。串流結束時回傳 `None`。
+async fn read_frame_bytes<R: AsyncReadExt + Unpin>(
+    reader: &mut R,
+) -> std::io::Result<Option<Vec<u8>>> {
+    let mut header = [0u8; 5];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    let mut frame = Vec::with_capacity(header.len() + len);
+    frame.extend_from_slice(&header);
+    frame.resize(header.len() + len, 0);
+    reader.read_exact(&mut frame[header.len()..]).await?;
+    Ok(Some(frame))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(tag: u8, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    #[tokio::test]
+    async fn coalesced_stream_is_split_back_into_frames() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        let first = frame(0x11, &[1, 2, 3]);
+        let second = frame(0x12 | 0x80, &[]);
+        let mut both = first.clone();
+        both.extend_from_slice(&second);
+        a.write_all(&both).await.unwrap();
+        drop(a);
+
+        assert_eq!(read_frame_bytes(&mut b).await.unwrap(), Some(first));
+        assert_eq!(read_frame_bytes(&mut b).await.unwrap(), Some(second));
+        assert_eq!(read_frame_bytes(&mut b).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn truncated_frame_is_an_error() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        let mut partial = frame(0x11, &[9; 8]);
+        partial.truncate(7);
+        a.write_all(&partial).await.unwrap();
+        drop(a);
+
+        assert!(read_frame_bytes(&mut b).await.is_err());
+    }
+}
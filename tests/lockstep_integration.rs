@@ -1,72 +1,427 @@
-//! 階段 2 鎖步線路整合測試（用於手動驗證的佔位符）。
+//! 階段 6.16：進程內鎖步整合測試。
 //!
-//! 完整的快樂路徑工具 — 啟動真正的 tokio KCP 伺服器，
-//! 連接兩個 GameClient 實例，交換 JoinRequest /
-//! GameStart / InputSubmit / TickBatch — 被故意延後
-//! 階段 2.5。在這裡旋轉進程內伺服器必須：
+//! `LockstepHarness` 在本機臨時連接埠啟動真正的
+//! `kcp_transport::start` 與 `TickBroadcaster`，`ScriptedClient` 以 KCP
+//! 連線走完 JoinRequest → GameStart → InputSubmit → TickBatch，
+//! 讓鎖步線路層在 `cargo test` 中有端到端覆蓋。這一段不跑模擬，
+//! StateHash 為廣播器的佔位雜湊。
 //!
-//! 1.建構一個新的ECS World +調度程式（omb的main.rs組裝~30
-//! 傳輸執行緒啟動之前的資源/系統），或者
-//! 2. 產生 `target/debug/omobab.exe` 作為子程序 + 解析標準輸出
-//! 撥號前「正在收聽…」。
-//!
-//! 兩條路徑都增加了真實的測試基礎設施表面（連接埠分配、子進程
-//! Windows 上的生命週期，「scripts/base_content.dll」的 DLL 暫存，
-//! game.toml STORY 覆蓋）僅在第 3 階段引入時才有回報
-//! 真實的客戶端 SIM 消耗。直到那時電線層是
-//! 行使者：
-//!
-//! - `omb/src/lockstep/input_buffer.rs` — 提交+耗盡+驅逐測試
-//!   - `omb/src/lockstep/tick_broadcaster.rs` — fire_one_tick / hash /
-//! 通道關閉/60 次驅逐測試（階段 2.5）
-//! - `omb/src/transport/kcp_transport.rs` 測試 — 幀編碼，
-//! InputSubmit 解碼往返、JoinRole 映射
-//!
-//! 下面的兩個「#[ignore]」佔位符固定網路的*形狀*
-//! 階段 3 將填寫的往返測試，並記錄所需的內容
-//! 函數體中的不變量作為註釋。
-//!
-//! 運行：`cargo test --test lockstep_integration -- --ignored`
-
-#[tokio::test]
-#[ignore] // requires real omb server; run via run.bat manually for Phase 2
-async fn two_clients_receive_synchronized_tick_batch() {
-    // 所需的不變量（第 3 階段將斷言這些）：
-    // 1. `GameClient::connect("127.0.0.1:50062").await` 皆成功。
-    // 2. 每個呼叫 `join_lockstep(name, JoinRole::Player)` 並接收
-    // `GameStart {player_id, master_seed, start_tick }`。兩人
-    // player_ids 不同，兩個 master_seeds 匹配。
-    // 3. 每個人都透過 `subscribe_lockstep()` 訂閱並返回一個串流
-    // `LockstepFrame`。
-    // 4. 分別呼叫 `submit_input(target_tick = start_tick + 10,
-    // PlayerInput::NoOp)`。
-    // 5. 兩個流都會發出“TickBatch {tick: start_tick + 10, input }”
-    // 其中 `inputs.len() == 2` 和 `inputs.iter().map(|i| i.player_id)
-    // .collect::<BTreeSet<_>>()` 涵蓋了兩個player_ids。
-    // 6. 兩個客戶端都以相同的順序看到 TickBatches（確定性
-    // 階段 3 omoba-sim 消耗的重播前提條件）。
-    panic!(
-        "Phase 2.5 placeholder: spin up server + 2-client roundtrip is \
-         deferred to Phase 3 when omoba-sim worker thread lands on omfx. \
-         Manual smoke: run.bat + observe legacy GameEvent stream."
-    );
+//! 狀態雜湊一段改走 loopback 傳輸：`SimHarness` 以 story `TD_1` 建立
+//! 主機 `State`，每次 `TickBroadcaster::step` 之後跑一刻 `State::tick`
+//! （輸入經 host_input_tx，與 `main.rs` 相同的接法），客戶端把收到的
+//! TickBatch 依 `omobab-replay` 的重播契約餵給另一個 `State`，比對兩邊
+//! `compute_state_hash` 的結果並以 `ClientStateHash` 的規則回報。
+
+#![cfg(feature = "kcp")]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crossbeam_channel::{unbounded, Sender};
+use omobab::comp::{MasterSeed, SnapshotStore};
+use omobab::config::server_config::{apply_runtime_env_from_game_toml, CONFIG};
+use omobab::lockstep::{
+    GameStart, InputBuffer, InputSubmit, JoinRequest, JoinRole, JoinRoleEnum, LockstepFrame,
+    LockstepState, NoOp, PlayerInput, PlayerInputEnum, StateHash, TickBatch, TickBroadcaster,
+    TickBroadcasterConfig, TickHistory,
+};
+use omobab::state::State;
+use omobab::transport::kcp_transport::{self, LockstepShared};
+use omobab::transport::loopback_transport::{self, LoopbackClient};
+use omobab::transport::TransportHandle;
+use prost::Message;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::task::JoinHandle;
+use tokio_kcp::{KcpConfig, KcpNoDelayConfig, KcpStream};
+
+const TAG_INPUT_SUBMIT: u8 = 0x10;
+const TAG_TICK_BATCH: u8 = 0x11;
+const TAG_STATE_HASH: u8 = 0x12;
+const TAG_JOIN_REQUEST: u8 = 0x13;
+const TAG_GAME_START: u8 = 0x14;
+const COMPRESSION_FLAG: u8 = 0x80;
+
+/// KCP 測試用刻度：100Hz、每 20 刻一次 StateHash。
+const STEP_FPS: u32 = 100;
+const STATE_HASH_INTERVAL: u32 = 20;
+/// 輸入提前量，遠大於本機 RTT，不會被 retarget。
+const INPUT_LEAD_TICKS: u32 = 30;
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+// ===== 伺服器端 =====
+
+struct LockstepHarness {
+    addr: SocketAddr,
+    /// 持有傳輸通道；丟棄後廣播執行緒會隨出站通道關閉而結束。
+    _transport: TransportHandle,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl LockstepHarness {
+    async fn start() -> Self {
+        let port = free_udp_port();
+        let state = Arc::new(Mutex::new(LockstepState::new(0x5EED)));
+        let input_buffer = Arc::new(Mutex::new(InputBuffer::new()));
+        let snapshot_store = Arc::new(Mutex::new(SnapshotStore::default()));
+        let history = Arc::new(Mutex::new(TickHistory::new(1024)));
+
+        let handle = kcp_transport::start(
+            "127.0.0.1".to_string(),
+            port.to_string(),
+            input_buffer.clone(),
+            state.clone(),
+            snapshot_store,
+            history.clone(),
+        )
+        .await
+        .expect("kcp transport starts on an ephemeral port");
+
+        let config = TickBroadcasterConfig {
+            tick_period_us: 1_000_000 / STEP_FPS as u64,
+            step_fps: STEP_FPS,
+            state_hash_interval: STATE_HASH_INTERVAL,
+            input_evict_interval: STEP_FPS,
+            input_retention_ticks: STEP_FPS * 2,
+        };
+        let broadcaster =
+            TickBroadcaster::new(config, input_buffer, state, handle.lockstep_tx.clone())
+                .with_tick_history(history);
+
+        let tasks = vec![tokio::spawn(broadcaster.run())];
+
+        Self {
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+            _transport: handle,
+            tasks,
+        }
+    }
+}
+
+impl Drop for LockstepHarness {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+fn free_udp_port() -> u16 {
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").expect("bind ephemeral udp port");
+    socket.local_addr().unwrap().port()
+}
+
+// ===== 客戶端 =====
+
+struct ScriptedClient {
+    stream: KcpStream,
+    player_id: u32,
+    start_tick: u32,
+    batches: Vec<TickBatch>,
+    hashes: Vec<StateHash>,
 }
 
-#[tokio::test]
-#[ignore]
-async fn state_hash_broadcast_every_600_ticks() {
-    // 所需的不變量：
-    // 1. GameStart後，subscribe_lockstep串流接收第一個
-    // StateHash 位於 configured 10s lockstep interval。
-    // 2. 兩個客戶端收到相同的StateHash（佔位符哈希
-    // 公式“tick * 0x9E3779B97F4A7C15”純粹依賴tick
-    // 所以這在第 2 階段是非常正確的）。
-    // 3. 第 3 階段交換到真正的 ECS 雜湊：客戶端運作相同
-    // 相同 TickBatch 序列上的 omoba-sim 實例必須看到
-    // 位元相同的哈希值——任何分歧都是一個不同步錯誤。
-    panic!(
-        "Phase 2.5 placeholder: see comment + tick_broadcaster unit tests \
-         (`emits_state_hash_at_interval_multiples`, \
-         `placeholder_state_hash_is_deterministic_pin`)."
+impl ScriptedClient {
+    async fn connect(addr: SocketAddr) -> Self {
+        let mut config = KcpConfig::default();
+        config.nodelay = KcpNoDelayConfig::fastest();
+        let stream = KcpStream::connect(&config, addr)
+            .await
+            .expect("kcp client connects");
+        Self {
+            stream,
+            player_id: 0,
+            start_tick: 0,
+            batches: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    async fn send(&mut self, tag: u8, msg: &impl Message) {
+        let payload = msg.encode_to_vec();
+        let mut frame = Vec::with_capacity(5 + payload.len());
+        frame.push(tag);
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&payload);
+        self.stream.write_all(&frame).await.expect("write frame");
+        self.stream.flush().await.expect("flush frame");
+    }
+
+    async fn recv(&mut self) -> (u8, Vec<u8>) {
+        let tag = self.stream.read_u8().await.expect("read tag");
+        let len = self.stream.read_u32().await.expect("read len") as usize;
+        let mut buf = vec![0u8; len];
+        self.stream.read_exact(&mut buf).await.expect("read payload");
+        if tag & COMPRESSION_FLAG != 0 {
+            let payload = lz4_flex::block::decompress_size_prepended(&buf).expect("lz4 frame");
+            (tag & !COMPRESSION_FLAG, payload)
+        } else {
+            (tag, buf)
+        }
+    }
+
+    async fn join(&mut self, name: &str) {
+        let req = JoinRequest {
+            player_name: name.to_string(),
+            role: JoinRole::RolePlayer as i32,
+            player_id: 0,
+        };
+        self.send(TAG_JOIN_REQUEST, &req).await;
+        loop {
+            let (tag, payload) = self.recv().await;
+            if tag == TAG_GAME_START {
+                let gs = GameStart::decode(payload.as_slice()).expect("decode GameStart");
+                self.player_id = gs.player_id;
+                self.start_tick = gs.start_tick;
+                return;
+            }
+            // GameStart 之前可能已收到批次（會話先標記為已加入）。
+            self.handle(tag, &payload);
+        }
+    }
+
+    async fn submit_noop(&mut self, target_tick: u32, input_id: u32) {
+        let req = InputSubmit {
+            player_id: self.player_id,
+            target_tick,
+            input: Some(PlayerInput {
+                action: Some(PlayerInputEnum::NoOp(NoOp {})),
+            }),
+            input_id,
+        };
+        self.send(TAG_INPUT_SUBMIT, &req).await;
+    }
+
+    /// 處理一個收到的幀：記錄批次與 StateHash。
+    fn handle(&mut self, tag: u8, payload: &[u8]) {
+        match tag {
+            TAG_TICK_BATCH => {
+                let batch = TickBatch::decode(payload).expect("decode TickBatch");
+                self.batches.push(batch);
+            }
+            TAG_STATE_HASH => {
+                let sh = StateHash::decode(payload).expect("decode StateHash");
+                self.hashes.push(sh);
+            }
+            _ => {}
+        }
+    }
+
+    /// 持續處理幀，直到收到刻度 `tick` 之後的 `hashes_after` 個 StateHash。
+    async fn pump_until(&mut self, tick: u32, hashes_after: usize) {
+        while self.hashes.iter().filter(|h| h.tick > tick).count() < hashes_after {
+            let (tag, payload) = self.recv().await;
+            self.handle(tag, &payload);
+        }
+    }
+}
+
+// ===== 測試 =====
+
+async fn join_all(harness: &LockstepHarness, names: &[&str]) -> Vec<ScriptedClient> {
+    let mut clients = Vec::new();
+    for name in names {
+        let mut client = ScriptedClient::connect(harness.addr).await;
+        client.join(name).await;
+        clients.push(client);
+    }
+    clients
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn scripted_clients_receive_identical_tick_batches() {
+    tokio::time::timeout(TIMEOUT, async {
+        let harness = LockstepHarness::start().await;
+        let mut clients = join_all(&harness, &["alice", "bob", "carol"]).await;
+
+        let ids: BTreeSet<u32> = clients.iter().map(|c| c.player_id).collect();
+        assert_eq!(ids.len(), clients.len(), "each seat gets its own player_id");
+
+        let target = clients.iter().map(|c| c.start_tick).max().unwrap() + INPUT_LEAD_TICKS;
+        for (i, client) in clients.iter_mut().enumerate() {
+            client.submit_noop(target, 100 + i as u32).await;
+        }
+        for client in clients.iter_mut() {
+            client.pump_until(target, 1).await;
+        }
+
+        for client in &clients {
+            // 批次刻度連續遞增、沒有重複。
+            let ticks: Vec<u32> = client.batches.iter().map(|b| b.tick).collect();
+            assert!(
+                ticks.windows(2).all(|w| w[1] == w[0] + 1),
+                "player {} saw out-of-order ticks {:?}",
+                client.player_id,
+                ticks
+            );
+            let batch = client
+                .batches
+                .iter()
+                .find(|b| b.tick == target)
+                .expect("target tick batch delivered");
+            let seen: BTreeSet<u32> = batch.inputs.iter().map(|i| i.player_id).collect();
+            assert_eq!(seen, ids, "target batch carries every player's input");
+        }
+
+        // 重疊區間內所有客戶端看到位元組相同的批次。
+        let first = clients.iter().map(|c| c.batches[0].tick).max().unwrap();
+        let last = clients.iter().map(|c| c.batches.last().unwrap().tick).min().unwrap();
+        for tick in first..=last {
+            let encoded: BTreeSet<Vec<u8>> = clients
+                .iter()
+                .map(|c| {
+                    c.batches
+                        .iter()
+                        .find(|b| b.tick == tick)
+                        .expect("tick present")
+                        .encode_to_vec()
+                })
+                .collect();
+            assert_eq!(encoded.len(), 1, "clients disagree on TickBatch {tick}");
+        }
+    })
+    .await
+    .expect("lockstep roundtrip timed out");
+}
+
+// ===== 模擬狀態雜湊（loopback）=====
+
+/// 以 story `TD_1` 建立的 `State` 與它的 loopback 客戶端（保持通道存活）。
+struct SimHost {
+    state: State,
+    client: LoopbackClient,
+    input_tx: Sender<Vec<(u32, PlayerInput)>>,
+}
+
+impl SimHost {
+    /// `hash_tx` 收 `State` 每 10 秒發布一次的 `compute_state_hash` 樣本。
+    fn new(handle: TransportHandle, client: LoopbackClient, hash_tx: Sender<(u32, u64)>) -> Self {
+        let campaign = omobab::ue4::import_campaign::load_generated("TD_1").expect("TD_1 campaign");
+        let mut state = State::new_with_campaign(
+            campaign,
+            handle.tx,
+            handle.rx,
+            handle.query_rx,
+            handle.viewport_rx,
+        );
+        let (input_tx, input_rx) = unbounded();
+        state.attach_host_input_rx(input_rx);
+        state.set_state_hash_tx(hash_tx);
+        Self {
+            state,
+            client,
+            input_tx,
+        }
+    }
+
+    /// 跑一刻並丟棄舊路徑的出站訊息。
+    fn tick(&mut self) {
+        let dt = CONFIG.lockstep_timing().dt_duration();
+        self.state.tick(dt).expect("State::tick");
+        self.client.drain();
+    }
+}
+
+#[test]
+fn host_and_replica_state_hashes_agree() {
+    apply_runtime_env_from_game_toml();
+    let timing = CONFIG.lockstep_timing();
+    let hash_every = timing.ticks_for_seconds(10);
+
+    let input_buffer = Arc::new(Mutex::new(InputBuffer::new()));
+    let lockstep = Arc::new(Mutex::new(LockstepState::new(MasterSeed::default().0)));
+    let shared = LockstepShared::new(
+        input_buffer.clone(),
+        lockstep.clone(),
+        Arc::new(Mutex::new(SnapshotStore::default())),
+        Arc::new(Mutex::new(TickHistory::new(1024))),
     );
+    let (handle, client) = loopback_transport::pair_with_lockstep(shared);
+    let lockstep_tx = handle.lockstep_tx.clone();
+    let (host_hash_tx, host_hash_rx) = unbounded();
+    let mut host = SimHost::new(handle, client, host_hash_tx);
+    let (replica_handle, replica_client) = loopback_transport::pair();
+    let (replica_hash_tx, replica_hash_rx) = unbounded();
+    let mut replica = SimHost::new(replica_handle, replica_client, replica_hash_tx);
+
+    // 每刻都發 StateHash：主機的樣本一出現就在下一刻轉發。
+    let config = TickBroadcasterConfig {
+        state_hash_interval: 1,
+        ..TickBroadcasterConfig::from_timing(timing)
+    };
+    let broadcaster = TickBroadcaster::new(config, input_buffer, lockstep.clone(), lockstep_tx)
+        .with_state_hash_rx(host_hash_rx)
+        .with_host_input_tx(host.input_tx.clone());
+
+    let seats: Vec<_> = ["alice", "bob"]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            host.client
+                .join(i as u32 + 1, name, JoinRoleEnum::Player)
+                .expect("seat granted")
+        })
+        .collect();
+    let noop = PlayerInput {
+        action: Some(PlayerInputEnum::NoOp(NoOp {})),
+    };
+    for (i, seat) in seats.iter().enumerate() {
+        for (n, target) in [5, INPUT_LEAD_TICKS].into_iter().enumerate() {
+            host.client
+                .submit(seat, target, noop.clone(), (i * 10 + n) as u32)
+                .expect("input accepted");
+        }
+    }
+
+    let mut server_hashes = Vec::new();
+    let mut input_ticks = BTreeSet::new();
+    while server_hashes.is_empty() {
+        assert!(broadcaster.step());
+        host.tick();
+        // 客戶端：批次 `N` 在副本的調度器刻度 `N` 套用。
+        for frame in host.client.drain_frames() {
+            match frame {
+                LockstepFrame::TickBatch(batch) => {
+                    let inputs: Vec<(u32, PlayerInput)> = batch
+                        .inputs
+                        .iter()
+                        .map(|i| (i.player_id, i.input.clone().unwrap_or_default()))
+                        .collect();
+                    if !inputs.is_empty() {
+                        input_ticks.insert(batch.tick);
+                        replica.input_tx.send(inputs).unwrap();
+                    }
+                    replica.tick();
+                }
+                LockstepFrame::StateHash(sh) if sh.hash != 0 => server_hashes.push(sh),
+                _ => {}
+            }
+        }
+        assert!(
+            lockstep.lock().unwrap().current_tick <= hash_every + 1,
+            "no dispatcher state hash reached the broadcaster"
+        );
+    }
+
+    assert_eq!(input_ticks, BTreeSet::from([5, INPUT_LEAD_TICKS]));
+    let sh = &server_hashes[0];
+    assert_eq!(sh.tick, hash_every);
+    let replica_hashes: BTreeMap<u32, u64> = replica_hash_rx.try_iter().collect();
+    assert_eq!(
+        replica_hashes.get(&sh.tick),
+        Some(&sh.hash),
+        "replica compute_state_hash diverged from the host at tick {}",
+        sh.tick
+    );
+
+    // 兩個座位以副本的雜湊回報（同 `ClientStateHash`），比對沒有不同步。
+    for seat in &seats {
+        let mut lockstep = lockstep.lock().unwrap();
+        let report = lockstep.record_client_hash(seat.player_id, sh.tick, replica_hashes[&sh.tick]);
+        assert!(report.is_none(), "{:?}", report);
+    }
+    let stats = lockstep.lock().unwrap().desync_stats();
+    assert_eq!(stats.reports_checked, seats.len() as u64);
+    assert_eq!(stats.reports_mismatched, 0);
 }